@@ -0,0 +1,179 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use clap::{Parser, ValueEnum};
+use moqtail::relay::config::{self, RelayConfig};
+
+/// Cache expiration strategy
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CacheExpirationType {
+  /// Time-to-live: entries expire after a fixed duration from creation
+  Ttl,
+  /// Time-to-idle: entries expire after a period of inactivity
+  Tti,
+}
+
+impl From<CacheExpirationType> for config::CacheExpirationType {
+  fn from(value: CacheExpirationType) -> Self {
+    match value {
+      CacheExpirationType::Ttl => config::CacheExpirationType::Ttl,
+      CacheExpirationType::Tti => config::CacheExpirationType::Tti,
+    }
+  }
+}
+
+/// Policy for overlapping namespaces, e.g. `live` and `live/room`, announced
+/// by more than one publisher
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum AnnounceConflictPolicy {
+  /// Keep the first publisher and reject the others
+  Reject,
+  /// Accept the latest publisher and cancel the previous ones
+  ReplaceLatest,
+  /// Accept every publisher
+  MultiPublisher,
+}
+
+impl From<AnnounceConflictPolicy> for config::AnnounceConflictPolicy {
+  fn from(value: AnnounceConflictPolicy) -> Self {
+    match value {
+      AnnounceConflictPolicy::Reject => config::AnnounceConflictPolicy::Reject,
+      AnnounceConflictPolicy::ReplaceLatest => config::AnnounceConflictPolicy::ReplaceLatest,
+      AnnounceConflictPolicy::MultiPublisher => config::AnnounceConflictPolicy::MultiPublisher,
+    }
+  }
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Cli {
+  /// Port to bind
+  #[arg(long, default_value_t = 4433)]
+  pub port: u16,
+  /// UDP port for native QUIC (moqt://) sessions, next to WebTransport
+  #[arg(long)]
+  pub quic_port: Option<u16>,
+  /// Host to bind
+  #[arg(long, default_value = "localhost")]
+  pub host: String,
+  /// Certificate PEM file
+  #[arg(long, default_value = "apps/relay/cert/cert.pem")]
+  pub cert_file: String,
+  /// Private key PEM file
+  #[arg(long, default_value = "apps/relay/cert/key.pem")]
+  pub key_file: String,
+  /// Number of cached subgroups/fetches per track
+  #[arg(long, default_value_t = 1000)]
+  pub cache_size: u16,
+  /// Cache grow ratio before evicting - allows cache to grow to this multiple of cache_size before evicting
+  #[arg(long, default_value_t = 7)]
+  pub max_idle_timeout: u64,
+  #[arg(long, default_value_t = 3)]
+  pub keep_alive_interval: u64,
+  #[arg(long, default_value = "/tmp")]
+  pub log_folder: String,
+  /// Cache expiration type (ttl or tti)
+  #[arg(long, value_enum, default_value = "ttl")]
+  pub cache_expiration_type: CacheExpirationType,
+  /// Cache expiration duration in minutes, shortened per track by the
+  /// publisher's MAX_CACHE_DURATION
+  #[arg(long, default_value_t = 30)]
+  pub cache_expiration_minutes: u64,
+  /// Enable object logging
+  #[arg(long, default_value_t = false)]
+  pub enable_object_logging: bool,
+  /// Initial maximum request ID
+  #[arg(long, default_value_t = u64::MAX / 8)]
+  pub initial_max_request_id: u64,
+  /// Request IDs of completed requests handed back to a client with each MAX_REQUEST_ID (0 disables)
+  #[arg(long, default_value_t = 64)]
+  pub request_id_window: u64,
+  /// Policy when a namespace is announced by more than one publisher
+  #[arg(long, value_enum, default_value = "reject")]
+  pub announce_conflict_policy: AnnounceConflictPolicy,
+  /// URI sent in GOAWAY when draining the relay on shutdown
+  #[arg(long)]
+  pub drain_new_session_uri: Option<String>,
+  /// Seconds sessions get to move away before they are closed on shutdown
+  #[arg(long, default_value_t = 30)]
+  pub drain_timeout: u64,
+  /// Bytes of registered auth tokens the relay caches per session
+  #[arg(long, default_value_t = 4096)]
+  pub auth_token_cache_size: u64,
+  /// File with the HMAC secret of HS256 auth tokens; requests without a valid token are rejected
+  #[arg(long, conflicts_with = "auth_allowlist")]
+  pub jwt_secret_file: Option<String>,
+  /// JSON file listing the accepted auth tokens and their grants, for local testing
+  #[arg(long)]
+  pub auth_allowlist: Option<String>,
+}
+
+impl From<Cli> for RelayConfig {
+  fn from(cli: Cli) -> Self {
+    RelayConfig {
+      port: cli.port,
+      quic_port: cli.quic_port,
+      host: cli.host,
+      cert_file: cli.cert_file,
+      key_file: cli.key_file,
+      max_idle_timeout: cli.max_idle_timeout,
+      keep_alive_interval: cli.keep_alive_interval,
+      cache_size: cli.cache_size,
+      log_folder: cli.log_folder,
+      cache_expiration_type: cli.cache_expiration_type.into(),
+      cache_expiration_minutes: cli.cache_expiration_minutes,
+      enable_object_logging: cli.enable_object_logging,
+      initial_max_request_id: cli.initial_max_request_id,
+      request_id_window: cli.request_id_window,
+      announce_conflict_policy: cli.announce_conflict_policy.into(),
+      auth_token_cache_size: cli.auth_token_cache_size,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_default_initial_max_request_id() {
+    // Test that the default value for initial_max_request_id is u64::MAX / 8
+    let cli = Cli {
+      port: 4433,
+      quic_port: None,
+      host: "localhost".to_string(),
+      cert_file: "apps/relay/cert/cert.pem".to_string(),
+      key_file: "apps/relay/cert/key.pem".to_string(),
+      cache_size: 1000,
+      max_idle_timeout: 7,
+      keep_alive_interval: 3,
+      log_folder: "/tmp".to_string(),
+      cache_expiration_type: CacheExpirationType::Ttl,
+      cache_expiration_minutes: 30,
+      enable_object_logging: false,
+      initial_max_request_id: u64::MAX / 8,
+      request_id_window: 64,
+      announce_conflict_policy: AnnounceConflictPolicy::Reject,
+      drain_new_session_uri: None,
+      drain_timeout: 30,
+      auth_token_cache_size: 4096,
+      jwt_secret_file: None,
+      auth_allowlist: None,
+    };
+
+    let config = RelayConfig::from(cli);
+
+    assert_eq!(config.initial_max_request_id, u64::MAX / 8);
+  }
+}
@@ -12,16 +12,98 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use tracing::Instrument;
-mod server;
-use server::Server;
+mod config;
+
+use clap::Parser;
+use config::Cli;
+use moqtail::relay::RelayBuilder;
+use moqtail::relay::auth::{JwtAuthorizer, StaticAllowlist};
+use moqtail::relay::config::RelayConfig;
+use std::time::Duration;
+use tracing::{Instrument, debug, error, info};
+use tracing_subscriber::fmt::writer::MakeWriterExt;
+use tracing_subscriber::{EnvFilter, filter::LevelFilter};
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
-  let mut server = Server::new();
-  let _ = server
-    .start()
-    .instrument(tracing::info_span!("server"))
-    .await;
+  let cli = Cli::parse();
+  let drain_new_session_uri = cli.drain_new_session_uri.clone();
+  let drain_timeout = Duration::from_secs(cli.drain_timeout);
+  let jwt_secret_file = cli.jwt_secret_file.clone();
+  let auth_allowlist = cli.auth_allowlist.clone();
+  let config = RelayConfig::from(cli);
+
+  let _guard = init_logging(&config.log_folder);
+
+  debug!("Server | App. Config.: {:?}", config);
+
+  let mut builder = RelayBuilder::new(config);
+  if let Some(path) = jwt_secret_file {
+    info!("Server | requests need a JWT signed with {}", path);
+    builder = builder.with_authorizer(JwtAuthorizer::from_secret_file(&path)?);
+  } else if let Some(path) = auth_allowlist {
+    info!("Server | requests need a token listed in {}", path);
+    builder = builder.with_authorizer(StaticAllowlist::from_file(&path)?);
+  }
+  let relay = builder.build();
+  let mut server = relay.clone();
+  let serve = tokio::spawn(async move {
+    server
+      .start()
+      .instrument(tracing::info_span!("server"))
+      .await
+  });
+
+  tokio::select! {
+    served = serve => {
+      if let Err(e) = served? {
+        error!("Server | stopped: {:?}", e);
+        return Err(e);
+      }
+    }
+    _ = shutdown_signal() => {
+      // send the sessions away and give them time to move before exiting
+      let sessions = relay.drain(drain_new_session_uri, drain_timeout).await;
+      info!("Server | draining {} sessions", sessions);
+      tokio::time::sleep(drain_timeout + Duration::from_secs(1)).await;
+    }
+  }
   Ok(())
 }
+
+async fn shutdown_signal() {
+  #[cfg(unix)]
+  {
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+      .expect("Failed to install SIGTERM handler");
+    tokio::select! {
+      _ = tokio::signal::ctrl_c() => {}
+      _ = terminate.recv() => {}
+    }
+  }
+  #[cfg(not(unix))]
+  {
+    let _ = tokio::signal::ctrl_c().await;
+  }
+}
+
+fn init_logging(log_dir: &str) -> tracing_appender::non_blocking::WorkerGuard {
+  let env_filter = EnvFilter::builder()
+    .with_default_directive(LevelFilter::INFO.into())
+    .from_env_lossy();
+
+  // Ensure the log directory exists
+  std::fs::create_dir_all(log_dir).expect("Failed to create log directory");
+
+  let file_appender = tracing_appender::rolling::daily(log_dir, "relay.log");
+  let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
+
+  tracing_subscriber::fmt()
+    .with_target(true)
+    .with_level(true)
+    .with_env_filter(env_filter)
+    .with_writer(non_blocking.and(std::io::stdout))
+    .init();
+
+  guard
+}
@@ -1,75 +0,0 @@
-// Copyright 2025 The MOQtail Authors
-//
-// Licensed under the Apache License, Version 2.0 (the "License");
-// you may not use this file except in compliance with the License.
-// You may obtain a copy of the License at
-//
-//     http://www.apache.org/licenses/LICENSE-2.0
-//
-// Unless required by applicable law or agreed to in writing, software
-// distributed under the License is distributed on an "AS IS" BASIS,
-// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
-// See the License for the specific language governing permissions and
-// limitations under the License.
-
-use bytes::Bytes;
-use moqtail::{
-  model::{control::control_message::ControlMessage, error::TerminationCode},
-  transport::control_stream_handler::ControlStreamHandler,
-};
-use tracing::info;
-
-use crate::server::{client::MOQTClient, session_context::SessionContext};
-use std::sync::Arc;
-mod announce_handler;
-mod fetch_handler;
-mod max_request_id_handler;
-mod subscribe_handler;
-use super::utils;
-
-pub struct MessageHandler {}
-
-impl MessageHandler {
-  pub async fn handle(
-    client: Arc<MOQTClient>,
-    control_stream_handler: &mut ControlStreamHandler,
-    msg: ControlMessage,
-    context: Arc<SessionContext>,
-  ) -> Result<(), TerminationCode> {
-    let handling_result = match &msg {
-      ControlMessage::Announce(_) => {
-        announce_handler::handle(client.clone(), control_stream_handler, msg, context.clone()).await
-      }
-      ControlMessage::MaxRequestId(_) => {
-        max_request_id_handler::handle(client.clone(), control_stream_handler, msg, context.clone())
-          .await
-      }
-      ControlMessage::Subscribe(_)
-      | ControlMessage::SubscribeOk(_)
-      | ControlMessage::SubscribeUpdate(_)
-      | ControlMessage::SubscribeDone(_)
-      | ControlMessage::SubscribeError(_)
-      | ControlMessage::Unsubscribe(_) => {
-        subscribe_handler::handle(client.clone(), control_stream_handler, msg, context.clone())
-          .await
-      }
-      ControlMessage::Fetch(_) | ControlMessage::FetchOk(_) => {
-        fetch_handler::handle(client.clone(), control_stream_handler, msg, context.clone()).await
-      }
-
-      m => {
-        info!("some message received");
-        let a = m.serialize().unwrap();
-        let buf = Bytes::from_iter(a);
-        utils::print_bytes(&buf);
-        Ok(())
-      }
-    }; // end of if
-
-    if let Err(termination_code) = handling_result {
-      Err(termination_code)
-    } else {
-      Ok(())
-    }
-  }
-}
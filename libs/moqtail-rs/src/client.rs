@@ -11,3 +11,12 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+
+mod error;
+mod handle;
+mod request_id;
+mod session;
+/// Re-export the client types for easier access
+pub use error::ClientError;
+pub use handle::{Announcement, FetchStream, Subscription};
+pub use session::Session;
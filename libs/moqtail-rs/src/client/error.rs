@@ -0,0 +1,57 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::model::control::announce_error::AnnounceError;
+use crate::model::control::control_message::ControlMessage;
+use crate::model::control::fetch_error::FetchError;
+use crate::model::control::subscribe_announces_error::SubscribeAnnouncesError;
+use crate::model::control::subscribe_error::SubscribeError;
+use crate::model::error::{ParseError, TerminationCode};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+  #[error("Connection error: [{0}]")]
+  Connection(String),
+
+  #[error("Version negotiation failed, server selected {selected_version:#X}")]
+  VersionNegotiationFailed { selected_version: u32 },
+
+  #[error("Requests blocked by peer, max request id is {max_request_id}")]
+  RequestsBlocked { max_request_id: u64 },
+
+  #[error("Unexpected message: {0:?}")]
+  UnexpectedMessage(Box<ControlMessage>),
+
+  #[error("Announce rejected: {0:?}")]
+  AnnounceRejected(Box<AnnounceError>),
+
+  #[error("Subscribe rejected: {0:?}")]
+  SubscribeRejected(Box<SubscribeError>),
+
+  #[error("Subscribe announces rejected: {0:?}")]
+  SubscribeAnnouncesRejected(Box<SubscribeAnnouncesError>),
+
+  #[error("Fetch rejected: {0:?}")]
+  FetchRejected(Box<FetchError>),
+
+  #[error("Session terminated: {0}")]
+  Terminated(TerminationCode),
+
+  #[error("Session closed")]
+  Closed,
+
+  #[error(transparent)]
+  Parse(#[from] ParseError),
+}
@@ -0,0 +1,198 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tokio::sync::mpsc;
+
+use super::error::ClientError;
+use super::session::Session;
+use crate::model::common::location::Location;
+use crate::model::common::tuple::Tuple;
+use crate::model::control::control_message::ControlMessage;
+use crate::model::control::fetch_cancel::FetchCancel;
+use crate::model::control::fetch_ok::FetchOk;
+use crate::model::control::subscribe_ok::SubscribeOk;
+use crate::model::control::subscribe_update::SubscribeUpdate;
+use crate::model::control::unannounce::Unannounce;
+use crate::model::control::unsubscribe::Unsubscribe;
+use crate::model::data::object::Object;
+
+/// A namespace announced by this session.
+pub struct Announcement {
+  session: Session,
+  request_id: u64,
+  track_namespace: Tuple,
+}
+
+impl Announcement {
+  pub(crate) fn new(session: Session, request_id: u64, track_namespace: Tuple) -> Self {
+    Self {
+      session,
+      request_id,
+      track_namespace,
+    }
+  }
+
+  pub fn request_id(&self) -> u64 {
+    self.request_id
+  }
+
+  pub fn track_namespace(&self) -> &Tuple {
+    &self.track_namespace
+  }
+
+  /// Withdraws the namespace with UNANNOUNCE.
+  pub fn unannounce(self) -> Result<(), ClientError> {
+    self.session.remove_announcement(&self.track_namespace);
+    self
+      .session
+      .send(ControlMessage::Unannounce(Box::new(Unannounce::new(
+        self.track_namespace,
+      ))))
+  }
+}
+
+/// An accepted subscription. Objects are yielded in the order they arrive,
+/// until the publisher sends SUBSCRIBE_DONE or the session closes.
+pub struct Subscription {
+  session: Session,
+  track_alias: u64,
+  subscribe_ok: SubscribeOk,
+  objects: mpsc::UnboundedReceiver<Object>,
+  // the request id on the current connection, which changes after GOAWAY
+  request_id: Arc<AtomicU64>,
+}
+
+impl Subscription {
+  pub(crate) fn new(
+    session: Session,
+    track_alias: u64,
+    subscribe_ok: SubscribeOk,
+    objects: mpsc::UnboundedReceiver<Object>,
+    request_id: Arc<AtomicU64>,
+  ) -> Self {
+    Self {
+      session,
+      track_alias,
+      subscribe_ok,
+      objects,
+      request_id,
+    }
+  }
+
+  pub fn request_id(&self) -> u64 {
+    self.request_id.load(Ordering::Acquire)
+  }
+
+  pub fn track_alias(&self) -> u64 {
+    self.track_alias
+  }
+
+  pub fn subscribe_ok(&self) -> &SubscribeOk {
+    &self.subscribe_ok
+  }
+
+  pub async fn next_object(&mut self) -> Option<Object> {
+    self.objects.recv().await
+  }
+
+  /// Changes the subscription with SUBSCRIBE_UPDATE. The range can only be
+  /// narrowed; `end_group` is the last group plus one, zero for open-ended.
+  /// Clearing `forward` pauses delivery without unsubscribing.
+  pub async fn update(
+    &self,
+    start_location: Location,
+    end_group: u64,
+    subscriber_priority: u8,
+    forward: bool,
+  ) -> Result<(), ClientError> {
+    let update = SubscribeUpdate::new(
+      self.request_id(),
+      start_location,
+      end_group,
+      subscriber_priority,
+      forward,
+      vec![],
+    );
+    self
+      .session
+      .send(ControlMessage::SubscribeUpdate(Box::new(update.clone())))?;
+    self
+      .session
+      .update_subscription(self.track_alias, &update)
+      .await;
+    Ok(())
+  }
+
+  /// Ends the subscription with UNSUBSCRIBE.
+  pub async fn unsubscribe(self) -> Result<(), ClientError> {
+    self.session.remove_subscription(self.track_alias).await;
+    self
+      .session
+      .send(ControlMessage::Unsubscribe(Box::new(Unsubscribe::new(
+        self.request_id(),
+      ))))
+  }
+}
+
+/// An accepted fetch. Objects are yielded until the fetch stream ends.
+pub struct FetchStream {
+  session: Session,
+  fetch_ok: FetchOk,
+  objects: mpsc::UnboundedReceiver<Object>,
+}
+
+impl FetchStream {
+  pub(crate) fn new(
+    session: Session,
+    fetch_ok: FetchOk,
+    objects: mpsc::UnboundedReceiver<Object>,
+  ) -> Self {
+    Self {
+      session,
+      fetch_ok,
+      objects,
+    }
+  }
+
+  pub fn request_id(&self) -> u64 {
+    self.fetch_ok.request_id
+  }
+
+  pub fn fetch_ok(&self) -> &FetchOk {
+    &self.fetch_ok
+  }
+
+  pub async fn next_object(&mut self) -> Option<Object> {
+    match self.objects.recv().await {
+      Some(object) => Some(object),
+      None => {
+        self.session.remove_fetch(self.fetch_ok.request_id).await;
+        None
+      }
+    }
+  }
+
+  /// Stops the fetch with FETCH_CANCEL.
+  pub async fn cancel(self) -> Result<(), ClientError> {
+    self.session.remove_fetch(self.fetch_ok.request_id).await;
+    self
+      .session
+      .send(ControlMessage::FetchCancel(Box::new(FetchCancel::new(
+        self.fetch_ok.request_id,
+      ))))
+  }
+}
@@ -0,0 +1,89 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// Hands out request IDs for one side of a session.
+///
+/// Clients use even IDs and servers use odd IDs, so consecutive requests are
+/// two apart. An ID may only be used while it is below the MAX_REQUEST_ID
+/// advertised by the peer.
+#[derive(Debug, Clone)]
+pub(crate) struct RequestIdAllocator {
+  next_request_id: u64,
+  max_request_id: u64,
+}
+
+impl RequestIdAllocator {
+  pub fn new(first_request_id: u64, max_request_id: u64) -> Self {
+    Self {
+      next_request_id: first_request_id,
+      max_request_id,
+    }
+  }
+
+  /// Returns the next request ID, or the current limit if the peer has not
+  /// granted enough credit yet.
+  pub fn next(&mut self) -> Result<u64, u64> {
+    if self.next_request_id >= self.max_request_id {
+      return Err(self.max_request_id);
+    }
+    let request_id = self.next_request_id;
+    self.next_request_id += 2;
+    Ok(request_id)
+  }
+
+  /// Applies a MAX_REQUEST_ID from the peer. The limit can only grow.
+  pub fn update_max(&mut self, max_request_id: u64) -> bool {
+    if max_request_id <= self.max_request_id {
+      return false;
+    }
+    self.max_request_id = max_request_id;
+    true
+  }
+
+  pub fn max_request_id(&self) -> u64 {
+    self.max_request_id
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_allocates_even_ids() {
+    let mut allocator = RequestIdAllocator::new(0, 10);
+    assert_eq!(allocator.next(), Ok(0));
+    assert_eq!(allocator.next(), Ok(2));
+    assert_eq!(allocator.next(), Ok(4));
+  }
+
+  #[test]
+  fn test_blocks_at_max_request_id() {
+    let mut allocator = RequestIdAllocator::new(0, 3);
+    assert_eq!(allocator.next(), Ok(0));
+    assert_eq!(allocator.next(), Ok(2));
+    assert_eq!(allocator.next(), Err(3));
+  }
+
+  #[test]
+  fn test_update_max_only_grows() {
+    let mut allocator = RequestIdAllocator::new(0, 1);
+    assert_eq!(allocator.next(), Ok(0));
+    assert_eq!(allocator.next(), Err(1));
+    assert!(!allocator.update_max(1));
+    assert!(allocator.update_max(5));
+    assert_eq!(allocator.max_request_id(), 5);
+    assert_eq!(allocator.next(), Ok(2));
+  }
+}
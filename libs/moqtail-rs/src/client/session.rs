@@ -0,0 +1,1237 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use tokio::sync::{Mutex, MutexGuard, RwLock, mpsc, oneshot};
+use tracing::{debug, info, warn};
+use wtransport::{ClientConfig, Endpoint, quinn};
+
+use super::error::ClientError;
+use super::handle::{Announcement, FetchStream, Subscription};
+use super::request_id::RequestIdAllocator;
+use crate::model::common::pair::KeyValuePair;
+use crate::model::common::tuple::Tuple;
+use crate::model::control::announce::Announce;
+use crate::model::control::client_setup::ClientSetup;
+use crate::model::control::constant::FilterType;
+use crate::model::control::control_message::ControlMessage;
+use crate::model::control::fetch::Fetch;
+use crate::model::control::requests_blocked::RequestsBlocked;
+use crate::model::control::subscribe::Subscribe;
+use crate::model::control::subscribe_announces::SubscribeAnnounces;
+use crate::model::control::subscribe_update::SubscribeUpdate;
+use crate::model::control::track_status::TrackStatus;
+use crate::model::control::track_status_request::TrackStatusRequest;
+use crate::model::control::unsubscribe_announces::UnsubscribeAnnounces;
+use crate::model::data::fetch_header::FetchHeader;
+use crate::model::data::object::Object;
+use crate::model::data::subgroup_header::SubgroupHeader;
+use crate::model::error::TerminationCode;
+use crate::model::parameter::auth_token::{AuthToken, AuthTokenCache};
+use crate::model::parameter::constant::{TokenAliasType, VersionSpecificParameterType};
+use crate::model::parameter::setup_parameter::SetupParameter;
+use crate::model::parameter::version_parameter::VersionParameter;
+use crate::model::version::{VersionCodec, VersionRegistry};
+use crate::transport::control_stream_handler::ControlStreamHandler;
+use crate::transport::data_stream_handler::{
+  FetchRequest, HeaderInfo, RecvDataStream, SendDataStream,
+};
+use crate::transport::moq_transport::{BoxRecvStream, MoqConnector, MoqTransport};
+use crate::transport::quic::{self, MOQT_SCHEME, QuicConnector};
+
+/// MAX_REQUEST_ID advertised to the peer in CLIENT_SETUP
+const DEFAULT_MAX_REQUEST_ID: u64 = u64::MAX / 8;
+
+struct SubscriptionEntry {
+  // changes when the subscription moves to a new connection
+  request_id: Arc<AtomicU64>,
+  subscribe: Subscribe,
+  objects: mpsc::UnboundedSender<Object>,
+  // subgroup streams received on the current connection
+  streams: u64,
+  // stream count of SUBSCRIBE_DONE, the entry goes once that many arrived
+  done_after: Option<u64>,
+}
+
+/// One connection of a session. GOAWAY from the peer replaces it with a
+/// connection to the new session URI.
+struct SessionState {
+  url: String,
+  connection: Arc<dyn MoqTransport>,
+  codec: Arc<dyn VersionCodec>,
+  outbound: mpsc::UnboundedSender<ControlMessage>,
+  request_ids: Mutex<RequestIdAllocator>,
+  // mirrors the relay's cache of the tokens registered on this connection
+  auth_tokens: std::sync::Mutex<AuthTokenCache>,
+  pending_responses: Mutex<BTreeMap<u64, oneshot::Sender<ControlMessage>>>,
+  // keyed by request id
+  fetches: RwLock<BTreeMap<u64, mpsc::UnboundedSender<Object>>>,
+  pending_fetches: Arc<RwLock<BTreeMap<u64, FetchRequest>>>,
+  termination: RwLock<Option<TerminationCode>>,
+  // set once the subscriptions are moving to another connection
+  migrated: AtomicBool,
+}
+
+/// A request ID taken from the allocator of a connection. The allocator
+/// stays locked until the request is queued, so requests reach the wire in
+/// request ID order.
+struct RequestSlot<'a> {
+  state: &'a SessionState,
+  _request_ids: MutexGuard<'a, RequestIdAllocator>,
+  request_id: u64,
+}
+
+impl RequestSlot<'_> {
+  /// Queues the request and returns the receiver of its response
+  async fn send(
+    self,
+    message: ControlMessage,
+  ) -> Result<oneshot::Receiver<ControlMessage>, ClientError> {
+    let (response_tx, response_rx) = oneshot::channel();
+    self
+      .state
+      .pending_responses
+      .lock()
+      .await
+      .insert(self.request_id, response_tx);
+
+    if self.state.outbound.send(message).is_err() {
+      self
+        .state
+        .pending_responses
+        .lock()
+        .await
+        .remove(&self.request_id);
+      return Err(ClientError::Closed);
+    }
+    Ok(response_rx)
+  }
+}
+
+/// The part of a session that outlives its connections.
+struct SharedState {
+  endpoint: Arc<dyn MoqConnector>,
+  // offered again on reconnect
+  versions: Arc<VersionRegistry>,
+  current: std::sync::RwLock<Arc<SessionState>>,
+  next_track_alias: Mutex<u64>,
+  // keyed by track alias
+  subscriptions: RwLock<BTreeMap<u64, SubscriptionEntry>>,
+  // announced again on a new connection
+  announcements: std::sync::Mutex<Vec<(Tuple, Vec<KeyValuePair>)>>,
+  incoming: Mutex<mpsc::UnboundedReceiver<ControlMessage>>,
+}
+
+/// A MOQT session with a relay or publisher.
+///
+/// The session owns the control stream and the incoming data streams. Responses
+/// to announce, subscribe and fetch requests are matched by request ID, objects
+/// are routed to the handle that requested them, and every other control
+/// message is handed to the application through [`Session::next_message`].
+///
+/// When the peer sends GOAWAY, the session connects to the new session URI,
+/// announces its namespaces and subscribes to its tracks again, and then
+/// closes the old connection. Subscription handles keep receiving objects;
+/// fetches stay on the connection they were sent on.
+///
+/// # Example
+/// ```no_run
+/// # use moqtail::client::{ClientError, Session};
+/// # use moqtail::model::common::tuple::Tuple;
+/// # use moqtail::model::control::constant::GroupOrder;
+/// # use moqtail::model::control::subscribe::Subscribe;
+/// # async fn run() -> Result<(), ClientError> {
+/// // or "moqt://relay.example:4443" for native QUIC
+/// let session = Session::connect("https://relay.example:4433").await?;
+/// let namespace = Tuple::from_utf8_path("live/room");
+/// let name = "video".to_string();
+/// let subscribe = Subscribe::new_latest_object(0, 0, namespace, name, 1, GroupOrder::Ascending, true, vec![]);
+/// let mut subscription = session.subscribe(subscribe).await?;
+/// while let Some(object) = subscription.next_object().await {
+///   // consume the object
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Session {
+  shared: Arc<SharedState>,
+  // set for handles whose requests belong to one connection
+  pinned: Option<Arc<SessionState>>,
+}
+
+impl Session {
+  /// Connects over native QUIC for `moqt://` URLs and over WebTransport
+  /// otherwise.
+  pub async fn connect(url: &str) -> Result<Self, ClientError> {
+    if url.starts_with(&format!("{MOQT_SCHEME}://")) {
+      let config =
+        quic::build_client_config(None).map_err(|e| ClientError::Connection(e.to_string()))?;
+      return Self::connect_quic_with_config(url, config).await;
+    }
+    let config = ClientConfig::builder()
+      .with_bind_default()
+      .with_native_certs()
+      .build();
+    Self::connect_with_config(url, config).await
+  }
+
+  /// Connects over WebTransport.
+  pub async fn connect_with_config(url: &str, config: ClientConfig) -> Result<Self, ClientError> {
+    let endpoint = Endpoint::client(config).map_err(|e| ClientError::Connection(e.to_string()))?;
+    Self::connect_with(Arc::new(endpoint), url).await
+  }
+
+  /// Connects to a `moqt://` URL over native QUIC, see
+  /// [`quic::build_client_config`].
+  pub async fn connect_quic_with_config(
+    url: &str,
+    config: quinn::ClientConfig,
+  ) -> Result<Self, ClientError> {
+    let connector =
+      QuicConnector::new(config).map_err(|e| ClientError::Connection(e.to_string()))?;
+    Self::connect_with(Arc::new(connector), url).await
+  }
+
+  /// Connects through any transport. Sessions moved by GOAWAY reconnect
+  /// with the same connector.
+  pub async fn connect_with(
+    endpoint: Arc<dyn MoqConnector>,
+    url: &str,
+  ) -> Result<Self, ClientError> {
+    Self::connect_with_versions(endpoint, url, VersionRegistry::default()).await
+  }
+
+  /// Connects through any transport, offering the versions in `versions`.
+  /// The relay picks one of them in SERVER_SETUP.
+  pub async fn connect_with_versions(
+    endpoint: Arc<dyn MoqConnector>,
+    url: &str,
+    versions: VersionRegistry,
+  ) -> Result<Self, ClientError> {
+    let (state, control_stream_handler, outbound_rx) =
+      Self::open(endpoint.as_ref(), &versions, url).await?;
+    let (incoming_tx, incoming_rx) = mpsc::unbounded_channel();
+
+    let shared = Arc::new(SharedState {
+      endpoint,
+      versions: Arc::new(versions),
+      current: std::sync::RwLock::new(state.clone()),
+      next_track_alias: Mutex::new(1),
+      subscriptions: RwLock::new(BTreeMap::new()),
+      announcements: std::sync::Mutex::new(Vec::new()),
+      incoming: Mutex::new(incoming_rx),
+    });
+    Self::start(
+      shared.clone(),
+      state,
+      control_stream_handler,
+      outbound_rx,
+      incoming_tx,
+    );
+
+    Ok(Session {
+      shared,
+      pinned: None,
+    })
+  }
+
+  /// Connects to `url` and runs the setup handshake.
+  async fn open(
+    endpoint: &dyn MoqConnector,
+    versions: &VersionRegistry,
+    url: &str,
+  ) -> Result<
+    (
+      Arc<SessionState>,
+      ControlStreamHandler,
+      mpsc::UnboundedReceiver<ControlMessage>,
+    ),
+    ClientError,
+  > {
+    let connection = endpoint
+      .connect(url)
+      .await
+      .map_err(|e| ClientError::Connection(e.to_string()))?;
+
+    let (send_stream, recv_stream) = connection
+      .open_bi()
+      .await
+      .map_err(|e| ClientError::Connection(e.to_string()))?;
+
+    let mut control_stream_handler = ControlStreamHandler::new(send_stream, recv_stream);
+    let (codec, max_request_id, auth_token_cache_size) =
+      Self::negotiate(&mut control_stream_handler, versions).await?;
+    info!(
+      "Session established, version: {:#X} max_request_id: {} auth_token_cache_size: {}",
+      codec.version(),
+      max_request_id,
+      auth_token_cache_size
+    );
+    control_stream_handler.set_codec(codec.clone());
+
+    let (outbound_tx, outbound_rx) = mpsc::unbounded_channel();
+    let state = Arc::new(SessionState {
+      url: url.to_string(),
+      connection,
+      codec,
+      outbound: outbound_tx,
+      request_ids: Mutex::new(RequestIdAllocator::new(0, max_request_id)),
+      auth_tokens: std::sync::Mutex::new(AuthTokenCache::new(auth_token_cache_size)),
+      pending_responses: Mutex::new(BTreeMap::new()),
+      fetches: RwLock::new(BTreeMap::new()),
+      pending_fetches: Arc::new(RwLock::new(BTreeMap::new())),
+      termination: RwLock::new(None),
+      migrated: AtomicBool::new(false),
+    });
+    Ok((state, control_stream_handler, outbound_rx))
+  }
+
+  fn start(
+    shared: Arc<SharedState>,
+    state: Arc<SessionState>,
+    control_stream_handler: ControlStreamHandler,
+    outbound_rx: mpsc::UnboundedReceiver<ControlMessage>,
+    incoming_tx: mpsc::UnboundedSender<ControlMessage>,
+  ) {
+    tokio::spawn(Self::run_control_loop(
+      shared.clone(),
+      state.clone(),
+      control_stream_handler,
+      outbound_rx,
+      incoming_tx,
+    ));
+    tokio::spawn(Self::accept_streams(shared, state));
+  }
+
+  async fn negotiate(
+    control_stream_handler: &mut ControlStreamHandler,
+    versions: &VersionRegistry,
+  ) -> Result<(Arc<dyn VersionCodec>, u64, u64), ClientError> {
+    let max_request_id_param: KeyValuePair =
+      SetupParameter::new_max_request_id(DEFAULT_MAX_REQUEST_ID).try_into()?;
+    let client_setup = ClientSetup::new(versions.supported_versions(), vec![max_request_id_param]);
+    control_stream_handler
+      .send_impl(&client_setup)
+      .await
+      .map_err(ClientError::Terminated)?;
+
+    let server_setup = match control_stream_handler.next_message().await {
+      Ok(ControlMessage::ServerSetup(m)) => m,
+      Ok(m) => return Err(ClientError::UnexpectedMessage(Box::new(m))),
+      Err(code) => return Err(ClientError::Terminated(code)),
+    };
+    debug!("Received server setup: {:?}", server_setup);
+
+    // the relay must pick one of the offered versions
+    let Some(codec) = versions.get(server_setup.selected_version) else {
+      return Err(ClientError::VersionNegotiationFailed {
+        selected_version: server_setup.selected_version,
+      });
+    };
+
+    let setup_parameters: Vec<SetupParameter> = server_setup
+      .setup_parameters
+      .iter()
+      .filter_map(|kvp| SetupParameter::deserialize(kvp).ok())
+      .collect();
+    // the peer may not send any requests until it receives MAX_REQUEST_ID
+    let max_request_id = setup_parameters
+      .iter()
+      .find_map(|param| match param {
+        SetupParameter::MaxRequestId { request_id } => Some(*request_id),
+        _ => None,
+      })
+      .unwrap_or(0);
+    // no tokens may be registered without MAX_AUTH_TOKEN_CACHE_SIZE
+    let auth_token_cache_size = setup_parameters
+      .iter()
+      .find_map(|param| match param {
+        SetupParameter::MaxAuthTokenCacheSize { max_size } => Some(*max_size),
+        _ => None,
+      })
+      .unwrap_or(0);
+
+    Ok((codec, max_request_id, auth_token_cache_size))
+  }
+
+  pub fn selected_version(&self) -> u32 {
+    self.state().codec.version()
+  }
+
+  /// The current connection, which changes when the session moves after GOAWAY.
+  pub fn connection(&self) -> Arc<dyn MoqTransport> {
+    self.state().connection.clone()
+  }
+
+  pub async fn max_request_id(&self) -> u64 {
+    self.state().request_ids.lock().await.max_request_id()
+  }
+
+  /// The AUTHORIZATION TOKEN parameter that presents `token` to the relay.
+  /// The first use registers the token under an alias if it fits in the
+  /// relay's MAX_AUTH_TOKEN_CACHE_SIZE, later uses send only the alias. The
+  /// registration takes effect once a request carrying it is sent on this
+  /// connection, parameters that are never sent leave the alias free.
+  /// Requests repeated after GOAWAY register their tokens again.
+  pub fn auth_token(&self, token: &AuthToken) -> Result<KeyValuePair, ClientError> {
+    let parameter = self
+      .state()
+      .auth_tokens
+      .lock()
+      .unwrap()
+      .parameter_for(token);
+    Ok(parameter.try_into()?)
+  }
+
+  fn state(&self) -> Arc<SessionState> {
+    match &self.pinned {
+      Some(state) => state.clone(),
+      None => self.shared.current.read().unwrap().clone(),
+    }
+  }
+
+  fn pinned_to(&self, state: Arc<SessionState>) -> Session {
+    Session {
+      shared: self.shared.clone(),
+      pinned: Some(state),
+    }
+  }
+
+  /// Announces a track namespace and waits for ANNOUNCE_OK.
+  pub async fn announce(
+    &self,
+    track_namespace: Tuple,
+    parameters: &[KeyValuePair],
+  ) -> Result<Announcement, ClientError> {
+    let request_id = self.send_announce(&track_namespace, parameters).await?;
+    self
+      .shared
+      .announcements
+      .lock()
+      .unwrap()
+      .push((track_namespace.clone(), parameters.to_vec()));
+    Ok(Announcement::new(self.clone(), request_id, track_namespace))
+  }
+
+  async fn send_announce(
+    &self,
+    track_namespace: &Tuple,
+    parameters: &[KeyValuePair],
+  ) -> Result<u64, ClientError> {
+    let state = self.state();
+    let slot = Self::reserve_request_id(&state).await?;
+    let request_id = slot.request_id;
+    let announce = Announce::new(request_id, track_namespace.clone(), parameters);
+    let response = slot
+      .send(ControlMessage::Announce(Box::new(announce)))
+      .await?;
+
+    match Self::response(&state, response).await? {
+      ControlMessage::AnnounceOk(_) => Ok(request_id),
+      ControlMessage::AnnounceError(m) => Err(ClientError::AnnounceRejected(m)),
+      m => Err(ClientError::UnexpectedMessage(Box::new(m))),
+    }
+  }
+
+  /// Subscribes to announcements under a namespace prefix and waits for
+  /// SUBSCRIBE_ANNOUNCES_OK. Matching ANNOUNCE and UNANNOUNCE messages are
+  /// delivered through [`Session::next_message`].
+  pub async fn subscribe_announces(
+    &self,
+    track_namespace_prefix: Tuple,
+    parameters: Vec<KeyValuePair>,
+  ) -> Result<(), ClientError> {
+    let state = self.state();
+    let slot = Self::reserve_request_id(&state).await?;
+    let subscribe_announces =
+      SubscribeAnnounces::new(slot.request_id, track_namespace_prefix, parameters);
+    let response = slot
+      .send(ControlMessage::SubscribeAnnounces(Box::new(
+        subscribe_announces,
+      )))
+      .await?;
+
+    match Self::response(&state, response).await? {
+      ControlMessage::SubscribeAnnouncesOk(_) => Ok(()),
+      ControlMessage::SubscribeAnnouncesError(m) => Err(ClientError::SubscribeAnnouncesRejected(m)),
+      m => Err(ClientError::UnexpectedMessage(Box::new(m))),
+    }
+  }
+
+  pub fn unsubscribe_announces(&self, track_namespace_prefix: Tuple) -> Result<(), ClientError> {
+    self.send(ControlMessage::UnsubscribeAnnounces(Box::new(
+      UnsubscribeAnnounces::new(track_namespace_prefix),
+    )))
+  }
+
+  /// Subscribes to a track and waits for SUBSCRIBE_OK.
+  ///
+  /// The request ID and track alias of `subscribe` are assigned by the session.
+  pub async fn subscribe(&self, mut subscribe: Subscribe) -> Result<Subscription, ClientError> {
+    let state = self.state();
+    let slot = Self::reserve_request_id(&state).await?;
+    let request_id = slot.request_id;
+    let track_alias = {
+      let mut next_track_alias = self.shared.next_track_alias.lock().await;
+      let track_alias = *next_track_alias;
+      *next_track_alias += 1;
+      track_alias
+    };
+    subscribe.request_id = request_id;
+    subscribe.track_alias = track_alias;
+
+    // objects may arrive before SUBSCRIBE_OK, so register the route first
+    let (objects_tx, objects_rx) = mpsc::unbounded_channel();
+    let current_request_id = Arc::new(AtomicU64::new(request_id));
+    self.shared.subscriptions.write().await.insert(
+      track_alias,
+      SubscriptionEntry {
+        request_id: current_request_id.clone(),
+        subscribe: subscribe.clone(),
+        objects: objects_tx,
+        streams: 0,
+        done_after: None,
+      },
+    );
+
+    let response = match slot
+      .send(ControlMessage::Subscribe(Box::new(subscribe)))
+      .await
+    {
+      Ok(response) => Self::response(&state, response).await,
+      Err(e) => Err(e),
+    };
+    match response {
+      Ok(ControlMessage::SubscribeOk(m)) => Ok(Subscription::new(
+        self.clone(),
+        track_alias,
+        *m,
+        objects_rx,
+        current_request_id,
+      )),
+      Ok(m) => {
+        self.shared.subscriptions.write().await.remove(&track_alias);
+        match m {
+          ControlMessage::SubscribeError(m) => Err(ClientError::SubscribeRejected(m)),
+          m => Err(ClientError::UnexpectedMessage(Box::new(m))),
+        }
+      }
+      Err(e) => {
+        self.shared.subscriptions.write().await.remove(&track_alias);
+        Err(e)
+      }
+    }
+  }
+
+  /// Fetches a range of objects and waits for FETCH_OK.
+  ///
+  /// The request ID of `fetch` is assigned by the session. Joining fetches
+  /// must refer to the request ID of a live [`Subscription`].
+  pub async fn fetch(&self, mut fetch: Fetch) -> Result<FetchStream, ClientError> {
+    // the fetch stream arrives on the connection the fetch was sent on
+    let session = self.pinned_to(self.state());
+    let state = session.state();
+    let slot = Self::reserve_request_id(&state).await?;
+    let request_id = slot.request_id;
+    fetch.request_id = request_id;
+
+    let track_alias = match &fetch.joining_fetch_props {
+      Some(props) => self
+        .shared
+        .subscriptions
+        .read()
+        .await
+        .iter()
+        .find(|(_, entry)| entry.request_id.load(Ordering::Acquire) == props.joining_request_id)
+        .map(|(track_alias, _)| *track_alias)
+        .unwrap_or(0),
+      None => 0,
+    };
+
+    let (objects_tx, objects_rx) = mpsc::unbounded_channel();
+    state.fetches.write().await.insert(request_id, objects_tx);
+    state.pending_fetches.write().await.insert(
+      request_id,
+      FetchRequest::new(request_id, 0, fetch.clone(), track_alias),
+    );
+
+    let result = match slot.send(ControlMessage::Fetch(Box::new(fetch))).await {
+      Ok(response) => Self::response(&state, response).await,
+      Err(e) => Err(e),
+    };
+    match result {
+      Ok(ControlMessage::FetchOk(m)) => Ok(FetchStream::new(session, *m, objects_rx)),
+      Ok(m) => {
+        session.remove_fetch(request_id).await;
+        match m {
+          ControlMessage::FetchError(m) => Err(ClientError::FetchRejected(m)),
+          m => Err(ClientError::UnexpectedMessage(Box::new(m))),
+        }
+      }
+      Err(e) => {
+        session.remove_fetch(request_id).await;
+        Err(e)
+      }
+    }
+  }
+
+  /// Asks for the status of a track, e.g. to pick a join point before
+  /// subscribing, and waits for TRACK_STATUS.
+  pub async fn track_status(
+    &self,
+    track_namespace: Tuple,
+    track_name: String,
+    parameters: Vec<KeyValuePair>,
+  ) -> Result<TrackStatus, ClientError> {
+    let state = self.state();
+    let slot = Self::reserve_request_id(&state).await?;
+    let track_status_request =
+      TrackStatusRequest::new(slot.request_id, track_namespace, track_name, parameters);
+    let response = slot
+      .send(ControlMessage::TrackStatusRequest(Box::new(
+        track_status_request,
+      )))
+      .await?;
+
+    match Self::response(&state, response).await? {
+      ControlMessage::TrackStatus(m) => Ok(*m),
+      m => Err(ClientError::UnexpectedMessage(Box::new(m))),
+    }
+  }
+
+  /// Waits for the next control message that is not a response to one of our
+  /// own requests, e.g. SUBSCRIBE or FETCH sent to a publisher.
+  /// Returns `None` once the session is closed.
+  pub async fn next_message(&self) -> Option<ControlMessage> {
+    self.shared.incoming.lock().await.recv().await
+  }
+
+  /// Queues a control message on the control stream.
+  pub fn send(&self, message: ControlMessage) -> Result<(), ClientError> {
+    self
+      .state()
+      .outbound
+      .send(message)
+      .map_err(|_| ClientError::Closed)
+  }
+
+  /// Opens a unidirectional stream and writes the subgroup header.
+  pub async fn open_subgroup_stream(
+    &self,
+    header: SubgroupHeader,
+  ) -> Result<SendDataStream, ClientError> {
+    self.open_data_stream(HeaderInfo::Subgroup { header }).await
+  }
+
+  /// Opens a unidirectional stream and writes the fetch header for `fetch`.
+  pub async fn open_fetch_stream(&self, fetch: Fetch) -> Result<SendDataStream, ClientError> {
+    let header = FetchHeader::new(fetch.request_id);
+    self
+      .open_data_stream(HeaderInfo::Fetch {
+        header,
+        fetch_request: fetch,
+      })
+      .await
+  }
+
+  async fn open_data_stream(&self, header_info: HeaderInfo) -> Result<SendDataStream, ClientError> {
+    let state = self.state();
+    let stream = state
+      .connection
+      .open_uni()
+      .await
+      .map_err(|e| ClientError::Connection(e.to_string()))?;
+    Ok(
+      SendDataStream::new_with_codec(
+        Arc::new(Mutex::new(stream)),
+        header_info,
+        state.codec.as_ref(),
+      )
+      .await?,
+    )
+  }
+
+  /// Closes the connection with the given termination code.
+  pub fn close(&self, code: TerminationCode, reason: &str) {
+    self
+      .state()
+      .connection
+      .close(code.to_u32(), reason.as_bytes());
+  }
+
+  /// Records a SUBSCRIBE_UPDATE, so a subscription moved after GOAWAY
+  /// resumes with the updated range, priority and forwarding
+  pub(crate) async fn update_subscription(&self, track_alias: u64, update: &SubscribeUpdate) {
+    if let Some(entry) = self
+      .shared
+      .subscriptions
+      .write()
+      .await
+      .get_mut(&track_alias)
+    {
+      let subscribe = &mut entry.subscribe;
+      // SUBSCRIBE_UPDATE carries the end group plus one, zero is open-ended
+      subscribe.end_group = update.end_group.checked_sub(1);
+      subscribe.filter_type = match subscribe.end_group {
+        Some(_) => FilterType::AbsoluteRange,
+        None => FilterType::AbsoluteStart,
+      };
+      subscribe.start_location = Some(update.start_location.clone());
+      subscribe.subscriber_priority = update.subscriber_priority;
+      subscribe.forward = update.forward;
+    }
+  }
+
+  pub(crate) async fn remove_subscription(&self, track_alias: u64) {
+    self.shared.subscriptions.write().await.remove(&track_alias);
+  }
+
+  pub(crate) fn remove_announcement(&self, track_namespace: &Tuple) {
+    self
+      .shared
+      .announcements
+      .lock()
+      .unwrap()
+      .retain(|(announced, _)| announced != track_namespace);
+  }
+
+  pub(crate) async fn remove_fetch(&self, request_id: u64) {
+    let state = self.state();
+    state.fetches.write().await.remove(&request_id);
+    state.pending_fetches.write().await.remove(&request_id);
+  }
+
+  async fn reserve_request_id(state: &SessionState) -> Result<RequestSlot<'_>, ClientError> {
+    let mut request_ids = state.request_ids.lock().await;
+    match request_ids.next() {
+      Ok(request_id) => Ok(RequestSlot {
+        state,
+        _request_ids: request_ids,
+        request_id,
+      }),
+      Err(max_request_id) => {
+        warn!("Requests blocked, max_request_id: {}", max_request_id);
+        state
+          .outbound
+          .send(ControlMessage::RequestsBlocked(Box::new(RequestsBlocked {
+            maximum_request_id: max_request_id,
+          })))
+          .map_err(|_| ClientError::Closed)?;
+        Err(ClientError::RequestsBlocked { max_request_id })
+      }
+    }
+  }
+
+  /// Waits for the response to a request queued on `state`
+  async fn response(
+    state: &SessionState,
+    response_rx: oneshot::Receiver<ControlMessage>,
+  ) -> Result<ControlMessage, ClientError> {
+    match response_rx.await {
+      Ok(response) => Ok(response),
+      Err(_) => Err(match *state.termination.read().await {
+        Some(code) => ClientError::Terminated(code),
+        None => ClientError::Closed,
+      }),
+    }
+  }
+
+  async fn run_control_loop(
+    shared: Arc<SharedState>,
+    state: Arc<SessionState>,
+    mut control_stream_handler: ControlStreamHandler,
+    mut outbound_rx: mpsc::UnboundedReceiver<ControlMessage>,
+    incoming_tx: mpsc::UnboundedSender<ControlMessage>,
+  ) {
+    let code = loop {
+      tokio::select! {
+        message = outbound_rx.recv() => {
+          let Some(mut message) = message else {
+            break TerminationCode::NoError;
+          };
+          Self::settle_auth_tokens(&state, &mut message);
+          if let Err(code) = control_stream_handler.send(&message).await {
+            break code;
+          }
+        }
+        message = control_stream_handler.next_message() => {
+          match message {
+            Ok(message) => Self::dispatch(&shared, &state, message, &incoming_tx).await,
+            Err(code) => break code,
+          }
+        }
+      }
+    };
+
+    info!("Control stream closed: {:?}", code);
+    *state.termination.write().await = Some(code);
+    // dropping the senders wakes up every waiting request and handle
+    state.pending_responses.lock().await.clear();
+    // moved subscriptions live on in the new connection
+    if !state.migrated.load(Ordering::Acquire) {
+      shared.subscriptions.write().await.clear();
+    }
+    state.fetches.write().await.clear();
+  }
+
+  async fn dispatch(
+    shared: &Arc<SharedState>,
+    state: &Arc<SessionState>,
+    message: ControlMessage,
+    incoming_tx: &mpsc::UnboundedSender<ControlMessage>,
+  ) {
+    debug!("Received control message: {:?}", message);
+    match &message {
+      ControlMessage::MaxRequestId(m) => {
+        state.request_ids.lock().await.update_max(m.request_id);
+        return;
+      }
+      // request ids of a moved connection may be reused by the new one
+      ControlMessage::SubscribeDone(m) if !state.migrated.load(Ordering::Acquire) => {
+        // streams may still be in flight, keep routing them until all arrived
+        let mut subscriptions = shared.subscriptions.write().await;
+        let done = subscriptions
+          .iter_mut()
+          .find(|(_, entry)| entry.request_id.load(Ordering::Acquire) == m.request_id)
+          .map(|(track_alias, entry)| {
+            entry.done_after = Some(m.stream_count);
+            (*track_alias, entry.streams >= m.stream_count)
+          });
+        if let Some((track_alias, true)) = done {
+          subscriptions.remove(&track_alias);
+        }
+      }
+      ControlMessage::Goaway(m) if !state.migrated.swap(true, Ordering::AcqRel) => {
+        tokio::spawn(Self::migrate(
+          shared.clone(),
+          state.clone(),
+          m.new_session_uri.clone(),
+          incoming_tx.clone(),
+        ));
+      }
+      _ => {}
+    }
+
+    if let Some(request_id) = response_request_id(&message) {
+      if let Some(response_tx) = state.pending_responses.lock().await.remove(&request_id) {
+        let _ = response_tx.send(message);
+        return;
+      }
+      warn!("Response for unknown request id: {}", request_id);
+    }
+
+    let _ = incoming_tx.send(message);
+  }
+
+  /// Settles the AUTHORIZATION TOKEN parameters of a request in the mirror
+  /// of the relay's cache as the request is sent, so only registrations the
+  /// relay receives take up an alias.
+  fn settle_auth_tokens(state: &SessionState, message: &mut ControlMessage) {
+    let parameters = match message {
+      ControlMessage::Announce(m) => &mut m.parameters,
+      ControlMessage::SubscribeAnnounces(m) => &mut m.parameters,
+      ControlMessage::Subscribe(m) => &mut m.subscribe_parameters,
+      ControlMessage::Fetch(m) => &mut m.parameters,
+      ControlMessage::TrackStatusRequest(m) => &mut m.parameters,
+      _ => return,
+    };
+    let mut auth_tokens = state.auth_tokens.lock().unwrap();
+    for kvp in parameters.iter_mut() {
+      if !matches!(kvp, KeyValuePair::Bytes { type_value, .. }
+        if *type_value == VersionSpecificParameterType::AuthorizationToken as u64)
+      {
+        continue;
+      }
+      if let Ok(parameter) = VersionParameter::deserialize(kvp)
+        && let Ok(settled) = auth_tokens.settle(&parameter).try_into()
+      {
+        *kvp = settled;
+      }
+    }
+  }
+
+  /// Rewrites the AUTHORIZATION TOKEN parameters of a request sent on `from`
+  /// for `to`, where the aliases registered on `from` are unknown.
+  fn move_auth_tokens(
+    from: &SessionState,
+    to: &SessionState,
+    parameters: &[KeyValuePair],
+  ) -> Vec<KeyValuePair> {
+    let is_auth_token = |kvp: &KeyValuePair| {
+      matches!(kvp, KeyValuePair::Bytes { type_value, .. }
+        if *type_value == VersionSpecificParameterType::AuthorizationToken as u64)
+    };
+    parameters
+      .iter()
+      .filter_map(|kvp| {
+        if !is_auth_token(kvp) {
+          return Some(kvp.clone());
+        }
+        let token = match VersionParameter::deserialize(kvp).ok()? {
+          VersionParameter::AuthorizationToken {
+            token_type: Some(token_type),
+            token_value: Some(value),
+            ..
+          } => AuthToken { token_type, value },
+          VersionParameter::AuthorizationToken {
+            alias_type,
+            token_alias: Some(alias),
+            ..
+          } if alias_type == TokenAliasType::UseAlias as u64 => {
+            from.auth_tokens.lock().unwrap().get(alias)?.clone()
+          }
+          // deletions refer to the old connection
+          _ => return None,
+        };
+        let parameter = to.auth_tokens.lock().unwrap().parameter_for(&token);
+        parameter.try_into().ok()
+      })
+      .collect()
+  }
+
+  /// Moves the session to `new_session_uri` after GOAWAY, or back to the same
+  /// URL when the peer sent none. Namespaces are announced and tracks are
+  /// subscribed again under their track aliases, so the handles keep working,
+  /// and then the old connection is closed.
+  async fn migrate(
+    shared: Arc<SharedState>,
+    old_state: Arc<SessionState>,
+    new_session_uri: Option<String>,
+    incoming_tx: mpsc::UnboundedSender<ControlMessage>,
+  ) {
+    let url = new_session_uri.unwrap_or_else(|| old_state.url.clone());
+    info!("GOAWAY received, moving session to {}", url);
+
+    let (state, control_stream_handler, outbound_rx) =
+      match Self::open(shared.endpoint.as_ref(), &shared.versions, &url).await {
+        Ok(opened) => opened,
+        Err(e) => {
+          warn!("Failed to move session to {}: {:?}", url, e);
+          old_state.migrated.store(false, Ordering::Release);
+          return;
+        }
+      };
+    Self::start(
+      shared.clone(),
+      state.clone(),
+      control_stream_handler,
+      outbound_rx,
+      incoming_tx,
+    );
+    *shared.current.write().unwrap() = state.clone();
+    let session = Session {
+      shared: shared.clone(),
+      pinned: None,
+    };
+
+    let announcements = shared.announcements.lock().unwrap().clone();
+    for (track_namespace, parameters) in announcements {
+      let parameters = Self::move_auth_tokens(&old_state, &state, &parameters);
+      if let Err(e) = session.send_announce(&track_namespace, &parameters).await {
+        warn!("Failed to announce {:?} again: {:?}", track_namespace, e);
+      }
+    }
+
+    let subscriptions: Vec<(u64, Subscribe, Arc<AtomicU64>)> = shared
+      .subscriptions
+      .write()
+      .await
+      .iter_mut()
+      .map(|(track_alias, entry)| {
+        entry.streams = 0;
+        (
+          *track_alias,
+          entry.subscribe.clone(),
+          entry.request_id.clone(),
+        )
+      })
+      .collect();
+    for (track_alias, mut subscribe, current_request_id) in subscriptions {
+      let response = match Self::reserve_request_id(&state).await {
+        Ok(slot) => {
+          subscribe.request_id = slot.request_id;
+          subscribe.subscribe_parameters =
+            Self::move_auth_tokens(&old_state, &state, &subscribe.subscribe_parameters);
+          current_request_id.store(slot.request_id, Ordering::Release);
+          match slot
+            .send(ControlMessage::Subscribe(Box::new(subscribe)))
+            .await
+          {
+            Ok(response) => Self::response(&state, response).await,
+            Err(e) => Err(e),
+          }
+        }
+        Err(e) => Err(e),
+      };
+      match response {
+        Ok(ControlMessage::SubscribeOk(_)) => {
+          debug!("Subscribed to track {} again", track_alias);
+        }
+        response => {
+          warn!(
+            "Failed to subscribe to track {} again: {:?}",
+            track_alias, response
+          );
+          shared.subscriptions.write().await.remove(&track_alias);
+        }
+      }
+    }
+
+    old_state
+      .connection
+      .close(TerminationCode::NoError.to_u32(), b"Session moved");
+  }
+
+  async fn accept_streams(shared: Arc<SharedState>, state: Arc<SessionState>) {
+    loop {
+      match state.connection.accept_uni().await {
+        Ok(stream) => {
+          tokio::spawn(Self::handle_uni_stream(
+            shared.clone(),
+            state.clone(),
+            stream,
+          ));
+        }
+        Err(e) => {
+          debug!("Stopped accepting streams: {:?}", e);
+          break;
+        }
+      }
+    }
+  }
+
+  async fn handle_uni_stream(
+    shared: Arc<SharedState>,
+    state: Arc<SessionState>,
+    stream: BoxRecvStream,
+  ) {
+    let recv_data_stream =
+      RecvDataStream::new_with_codec(stream, state.pending_fetches.clone(), state.codec.clone());
+    let mut stream_handler = &recv_data_stream;
+    let mut route: Option<(mpsc::UnboundedSender<Object>, Option<u64>)> = None;
+    let mut fetch_request_id = None;
+
+    loop {
+      let (handler, object) = stream_handler.next_object().await;
+      stream_handler = handler;
+      let Some(mut object) = object else {
+        break;
+      };
+
+      if route.is_none() {
+        route = match stream_handler.get_header_info().await {
+          Some(HeaderInfo::Subgroup { header }) => {
+            let current = Arc::ptr_eq(&state, &shared.current.read().unwrap());
+            let mut subscriptions = shared.subscriptions.write().await;
+            let route = subscriptions.get_mut(&header.track_alias).map(|entry| {
+              if current {
+                entry.streams += 1;
+              }
+              let done = entry.done_after.is_some_and(|count| entry.streams >= count);
+              (entry.objects.clone(), done)
+            });
+            match route {
+              Some((objects, done)) => {
+                // the last stream before SUBSCRIBE_DONE keeps its own sender
+                if done {
+                  subscriptions.remove(&header.track_alias);
+                }
+                Some((objects, None))
+              }
+              None => None,
+            }
+          }
+          Some(HeaderInfo::Fetch { header, .. }) => {
+            fetch_request_id = Some(header.request_id);
+            let track_alias = state
+              .pending_fetches
+              .read()
+              .await
+              .get(&header.request_id)
+              .map(|request| request.track_alias);
+            state
+              .fetches
+              .read()
+              .await
+              .get(&header.request_id)
+              .map(|objects| (objects.clone(), track_alias))
+          }
+          None => None,
+        };
+        if route.is_none() {
+          warn!("Received stream for unknown track alias or request id");
+          break;
+        }
+      }
+
+      if let Some((objects, track_alias)) = &route {
+        // fetch objects do not carry a track alias on the wire
+        if let Some(track_alias) = track_alias {
+          object.track_alias = *track_alias;
+        }
+        if objects.send(object).is_err() {
+          // the handle is gone, e.g. a cancelled fetch, stop reading
+          recv_data_stream.cancel();
+          break;
+        }
+      }
+    }
+
+    // a fetch is served on a single stream, so its end completes the fetch
+    if let Some(request_id) = fetch_request_id {
+      state.fetches.write().await.remove(&request_id);
+      state.pending_fetches.write().await.remove(&request_id);
+    }
+  }
+}
+
+fn response_request_id(message: &ControlMessage) -> Option<u64> {
+  match message {
+    ControlMessage::AnnounceOk(m) => Some(m.request_id),
+    ControlMessage::AnnounceError(m) => Some(m.request_id),
+    ControlMessage::SubscribeOk(m) => Some(m.request_id),
+    ControlMessage::SubscribeError(m) => Some(m.request_id),
+    ControlMessage::FetchOk(m) => Some(m.request_id),
+    ControlMessage::FetchError(m) => Some(m.request_id),
+    ControlMessage::SubscribeAnnouncesOk(m) => Some(m.request_id),
+    ControlMessage::SubscribeAnnouncesError(m) => Some(m.request_id),
+    ControlMessage::TrackStatus(m) => Some(m.request_id),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::common::location::Location;
+  use crate::model::control::constant::{DRAFT_11, TrackStatusCode};
+  use crate::model::control::server_setup::ServerSetup;
+  use crate::transport::loopback::{self, LoopbackConfig, LoopbackListener, LoopbackTransport};
+
+  /// Accepts one session, answers the setup handshake with `max_request_id`
+  /// and returns the server end of the connection and control stream
+  async fn accept_session(
+    listener: &mut LoopbackListener,
+    max_request_id: u64,
+  ) -> (LoopbackTransport, ControlStreamHandler) {
+    let connection = listener.accept().await.unwrap();
+    let (send_stream, recv_stream) = connection.accept_bi().await.unwrap();
+    let mut control_stream_handler = ControlStreamHandler::new(send_stream, recv_stream);
+    match control_stream_handler.next_message().await {
+      Ok(ControlMessage::ClientSetup(_)) => {}
+      other => panic!("Expected CLIENT_SETUP, got {other:?}"),
+    }
+    let max_request_id_param: KeyValuePair = SetupParameter::new_max_request_id(max_request_id)
+      .try_into()
+      .unwrap();
+    let server_setup = ServerSetup::new(DRAFT_11, vec![max_request_id_param]);
+    control_stream_handler
+      .send(&ControlMessage::ServerSetup(Box::new(server_setup)))
+      .await
+      .unwrap();
+    (connection, control_stream_handler)
+  }
+
+  #[tokio::test]
+  async fn test_concurrent_requests_are_sent_in_order() {
+    let (connector, mut listener) = loopback::listener(LoopbackConfig::default());
+    let server = tokio::spawn(async move {
+      let (_connection, mut control_stream_handler) = accept_session(&mut listener, 100).await;
+      let mut request_ids = Vec::new();
+      while request_ids.len() < 20 {
+        let request_id = match control_stream_handler.next_message().await {
+          Ok(ControlMessage::TrackStatusRequest(m)) => m.request_id,
+          other => panic!("Expected TRACK_STATUS_REQUEST, got {other:?}"),
+        };
+        request_ids.push(request_id);
+        let track_status = TrackStatus::new(
+          request_id,
+          TrackStatusCode::InProgress,
+          Location::new(0, 0),
+          vec![],
+        );
+        control_stream_handler
+          .send(&ControlMessage::TrackStatus(Box::new(track_status)))
+          .await
+          .unwrap();
+      }
+      request_ids
+    });
+
+    let session = Session::connect_with(Arc::new(connector), "loopback")
+      .await
+      .unwrap();
+    let requests = (0..20).map(|_| {
+      let session = session.clone();
+      tokio::spawn(async move {
+        session
+          .track_status(Tuple::from_utf8_path("live"), "video".to_string(), vec![])
+          .await
+          .unwrap()
+      })
+    });
+    for request in requests.collect::<Vec<_>>() {
+      request.await.unwrap();
+    }
+
+    let request_ids = server.await.unwrap();
+    assert_eq!(request_ids, (0..40).step_by(2).collect::<Vec<_>>());
+  }
+
+  #[tokio::test]
+  async fn test_requests_blocked() {
+    let (connector, mut listener) = loopback::listener(LoopbackConfig::default());
+    let server = tokio::spawn(async move {
+      let (_connection, mut control_stream_handler) = accept_session(&mut listener, 1).await;
+      let request_id = match control_stream_handler.next_message().await {
+        Ok(ControlMessage::TrackStatusRequest(m)) => m.request_id,
+        other => panic!("Expected TRACK_STATUS_REQUEST, got {other:?}"),
+      };
+      let track_status = TrackStatus::new(
+        request_id,
+        TrackStatusCode::InProgress,
+        Location::new(0, 0),
+        vec![],
+      );
+      control_stream_handler
+        .send(&ControlMessage::TrackStatus(Box::new(track_status)))
+        .await
+        .unwrap();
+      match control_stream_handler.next_message().await {
+        Ok(ControlMessage::RequestsBlocked(m)) => m.maximum_request_id,
+        other => panic!("Expected REQUESTS_BLOCKED, got {other:?}"),
+      }
+    });
+
+    let session = Session::connect_with(Arc::new(connector), "loopback")
+      .await
+      .unwrap();
+    session
+      .track_status(Tuple::from_utf8_path("live"), "video".to_string(), vec![])
+      .await
+      .unwrap();
+    match session
+      .track_status(Tuple::from_utf8_path("live"), "video".to_string(), vec![])
+      .await
+    {
+      Err(ClientError::RequestsBlocked { max_request_id }) => assert_eq!(max_request_id, 1),
+      other => panic!("Expected RequestsBlocked, got {other:?}"),
+    }
+    assert_eq!(server.await.unwrap(), 1);
+  }
+}
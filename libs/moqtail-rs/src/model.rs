@@ -19,3 +19,4 @@ pub mod data;
 pub mod error;
 pub mod extension_header;
 pub mod parameter;
+pub mod version;
@@ -18,6 +18,7 @@ use crate::model::common::tuple::Tuple;
 use crate::model::common::varint::{BufMutVarIntExt, BufVarIntExt};
 use crate::model::control::constant::ControlMessageType;
 use crate::model::error::ParseError;
+use crate::model::parameter::version_parameter::{VersionParameters, validate_version_parameters};
 use bytes::{BufMut, Bytes, BytesMut};
 
 #[derive(Debug, Clone, PartialEq)]
@@ -38,6 +39,16 @@ impl Announce {
   }
 }
 
+impl VersionParameters for Announce {
+  fn version_parameters(&self) -> &[KeyValuePair] {
+    &self.parameters
+  }
+
+  fn version_parameters_mut(&mut self) -> &mut Vec<KeyValuePair> {
+    &mut self.parameters
+  }
+}
+
 impl ControlMessageTrait for Announce {
   fn serialize(&self) -> Result<Bytes, ParseError> {
     let mut buf = BytesMut::new();
@@ -86,6 +97,7 @@ impl ControlMessageTrait for Announce {
       let param = KeyValuePair::deserialize(payload)?;
       parameters.push(param);
     }
+    validate_version_parameters(&parameters, "Announce::deserialize")?;
 
     Ok(Box::new(Announce {
       request_id,
@@ -19,6 +19,7 @@ use crate::model::common::pair::KeyValuePair;
 use crate::model::common::tuple::Tuple;
 use crate::model::common::varint::{BufMutVarIntExt, BufVarIntExt};
 use crate::model::error::ParseError;
+use crate::model::parameter::version_parameter::{VersionParameters, validate_version_parameters};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 
 #[derive(Debug, PartialEq, Clone)]
@@ -116,6 +117,16 @@ impl Fetch {
   }
 }
 
+impl VersionParameters for Fetch {
+  fn version_parameters(&self) -> &[KeyValuePair] {
+    &self.parameters
+  }
+
+  fn version_parameters_mut(&mut self) -> &mut Vec<KeyValuePair> {
+    &mut self.parameters
+  }
+}
+
 impl ControlMessageTrait for Fetch {
   fn serialize(&self) -> Result<Bytes, ParseError> {
     let mut buf = BytesMut::new();
@@ -251,6 +262,7 @@ impl ControlMessageTrait for Fetch {
       let param = KeyValuePair::deserialize(payload)?;
       parameters.push(param);
     }
+    validate_version_parameters(&parameters, "Fetch::deserialize")?;
 
     Ok(Box::new(Fetch {
       request_id,
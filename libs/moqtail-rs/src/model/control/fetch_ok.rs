@@ -18,6 +18,7 @@ use crate::model::common::location::Location;
 use crate::model::common::pair::KeyValuePair;
 use crate::model::common::varint::{BufMutVarIntExt, BufVarIntExt};
 use crate::model::error::ParseError;
+use crate::model::parameter::version_parameter::{VersionParameters, validate_version_parameters};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 
 #[derive(Debug, PartialEq, Clone)]
@@ -61,6 +62,16 @@ impl FetchOk {
   }
 }
 
+impl VersionParameters for FetchOk {
+  fn version_parameters(&self) -> &[KeyValuePair] {
+    &self.subscribe_parameters
+  }
+
+  fn version_parameters_mut(&mut self) -> &mut Vec<KeyValuePair> {
+    &mut self.subscribe_parameters
+  }
+}
+
 impl ControlMessageTrait for FetchOk {
   fn serialize(&self) -> Result<Bytes, ParseError> {
     let mut buf = BytesMut::new();
@@ -140,6 +151,7 @@ impl ControlMessageTrait for FetchOk {
       let param = KeyValuePair::deserialize(payload)?;
       subscribe_parameters.push(param);
     }
+    validate_version_parameters(&subscribe_parameters, "FetchOk::deserialize")?;
 
     Ok(Box::new(FetchOk {
       request_id,
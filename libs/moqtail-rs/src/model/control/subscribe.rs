@@ -19,6 +19,7 @@ use crate::model::common::pair::KeyValuePair;
 use crate::model::common::tuple::Tuple;
 use crate::model::common::varint::{BufMutVarIntExt, BufVarIntExt};
 use crate::model::error::ParseError;
+use crate::model::parameter::version_parameter::{VersionParameters, validate_version_parameters};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 
 #[derive(Debug, PartialEq, Clone)]
@@ -146,6 +147,17 @@ impl Subscribe {
     }
   }
 }
+
+impl VersionParameters for Subscribe {
+  fn version_parameters(&self) -> &[KeyValuePair] {
+    &self.subscribe_parameters
+  }
+
+  fn version_parameters_mut(&mut self) -> &mut Vec<KeyValuePair> {
+    &mut self.subscribe_parameters
+  }
+}
+
 impl ControlMessageTrait for Subscribe {
   fn serialize(&self) -> Result<Bytes, ParseError> {
     let mut buf = BytesMut::new();
@@ -296,6 +308,7 @@ impl ControlMessageTrait for Subscribe {
       let param = KeyValuePair::deserialize(payload)?;
       subscribe_parameters.push(param);
     }
+    validate_version_parameters(&subscribe_parameters, "Subscribe::deserialize")?;
 
     Ok(Box::new(Subscribe {
       request_id,
@@ -318,7 +331,9 @@ impl ControlMessageTrait for Subscribe {
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::model::parameter::version_parameter::VersionParameter;
   use bytes::Buf;
+  use std::time::Duration;
 
   #[test]
   fn test_roundtrip() {
@@ -456,4 +471,74 @@ mod tests {
     let deserialized = Subscribe::parse_payload(&mut partial);
     assert!(deserialized.is_err());
   }
+
+  #[test]
+  fn test_typed_parameters() {
+    let subscribe = Subscribe::new_latest_object(
+      1,
+      2,
+      Tuple::from_utf8_path("a/b"),
+      "track".to_string(),
+      0,
+      GroupOrder::Ascending,
+      true,
+      vec![KeyValuePair::try_new_varint(0x3E, 7).unwrap()],
+    )
+    .with_delivery_timeout(Duration::from_millis(500))
+    .unwrap()
+    .with_delivery_timeout(Duration::from_millis(250))
+    .unwrap()
+    .with_auth_token(VersionParameter::new_auth_token_use_alias(4))
+    .unwrap();
+
+    assert_eq!(
+      subscribe.delivery_timeout(),
+      Some(Duration::from_millis(250))
+    );
+    assert_eq!(subscribe.max_cache_duration(), None);
+    assert_eq!(
+      subscribe.auth_tokens(),
+      vec![VersionParameter::new_auth_token_use_alias(4)]
+    );
+
+    let mut buf = subscribe.serialize().unwrap();
+    buf.get_vi().unwrap();
+    buf.get_u16();
+    let deserialized = Subscribe::parse_payload(&mut buf).unwrap();
+    assert_eq!(*deserialized, subscribe);
+    assert_eq!(
+      deserialized.forwarded_parameters(),
+      vec![
+        KeyValuePair::try_new_varint(0x3E, 7).unwrap(),
+        VersionParameter::new_delivery_timeout(250)
+          .try_into()
+          .unwrap(),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_duplicate_delivery_timeout() {
+    let timeout: KeyValuePair = VersionParameter::new_delivery_timeout(100)
+      .try_into()
+      .unwrap();
+    let subscribe = Subscribe::new_next_group_start(
+      1,
+      2,
+      Tuple::from_utf8_path("a/b"),
+      "track".to_string(),
+      0,
+      GroupOrder::Ascending,
+      true,
+      vec![timeout.clone(), timeout],
+    );
+
+    let mut buf = subscribe.serialize().unwrap();
+    buf.get_vi().unwrap();
+    buf.get_u16();
+    assert!(matches!(
+      Subscribe::parse_payload(&mut buf),
+      Err(ParseError::ProtocolViolation { .. })
+    ));
+  }
 }
@@ -18,6 +18,7 @@ use crate::model::common::pair::KeyValuePair;
 use crate::model::common::tuple::Tuple;
 use crate::model::common::varint::{BufMutVarIntExt, BufVarIntExt};
 use crate::model::error::ParseError;
+use crate::model::parameter::version_parameter::{VersionParameters, validate_version_parameters};
 use bytes::{BufMut, Bytes, BytesMut};
 
 #[derive(Debug, PartialEq, Clone)]
@@ -41,6 +42,16 @@ impl SubscribeAnnounces {
   }
 }
 
+impl VersionParameters for SubscribeAnnounces {
+  fn version_parameters(&self) -> &[KeyValuePair] {
+    &self.parameters
+  }
+
+  fn version_parameters_mut(&mut self) -> &mut Vec<KeyValuePair> {
+    &mut self.parameters
+  }
+}
+
 impl ControlMessageTrait for SubscribeAnnounces {
   fn serialize(&self) -> Result<Bytes, ParseError> {
     let mut buf = BytesMut::new();
@@ -89,6 +100,7 @@ impl ControlMessageTrait for SubscribeAnnounces {
       let param = KeyValuePair::deserialize(payload)?;
       parameters.push(param);
     }
+    validate_version_parameters(&parameters, "SubscribeAnnounces::deserialize")?;
 
     Ok(Box::new(SubscribeAnnounces {
       request_id,
@@ -18,6 +18,7 @@ use crate::model::common::location::Location;
 use crate::model::common::pair::KeyValuePair;
 use crate::model::common::varint::{BufMutVarIntExt, BufVarIntExt};
 use crate::model::error::ParseError;
+use crate::model::parameter::version_parameter::{VersionParameters, validate_version_parameters};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use std::convert::TryInto;
 
@@ -94,6 +95,17 @@ impl SubscribeOk {
     }
   }
 }
+
+impl VersionParameters for SubscribeOk {
+  fn version_parameters(&self) -> &[KeyValuePair] {
+    self.subscribe_parameters.as_deref().unwrap_or(&[])
+  }
+
+  fn version_parameters_mut(&mut self) -> &mut Vec<KeyValuePair> {
+    self.subscribe_parameters.get_or_insert_with(Vec::new)
+  }
+}
+
 impl ControlMessageTrait for SubscribeOk {
   fn serialize(&self) -> Result<Bytes, ParseError> {
     let mut buf = BytesMut::new();
@@ -206,6 +218,7 @@ impl ControlMessageTrait for SubscribeOk {
         let param = KeyValuePair::deserialize(payload)?;
         params.push(param);
       }
+      validate_version_parameters(&params, "SubscribeOk::deserialize")?;
       Some(params)
     } else {
       None
@@ -96,14 +96,18 @@ impl ControlMessageTrait for TrackStatus {
     let status_code_raw = payload.get_vi()?;
     let status_code = TrackStatusCode::try_from(status_code_raw)?;
 
+    // always on the wire, zero for tracks without objects
     let largest_location = match status_code {
       TrackStatusCode::InProgress
       | TrackStatusCode::Finished
       | TrackStatusCode::RelayUnavailable => Location::deserialize(payload)?,
-      TrackStatusCode::DoesNotExist | TrackStatusCode::NotYetBegun => Location {
-        group: 0,
-        object: 0,
-      },
+      TrackStatusCode::DoesNotExist | TrackStatusCode::NotYetBegun => {
+        Location::deserialize(payload)?;
+        Location {
+          group: 0,
+          object: 0,
+        }
+      }
     };
 
     let param_count_u64 = payload.get_vi()?;
@@ -170,6 +174,26 @@ mod tests {
     assert!(!buf.has_remaining());
   }
 
+  #[test]
+  fn test_not_yet_begun_roundtrip() {
+    let track_status = TrackStatus::new(
+      7,
+      TrackStatusCode::NotYetBegun,
+      Location {
+        group: 0,
+        object: 0,
+      },
+      vec![],
+    );
+
+    let mut buf = track_status.serialize().unwrap();
+    buf.get_vi().unwrap();
+    buf.get_u16();
+    let deserialized = TrackStatus::parse_payload(&mut buf).unwrap();
+    assert_eq!(*deserialized, track_status);
+    assert!(!buf.has_remaining());
+  }
+
   #[test]
   fn test_excess_roundtrip() {
     let request_id = 241421;
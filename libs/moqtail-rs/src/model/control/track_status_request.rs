@@ -18,6 +18,7 @@ use crate::model::common::pair::KeyValuePair;
 use crate::model::common::tuple::Tuple;
 use crate::model::common::varint::{BufMutVarIntExt, BufVarIntExt};
 use crate::model::error::ParseError;
+use crate::model::parameter::version_parameter::{VersionParameters, validate_version_parameters};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 
 #[derive(Debug, PartialEq, Clone)]
@@ -44,6 +45,16 @@ impl TrackStatusRequest {
   }
 }
 
+impl VersionParameters for TrackStatusRequest {
+  fn version_parameters(&self) -> &[KeyValuePair] {
+    &self.parameters
+  }
+
+  fn version_parameters_mut(&mut self) -> &mut Vec<KeyValuePair> {
+    &mut self.parameters
+  }
+}
+
 impl ControlMessageTrait for TrackStatusRequest {
   fn serialize(&self) -> Result<Bytes, ParseError> {
     let mut buf = BytesMut::new();
@@ -119,6 +130,7 @@ impl ControlMessageTrait for TrackStatusRequest {
       let param = KeyValuePair::deserialize(payload)?;
       parameters.push(param);
     }
+    validate_version_parameters(&parameters, "TrackStatusRequest::deserialize")?;
 
     Ok(Box::new(TrackStatusRequest {
       request_id,
@@ -71,6 +71,21 @@ impl From<SubgroupHeaderType> for u64 {
   }
 }
 
+/// Error codes used when resetting or stopping a data stream
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StreamResetCode {
+  InternalError = 0x00,
+  Cancelled = 0x01,
+  DeliveryTimeout = 0x02,
+  SessionClosed = 0x03,
+}
+
+impl From<StreamResetCode> for u64 {
+  fn from(code: StreamResetCode) -> Self {
+    code as u64
+  }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ObjectForwardingPreference {
   Subgroup,
@@ -79,16 +79,15 @@ impl DatagramObject {
     buf.put_vi(self.object_id)?;
     buf.put_u8(self.publisher_priority);
 
-    let mut payload_buf = BytesMut::new();
-
+    // the extension headers length is only present for the extension types
     if let Some(ext_headers) = &self.extension_headers {
+      let mut ext_buf = BytesMut::new();
       for header in ext_headers {
-        payload_buf.extend_from_slice(&header.serialize()?);
+        ext_buf.extend_from_slice(&header.serialize()?);
       }
+      buf.put_vi(ext_buf.len())?;
+      buf.extend_from_slice(&ext_buf);
     }
-
-    buf.put_vi(payload_buf.len())?;
-    buf.extend_from_slice(&payload_buf);
     buf.extend_from_slice(&self.payload);
     Ok(buf.freeze())
   }
@@ -206,4 +205,20 @@ mod tests {
     assert_eq!(deserialized, datagram_object);
     assert!(!buf.has_remaining());
   }
+
+  #[test]
+  fn test_roundtrip_without_extensions() {
+    let datagram_object = DatagramObject::new(
+      144,
+      9,
+      10,
+      255,
+      Bytes::from_static(b"01239gjawkk92837aldmi"),
+    );
+
+    let mut buf = datagram_object.serialize().unwrap();
+    let deserialized = DatagramObject::deserialize(&mut buf).unwrap();
+    assert_eq!(deserialized, datagram_object);
+    assert!(!buf.has_remaining());
+  }
 }
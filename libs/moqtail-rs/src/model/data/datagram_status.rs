@@ -80,16 +80,15 @@ impl DatagramStatus {
     buf.put_vi(self.object_id)?;
     buf.put_u8(self.publisher_priority);
 
-    let mut payload = BytesMut::new();
-
+    // the extension headers length is only present for the extension types
     if let Some(ext_headers) = &self.extension_headers {
+      let mut ext_buf = BytesMut::new();
       for header in ext_headers {
-        payload.extend_from_slice(&header.serialize()?);
+        ext_buf.extend_from_slice(&header.serialize()?);
       }
+      buf.put_vi(ext_buf.len())?;
+      buf.extend_from_slice(&ext_buf);
     }
-
-    buf.put_vi(payload.len())?;
-    buf.extend_from_slice(&payload);
     buf.put_vi(self.object_status)?;
     Ok(buf.freeze())
   }
@@ -204,4 +203,14 @@ mod tests {
     assert_eq!(deserialized, datagram_object);
     assert!(!buf.has_remaining());
   }
+
+  #[test]
+  fn test_roundtrip_without_extensions() {
+    let datagram_object = DatagramStatus::new(144, 9, 10, 255, ObjectStatus::EndOfGroup);
+
+    let mut buf = datagram_object.serialize().unwrap();
+    let deserialized = DatagramStatus::deserialize(&mut buf).unwrap();
+    assert_eq!(deserialized, datagram_object);
+    assert!(!buf.has_remaining());
+  }
 }
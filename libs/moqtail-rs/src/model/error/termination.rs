@@ -30,7 +30,7 @@ pub enum TerminationCode {
   GoawayTimeout = 0x10,
   ControlMessageTimeout = 0x11,
   DataStreamTimeout = 0x12,
-  AuthTokenNotFound = 0x13,
+  AuthTokenCacheOverflow = 0x13,
   DuplicateAuthTokenAlias = 0x14,
   VersionNegotiationFailed = 0x15,
 }
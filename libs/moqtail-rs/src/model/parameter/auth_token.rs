@@ -0,0 +1,352 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use bytes::Bytes;
+
+use super::constant::TokenAliasType;
+use super::version_parameter::VersionParameter;
+use crate::model::error::TerminationCode;
+
+/// A token presented in the AUTHORIZATION TOKEN parameter of a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthToken {
+  pub token_type: u64,
+  pub value: Bytes,
+}
+
+impl AuthToken {
+  /// The bytes a registered token takes up in the peer's cache
+  pub fn size(&self) -> u64 {
+    self.value.len() as u64
+  }
+}
+
+/// The tokens registered under an alias during one session, bounded by the
+/// MAX_AUTH_TOKEN_CACHE_SIZE the receiver advertised in its SETUP message.
+///
+/// The receiver resolves the AUTHORIZATION TOKEN parameters of incoming
+/// requests with [`AuthTokenCache::apply`]. The sender keeps a mirror of the
+/// peer's cache and picks a parameter with [`AuthTokenCache::parameter_for`],
+/// so large tokens are only sent once, settling it with
+/// [`AuthTokenCache::settle`] when the request goes out.
+#[derive(Debug, Clone, Default)]
+pub struct AuthTokenCache {
+  max_size: u64,
+  size: u64,
+  tokens: BTreeMap<u64, AuthToken>,
+}
+
+impl AuthTokenCache {
+  pub fn new(max_size: u64) -> Self {
+    Self {
+      max_size,
+      size: 0,
+      tokens: BTreeMap::new(),
+    }
+  }
+
+  pub fn max_size(&self) -> u64 {
+    self.max_size
+  }
+
+  /// The total size of the registered tokens
+  pub fn size(&self) -> u64 {
+    self.size
+  }
+
+  pub fn get(&self, alias: u64) -> Option<&AuthToken> {
+    self.tokens.get(&alias)
+  }
+
+  pub fn register(&mut self, alias: u64, token: AuthToken) -> Result<(), TerminationCode> {
+    if self.tokens.contains_key(&alias) {
+      return Err(TerminationCode::DuplicateAuthTokenAlias);
+    }
+    if self.size + token.size() > self.max_size {
+      return Err(TerminationCode::AuthTokenCacheOverflow);
+    }
+    self.size += token.size();
+    self.tokens.insert(alias, token);
+    Ok(())
+  }
+
+  /// Removes a registered token, an unknown alias is a protocol violation
+  pub fn delete(&mut self, alias: u64) -> Result<AuthToken, TerminationCode> {
+    let token = self
+      .tokens
+      .remove(&alias)
+      .ok_or(TerminationCode::ProtocolViolation)?;
+    self.size -= token.size();
+    Ok(token)
+  }
+
+  /// Applies an AUTHORIZATION TOKEN parameter of an incoming request and
+  /// returns the token it presents. Registered tokens are used for the
+  /// request that registers them, deletions present no token.
+  pub fn apply(
+    &mut self,
+    parameter: &VersionParameter,
+  ) -> Result<Option<AuthToken>, TerminationCode> {
+    let VersionParameter::AuthorizationToken {
+      alias_type,
+      token_alias,
+      token_type,
+      token_value,
+    } = parameter
+    else {
+      return Ok(None);
+    };
+    let alias_type = TokenAliasType::try_from(*alias_type)
+      .map_err(|_| TerminationCode::KeyValueFormattingError)?;
+    match (alias_type, token_alias, token_type, token_value) {
+      (TokenAliasType::Register, Some(alias), Some(token_type), Some(value)) => {
+        let token = AuthToken {
+          token_type: *token_type,
+          value: value.clone(),
+        };
+        self.register(*alias, token.clone())?;
+        Ok(Some(token))
+      }
+      (TokenAliasType::UseValue, _, Some(token_type), Some(value)) => Ok(Some(AuthToken {
+        token_type: *token_type,
+        value: value.clone(),
+      })),
+      (TokenAliasType::UseAlias, Some(alias), _, _) => self
+        .get(*alias)
+        .cloned()
+        .map(Some)
+        .ok_or(TerminationCode::ProtocolViolation),
+      (TokenAliasType::Delete, Some(alias), _, _) => {
+        self.delete(*alias)?;
+        Ok(None)
+      }
+      _ => Err(TerminationCode::KeyValueFormattingError),
+    }
+  }
+
+  /// The parameter that presents `token` to the peer whose cache this
+  /// mirrors: an alias once the token is registered, a registration while
+  /// it fits in the cache, and the value itself otherwise. The mirror only
+  /// changes once the request is sent, see [`AuthTokenCache::settle`].
+  pub fn parameter_for(&self, token: &AuthToken) -> VersionParameter {
+    if let Some((alias, _)) = self.tokens.iter().find(|(_, cached)| *cached == token) {
+      return VersionParameter::new_auth_token_use_alias(*alias);
+    }
+    let alias = self.tokens.keys().next_back().map_or(0, |alias| alias + 1);
+    if self.size + token.size() <= self.max_size {
+      VersionParameter::new_auth_token_register(alias, token.token_type, token.value.clone())
+    } else {
+      VersionParameter::new_auth_token_use_value(token.token_type, token.value.clone())
+    }
+  }
+
+  /// Settles an AUTHORIZATION TOKEN parameter of a request as it is sent, so
+  /// the mirror registers and deletes what the peer will. A registration
+  /// built before an earlier request registered the same token, or took its
+  /// alias, is rewritten like [`AuthTokenCache::parameter_for`] would.
+  pub fn settle(&mut self, parameter: &VersionParameter) -> VersionParameter {
+    let VersionParameter::AuthorizationToken {
+      alias_type,
+      token_alias: Some(alias),
+      token_type,
+      token_value,
+    } = parameter
+    else {
+      return parameter.clone();
+    };
+    match (
+      TokenAliasType::try_from(*alias_type),
+      token_type,
+      token_value,
+    ) {
+      (Ok(TokenAliasType::Register), Some(token_type), Some(value)) => {
+        let token = AuthToken {
+          token_type: *token_type,
+          value: value.clone(),
+        };
+        if !self.tokens.values().any(|cached| *cached == token)
+          && self.register(*alias, token.clone()).is_ok()
+        {
+          return parameter.clone();
+        }
+        let settled = self.parameter_for(&token);
+        if let VersionParameter::AuthorizationToken {
+          alias_type,
+          token_alias: Some(alias),
+          ..
+        } = &settled
+          && *alias_type == TokenAliasType::Register as u64
+        {
+          // parameter_for only offers a registration that fits
+          let _ = self.register(*alias, token);
+        }
+        settled
+      }
+      (Ok(TokenAliasType::Delete), _, _) => {
+        let _ = self.delete(*alias);
+        parameter.clone()
+      }
+      _ => parameter.clone(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn token(value: &'static [u8]) -> AuthToken {
+    AuthToken {
+      token_type: 1,
+      value: Bytes::from_static(value),
+    }
+  }
+
+  #[test]
+  fn test_register_use_delete() {
+    let mut cache = AuthTokenCache::new(16);
+    let registered = cache
+      .apply(&VersionParameter::new_auth_token_register(
+        3,
+        1,
+        Bytes::from_static(b"secret"),
+      ))
+      .unwrap();
+    assert_eq!(registered, Some(token(b"secret")));
+    assert_eq!(cache.size(), 6);
+
+    let used = cache
+      .apply(&VersionParameter::new_auth_token_use_alias(3))
+      .unwrap();
+    assert_eq!(used, Some(token(b"secret")));
+
+    let deleted = cache
+      .apply(&VersionParameter::new_auth_token_delete(3))
+      .unwrap();
+    assert_eq!(deleted, None);
+    assert_eq!(cache.size(), 0);
+    assert_eq!(
+      cache.apply(&VersionParameter::new_auth_token_use_alias(3)),
+      Err(TerminationCode::ProtocolViolation)
+    );
+  }
+
+  #[test]
+  fn test_misuse_terminates() {
+    let mut cache = AuthTokenCache::new(16);
+    cache.register(1, token(b"a")).unwrap();
+    assert_eq!(
+      cache.register(1, token(b"b")),
+      Err(TerminationCode::DuplicateAuthTokenAlias)
+    );
+    assert_eq!(cache.delete(2), Err(TerminationCode::ProtocolViolation));
+    assert_eq!(
+      cache.register(2, token(b"more than sixteen bytes")),
+      Err(TerminationCode::AuthTokenCacheOverflow)
+    );
+    assert_eq!(cache.size(), 1);
+  }
+
+  #[test]
+  fn test_use_value_needs_no_cache() {
+    let mut cache = AuthTokenCache::new(0);
+    let used = cache
+      .apply(&VersionParameter::new_auth_token_use_value(
+        1,
+        Bytes::from_static(b"secret"),
+      ))
+      .unwrap();
+    assert_eq!(used, Some(token(b"secret")));
+    assert_eq!(
+      cache.apply(&VersionParameter::new_auth_token_register(
+        0,
+        1,
+        Bytes::from_static(b"secret")
+      )),
+      Err(TerminationCode::AuthTokenCacheOverflow)
+    );
+  }
+
+  #[test]
+  fn test_overflow_closes_with_cache_overflow() {
+    let mut cache = AuthTokenCache::new(8);
+    cache
+      .apply(&VersionParameter::new_auth_token_register(
+        0,
+        1,
+        Bytes::from_static(b"secret"),
+      ))
+      .unwrap();
+    let code = cache
+      .apply(&VersionParameter::new_auth_token_register(
+        1,
+        1,
+        Bytes::from_static(b"other"),
+      ))
+      .unwrap_err();
+    assert_eq!(code, TerminationCode::AuthTokenCacheOverflow);
+    // AUTH_TOKEN_CACHE_OVERFLOW
+    assert_eq!(code.to_u32(), 0x13);
+    assert_eq!(cache.size(), 6);
+  }
+
+  #[test]
+  fn test_parameter_for() {
+    let mut mirror = AuthTokenCache::new(8);
+    let register = VersionParameter::new_auth_token_register(0, 1, Bytes::from_static(b"secret"));
+    assert_eq!(mirror.parameter_for(&token(b"secret")), register);
+    // nothing is registered until the request is sent
+    assert_eq!(mirror.parameter_for(&token(b"secret")), register);
+    assert_eq!(mirror.size(), 0);
+
+    assert_eq!(mirror.settle(&register), register);
+    assert_eq!(
+      mirror.parameter_for(&token(b"secret")),
+      VersionParameter::new_auth_token_use_alias(0)
+    );
+    // does not fit next to the first one
+    assert_eq!(
+      mirror.parameter_for(&token(b"other")),
+      VersionParameter::new_auth_token_use_value(1, Bytes::from_static(b"other"))
+    );
+  }
+
+  #[test]
+  fn test_settle_registrations_built_before_sending() {
+    let mut mirror = AuthTokenCache::new(16);
+    let first = mirror.parameter_for(&token(b"first"));
+    let again = mirror.parameter_for(&token(b"first"));
+    let second = mirror.parameter_for(&token(b"second"));
+
+    assert_eq!(mirror.settle(&first), first);
+    // the token is registered by now
+    assert_eq!(
+      mirror.settle(&again),
+      VersionParameter::new_auth_token_use_alias(0)
+    );
+    // alias 0 is taken
+    assert_eq!(
+      mirror.settle(&second),
+      VersionParameter::new_auth_token_register(1, 1, Bytes::from_static(b"second"))
+    );
+    assert_eq!(mirror.size(), 11);
+
+    let delete = VersionParameter::new_auth_token_delete(0);
+    assert_eq!(mirror.settle(&delete), delete);
+    assert_eq!(mirror.get(0), None);
+    assert_eq!(mirror.size(), 6);
+  }
+}
@@ -19,6 +19,7 @@ use crate::model::{
 };
 
 use bytes::{Bytes, BytesMut};
+use std::time::Duration;
 
 use crate::model::common::varint::BufVarIntExt;
 
@@ -211,6 +212,159 @@ impl VersionParameter {
   }
 }
 
+impl TryInto<KeyValuePair> for VersionParameter {
+  type Error = ParseError;
+  fn try_into(self) -> Result<KeyValuePair, Self::Error> {
+    KeyValuePair::deserialize(&mut self.serialize()?)
+  }
+}
+
+/// The type of a version specific parameter, None for types this version
+/// does not know
+fn parameter_type(kvp: &KeyValuePair) -> Option<VersionSpecificParameterType> {
+  let (KeyValuePair::VarInt { type_value, .. } | KeyValuePair::Bytes { type_value, .. }) = kvp;
+  VersionSpecificParameterType::try_from(*type_value).ok()
+}
+
+/// Checks the version specific parameters of a received message.
+/// DELIVERY_TIMEOUT and MAX_CACHE_DURATION must parse and may appear at most
+/// once. Authorization tokens are left to the receiver, which answers a
+/// malformed one with a request error, and unknown parameters are kept as
+/// they are.
+pub fn validate_version_parameters(
+  parameters: &[KeyValuePair],
+  context: &'static str,
+) -> Result<(), ParseError> {
+  let mut seen = Vec::new();
+  for kvp in parameters {
+    let Some(parameter_type) = parameter_type(kvp) else {
+      continue;
+    };
+    if parameter_type == VersionSpecificParameterType::AuthorizationToken {
+      continue;
+    }
+    VersionParameter::deserialize(kvp)?;
+    if seen.contains(&parameter_type) {
+      return Err(ParseError::ProtocolViolation {
+        context,
+        details: format!("duplicate {parameter_type:?} parameter"),
+      });
+    }
+    seen.push(parameter_type);
+  }
+  Ok(())
+}
+
+/// Typed access to the version specific parameters of a control message.
+/// Parameters of unknown types are left untouched.
+///
+/// # Example
+/// ```
+/// # use std::time::Duration;
+/// # use moqtail::model::common::tuple::Tuple;
+/// # use moqtail::model::control::constant::GroupOrder;
+/// # use moqtail::model::control::subscribe::Subscribe;
+/// # use moqtail::model::parameter::version_parameter::VersionParameters;
+/// # fn main() -> Result<(), moqtail::model::error::ParseError> {
+/// let subscribe = Subscribe::new_latest_object(
+///   0, 0, Tuple::from_utf8_path("live/room"), "video".to_string(), 1,
+///   GroupOrder::Ascending, true, vec![],
+/// )
+/// .with_delivery_timeout(Duration::from_millis(500))?;
+/// assert_eq!(subscribe.delivery_timeout(), Some(Duration::from_millis(500)));
+/// # Ok(())
+/// # }
+/// ```
+pub trait VersionParameters {
+  fn version_parameters(&self) -> &[KeyValuePair];
+
+  fn version_parameters_mut(&mut self) -> &mut Vec<KeyValuePair>;
+
+  fn delivery_timeout(&self) -> Option<Duration> {
+    self
+      .version_parameters()
+      .iter()
+      .find_map(|kvp| match VersionParameter::deserialize(kvp) {
+        Ok(VersionParameter::DeliveryTimeout { object_timeout }) => {
+          Some(Duration::from_millis(object_timeout))
+        }
+        _ => None,
+      })
+  }
+
+  fn max_cache_duration(&self) -> Option<Duration> {
+    self
+      .version_parameters()
+      .iter()
+      .find_map(|kvp| match VersionParameter::deserialize(kvp) {
+        Ok(VersionParameter::MaxCacheDuration { duration }) => {
+          Some(Duration::from_millis(duration))
+        }
+        _ => None,
+      })
+  }
+
+  /// The AUTHORIZATION TOKEN parameters, in order
+  fn auth_tokens(&self) -> Vec<VersionParameter> {
+    self
+      .version_parameters()
+      .iter()
+      .filter(|kvp| parameter_type(kvp) == Some(VersionSpecificParameterType::AuthorizationToken))
+      .filter_map(|kvp| VersionParameter::deserialize(kvp).ok())
+      .collect()
+  }
+
+  /// Adds `parameter`, replacing a DELIVERY_TIMEOUT or MAX_CACHE_DURATION
+  /// that is already set. Authorization tokens are appended.
+  fn set_version_parameter(&mut self, parameter: VersionParameter) -> Result<(), ParseError> {
+    let kvp: KeyValuePair = parameter.try_into()?;
+    let new_type = parameter_type(&kvp);
+    let parameters = self.version_parameters_mut();
+    if new_type != Some(VersionSpecificParameterType::AuthorizationToken) {
+      parameters.retain(|existing| parameter_type(existing) != new_type);
+    }
+    parameters.push(kvp);
+    Ok(())
+  }
+
+  fn with_delivery_timeout(mut self, timeout: Duration) -> Result<Self, ParseError>
+  where
+    Self: Sized,
+  {
+    let millis = u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX);
+    self.set_version_parameter(VersionParameter::new_delivery_timeout(millis))?;
+    Ok(self)
+  }
+
+  fn with_max_cache_duration(mut self, duration: Duration) -> Result<Self, ParseError>
+  where
+    Self: Sized,
+  {
+    let millis = u64::try_from(duration.as_millis()).unwrap_or(u64::MAX);
+    self.set_version_parameter(VersionParameter::new_max_cache_duration(millis))?;
+    Ok(self)
+  }
+
+  fn with_auth_token(mut self, token: VersionParameter) -> Result<Self, ParseError>
+  where
+    Self: Sized,
+  {
+    self.set_version_parameter(token)?;
+    Ok(self)
+  }
+
+  /// The parameters a relay sends on with a forwarded message: all but the
+  /// AUTHORIZATION TOKEN, whose aliases belong to the session they came on
+  fn forwarded_parameters(&self) -> Vec<KeyValuePair> {
+    self
+      .version_parameters()
+      .iter()
+      .filter(|kvp| parameter_type(kvp) != Some(VersionSpecificParameterType::AuthorizationToken))
+      .cloned()
+      .collect()
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::VersionParameter;
@@ -0,0 +1,243 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use super::control::constant::DRAFT_11;
+use super::control::control_message::ControlMessage;
+use super::data::constant::FetchHeaderType;
+use super::data::fetch_header::FetchHeader;
+use super::data::subgroup_header::SubgroupHeader;
+use super::error::ParseError;
+
+/// Prefix of the version numbers of MOQT drafts, e.g. `0xFF00000B`
+const DRAFT_VERSION_PREFIX: u32 = 0xFF00_0000;
+
+/// The wire format of one MOQT version. A session picks its codec during
+/// the setup handshake and uses it for every control message and data
+/// stream header after that.
+pub trait VersionCodec: Debug + Send + Sync {
+  /// The version number sent in CLIENT_SETUP and SERVER_SETUP
+  fn version(&self) -> u32;
+
+  fn serialize_control_message(&self, message: &ControlMessage) -> Result<Bytes, ParseError>;
+
+  fn deserialize_control_message(&self, bytes: &mut Bytes) -> Result<ControlMessage, ParseError>;
+
+  fn serialize_subgroup_header(&self, header: &SubgroupHeader) -> Result<Bytes, ParseError>;
+
+  fn deserialize_subgroup_header(&self, bytes: &mut Bytes) -> Result<SubgroupHeader, ParseError>;
+
+  fn serialize_fetch_header(&self, header: &FetchHeader) -> Result<Bytes, ParseError>;
+
+  fn deserialize_fetch_header(&self, bytes: &mut Bytes) -> Result<FetchHeader, ParseError>;
+
+  /// Whether a unidirectional stream whose first byte is `stream_type`
+  /// carries a fetch response rather than a subgroup
+  fn is_fetch_stream(&self, stream_type: u8) -> bool;
+}
+
+/// draft-ietf-moq-transport-11
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Draft11;
+
+impl VersionCodec for Draft11 {
+  fn version(&self) -> u32 {
+    DRAFT_11
+  }
+
+  fn serialize_control_message(&self, message: &ControlMessage) -> Result<Bytes, ParseError> {
+    message.serialize()
+  }
+
+  fn deserialize_control_message(&self, bytes: &mut Bytes) -> Result<ControlMessage, ParseError> {
+    ControlMessage::deserialize(bytes)
+  }
+
+  fn serialize_subgroup_header(&self, header: &SubgroupHeader) -> Result<Bytes, ParseError> {
+    header.serialize()
+  }
+
+  fn deserialize_subgroup_header(&self, bytes: &mut Bytes) -> Result<SubgroupHeader, ParseError> {
+    SubgroupHeader::deserialize(bytes)
+  }
+
+  fn serialize_fetch_header(&self, header: &FetchHeader) -> Result<Bytes, ParseError> {
+    header.serialize()
+  }
+
+  fn deserialize_fetch_header(&self, bytes: &mut Bytes) -> Result<FetchHeader, ParseError> {
+    FetchHeader::deserialize(bytes)
+  }
+
+  fn is_fetch_stream(&self, stream_type: u8) -> bool {
+    stream_type == FetchHeaderType::Type0x05 as u8
+  }
+}
+
+/// The versions an endpoint speaks, keyed by version number.
+///
+/// # Example
+/// ```
+/// # use std::sync::Arc;
+/// # use moqtail::model::control::constant::DRAFT_11;
+/// # use moqtail::model::version::{Draft11, VersionCodec, VersionRegistry};
+/// let mut versions = VersionRegistry::empty();
+/// versions.register(Arc::new(Draft11)); // and a codec for each other version
+/// assert_eq!(versions.supported_versions(), vec![DRAFT_11]);
+///
+/// // the versions offered in CLIENT_SETUP, draft-12 is not registered
+/// let codec = versions.negotiate(&[0xff00000c, DRAFT_11]).unwrap();
+/// assert_eq!(codec.version(), DRAFT_11);
+/// ```
+#[derive(Debug, Clone)]
+pub struct VersionRegistry {
+  codecs: BTreeMap<u32, Arc<dyn VersionCodec>>,
+}
+
+impl Default for VersionRegistry {
+  fn default() -> Self {
+    let mut registry = Self::empty();
+    registry.register(Arc::new(Draft11));
+    registry
+  }
+}
+
+impl VersionRegistry {
+  pub fn empty() -> Self {
+    Self {
+      codecs: BTreeMap::new(),
+    }
+  }
+
+  /// Adds a codec, replacing the one registered for the same version
+  pub fn register(&mut self, codec: Arc<dyn VersionCodec>) {
+    self.codecs.insert(codec.version(), codec);
+  }
+
+  pub fn get(&self, version: u32) -> Option<Arc<dyn VersionCodec>> {
+    self.codecs.get(&version).cloned()
+  }
+
+  /// The registered versions, most preferred first
+  pub fn supported_versions(&self) -> Vec<u32> {
+    let mut versions: Vec<u32> = self.codecs.keys().copied().collect();
+    versions.sort_by_key(|version| std::cmp::Reverse(preference(*version)));
+    versions
+  }
+
+  /// Picks the most preferred version that both ends support
+  pub fn negotiate(&self, offered_versions: &[u32]) -> Option<Arc<dyn VersionCodec>> {
+    offered_versions
+      .iter()
+      .filter_map(|version| self.get(*version))
+      .max_by_key(|codec| preference(codec.version()))
+  }
+}
+
+/// Ranks published versions above drafts, and newer drafts above older ones
+fn preference(version: u32) -> (bool, u32) {
+  let is_draft = version & DRAFT_VERSION_PREFIX == DRAFT_VERSION_PREFIX;
+  (!is_draft, version)
+}
+
+/// Speaks the draft-11 wire format under another version number
+#[cfg(test)]
+#[derive(Debug)]
+pub(crate) struct Renumbered(pub u32);
+
+#[cfg(test)]
+impl VersionCodec for Renumbered {
+  fn version(&self) -> u32 {
+    self.0
+  }
+
+  fn serialize_control_message(&self, message: &ControlMessage) -> Result<Bytes, ParseError> {
+    Draft11.serialize_control_message(message)
+  }
+
+  fn deserialize_control_message(&self, bytes: &mut Bytes) -> Result<ControlMessage, ParseError> {
+    Draft11.deserialize_control_message(bytes)
+  }
+
+  fn serialize_subgroup_header(&self, header: &SubgroupHeader) -> Result<Bytes, ParseError> {
+    Draft11.serialize_subgroup_header(header)
+  }
+
+  fn deserialize_subgroup_header(&self, bytes: &mut Bytes) -> Result<SubgroupHeader, ParseError> {
+    Draft11.deserialize_subgroup_header(bytes)
+  }
+
+  fn serialize_fetch_header(&self, header: &FetchHeader) -> Result<Bytes, ParseError> {
+    Draft11.serialize_fetch_header(header)
+  }
+
+  fn deserialize_fetch_header(&self, bytes: &mut Bytes) -> Result<FetchHeader, ParseError> {
+    Draft11.deserialize_fetch_header(bytes)
+  }
+
+  fn is_fetch_stream(&self, stream_type: u8) -> bool {
+    Draft11.is_fetch_stream(stream_type)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const DRAFT_12: u32 = 0xFF00000C;
+
+  #[test]
+  fn test_default_registry() {
+    let registry = VersionRegistry::default();
+    assert_eq!(registry.supported_versions(), vec![DRAFT_11]);
+    assert!(registry.get(DRAFT_12).is_none());
+  }
+
+  #[test]
+  fn test_negotiate_highest_mutual_version() {
+    let mut registry = VersionRegistry::default();
+    registry.register(Arc::new(Renumbered(DRAFT_12)));
+
+    let codec = registry.negotiate(&[DRAFT_11, DRAFT_12, 12345]).unwrap();
+    assert_eq!(codec.version(), DRAFT_12);
+
+    let codec = registry.negotiate(&[12345, DRAFT_11]).unwrap();
+    assert_eq!(codec.version(), DRAFT_11);
+
+    assert!(registry.negotiate(&[12345]).is_none());
+    assert!(registry.negotiate(&[]).is_none());
+  }
+
+  #[test]
+  fn test_published_version_preferred_over_drafts() {
+    let mut registry = VersionRegistry::default();
+    registry.register(Arc::new(Renumbered(DRAFT_12)));
+    registry.register(Arc::new(Renumbered(1)));
+
+    assert_eq!(registry.supported_versions(), vec![1, DRAFT_12, DRAFT_11]);
+    let codec = registry.negotiate(&[DRAFT_12, 1]).unwrap();
+    assert_eq!(codec.version(), 1);
+  }
+
+  #[test]
+  fn test_draft11_fetch_stream_type() {
+    assert!(Draft11.is_fetch_stream(FetchHeaderType::Type0x05 as u8));
+    assert!(!Draft11.is_fetch_stream(0x08));
+  }
+}
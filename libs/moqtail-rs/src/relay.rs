@@ -11,3 +11,2083 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+
+pub mod auth;
+mod builder;
+mod client;
+mod client_manager;
+pub mod config;
+mod errors;
+pub mod hooks;
+mod message_handlers;
+mod namespace;
+mod object_logger;
+mod priority;
+mod request_credit;
+mod session;
+mod session_context;
+mod stream_id;
+mod subscription;
+mod track;
+pub mod track_cache;
+mod utils;
+
+pub use builder::RelayBuilder;
+
+use crate::model::common::tuple::Tuple;
+use crate::model::control::constant::AnnounceErrorCode;
+use crate::model::control::control_message::ControlMessage;
+use crate::model::control::goaway::GoAway;
+use crate::model::error::TerminationCode;
+use crate::transport::data_stream_handler::{FetchRequest, SubscribeRequest};
+use crate::transport::moq_transport::MoqTransport;
+use anyhow::Result;
+use client_manager::ClientManager;
+use config::RelayConfig;
+use hooks::RelayHooks;
+use session::Session;
+use session_context::{RelayRequestKey, TrackStatusForward};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{error, info, warn};
+use track::Track;
+use track_cache::FillEvent;
+use wtransport::endpoint::endpoint_side;
+use wtransport::{Endpoint, quinn};
+
+/// The relay forwarding engine. Build one with [`RelayBuilder`].
+#[derive(Clone)]
+pub struct Relay {
+  pub(crate) client_manager: Arc<RwLock<ClientManager>>,
+  pub(crate) tracks: Arc<RwLock<BTreeMap<u64, Track>>>, // the tracks the relay is subscribed to, key is the track alias
+  // the requests the relay sent upstream, keyed by the publisher and the relay's request id
+  pub(crate) relay_fetch_requests: Arc<RwLock<BTreeMap<RelayRequestKey, FetchRequest>>>,
+  pub(crate) relay_subscribe_requests: Arc<RwLock<BTreeMap<RelayRequestKey, SubscribeRequest>>>,
+  pub(crate) relay_track_status_requests:
+    Arc<RwLock<BTreeMap<RelayRequestKey, TrackStatusForward>>>,
+  pub(crate) relay_fetch_fills: Arc<RwLock<BTreeMap<RelayRequestKey, UnboundedSender<FillEvent>>>>,
+  pub(crate) config: Arc<RelayConfig>,
+  pub(crate) hooks: RelayHooks,
+  pub(crate) drain: Arc<RwLock<Option<GoAway>>>, // set once the relay starts draining
+}
+
+impl Relay {
+  pub(crate) fn new(config: Arc<RelayConfig>, hooks: RelayHooks) -> Self {
+    Relay {
+      client_manager: Arc::new(RwLock::new(ClientManager::new(
+        hooks.namespace_router.clone(),
+      ))),
+      tracks: Arc::new(RwLock::new(BTreeMap::new())),
+      relay_fetch_requests: Arc::new(RwLock::new(BTreeMap::new())),
+      relay_subscribe_requests: Arc::new(RwLock::new(BTreeMap::new())),
+      relay_track_status_requests: Arc::new(RwLock::new(BTreeMap::new())),
+      relay_fetch_fills: Arc::new(RwLock::new(BTreeMap::new())),
+      config,
+      hooks,
+      drain: Arc::new(RwLock::new(None)),
+    }
+  }
+
+  pub fn config(&self) -> &RelayConfig {
+    &self.config
+  }
+
+  /// Revokes a namespace from every publisher that announced it. Each
+  /// publisher receives ANNOUNCE_CANCEL and the tracks under the namespace
+  /// end with SUBSCRIBE_DONE. Returns the number of publishers affected.
+  pub async fn revoke_namespace(
+    &self,
+    track_namespace: &Tuple,
+    error_code: AnnounceErrorCode,
+    reason: &str,
+  ) -> usize {
+    let clients: Vec<_> = {
+      let client_manager = self.client_manager.read().await;
+      let clients = client_manager.clients.read().await;
+      clients.values().cloned().collect()
+    };
+
+    let mut revoked = 0;
+    for client in clients {
+      if namespace::revoke_namespace(
+        &self.client_manager,
+        &self.tracks,
+        &self.relay_subscribe_requests,
+        &client,
+        track_namespace,
+        error_code,
+        reason,
+      )
+      .await
+      {
+        revoked += 1;
+      }
+    }
+    revoked
+  }
+
+  /// Drains the relay before a shutdown or deploy. Every session is sent
+  /// GOAWAY with `new_session_uri`, new subscriptions and fetches are
+  /// refused, and the sessions still open after `timeout` are closed with
+  /// GOAWAY_TIMEOUT.
+  /// Returns the number of sessions sent GOAWAY.
+  pub async fn drain(&self, new_session_uri: Option<String>, timeout: Duration) -> usize {
+    let goaway = GoAway::new(new_session_uri);
+    {
+      let mut drain = self.drain.write().await;
+      if drain.is_some() {
+        warn!("relay is already draining");
+        return 0;
+      }
+      *drain = Some(goaway.clone());
+    }
+    info!(
+      "draining relay, new session uri: {:?} timeout: {:?}",
+      goaway.new_session_uri, timeout
+    );
+
+    let clients: Vec<_> = {
+      let client_manager = self.client_manager.read().await;
+      let clients = client_manager.clients.read().await;
+      clients.values().cloned().collect()
+    };
+    for client in clients.iter() {
+      client
+        .queue_message(ControlMessage::Goaway(Box::new(goaway.clone())))
+        .await;
+    }
+
+    let client_manager = self.client_manager.clone();
+    tokio::spawn(async move {
+      tokio::time::sleep(timeout).await;
+      let clients: Vec<_> = {
+        let client_manager = client_manager.read().await;
+        let clients = client_manager.clients.read().await;
+        clients.values().cloned().collect()
+      };
+      for client in clients {
+        info!(
+          "closing session {} after the drain timeout",
+          client.connection_id
+        );
+        client
+          .connection
+          .close(TerminationCode::GoawayTimeout.to_u32(), b"GOAWAY timeout");
+      }
+    });
+
+    clients.len()
+  }
+
+  /// Binds a WebTransport endpoint from the configuration and serves it,
+  /// next to a native QUIC endpoint when `quic_port` is set.
+  pub async fn start(&mut self) -> Result<()> {
+    let server_config = self.config.build_server_config().await?;
+    let endpoint = Endpoint::server(server_config)?;
+
+    info!("MOQtail Relay is running!");
+    info!("URL: https://{}:{}", self.config.host, self.config.port);
+
+    let Some((bind_address, quic_config)) = self.config.build_quic_server_config().await? else {
+      return self.serve(endpoint).await;
+    };
+    let quic_endpoint = quinn::Endpoint::server(quic_config, bind_address)?;
+    info!(
+      "URL: {}://{}:{}",
+      crate::transport::quic::MOQT_SCHEME,
+      self.config.host,
+      bind_address.port()
+    );
+
+    tokio::try_join!(self.serve(endpoint), self.serve_quic(quic_endpoint))?;
+    Ok(())
+  }
+
+  /// Accepts sessions on an endpoint created by the caller.
+  pub async fn serve(&self, endpoint: Endpoint<endpoint_side::Server>) -> Result<()> {
+    for id in 0.. {
+      let incoming_session = endpoint.accept().await;
+      let server = self.clone();
+      tokio::spawn(async move {
+        match Session::new(incoming_session, server).await {
+          Ok(_) => {
+            info!("new session: {}", id);
+          }
+          Err(e) => {
+            error!("Error occurred in session {}: {:?}", id, e);
+          }
+        }
+      });
+    }
+    Ok(())
+  }
+
+  /// Accepts native QUIC sessions on an endpoint created by the caller.
+  pub async fn serve_quic(&self, endpoint: quinn::Endpoint) -> Result<()> {
+    while let Some(incoming) = endpoint.accept().await {
+      let server = self.clone();
+      tokio::spawn(async move {
+        let connection = match incoming.await {
+          Ok(connection) => connection,
+          Err(e) => {
+            error!("Failed to accept QUIC connection: {:?}", e);
+            return;
+          }
+        };
+        info!("new QUIC session from {}", connection.remote_address());
+        if let Err(e) = server.serve_connection(Arc::new(connection)) {
+          error!("Error occurred in QUIC session: {:?}", e);
+        }
+      });
+    }
+    Ok(())
+  }
+
+  /// Runs a session over a connection accepted by the caller, e.g. from a
+  /// [`LoopbackListener`](crate::transport::loopback::LoopbackListener).
+  pub fn serve_connection(&self, connection: Arc<dyn MoqTransport>) -> Result<()> {
+    Session::start(connection, self.clone())?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::client::{
+    Announcement, ClientError, FetchStream, Session as ClientSession, Subscription,
+  };
+  use crate::model::common::location::Location;
+  use crate::model::common::pair::KeyValuePair;
+  use crate::model::common::reason_phrase::ReasonPhrase;
+  use crate::model::control::constant::{
+    DRAFT_11, FetchErrorCode, FilterType, GroupOrder, SubscribeAnnouncesErrorCode,
+    SubscribeDoneStatusCode, SubscribeErrorCode, TrackStatusCode,
+  };
+  use crate::model::control::fetch::{Fetch, StandAloneFetchProps};
+  use crate::model::control::fetch_cancel::FetchCancel;
+  use crate::model::control::fetch_error::FetchError;
+  use crate::model::control::fetch_ok::FetchOk;
+  use crate::model::control::subscribe::Subscribe;
+  use crate::model::control::subscribe_done::SubscribeDone;
+  use crate::model::control::subscribe_ok::SubscribeOk;
+  use crate::model::data::object::Object;
+  use crate::model::data::subgroup_header::SubgroupHeader;
+  use crate::model::data::subgroup_object::SubgroupObject;
+  use crate::model::parameter::auth_token::AuthToken;
+  use crate::model::parameter::version_parameter::{VersionParameter, VersionParameters};
+  use crate::model::version::{Renumbered, VersionRegistry};
+  use crate::relay::auth::StaticAllowlist;
+  use crate::relay::config::AnnounceConflictPolicy;
+  use crate::transport::loopback::{self, LoopbackConfig};
+  use crate::transport::moq_transport::{MoqConnector, TransportError};
+  use async_trait::async_trait;
+  use bytes::Bytes;
+
+  /// Serves loopback connections from the returned connector
+  fn spawn_relay(relay: Relay) -> Arc<loopback::LoopbackConnector> {
+    let (connector, mut listener) = loopback::listener(LoopbackConfig {
+      delay: Duration::from_millis(5),
+      ..Default::default()
+    });
+    tokio::spawn(async move {
+      while let Some(connection) = listener.accept().await {
+        relay.serve_connection(Arc::new(connection)).unwrap();
+      }
+    });
+    Arc::new(connector)
+  }
+
+  /// Publishes one object on `live/room` and checks it reaches the subscriber
+  async fn publish_and_receive(publisher: ClientSession, subscriber: ClientSession) {
+    let track_namespace = Tuple::from_utf8_path("live/room");
+    let _announcement = publisher
+      .announce(track_namespace.clone(), &[])
+      .await
+      .unwrap();
+
+    let publish = tokio::spawn(async move {
+      let subscribe = match publisher.next_message().await {
+        Some(ControlMessage::Subscribe(subscribe)) => subscribe,
+        other => panic!("Expected SUBSCRIBE, got {other:?}"),
+      };
+      publisher
+        .send(ControlMessage::SubscribeOk(Box::new(
+          SubscribeOk::new_ascending_no_content(subscribe.request_id, 0, None),
+        )))
+        .unwrap();
+
+      let header = SubgroupHeader::new_fixed_zero_id(subscribe.track_alias, 0, 1, false);
+      let mut stream = publisher.open_subgroup_stream(header).await.unwrap();
+      let object = Object::try_from_subgroup(
+        SubgroupObject {
+          object_id: 0,
+          extension_headers: None,
+          object_status: None,
+          payload: Some(Bytes::from_static(b"frame")),
+        },
+        subscribe.track_alias,
+        0,
+        Some(0),
+        1,
+      )
+      .unwrap();
+      stream.send_object(&object).await.unwrap();
+      stream.finish().await.unwrap();
+      publisher
+    });
+
+    let subscribe = Subscribe::new_latest_object(
+      0,
+      0,
+      track_namespace,
+      "video".to_string(),
+      1,
+      GroupOrder::Ascending,
+      true,
+      vec![],
+    );
+    let mut subscription = subscriber.subscribe(subscribe).await.unwrap();
+    let object = subscription.next_object().await.unwrap();
+
+    assert_eq!(object.payload, Some(Bytes::from_static(b"frame")));
+    assert_eq!(object.track_alias, subscription.track_alias());
+    publish.await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_publish_subscribe_over_loopback() {
+    let connector = spawn_relay(RelayBuilder::new(RelayConfig::default()).build());
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let subscriber = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+
+    publish_and_receive(publisher, subscriber).await;
+  }
+
+  #[tokio::test]
+  async fn test_clients_with_different_versions() {
+    const DRAFT_12: u32 = 0xFF00000C;
+    let relay = RelayBuilder::new(RelayConfig::default())
+      .with_version(Renumbered(DRAFT_12))
+      .build();
+    let connector = spawn_relay(relay);
+
+    // draft-11 only
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let mut versions = VersionRegistry::default();
+    versions.register(Arc::new(Renumbered(DRAFT_12)));
+    let subscriber = ClientSession::connect_with_versions(connector.clone(), "loopback", versions)
+      .await
+      .unwrap();
+    assert_eq!(publisher.selected_version(), DRAFT_11);
+    assert_eq!(subscriber.selected_version(), DRAFT_12);
+
+    publish_and_receive(publisher, subscriber).await;
+
+    // nothing in common
+    let mut versions = VersionRegistry::empty();
+    versions.register(Arc::new(Renumbered(0xFF00000A)));
+    let result = ClientSession::connect_with_versions(connector, "loopback", versions).await;
+    assert!(result.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_requests_need_a_granted_token() {
+    let allowlist = StaticAllowlist::from_json(
+      br#"[{"token": "alice", "publish": [{"namespace": "live"}]},
+           {"token": "bob", "subscribe": [{"namespace": "live", "track": "video"}]}]"#,
+    )
+    .unwrap();
+    let relay = RelayBuilder::new(RelayConfig::default())
+      .with_authorizer(allowlist)
+      .build();
+    let connector = spawn_relay(relay);
+    let token = |value: &'static [u8]| -> KeyValuePair {
+      VersionParameter::new_auth_token_use_value(0, Bytes::from_static(value))
+        .try_into()
+        .unwrap()
+    };
+
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let track_namespace = Tuple::from_utf8_path("live/room");
+    match publisher.announce(track_namespace.clone(), &[]).await {
+      Err(ClientError::AnnounceRejected(error)) => {
+        assert_eq!(error.error_code, AnnounceErrorCode::Unauthorized)
+      }
+      other => panic!("Expected ANNOUNCE_ERROR, got {:?}", other.err()),
+    }
+    let _announcement = publisher
+      .announce(track_namespace.clone(), &[token(b"alice")])
+      .await
+      .unwrap();
+
+    let subscriber = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let subscribe = Subscribe::new_latest_object(
+      0,
+      0,
+      track_namespace,
+      "audio".to_string(),
+      1,
+      GroupOrder::Ascending,
+      true,
+      vec![token(b"bob")],
+    );
+    match subscriber.subscribe(subscribe).await {
+      Err(ClientError::SubscribeRejected(error)) => {
+        assert_eq!(error.error_code, SubscribeErrorCode::Unauthorized)
+      }
+      other => panic!("Expected SUBSCRIBE_ERROR, got {:?}", other.err()),
+    }
+
+    // a refused track status request is answered, not dropped
+    let track_status = tokio::time::timeout(
+      Duration::from_secs(5),
+      subscriber.track_status(
+        Tuple::from_utf8_path("live/room"),
+        "audio".to_string(),
+        vec![token(b"bob")],
+      ),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(track_status.status_code, TrackStatusCode::DoesNotExist);
+  }
+
+  #[tokio::test]
+  async fn test_auth_token_aliases() {
+    let allowlist =
+      StaticAllowlist::from_json(br#"[{"token": "alice", "publish": [{"namespace": "live"}]}]"#)
+        .unwrap();
+    let relay = RelayBuilder::new(RelayConfig::default())
+      .with_authorizer(allowlist)
+      .build();
+    let connector = spawn_relay(relay);
+    let publisher = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let alice = AuthToken {
+      token_type: 0,
+      value: Bytes::from_static(b"alice"),
+    };
+
+    let register = publisher.auth_token(&alice).unwrap();
+    assert_eq!(
+      VersionParameter::deserialize(&register).unwrap(),
+      VersionParameter::new_auth_token_register(0, 0, alice.value.clone())
+    );
+    let _room = publisher
+      .announce(Tuple::from_utf8_path("live/room"), &[register])
+      .await
+      .unwrap();
+
+    let alias = publisher.auth_token(&alice).unwrap();
+    assert_eq!(
+      VersionParameter::deserialize(&alias).unwrap(),
+      VersionParameter::new_auth_token_use_alias(0)
+    );
+    let _hall = publisher
+      .announce(Tuple::from_utf8_path("live/hall"), &[alias])
+      .await
+      .unwrap();
+
+    // an alias that was never registered closes the session
+    let unknown: KeyValuePair = VersionParameter::new_auth_token_use_alias(7)
+      .try_into()
+      .unwrap();
+    let result = tokio::time::timeout(
+      Duration::from_secs(5),
+      publisher.announce(Tuple::from_utf8_path("live/lobby"), &[unknown]),
+    )
+    .await
+    .unwrap();
+    assert!(result.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_unsent_registration_is_not_mirrored() {
+    let allowlist =
+      StaticAllowlist::from_json(br#"[{"token": "alice", "publish": [{"namespace": "live"}]}]"#)
+        .unwrap();
+    let relay = RelayBuilder::new(RelayConfig::default())
+      .with_authorizer(allowlist)
+      .build();
+    let connector = spawn_relay(relay);
+    let publisher = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let alice = AuthToken {
+      token_type: 0,
+      value: Bytes::from_static(b"alice"),
+    };
+
+    // built but never sent
+    drop(publisher.auth_token(&alice).unwrap());
+    let first = publisher.auth_token(&alice).unwrap();
+    let second = publisher.auth_token(&alice).unwrap();
+    assert_eq!(first, second);
+    let _room = publisher
+      .announce(Tuple::from_utf8_path("live/room"), &[first])
+      .await
+      .unwrap();
+    // sent as the alias the first announce registered
+    let _hall = publisher
+      .announce(Tuple::from_utf8_path("live/hall"), &[second])
+      .await
+      .unwrap();
+
+    let alias = publisher.auth_token(&alice).unwrap();
+    assert_eq!(
+      VersionParameter::deserialize(&alias).unwrap(),
+      VersionParameter::new_auth_token_use_alias(0)
+    );
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn test_stalled_group_times_out() {
+    let connector = spawn_relay(RelayBuilder::new(RelayConfig::default()).build());
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let subscriber = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let track_namespace = Tuple::from_utf8_path("live/room");
+    let _announcement = publisher
+      .announce(track_namespace.clone(), &[])
+      .await
+      .unwrap();
+
+    let publish = tokio::spawn(async move {
+      let subscribe = match publisher.next_message().await {
+        Some(ControlMessage::Subscribe(subscribe)) => subscribe,
+        other => panic!("Expected SUBSCRIBE, got {other:?}"),
+      };
+      assert_eq!(
+        subscribe.delivery_timeout(),
+        Some(Duration::from_millis(100))
+      );
+      publisher
+        .send(ControlMessage::SubscribeOk(Box::new(
+          SubscribeOk::new_ascending_no_content(subscribe.request_id, 0, None),
+        )))
+        .unwrap();
+
+      let object = |group_id: u64, object_id: u64| {
+        Object::try_from_subgroup(
+          SubgroupObject {
+            object_id,
+            extension_headers: None,
+            object_status: None,
+            payload: Some(Bytes::from_static(b"frame")),
+          },
+          subscribe.track_alias,
+          group_id,
+          Some(0),
+          1,
+        )
+        .unwrap()
+      };
+      let header = SubgroupHeader::new_fixed_zero_id(subscribe.track_alias, 0, 1, false);
+      let mut stalled = publisher.open_subgroup_stream(header).await.unwrap();
+      stalled.send_object(&object(0, 0)).await.unwrap();
+      stalled.flush().await.unwrap();
+      tokio::time::sleep(Duration::from_millis(50)).await;
+
+      let header = SubgroupHeader::new_fixed_zero_id(subscribe.track_alias, 1, 1, false);
+      let mut next = publisher.open_subgroup_stream(header).await.unwrap();
+      next.send_object(&object(1, 0)).await.unwrap();
+      next.finish().await.unwrap();
+
+      // the relay stops reading group 0 once group 1 started
+      tokio::time::sleep(Duration::from_millis(300)).await;
+      assert!(stalled.send_object(&object(0, 1)).await.is_err());
+      publisher
+    });
+
+    let timeout: KeyValuePair = VersionParameter::new_delivery_timeout(100)
+      .try_into()
+      .unwrap();
+    let subscribe = Subscribe::new_latest_object(
+      0,
+      0,
+      track_namespace,
+      "video".to_string(),
+      1,
+      GroupOrder::Ascending,
+      true,
+      vec![timeout],
+    );
+    let mut subscription = subscriber.subscribe(subscribe).await.unwrap();
+    let first = subscription.next_object().await.unwrap();
+    assert_eq!(first.location.group, 0);
+    let second = subscription.next_object().await.unwrap();
+    assert_eq!(second.location.group, 1);
+    publish.await.unwrap();
+  }
+
+  fn subscribe_with_timeout(millis: Option<u64>) -> Subscribe {
+    let parameters = millis
+      .map(|millis| {
+        VersionParameter::new_delivery_timeout(millis)
+          .try_into()
+          .unwrap()
+      })
+      .into_iter()
+      .collect();
+    Subscribe::new_latest_object(
+      0,
+      0,
+      Tuple::from_utf8_path("live/room"),
+      "video".to_string(),
+      1,
+      GroupOrder::Ascending,
+      true,
+      parameters,
+    )
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn test_delivery_timeout_follows_subscribers() {
+    let connector = spawn_relay(RelayBuilder::new(RelayConfig::default()).build());
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let _room = publisher
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+    let mut subscribers = Vec::new();
+    for _ in 0..3 {
+      subscribers.push(
+        ClientSession::connect_with(connector.clone(), "loopback")
+          .await
+          .unwrap(),
+      );
+    }
+    let expect_update = |message: ControlMessage, request_id: u64, millis: u64| match message {
+      ControlMessage::SubscribeUpdate(m) => {
+        assert_eq!(m.request_id, request_id);
+        assert_eq!(m.delivery_timeout(), Some(Duration::from_millis(millis)));
+      }
+      other => panic!("Expected SUBSCRIBE_UPDATE, got {other:?}"),
+    };
+
+    let subscription = tokio::spawn({
+      let subscriber = subscribers[0].clone();
+      async move {
+        subscriber
+          .subscribe(subscribe_with_timeout(Some(100)))
+          .await
+          .unwrap()
+      }
+    });
+    let upstream = accept_subscribe(&publisher).await;
+    assert_eq!(
+      upstream.delivery_timeout(),
+      Some(Duration::from_millis(100))
+    );
+    let _short = subscription.await.unwrap();
+
+    // the longest timeout is asked for upstream
+    let long = subscribers[1]
+      .subscribe(subscribe_with_timeout(Some(300)))
+      .await
+      .unwrap();
+    expect_update(next_message(&publisher).await, upstream.request_id, 300);
+
+    // SUBSCRIBE_UPDATE cannot drop the timeout, a subscriber without one
+    // re-subscribes upstream
+    let unbounded = tokio::spawn({
+      let subscriber = subscribers[2].clone();
+      async move {
+        subscriber
+          .subscribe(subscribe_with_timeout(None))
+          .await
+          .unwrap()
+      }
+    });
+    match next_message(&publisher).await {
+      ControlMessage::Unsubscribe(m) => assert_eq!(m.request_id, upstream.request_id),
+      other => panic!("Expected UNSUBSCRIBE, got {other:?}"),
+    }
+    let resubscribed = accept_subscribe(&publisher).await;
+    assert_eq!(resubscribed.delivery_timeout(), None);
+    let unbounded = unbounded.await.unwrap();
+
+    // leaving subscribers hand the timeout back to the others
+    unbounded.unsubscribe().await.unwrap();
+    expect_update(next_message(&publisher).await, resubscribed.request_id, 300);
+    long.unsubscribe().await.unwrap();
+    expect_update(next_message(&publisher).await, resubscribed.request_id, 100);
+  }
+
+  /// Waits for the next control message that is not a response
+  async fn next_message(session: &ClientSession) -> ControlMessage {
+    tokio::time::timeout(Duration::from_secs(5), session.next_message())
+      .await
+      .expect("no control message")
+      .expect("session closed")
+  }
+
+  fn announced_namespace(message: ControlMessage) -> Tuple {
+    match message {
+      ControlMessage::Announce(m) => m.track_namespace,
+      other => panic!("Expected ANNOUNCE, got {other:?}"),
+    }
+  }
+
+  fn unannounced_namespace(message: ControlMessage) -> Tuple {
+    match message {
+      ControlMessage::Unannounce(m) => m.track_namespace,
+      other => panic!("Expected UNANNOUNCE, got {other:?}"),
+    }
+  }
+
+  #[tokio::test]
+  async fn test_subscribe_announces() {
+    let connector = spawn_relay(RelayBuilder::new(RelayConfig::default()).build());
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let subscriber = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let _room = publisher
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+
+    // namespaces announced before SUBSCRIBE_ANNOUNCES are sent right away
+    subscriber
+      .subscribe_announces(Tuple::from_utf8_path("live"), vec![])
+      .await
+      .unwrap();
+    assert_eq!(
+      announced_namespace(next_message(&subscriber).await),
+      Tuple::from_utf8_path("live/room")
+    );
+
+    match subscriber
+      .subscribe_announces(Tuple::from_utf8_path("live/room"), vec![])
+      .await
+    {
+      Err(ClientError::SubscribeAnnouncesRejected(error)) => assert_eq!(
+        error.error_code,
+        SubscribeAnnouncesErrorCode::NamespacePrefixOverlap
+      ),
+      other => panic!("Expected SUBSCRIBE_ANNOUNCES_ERROR, got {other:?}"),
+    }
+
+    // only namespaces under the prefix are forwarded
+    let other = publisher
+      .announce(Tuple::from_utf8_path("other/room"), &[])
+      .await
+      .unwrap();
+    let hall = publisher
+      .announce(Tuple::from_utf8_path("live/hall"), &[])
+      .await
+      .unwrap();
+    assert_eq!(
+      announced_namespace(next_message(&subscriber).await),
+      Tuple::from_utf8_path("live/hall")
+    );
+
+    other.unannounce().unwrap();
+    hall.unannounce().unwrap();
+    assert_eq!(
+      unannounced_namespace(next_message(&subscriber).await),
+      Tuple::from_utf8_path("live/hall")
+    );
+  }
+
+  #[tokio::test]
+  async fn test_unannounce_waits_for_the_last_publisher() {
+    let config = RelayConfig {
+      announce_conflict_policy: AnnounceConflictPolicy::MultiPublisher,
+      ..Default::default()
+    };
+    let connector = spawn_relay(RelayBuilder::new(config).build());
+    let first = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let second = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let subscriber = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    subscriber
+      .subscribe_announces(Tuple::from_utf8_path("live"), vec![])
+      .await
+      .unwrap();
+
+    let track_namespace = Tuple::from_utf8_path("live/room");
+    let first_room = first.announce(track_namespace.clone(), &[]).await.unwrap();
+    let second_room = second.announce(track_namespace.clone(), &[]).await.unwrap();
+    assert_eq!(
+      announced_namespace(next_message(&subscriber).await),
+      track_namespace
+    );
+    assert_eq!(
+      announced_namespace(next_message(&subscriber).await),
+      track_namespace
+    );
+
+    first_room.unannounce().unwrap();
+    // handled after the UNANNOUNCE on the same control stream
+    let _lobby = first
+      .announce(Tuple::from_utf8_path("live/lobby"), &[])
+      .await
+      .unwrap();
+    assert_eq!(
+      announced_namespace(next_message(&subscriber).await),
+      Tuple::from_utf8_path("live/lobby")
+    );
+
+    second_room.unannounce().unwrap();
+    assert_eq!(
+      unannounced_namespace(next_message(&subscriber).await),
+      track_namespace
+    );
+  }
+
+  /// Subscribes to `video` under `track_namespace` and accepts the
+  /// forwarded SUBSCRIBE on the publisher. Returns the subscription and the
+  /// relay's request ID of the upstream subscription.
+  async fn subscribe_through(
+    publisher: &ClientSession,
+    subscriber: &ClientSession,
+    track_namespace: Tuple,
+  ) -> (Subscription, u64) {
+    let subscribe = Subscribe::new_latest_object(
+      0,
+      0,
+      track_namespace,
+      "video".to_string(),
+      1,
+      GroupOrder::Ascending,
+      true,
+      vec![],
+    );
+    let subscription = tokio::spawn({
+      let subscriber = subscriber.clone();
+      async move { subscriber.subscribe(subscribe).await.unwrap() }
+    });
+    let upstream = match next_message(publisher).await {
+      ControlMessage::Subscribe(subscribe) => subscribe,
+      other => panic!("Expected SUBSCRIBE, got {other:?}"),
+    };
+    publisher
+      .send(ControlMessage::SubscribeOk(Box::new(
+        SubscribeOk::new_ascending_no_content(upstream.request_id, 0, None),
+      )))
+      .unwrap();
+    (subscription.await.unwrap(), upstream.request_id)
+  }
+
+  #[tokio::test]
+  async fn test_withdrawn_namespace_unsubscribes_upstream() {
+    let relay = RelayBuilder::new(RelayConfig::default()).build();
+    let connector = spawn_relay(relay.clone());
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let subscriber = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+
+    // UNANNOUNCE from the publisher
+    let room = publisher
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+    let (mut subscription, request_id) =
+      subscribe_through(&publisher, &subscriber, Tuple::from_utf8_path("live/room")).await;
+    room.unannounce().unwrap();
+    match next_message(&publisher).await {
+      ControlMessage::Unsubscribe(m) => assert_eq!(m.request_id, request_id),
+      other => panic!("Expected UNSUBSCRIBE, got {other:?}"),
+    }
+    assert!(subscription.next_object().await.is_none());
+
+    // revoked by the relay
+    let _hall = publisher
+      .announce(Tuple::from_utf8_path("live/hall"), &[])
+      .await
+      .unwrap();
+    let (mut subscription, request_id) =
+      subscribe_through(&publisher, &subscriber, Tuple::from_utf8_path("live/hall")).await;
+    let revoked = relay
+      .revoke_namespace(
+        &Tuple::from_utf8_path("live/hall"),
+        AnnounceErrorCode::Unauthorized,
+        "Revoked",
+      )
+      .await;
+    assert_eq!(revoked, 1);
+    match next_message(&publisher).await {
+      ControlMessage::Unsubscribe(m) => assert_eq!(m.request_id, request_id),
+      other => panic!("Expected UNSUBSCRIBE, got {other:?}"),
+    }
+    match next_message(&publisher).await {
+      ControlMessage::AnnounceCancel(m) => {
+        assert_eq!(m.track_namespace, Tuple::from_utf8_path("live/hall"))
+      }
+      other => panic!("Expected ANNOUNCE_CANCEL, got {other:?}"),
+    }
+    assert!(subscription.next_object().await.is_none());
+    assert!(relay.relay_subscribe_requests.read().await.is_empty());
+  }
+
+  /// Connects a relay with `policy` and two publishers, the first of which
+  /// announced `live/room`
+  async fn connect_publishers(
+    policy: AnnounceConflictPolicy,
+  ) -> (
+    Arc<loopback::LoopbackConnector>,
+    ClientSession,
+    Announcement,
+    ClientSession,
+  ) {
+    let config = RelayConfig {
+      announce_conflict_policy: policy,
+      ..Default::default()
+    };
+    let connector = spawn_relay(RelayBuilder::new(config).build());
+    let first = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let second = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let room = first
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+    (connector, first, room, second)
+  }
+
+  #[tokio::test]
+  async fn test_announce_conflict_reject() {
+    let (_connector, _first, _room, second) =
+      connect_publishers(AnnounceConflictPolicy::Reject).await;
+
+    // the same namespace, a prefix of it and a namespace under it
+    for path in ["live/room", "live", "live/room/cam"] {
+      match second.announce(Tuple::from_utf8_path(path), &[]).await {
+        Err(ClientError::AnnounceRejected(error)) => {
+          assert_eq!(error.error_code, AnnounceErrorCode::Unauthorized)
+        }
+        other => panic!("Expected ANNOUNCE_ERROR for {path}, got {:?}", other.err()),
+      }
+    }
+    let _hall = second
+      .announce(Tuple::from_utf8_path("live/hall"), &[])
+      .await
+      .unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_announce_conflict_replace_latest() {
+    let (connector, first, _room, second) =
+      connect_publishers(AnnounceConflictPolicy::ReplaceLatest).await;
+    let subscriber = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let (mut subscription, request_id) =
+      subscribe_through(&first, &subscriber, Tuple::from_utf8_path("live/room")).await;
+
+    // a prefix of the first publisher's namespace replaces it
+    let _live = second
+      .announce(Tuple::from_utf8_path("live"), &[])
+      .await
+      .unwrap();
+    match next_message(&first).await {
+      ControlMessage::Unsubscribe(m) => assert_eq!(m.request_id, request_id),
+      other => panic!("Expected UNSUBSCRIBE, got {other:?}"),
+    }
+    match next_message(&first).await {
+      ControlMessage::AnnounceCancel(m) => {
+        assert_eq!(m.track_namespace, Tuple::from_utf8_path("live/room"))
+      }
+      other => panic!("Expected ANNOUNCE_CANCEL, got {other:?}"),
+    }
+    assert!(subscription.next_object().await.is_none());
+
+    let (_subscription, _) =
+      subscribe_through(&second, &subscriber, Tuple::from_utf8_path("live/room")).await;
+  }
+
+  #[tokio::test]
+  async fn test_announce_conflict_multi_publisher() {
+    let (connector, first, room, second) =
+      connect_publishers(AnnounceConflictPolicy::MultiPublisher).await;
+    let subscriber = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let _room = second
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+    let _live = second
+      .announce(Tuple::from_utf8_path("live"), &[])
+      .await
+      .unwrap();
+
+    // the default router picks the first publisher
+    let (_subscription, _) =
+      subscribe_through(&first, &subscriber, Tuple::from_utf8_path("live/room")).await;
+
+    room.unannounce().unwrap();
+    // handled after the UNANNOUNCE on the same control stream
+    let _hall = first
+      .announce(Tuple::from_utf8_path("other/hall"), &[])
+      .await
+      .unwrap();
+    let (_subscription, _) =
+      subscribe_through(&second, &subscriber, Tuple::from_utf8_path("live/room")).await;
+  }
+
+  #[tokio::test]
+  async fn test_track_status_of_live_track() {
+    let connector = spawn_relay(RelayBuilder::new(RelayConfig::default()).build());
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let subscriber = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let track_namespace = Tuple::from_utf8_path("live/room");
+    let _room = publisher
+      .announce(track_namespace.clone(), &[])
+      .await
+      .unwrap();
+    let (_subscription, _) =
+      subscribe_through(&publisher, &subscriber, track_namespace.clone()).await;
+
+    // answered by the relay, the publisher has not sent any objects yet
+    let track_status = subscriber
+      .track_status(track_namespace, "video".to_string(), vec![])
+      .await
+      .unwrap();
+    assert_eq!(track_status.status_code, TrackStatusCode::NotYetBegun);
+  }
+
+  #[tokio::test]
+  async fn test_track_status_publisher_closes() {
+    let connector = spawn_relay(RelayBuilder::new(RelayConfig::default()).build());
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let subscriber = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let track_namespace = Tuple::from_utf8_path("live/room");
+    let _room = publisher
+      .announce(track_namespace.clone(), &[])
+      .await
+      .unwrap();
+
+    let track_status = tokio::spawn(async move {
+      subscriber
+        .track_status(track_namespace, "video".to_string(), vec![])
+        .await
+    });
+    match next_message(&publisher).await {
+      ControlMessage::TrackStatusRequest(_) => {}
+      other => panic!("Expected TRACK_STATUS_REQUEST, got {other:?}"),
+    }
+    publisher.close(TerminationCode::NoError, "bye");
+
+    let track_status = tokio::time::timeout(Duration::from_secs(5), track_status)
+      .await
+      .expect("no TRACK_STATUS")
+      .unwrap()
+      .unwrap();
+    assert_eq!(track_status.status_code, TrackStatusCode::RelayUnavailable);
+  }
+
+  /// Receives a SUBSCRIBE on the publisher and accepts it
+  async fn accept_subscribe(publisher: &ClientSession) -> Subscribe {
+    let subscribe = match next_message(publisher).await {
+      ControlMessage::Subscribe(subscribe) => *subscribe,
+      other => panic!("Expected SUBSCRIBE, got {other:?}"),
+    };
+    publisher
+      .send(ControlMessage::SubscribeOk(Box::new(
+        SubscribeOk::new_ascending_no_content(subscribe.request_id, 0, None),
+      )))
+      .unwrap();
+    subscribe
+  }
+
+  fn subscribe_range(start_group: u64, end_group: u64) -> Subscribe {
+    Subscribe::new_absolute_range(
+      0,
+      0,
+      Tuple::from_utf8_path("live/room"),
+      "video".to_string(),
+      1,
+      GroupOrder::Ascending,
+      true,
+      Location::new(start_group, 0),
+      end_group,
+      vec![],
+    )
+  }
+
+  #[tokio::test]
+  async fn test_subscribe_ok_carries_the_publisher_group_order() {
+    let connector = spawn_relay(RelayBuilder::new(RelayConfig::default()).build());
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let first = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let second = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let _room = publisher
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+
+    let subscription = tokio::spawn({
+      let first = first.clone();
+      async move { first.subscribe(subscribe_range(0, 10)).await.unwrap() }
+    });
+    let upstream = match next_message(&publisher).await {
+      ControlMessage::Subscribe(subscribe) => subscribe,
+      other => panic!("Expected SUBSCRIBE, got {other:?}"),
+    };
+    publisher
+      .send(ControlMessage::SubscribeOk(Box::new(
+        SubscribeOk::new_descending_with_content(
+          upstream.request_id,
+          0,
+          Some(Location::new(4, 2)),
+          None,
+        ),
+      )))
+      .unwrap();
+    let subscription = subscription.await.unwrap();
+    let subscribe_ok = subscription.subscribe_ok();
+    assert_eq!(subscribe_ok.group_order, GroupOrder::Descending);
+    assert!(subscribe_ok.content_exists);
+    assert_eq!(subscribe_ok.largest_location, Some(Location::new(4, 2)));
+
+    // later subscribers of the track get the stored order
+    let subscription = second.subscribe(subscribe_range(0, 10)).await.unwrap();
+    let subscribe_ok = subscription.subscribe_ok();
+    assert_eq!(subscribe_ok.group_order, GroupOrder::Descending);
+    assert!(!subscribe_ok.content_exists);
+  }
+
+  #[tokio::test]
+  async fn test_wider_subscriber_resubscribes_upstream() {
+    let connector = spawn_relay(RelayBuilder::new(RelayConfig::default()).build());
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let first = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let second = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let _room = publisher
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+
+    let subscription = tokio::spawn({
+      let first = first.clone();
+      async move { first.subscribe(subscribe_range(0, 10)).await.unwrap() }
+    });
+    let upstream = accept_subscribe(&publisher).await;
+    assert_eq!(upstream.end_group, Some(10));
+    let first_subscription = subscription.await.unwrap();
+
+    // narrowing does not shrink the upstream subscription
+    first_subscription
+      .update(Location::new(5, 0), 9, 1, true)
+      .await
+      .unwrap();
+    // handled after the SUBSCRIBE_UPDATE on the same control stream
+    first
+      .track_status(Tuple::from_utf8_path("live"), "none".to_string(), vec![])
+      .await
+      .unwrap();
+
+    // a wider range re-subscribes upstream
+    let second_subscription = tokio::spawn({
+      let second = second.clone();
+      async move { second.subscribe(subscribe_range(0, 20)).await.unwrap() }
+    });
+    match next_message(&publisher).await {
+      ControlMessage::Unsubscribe(m) => assert_eq!(m.request_id, upstream.request_id),
+      other => panic!("Expected UNSUBSCRIBE, got {other:?}"),
+    }
+    let widened = accept_subscribe(&publisher).await;
+    assert_eq!(widened.track_alias, upstream.track_alias);
+    assert_eq!(widened.filter_type, FilterType::AbsoluteRange);
+    assert_eq!(widened.start_location, Some(Location::new(0, 0)));
+    assert_eq!(widened.end_group, Some(20));
+    let second_subscription = second_subscription.await.unwrap();
+
+    // pausing both only updates forwarding, the range stays
+    first_subscription
+      .update(Location::new(5, 0), 9, 1, false)
+      .await
+      .unwrap();
+    first
+      .track_status(Tuple::from_utf8_path("live"), "none".to_string(), vec![])
+      .await
+      .unwrap();
+    second_subscription
+      .update(Location::new(0, 0), 21, 1, false)
+      .await
+      .unwrap();
+    match next_message(&publisher).await {
+      ControlMessage::SubscribeUpdate(m) => {
+        assert_eq!(m.request_id, widened.request_id);
+        assert_eq!(m.start_location, Location::new(0, 0));
+        assert_eq!(m.end_group, 21);
+        assert!(!m.forward);
+      }
+      other => panic!("Expected SUBSCRIBE_UPDATE, got {other:?}"),
+    }
+  }
+
+  fn frame(track_alias: u64, group_id: u64, object_id: u64) -> Object {
+    Object::try_from_subgroup(
+      SubgroupObject {
+        object_id,
+        extension_headers: None,
+        object_status: None,
+        payload: Some(Bytes::from_static(b"frame")),
+      },
+      track_alias,
+      group_id,
+      Some(0),
+      1,
+    )
+    .unwrap()
+  }
+
+  /// Sends a group of one object on its own stream
+  async fn publish_group(publisher: &ClientSession, track_alias: u64, group_id: u64) {
+    let header = SubgroupHeader::new_fixed_zero_id(track_alias, group_id, 1, false);
+    let mut stream = publisher.open_subgroup_stream(header).await.unwrap();
+    stream
+      .send_object(&frame(track_alias, group_id, 0))
+      .await
+      .unwrap();
+    stream.finish().await.unwrap();
+  }
+
+  async fn next_object(subscription: &mut Subscription) -> Option<Object> {
+    tokio::time::timeout(Duration::from_secs(5), subscription.next_object())
+      .await
+      .expect("subscription neither delivered nor ended")
+  }
+
+  #[tokio::test]
+  async fn test_range_ends_with_its_last_group() {
+    let connector = spawn_relay(RelayBuilder::new(RelayConfig::default()).build());
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let subscriber = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let _room = publisher
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+
+    let subscription = tokio::spawn({
+      let subscriber = subscriber.clone();
+      async move { subscriber.subscribe(subscribe_range(0, 1)).await.unwrap() }
+    });
+    let upstream = accept_subscribe(&publisher).await;
+    let mut subscription = subscription.await.unwrap();
+
+    // the publisher stops at the end group
+    publish_group(&publisher, upstream.track_alias, 0).await;
+    publish_group(&publisher, upstream.track_alias, 1).await;
+    assert_eq!(
+      next_object(&mut subscription).await.unwrap().location.group,
+      0
+    );
+    assert_eq!(
+      next_object(&mut subscription).await.unwrap().location.group,
+      1
+    );
+    assert!(next_object(&mut subscription).await.is_none());
+  }
+
+  #[tokio::test]
+  async fn test_publisher_subscribe_done_ends_subscriptions() {
+    let connector = spawn_relay(RelayBuilder::new(RelayConfig::default()).build());
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let subscriber = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let _room = publisher
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+    let (mut subscription, request_id) =
+      subscribe_through(&publisher, &subscriber, Tuple::from_utf8_path("live/room")).await;
+
+    publisher
+      .send(ControlMessage::SubscribeDone(Box::new(SubscribeDone::new(
+        request_id,
+        SubscribeDoneStatusCode::TrackEnded,
+        0,
+        ReasonPhrase::try_new("Track ended".to_string()).unwrap(),
+      ))))
+      .unwrap();
+    assert!(next_object(&mut subscription).await.is_none());
+  }
+
+  #[tokio::test]
+  async fn test_relay_request_ids_are_counted_per_peer() {
+    let relay = RelayBuilder::new(RelayConfig::default()).build();
+    let connector = spawn_relay(relay.clone());
+    let first = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let second = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let subscriber = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let _room = first
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+    let _hall = second
+      .announce(Tuple::from_utf8_path("live/hall"), &[])
+      .await
+      .unwrap();
+
+    // each publisher sees the relay's requests numbered from 1
+    let (_room_video, room_request_id) =
+      subscribe_through(&first, &subscriber, Tuple::from_utf8_path("live/room")).await;
+    let (_hall_video, hall_request_id) =
+      subscribe_through(&second, &subscriber, Tuple::from_utf8_path("live/hall")).await;
+    assert_eq!(room_request_id, 1);
+    assert_eq!(hall_request_id, 1);
+    assert_eq!(relay.relay_subscribe_requests.read().await.len(), 2);
+
+    // concurrent requests to one peer get distinct ids
+    let clients: Vec<Arc<client::MOQTClient>> = {
+      let client_manager = relay.client_manager.read().await;
+      let clients = client_manager.clients.read().await;
+      clients.values().cloned().collect()
+    };
+    let mut idle = None;
+    for client in clients {
+      if client.announced_track_namespaces.read().await.is_empty() {
+        idle = Some(client);
+      }
+    }
+    let idle = idle.unwrap();
+    let tasks: Vec<_> = (0..32)
+      .map(|_| {
+        let idle = idle.clone();
+        tokio::spawn(async move { idle.next_request_id().await })
+      })
+      .collect();
+    let mut request_ids = Vec::new();
+    for task in tasks {
+      request_ids.push(task.await.unwrap().unwrap());
+    }
+    request_ids.sort();
+    assert_eq!(
+      request_ids,
+      (0..32).map(|i| 1 + 2 * i).collect::<Vec<u64>>()
+    );
+  }
+
+  #[tokio::test]
+  async fn test_completed_requests_grant_request_ids() {
+    let config = RelayConfig {
+      initial_max_request_id: 4,
+      request_id_window: 4,
+      ..Default::default()
+    };
+    let connector = spawn_relay(RelayBuilder::new(config).build());
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let subscriber = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let _room = publisher
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+    let unknown = || {
+      let mut subscribe = subscribe_range(0, 1);
+      subscribe.track_namespace = Tuple::from_utf8_path("live/unknown");
+      subscribe
+    };
+    let wait_for_max = |max_request_id: u64| {
+      let subscriber = subscriber.clone();
+      async move {
+        tokio::time::timeout(Duration::from_secs(5), async {
+          while subscriber.max_request_id().await != max_request_id {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+          }
+        })
+        .await
+        .unwrap();
+      }
+    };
+
+    // request 0 stays open, request 2 is refused, which frees half a window
+    let (subscription, _) =
+      subscribe_through(&publisher, &subscriber, Tuple::from_utf8_path("live/room")).await;
+    assert!(matches!(
+      subscriber.subscribe(unknown()).await,
+      Err(ClientError::SubscribeRejected(_))
+    ));
+    assert_eq!(subscriber.max_request_id().await, 4);
+
+    // a blocked subscriber gets the freed ids right away
+    assert!(matches!(
+      subscriber.subscribe(unknown()).await,
+      Err(ClientError::RequestsBlocked { max_request_id: 4 })
+    ));
+    wait_for_max(6).await;
+
+    // UNSUBSCRIBE and another refusal free a whole window
+    subscription.unsubscribe().await.unwrap();
+    assert!(matches!(
+      subscriber.subscribe(unknown()).await,
+      Err(ClientError::SubscribeRejected(_))
+    ));
+    wait_for_max(10).await;
+  }
+
+  fn fetch_range(start_group: u64, end_group: u64) -> Fetch {
+    Fetch::new_standalone(
+      0,
+      1,
+      GroupOrder::Ascending,
+      StandAloneFetchProps {
+        track_namespace: Tuple::from_utf8_path("live/room"),
+        track_name: "video".to_string(),
+        start_location: Location::new(start_group, 0),
+        end_location: Location::new(end_group, 0),
+      },
+      vec![],
+    )
+  }
+
+  #[tokio::test]
+  async fn test_fetch_is_forwarded_to_the_publisher() {
+    let relay = RelayBuilder::new(RelayConfig::default()).build();
+    let connector = spawn_relay(relay.clone());
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let subscriber = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let _room = publisher
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+
+    let fetch = tokio::spawn({
+      let subscriber = subscriber.clone();
+      async move { subscriber.fetch(fetch_range(0, 1)).await.unwrap() }
+    });
+    let upstream = match next_message(&publisher).await {
+      ControlMessage::Fetch(fetch) => *fetch,
+      other => panic!("Expected FETCH, got {other:?}"),
+    };
+    // the relay numbers its own requests
+    assert_eq!(upstream.request_id % 2, 1);
+    assert!(
+      relay
+        .relay_fetch_requests
+        .read()
+        .await
+        .keys()
+        .any(|(_, request_id)| *request_id == upstream.request_id)
+    );
+    let props = upstream.standalone_fetch_props.as_ref().unwrap();
+    assert_eq!(props.track_namespace, Tuple::from_utf8_path("live/room"));
+    assert_eq!(props.end_location, Location::new(1, 0));
+
+    publisher
+      .send(ControlMessage::FetchOk(Box::new(FetchOk::new_ascending(
+        upstream.request_id,
+        false,
+        Location::new(1, 0),
+        vec![],
+      ))))
+      .unwrap();
+    let mut fetch = fetch.await.unwrap();
+
+    // the fetch stream is piped to the requester
+    let mut stream = publisher.open_fetch_stream(upstream).await.unwrap();
+    stream.send_object(&frame(0, 0, 0)).await.unwrap();
+    stream.send_object(&frame(0, 1, 0)).await.unwrap();
+    stream.finish().await.unwrap();
+    for group_id in 0..2 {
+      let object = tokio::time::timeout(Duration::from_secs(5), fetch.next_object())
+        .await
+        .unwrap()
+        .unwrap();
+      assert_eq!(object.location, Location::new(group_id, 0));
+      assert_eq!(object.payload, Some(Bytes::from_static(b"frame")));
+    }
+    assert!(
+      tokio::time::timeout(Duration::from_secs(5), fetch.next_object())
+        .await
+        .unwrap()
+        .is_none()
+    );
+    // the request is dropped once the upstream stream has been relayed
+    tokio::time::timeout(Duration::from_secs(5), async {
+      while !relay.relay_fetch_requests.read().await.is_empty() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+      }
+    })
+    .await
+    .unwrap();
+  }
+
+  /// Publishes `groups` of `live/room` `video` through a subscription, so
+  /// the relay caches them. Returns the subscription and the publisher's
+  /// upstream SUBSCRIBE.
+  async fn cache_groups(
+    publisher: &ClientSession,
+    subscriber: &ClientSession,
+    groups: &[u64],
+  ) -> (Subscription, Subscribe) {
+    let subscription = tokio::spawn({
+      let subscriber = subscriber.clone();
+      async move { subscriber.subscribe(subscribe_range(0, 10)).await.unwrap() }
+    });
+    let upstream = accept_subscribe(publisher).await;
+    let mut subscription = subscription.await.unwrap();
+    for group_id in groups {
+      publish_group(publisher, upstream.track_alias, *group_id).await;
+      assert_eq!(
+        next_object(&mut subscription).await.unwrap().location.group,
+        *group_id
+      );
+    }
+    (subscription, upstream)
+  }
+
+  async fn next_fetch(publisher: &ClientSession) -> Fetch {
+    match next_message(publisher).await {
+      ControlMessage::Fetch(fetch) => *fetch,
+      other => panic!("Expected FETCH, got {other:?}"),
+    }
+  }
+
+  #[tokio::test]
+  async fn test_fetch_merges_cache_and_fills() {
+    let connector = spawn_relay(RelayBuilder::new(RelayConfig::default()).build());
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let subscriber = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let fetcher = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let _room = publisher
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+    let (_subscription, upstream) = cache_groups(&publisher, &subscriber, &[0, 2]).await;
+
+    let fetch = tokio::spawn({
+      let fetcher = fetcher.clone();
+      async move { fetcher.fetch(fetch_range(0, 2)).await.unwrap() }
+    });
+
+    // group 1 is missing, and group 2 may still be growing
+    let gap = next_fetch(&publisher).await;
+    let gap_props = gap.standalone_fetch_props.clone().unwrap();
+    assert_eq!(gap_props.start_location, Location::new(1, 0));
+    assert_eq!(gap_props.end_location, Location::new(1, 0));
+    let tail = next_fetch(&publisher).await;
+    let tail_props = tail.standalone_fetch_props.clone().unwrap();
+    assert_eq!(tail_props.start_location, Location::new(2, 1));
+    assert_eq!(tail_props.end_location, Location::new(2, 0));
+
+    publisher
+      .send(ControlMessage::FetchError(Box::new(FetchError::new(
+        tail.request_id,
+        FetchErrorCode::NoObjects,
+        ReasonPhrase::try_new("No objects".to_string()).unwrap(),
+      ))))
+      .unwrap();
+    publisher
+      .send(ControlMessage::FetchOk(Box::new(FetchOk::new_ascending(
+        gap.request_id,
+        false,
+        Location::new(1, 0),
+        vec![],
+      ))))
+      .unwrap();
+    let mut stream = publisher.open_fetch_stream(gap).await.unwrap();
+    stream
+      .send_object(&frame(upstream.track_alias, 1, 0))
+      .await
+      .unwrap();
+    stream.finish().await.unwrap();
+
+    let mut fetch = fetch.await.unwrap();
+    assert_eq!(fetch.fetch_ok().end_location, Location::new(2, 0));
+    for group_id in 0..3 {
+      let object = tokio::time::timeout(Duration::from_secs(5), fetch.next_object())
+        .await
+        .unwrap()
+        .unwrap();
+      assert_eq!(object.location, Location::new(group_id, 0));
+    }
+    assert!(
+      tokio::time::timeout(Duration::from_secs(5), fetch.next_object())
+        .await
+        .unwrap()
+        .is_none()
+    );
+  }
+
+  /// Whether the relay still caches `group_id` of the track
+  async fn is_cached(relay: &Relay, track_alias: u64, group_id: u64) -> bool {
+    let track = relay
+      .tracks
+      .read()
+      .await
+      .get(&track_alias)
+      .cloned()
+      .unwrap();
+    track.cache.get_group(group_id).await.is_some()
+  }
+
+  #[tokio::test]
+  async fn test_subscribe_ok_max_cache_duration_expires_groups() {
+    let relay = RelayBuilder::new(RelayConfig::default()).build();
+    let connector = spawn_relay(relay.clone());
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let subscriber = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let _room = publisher
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+
+    let subscription = tokio::spawn({
+      let subscriber = subscriber.clone();
+      async move { subscriber.subscribe(subscribe_range(0, 10)).await.unwrap() }
+    });
+    let upstream = match next_message(&publisher).await {
+      ControlMessage::Subscribe(subscribe) => *subscribe,
+      other => panic!("Expected SUBSCRIBE, got {other:?}"),
+    };
+    let max_cache_duration: KeyValuePair = VersionParameter::new_max_cache_duration(100)
+      .try_into()
+      .unwrap();
+    publisher
+      .send(ControlMessage::SubscribeOk(Box::new(
+        SubscribeOk::new_ascending_no_content(
+          upstream.request_id,
+          0,
+          Some(vec![max_cache_duration]),
+        ),
+      )))
+      .unwrap();
+    let mut subscription = subscription.await.unwrap();
+    publish_group(&publisher, upstream.track_alias, 0).await;
+    assert_eq!(
+      next_object(&mut subscription).await.unwrap().location.group,
+      0
+    );
+    assert!(is_cached(&relay, upstream.track_alias, 0).await);
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(!is_cached(&relay, upstream.track_alias, 0).await);
+  }
+
+  #[tokio::test]
+  async fn test_fill_fetch_ok_max_cache_duration_expires_groups() {
+    let relay = RelayBuilder::new(RelayConfig::default()).build();
+    let connector = spawn_relay(relay.clone());
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let subscriber = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let fetcher = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let _room = publisher
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+    let (_subscription, upstream) = cache_groups(&publisher, &subscriber, &[0, 2]).await;
+
+    let fetch = tokio::spawn({
+      let fetcher = fetcher.clone();
+      async move { fetcher.fetch(fetch_range(0, 2)).await.unwrap() }
+    });
+    let gap = next_fetch(&publisher).await;
+    assert_eq!(
+      gap.standalone_fetch_props.clone().unwrap().start_location,
+      Location::new(1, 0)
+    );
+    let tail = next_fetch(&publisher).await;
+    publisher
+      .send(ControlMessage::FetchError(Box::new(FetchError::new(
+        tail.request_id,
+        FetchErrorCode::NoObjects,
+        ReasonPhrase::try_new("No objects".to_string()).unwrap(),
+      ))))
+      .unwrap();
+    let max_cache_duration: KeyValuePair = VersionParameter::new_max_cache_duration(100)
+      .try_into()
+      .unwrap();
+    publisher
+      .send(ControlMessage::FetchOk(Box::new(FetchOk::new_ascending(
+        gap.request_id,
+        false,
+        Location::new(1, 0),
+        vec![max_cache_duration],
+      ))))
+      .unwrap();
+    let mut stream = publisher.open_fetch_stream(gap).await.unwrap();
+    stream
+      .send_object(&frame(upstream.track_alias, 1, 0))
+      .await
+      .unwrap();
+    stream.finish().await.unwrap();
+
+    let mut fetch = fetch.await.unwrap();
+    for group_id in 0..3 {
+      let object = tokio::time::timeout(Duration::from_secs(5), fetch.next_object())
+        .await
+        .unwrap()
+        .unwrap();
+      assert_eq!(object.location, Location::new(group_id, 0));
+    }
+    assert!(is_cached(&relay, upstream.track_alias, 1).await);
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(!is_cached(&relay, upstream.track_alias, 1).await);
+    // cached without a MAX_CACHE_DURATION, so the relay's expiration applies
+    assert!(is_cached(&relay, upstream.track_alias, 0).await);
+    assert!(is_cached(&relay, upstream.track_alias, 2).await);
+  }
+
+  #[tokio::test]
+  async fn test_failed_fill_fails_the_fetch() {
+    let connector = spawn_relay(RelayBuilder::new(RelayConfig::default()).build());
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let subscriber = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let fetcher = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let _room = publisher
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+    let _cached = cache_groups(&publisher, &subscriber, &[0]).await;
+
+    let fetch = tokio::spawn({
+      let fetcher = fetcher.clone();
+      async move { fetcher.fetch(fetch_range(0, 1)).await }
+    });
+    let tail = next_fetch(&publisher).await;
+    assert_eq!(
+      tail.standalone_fetch_props.as_ref().unwrap().start_location,
+      Location::new(0, 1)
+    );
+    publisher
+      .send(ControlMessage::FetchError(Box::new(FetchError::new(
+        tail.request_id,
+        FetchErrorCode::InternalError,
+        ReasonPhrase::try_new("Storage failure".to_string()).unwrap(),
+      ))))
+      .unwrap();
+
+    // no FETCH_OK promises a range the relay cannot deliver
+    match fetch.await.unwrap() {
+      Err(ClientError::FetchRejected(m)) => assert_eq!(m.error_code, FetchErrorCode::InternalError),
+      Err(e) => panic!("Expected FETCH_ERROR, got {e:?}"),
+      Ok(_) => panic!("Expected FETCH_ERROR, got FETCH_OK"),
+    }
+  }
+
+  async fn assert_fetch_ends(fetch: &mut FetchStream) {
+    assert!(
+      tokio::time::timeout(Duration::from_secs(5), fetch.next_object())
+        .await
+        .expect("fetch stream was not reset")
+        .is_none()
+    );
+  }
+
+  #[tokio::test]
+  async fn test_fetch_cancel_stops_forwarded_fetch() {
+    let relay = RelayBuilder::new(RelayConfig::default()).build();
+    let connector = spawn_relay(relay.clone());
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let fetcher = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let _room = publisher
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+
+    let fetch = tokio::spawn({
+      let fetcher = fetcher.clone();
+      async move { fetcher.fetch(fetch_range(0, 9)).await.unwrap() }
+    });
+    let upstream = next_fetch(&publisher).await;
+    publisher
+      .send(ControlMessage::FetchOk(Box::new(FetchOk::new_ascending(
+        upstream.request_id,
+        false,
+        Location::new(9, 0),
+        vec![],
+      ))))
+      .unwrap();
+    let mut fetch = fetch.await.unwrap();
+    let mut stream = publisher.open_fetch_stream(upstream.clone()).await.unwrap();
+    stream.send_object(&frame(0, 0, 0)).await.unwrap();
+    assert!(fetch.next_object().await.is_some());
+
+    fetcher
+      .send(ControlMessage::FetchCancel(Box::new(FetchCancel::new(
+        fetch.request_id(),
+      ))))
+      .unwrap();
+    match next_message(&publisher).await {
+      ControlMessage::FetchCancel(m) => assert_eq!(m.request_id, upstream.request_id),
+      other => panic!("Expected FETCH_CANCEL, got {other:?}"),
+    }
+    assert!(relay.relay_fetch_requests.read().await.is_empty());
+    assert_fetch_ends(&mut fetch).await;
+
+    // the relay stops reading the upstream stream
+    tokio::time::timeout(Duration::from_secs(5), async {
+      let mut group_id = 1;
+      while stream.send_object(&frame(0, group_id, 0)).await.is_ok() {
+        group_id += 1;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+      }
+    })
+    .await
+    .expect("upstream fetch stream was not stopped");
+  }
+
+  #[tokio::test]
+  async fn test_fetch_cancel_aborts_merging_fetch() {
+    let relay = RelayBuilder::new(RelayConfig::default()).build();
+    let connector = spawn_relay(relay.clone());
+    let publisher = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let subscriber = ClientSession::connect_with(connector.clone(), "loopback")
+      .await
+      .unwrap();
+    let fetcher = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let _room = publisher
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+    let _cached = cache_groups(&publisher, &subscriber, &[0, 2]).await;
+
+    let fetch = tokio::spawn({
+      let fetcher = fetcher.clone();
+      async move { fetcher.fetch(fetch_range(0, 2)).await.unwrap() }
+    });
+    let gap = next_fetch(&publisher).await;
+    let tail = next_fetch(&publisher).await;
+    publisher
+      .send(ControlMessage::FetchError(Box::new(FetchError::new(
+        tail.request_id,
+        FetchErrorCode::NoObjects,
+        ReasonPhrase::try_new("No objects".to_string()).unwrap(),
+      ))))
+      .unwrap();
+
+    // group 0 is served from the cache while the gap fill is pending
+    let mut fetch = fetch.await.unwrap();
+    assert_eq!(
+      fetch.next_object().await.unwrap().location,
+      Location::new(0, 0)
+    );
+
+    fetcher
+      .send(ControlMessage::FetchCancel(Box::new(FetchCancel::new(
+        fetch.request_id(),
+      ))))
+      .unwrap();
+    match next_message(&publisher).await {
+      ControlMessage::FetchCancel(m) => assert_eq!(m.request_id, gap.request_id),
+      other => panic!("Expected FETCH_CANCEL, got {other:?}"),
+    }
+    assert!(relay.relay_fetch_requests.read().await.is_empty());
+    assert!(relay.relay_fetch_fills.read().await.is_empty());
+    // the merging task is aborted instead of waiting out the fill
+    assert_fetch_ends(&mut fetch).await;
+  }
+
+  /// Connects to the relay registered under the URL
+  struct Routes(BTreeMap<&'static str, Arc<loopback::LoopbackConnector>>);
+
+  #[async_trait]
+  impl MoqConnector for Routes {
+    async fn connect(&self, url: &str) -> Result<Arc<dyn MoqTransport>, TransportError> {
+      match self.0.get(url) {
+        Some(connector) => connector.connect(url).await,
+        None => Err(TransportError::NotConnected),
+      }
+    }
+  }
+
+  #[tokio::test]
+  async fn test_drain_moves_updated_subscriptions() {
+    let old_relay = RelayBuilder::new(RelayConfig::default()).build();
+    let old_connector = spawn_relay(old_relay.clone());
+    let new_connector = spawn_relay(RelayBuilder::new(RelayConfig::default()).build());
+    let routes: Arc<dyn MoqConnector> = Arc::new(Routes(BTreeMap::from([
+      ("loopback://old", old_connector.clone()),
+      ("loopback://new", new_connector.clone()),
+    ])));
+
+    // the track is published on both relays
+    let old_publisher = ClientSession::connect_with(old_connector, "loopback")
+      .await
+      .unwrap();
+    let new_publisher = ClientSession::connect_with(new_connector, "loopback")
+      .await
+      .unwrap();
+    let _old_room = old_publisher
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+    let _new_room = new_publisher
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+
+    let subscriber = ClientSession::connect_with(routes, "loopback://old")
+      .await
+      .unwrap();
+    let subscription = tokio::spawn({
+      let subscriber = subscriber.clone();
+      async move { subscriber.subscribe(subscribe_range(0, 10)).await.unwrap() }
+    });
+    accept_subscribe(&old_publisher).await;
+    let mut subscription = subscription.await.unwrap();
+    subscription
+      .update(Location::new(5, 0), 9, 1, true)
+      .await
+      .unwrap();
+
+    assert_eq!(
+      old_relay
+        .drain(Some("loopback://new".to_string()), Duration::from_secs(10))
+        .await,
+      2
+    );
+
+    // the subscription resumes on the new relay with the updated range
+    let moved = accept_subscribe(&new_publisher).await;
+    assert_eq!(moved.filter_type, FilterType::AbsoluteRange);
+    assert_eq!(moved.start_location, Some(Location::new(5, 0)));
+    assert_eq!(moved.end_group, Some(8));
+    assert_eq!(moved.subscriber_priority, 1);
+
+    publish_group(&new_publisher, moved.track_alias, 5).await;
+    assert_eq!(
+      next_object(&mut subscription).await.unwrap().location,
+      Location::new(5, 0)
+    );
+  }
+
+  #[tokio::test]
+  async fn test_draining_relay_refuses_new_requests() {
+    let relay = RelayBuilder::new(RelayConfig::default()).build();
+    let connector = spawn_relay(relay.clone());
+    // the new session URI cannot be reached, so the client stays
+    let routes: Arc<dyn MoqConnector> = Arc::new(Routes(BTreeMap::from([(
+      "loopback://old",
+      connector.clone(),
+    )])));
+    let publisher = ClientSession::connect_with(connector, "loopback")
+      .await
+      .unwrap();
+    let _room = publisher
+      .announce(Tuple::from_utf8_path("live/room"), &[])
+      .await
+      .unwrap();
+    let client = ClientSession::connect_with(routes, "loopback://old")
+      .await
+      .unwrap();
+
+    relay
+      .drain(Some("loopback://gone".to_string()), Duration::from_secs(10))
+      .await;
+
+    match client.subscribe(subscribe_range(0, 10)).await {
+      Err(ClientError::SubscribeRejected(error)) => {
+        assert_eq!(error.error_code, SubscribeErrorCode::NotSupported)
+      }
+      other => panic!("Expected SUBSCRIBE_ERROR, got {:?}", other.err()),
+    }
+    match client.fetch(fetch_range(0, 2)).await {
+      Err(ClientError::FetchRejected(error)) => {
+        assert_eq!(error.error_code, FetchErrorCode::NotSupported)
+      }
+      other => panic!("Expected FETCH_ERROR, got {:?}", other.err()),
+    }
+  }
+}
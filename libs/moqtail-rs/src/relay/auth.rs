@@ -0,0 +1,592 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use bytes::Bytes;
+use ring::hmac;
+use serde::Deserialize;
+
+use super::hooks::Authorizer;
+use crate::model::common::pair::KeyValuePair;
+use crate::model::common::tuple::Tuple;
+use crate::model::control::constant::FetchType;
+use crate::model::control::control_message::ControlMessage;
+use crate::model::parameter::constant::VersionSpecificParameterType;
+use crate::model::parameter::version_parameter::VersionParameter;
+
+/// Why a request was rejected. Each variant maps to the error code of the
+/// same name in ANNOUNCE_ERROR, SUBSCRIBE_ERROR, FETCH_ERROR and
+/// SUBSCRIBE_ANNOUNCES_ERROR.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AuthError {
+  #[error("Unauthorized: {0}")]
+  Unauthorized(String),
+  #[error("Malformed auth token: {0}")]
+  MalformedToken(String),
+  #[error("Expired auth token: {0}")]
+  ExpiredToken(String),
+  #[error("Unknown auth token alias: {0}")]
+  UnknownTokenAlias(String),
+}
+
+impl AuthError {
+  /// The reason phrase sent to the client
+  pub fn reason(&self) -> &str {
+    match self {
+      AuthError::Unauthorized(reason)
+      | AuthError::MalformedToken(reason)
+      | AuthError::ExpiredToken(reason)
+      | AuthError::UnknownTokenAlias(reason) => reason,
+    }
+  }
+}
+
+/// What a request asks the relay to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+  Publish,
+  Subscribe,
+}
+
+/// The namespace, and for track requests the track, a request is about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthScope {
+  pub permission: Permission,
+  pub track_namespace: Tuple,
+  pub track_name: Option<String>,
+}
+
+impl AuthScope {
+  /// None for messages that are not new requests. Joining fetches are
+  /// covered by the subscription they join.
+  pub fn of(message: &ControlMessage) -> Option<AuthScope> {
+    let (permission, track_namespace, track_name) = match message {
+      ControlMessage::Announce(m) => (Permission::Publish, m.track_namespace.clone(), None),
+      ControlMessage::SubscribeAnnounces(m) => (
+        Permission::Subscribe,
+        m.track_namespace_prefix.clone(),
+        None,
+      ),
+      ControlMessage::Subscribe(m) => (
+        Permission::Subscribe,
+        m.track_namespace.clone(),
+        Some(m.track_name.clone()),
+      ),
+      ControlMessage::Fetch(m) if m.fetch_type == FetchType::StandAlone => {
+        let props = m.standalone_fetch_props.as_ref()?;
+        (
+          Permission::Subscribe,
+          props.track_namespace.clone(),
+          Some(props.track_name.clone()),
+        )
+      }
+      ControlMessage::TrackStatusRequest(m) => (
+        Permission::Subscribe,
+        m.track_namespace.clone(),
+        Some(m.track_name.clone()),
+      ),
+      _ => return None,
+    };
+    Some(AuthScope {
+      permission,
+      track_namespace,
+      track_name,
+    })
+  }
+}
+
+pub use crate::model::parameter::auth_token::AuthToken;
+
+/// The AUTHORIZATION TOKEN parameters of a request, in order. Empty for
+/// messages that are not requests.
+pub fn token_parameters(message: &ControlMessage) -> Result<Vec<VersionParameter>, AuthError> {
+  let parameters = match message {
+    ControlMessage::Announce(m) => &m.parameters,
+    ControlMessage::SubscribeAnnounces(m) => &m.parameters,
+    ControlMessage::Subscribe(m) => &m.subscribe_parameters,
+    ControlMessage::Fetch(m) => &m.parameters,
+    ControlMessage::TrackStatusRequest(m) => &m.parameters,
+    _ => return Ok(Vec::new()),
+  };
+  parameters
+    .iter()
+    .filter(|kvp| {
+      matches!(kvp, KeyValuePair::Bytes { type_value, .. }
+        if *type_value == VersionSpecificParameterType::AuthorizationToken as u64)
+    })
+    .map(|kvp| {
+      VersionParameter::deserialize(kvp).map_err(|_| {
+        AuthError::MalformedToken(String::from("invalid authorization token parameter"))
+      })
+    })
+    .collect()
+}
+
+/// A control message and the token presented with it
+#[derive(Debug)]
+pub struct AuthRequest<'a> {
+  pub connection_id: usize,
+  pub message: &'a ControlMessage,
+  pub token: Option<&'a AuthToken>,
+}
+
+impl AuthRequest<'_> {
+  pub fn scope(&self) -> Option<AuthScope> {
+    AuthScope::of(self.message)
+  }
+}
+
+/// One namespace, or one track in it, a token may publish or subscribe to.
+/// `namespace` is a `/` separated path and covers every namespace below it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Grant {
+  pub namespace: String,
+  #[serde(default)]
+  pub track: Option<String>,
+}
+
+impl Grant {
+  fn covers(&self, scope: &AuthScope) -> bool {
+    if !scope
+      .track_namespace
+      .starts_with(&Tuple::from_utf8_path(&self.namespace))
+    {
+      return false;
+    }
+    match &self.track {
+      Some(track) => scope.track_name.as_ref() == Some(track),
+      None => true,
+    }
+  }
+}
+
+/// The namespaces and tracks a token may publish and subscribe to
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct Grants {
+  #[serde(default)]
+  pub publish: Vec<Grant>,
+  #[serde(default)]
+  pub subscribe: Vec<Grant>,
+}
+
+impl Grants {
+  pub fn check(&self, scope: &AuthScope) -> Result<(), AuthError> {
+    let grants = match scope.permission {
+      Permission::Publish => &self.publish,
+      Permission::Subscribe => &self.subscribe,
+    };
+    if grants.iter().any(|grant| grant.covers(scope)) {
+      Ok(())
+    } else {
+      Err(AuthError::Unauthorized(format!(
+        "token does not grant {:?} on {}",
+        scope.permission,
+        scope.track_namespace.to_utf8_path()
+      )))
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+struct JwtHeader {
+  alg: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct JwtClaims {
+  #[serde(default)]
+  exp: Option<u64>,
+  #[serde(default)]
+  nbf: Option<u64>,
+  #[serde(flatten)]
+  grants: Grants,
+}
+
+/// Accepts requests carrying an HS256 JSON Web Token signed with a shared
+/// secret. The claims list the granted scopes next to the optional `exp`
+/// and `nbf` times.
+///
+/// # Example
+/// ```
+/// # use base64::Engine;
+/// # use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+/// # use bytes::Bytes;
+/// # use moqtail::model::common::tuple::Tuple;
+/// # use moqtail::model::control::constant::GroupOrder;
+/// # use moqtail::model::control::control_message::ControlMessage;
+/// # use moqtail::model::control::subscribe::Subscribe;
+/// # use moqtail::model::parameter::auth_token::AuthToken;
+/// # use moqtail::relay::auth::{AuthRequest, JwtAuthorizer};
+/// # use moqtail::relay::hooks::Authorizer;
+/// # use ring::hmac;
+/// # fn sign(secret: &[u8], claims: &str) -> Bytes {
+/// #   let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#);
+/// #   let signing_input = format!("{header}.{}", URL_SAFE_NO_PAD.encode(claims));
+/// #   let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+/// #   let signature = URL_SAFE_NO_PAD.encode(hmac::sign(&key, signing_input.as_bytes()));
+/// #   Bytes::from(format!("{signing_input}.{signature}"))
+/// # }
+/// # let subscribe = |track: &str| {
+/// #   ControlMessage::Subscribe(Box::new(Subscribe::new_latest_object(
+/// #     0, 0, Tuple::from_utf8_path("live"), track.to_string(), 1,
+/// #     GroupOrder::Ascending, true, vec![],
+/// #   )))
+/// # };
+/// let authorizer = JwtAuthorizer::new(b"secret");
+/// let claims = r#"{"publish": [{"namespace": "live/room"}],
+///   "subscribe": [{"namespace": "live", "track": "video"}]}"#;
+/// let token = AuthToken { token_type: 0, value: sign(b"secret", claims) };
+///
+/// let video = subscribe("video");
+/// let request = AuthRequest { connection_id: 1, message: &video, token: Some(&token) };
+/// assert!(authorizer.authorize(&request).is_ok());
+/// let audio = subscribe("audio");
+/// let request = AuthRequest { connection_id: 1, message: &audio, token: Some(&token) };
+/// assert!(authorizer.authorize(&request).is_err());
+/// ```
+#[derive(Debug)]
+pub struct JwtAuthorizer {
+  key: hmac::Key,
+}
+
+impl JwtAuthorizer {
+  pub fn new(secret: &[u8]) -> Self {
+    Self {
+      key: hmac::Key::new(hmac::HMAC_SHA256, secret),
+    }
+  }
+
+  /// Reads the secret from a file, ignoring trailing whitespace
+  pub fn from_secret_file(path: &str) -> Result<Self> {
+    let secret = std::fs::read(path).with_context(|| format!("reading JWT secret {path}"))?;
+    let secret = secret.trim_ascii_end();
+    anyhow::ensure!(!secret.is_empty(), "JWT secret {path} is empty");
+    Ok(Self::new(secret))
+  }
+
+  fn verify(&self, token: &[u8]) -> Result<JwtClaims, AuthError> {
+    let malformed = |reason: &str| AuthError::MalformedToken(String::from(reason));
+
+    let token = std::str::from_utf8(token).map_err(|_| malformed("token is not UTF-8"))?;
+    let mut parts = token.split('.');
+    let (Some(header), Some(payload), Some(signature), None) =
+      (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+      return Err(malformed("token is not a JWT"));
+    };
+
+    let decode = |part: &str| {
+      URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|_| malformed("invalid base64url"))
+    };
+    let jwt_header: JwtHeader =
+      serde_json::from_slice(&decode(header)?).map_err(|_| malformed("invalid JWT header"))?;
+    if jwt_header.alg != "HS256" {
+      return Err(malformed("unsupported JWT algorithm"));
+    }
+
+    let signing_input = &token[..header.len() + 1 + payload.len()];
+    hmac::verify(&self.key, signing_input.as_bytes(), &decode(signature)?)
+      .map_err(|_| AuthError::Unauthorized(String::from("invalid token signature")))?;
+
+    let claims: JwtClaims =
+      serde_json::from_slice(&decode(payload)?).map_err(|_| malformed("invalid JWT claims"))?;
+
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap_or_default()
+      .as_secs();
+    if claims.exp.is_some_and(|exp| exp <= now) {
+      return Err(AuthError::ExpiredToken(String::from("token has expired")));
+    }
+    if claims.nbf.is_some_and(|nbf| nbf > now) {
+      return Err(AuthError::Unauthorized(String::from(
+        "token is not valid yet",
+      )));
+    }
+    Ok(claims)
+  }
+}
+
+impl Authorizer for JwtAuthorizer {
+  fn authorize(&self, request: &AuthRequest<'_>) -> Result<(), AuthError> {
+    let Some(scope) = request.scope() else {
+      return Ok(());
+    };
+    let token = request
+      .token
+      .ok_or_else(|| AuthError::Unauthorized(String::from("missing authorization token")))?;
+    self.verify(&token.value)?.grants.check(&scope)
+  }
+}
+
+#[derive(Debug, Deserialize)]
+struct AllowlistEntry {
+  token: String,
+  #[serde(flatten)]
+  grants: Grants,
+}
+
+/// Accepts requests whose token is listed in a JSON file, for local
+/// testing. Tokens are compared as plain strings.
+///
+/// # Example
+/// ```
+/// # use bytes::Bytes;
+/// # use moqtail::model::common::tuple::Tuple;
+/// # use moqtail::model::control::announce::Announce;
+/// # use moqtail::model::control::control_message::ControlMessage;
+/// # use moqtail::model::parameter::auth_token::AuthToken;
+/// # use moqtail::relay::auth::{AuthRequest, StaticAllowlist};
+/// # use moqtail::relay::hooks::Authorizer;
+/// // or StaticAllowlist::from_file("allowlist.json")
+/// let allowlist = StaticAllowlist::from_json(br#"[
+///   {"token": "alice", "publish": [{"namespace": "live"}]},
+///   {"token": "bob", "subscribe": [{"namespace": "live"}]}
+/// ]"#).unwrap();
+///
+/// let announce = ControlMessage::Announce(Box::new(Announce::new(0, Tuple::from_utf8_path("live/room"), &[])));
+/// let alice = AuthToken { token_type: 0, value: Bytes::from_static(b"alice") };
+/// let request = AuthRequest { connection_id: 1, message: &announce, token: Some(&alice) };
+/// assert!(allowlist.authorize(&request).is_ok());
+/// let bob = AuthToken { token_type: 0, value: Bytes::from_static(b"bob") };
+/// let request = AuthRequest { connection_id: 1, message: &announce, token: Some(&bob) };
+/// assert!(allowlist.authorize(&request).is_err());
+/// ```
+#[derive(Debug, Default)]
+pub struct StaticAllowlist {
+  tokens: BTreeMap<Bytes, Grants>,
+}
+
+impl StaticAllowlist {
+  pub fn from_file(path: &str) -> Result<Self> {
+    let json = std::fs::read(path).with_context(|| format!("reading allowlist {path}"))?;
+    Self::from_json(&json).with_context(|| format!("parsing allowlist {path}"))
+  }
+
+  pub fn from_json(json: &[u8]) -> Result<Self> {
+    let entries: Vec<AllowlistEntry> = serde_json::from_slice(json)?;
+    let mut allowlist = Self::default();
+    for entry in entries {
+      allowlist.allow(entry.token.as_bytes(), entry.grants);
+    }
+    Ok(allowlist)
+  }
+
+  pub fn allow(&mut self, token: &[u8], grants: Grants) {
+    self.tokens.insert(Bytes::copy_from_slice(token), grants);
+  }
+}
+
+impl Authorizer for StaticAllowlist {
+  fn authorize(&self, request: &AuthRequest<'_>) -> Result<(), AuthError> {
+    let Some(scope) = request.scope() else {
+      return Ok(());
+    };
+    let token = request
+      .token
+      .ok_or_else(|| AuthError::Unauthorized(String::from("missing authorization token")))?;
+    self
+      .tokens
+      .get(&token.value)
+      .ok_or_else(|| AuthError::Unauthorized(String::from("unknown token")))?
+      .check(&scope)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::control::announce::Announce;
+  use crate::model::control::constant::GroupOrder;
+  use crate::model::control::subscribe::Subscribe;
+
+  const SECRET: &[u8] = b"relay-secret";
+
+  fn sign(secret: &[u8], claims: &str) -> Bytes {
+    let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#);
+    let payload = URL_SAFE_NO_PAD.encode(claims);
+    let signing_input = format!("{header}.{payload}");
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    let signature = URL_SAFE_NO_PAD.encode(hmac::sign(&key, signing_input.as_bytes()));
+    Bytes::from(format!("{signing_input}.{signature}"))
+  }
+
+  fn subscribe(namespace: &str, track: &str) -> ControlMessage {
+    ControlMessage::Subscribe(Box::new(Subscribe::new_latest_object(
+      0,
+      0,
+      Tuple::from_utf8_path(namespace),
+      track.to_string(),
+      1,
+      GroupOrder::Ascending,
+      true,
+      vec![],
+    )))
+  }
+
+  fn announce(namespace: &str) -> ControlMessage {
+    ControlMessage::Announce(Box::new(Announce::new(
+      0,
+      Tuple::from_utf8_path(namespace),
+      &[],
+    )))
+  }
+
+  fn authorize(
+    authorizer: &dyn Authorizer,
+    message: &ControlMessage,
+    token: Option<Bytes>,
+  ) -> Result<(), AuthError> {
+    let token = token.map(|value| AuthToken {
+      token_type: 0,
+      value,
+    });
+    authorizer.authorize(&AuthRequest {
+      connection_id: 1,
+      message,
+      token: token.as_ref(),
+    })
+  }
+
+  #[test]
+  fn test_jwt_scopes() {
+    let authorizer = JwtAuthorizer::new(SECRET);
+    let token = sign(
+      SECRET,
+      r#"{"exp": 4102444800, "publish": [{"namespace": "live/room"}],
+          "subscribe": [{"namespace": "live", "track": "video"}]}"#,
+    );
+
+    assert!(authorize(&authorizer, &announce("live/room"), Some(token.clone())).is_ok());
+    assert!(authorize(&authorizer, &announce("live/room/cam"), Some(token.clone())).is_ok());
+    assert!(
+      authorize(
+        &authorizer,
+        &subscribe("live/room", "video"),
+        Some(token.clone())
+      )
+      .is_ok()
+    );
+
+    assert!(matches!(
+      authorize(&authorizer, &announce("live"), Some(token.clone())),
+      Err(AuthError::Unauthorized(_))
+    ));
+    assert!(matches!(
+      authorize(&authorizer, &subscribe("live/room", "audio"), Some(token)),
+      Err(AuthError::Unauthorized(_))
+    ));
+    assert!(matches!(
+      authorize(&authorizer, &announce("live/room"), None),
+      Err(AuthError::Unauthorized(_))
+    ));
+  }
+
+  #[test]
+  fn test_jwt_rejects_bad_tokens() {
+    let authorizer = JwtAuthorizer::new(SECRET);
+    let message = announce("live");
+
+    let expired = sign(SECRET, r#"{"exp": 1, "publish": [{"namespace": "live"}]}"#);
+    assert!(matches!(
+      authorize(&authorizer, &message, Some(expired)),
+      Err(AuthError::ExpiredToken(_))
+    ));
+
+    let forged = sign(b"other-secret", r#"{"publish": [{"namespace": "live"}]}"#);
+    assert!(matches!(
+      authorize(&authorizer, &message, Some(forged)),
+      Err(AuthError::Unauthorized(_))
+    ));
+
+    assert!(matches!(
+      authorize(
+        &authorizer,
+        &message,
+        Some(Bytes::from_static(b"not-a-jwt"))
+      ),
+      Err(AuthError::MalformedToken(_))
+    ));
+  }
+
+  #[test]
+  fn test_non_requests_are_allowed() {
+    let authorizer = JwtAuthorizer::new(SECRET);
+    let message = ControlMessage::Unsubscribe(Box::new(
+      crate::model::control::unsubscribe::Unsubscribe::new(0),
+    ));
+    assert!(authorize(&authorizer, &message, None).is_ok());
+  }
+
+  #[test]
+  fn test_static_allowlist() {
+    let allowlist = StaticAllowlist::from_json(
+      br#"[{"token": "alice", "publish": [{"namespace": "live"}]},
+           {"token": "bob", "subscribe": [{"namespace": "live"}]}]"#,
+    )
+    .unwrap();
+
+    let alice = Some(Bytes::from_static(b"alice"));
+    let bob = Some(Bytes::from_static(b"bob"));
+    assert!(authorize(&allowlist, &announce("live/room"), alice.clone()).is_ok());
+    assert!(authorize(&allowlist, &subscribe("live/room", "video"), bob.clone()).is_ok());
+    assert!(authorize(&allowlist, &subscribe("live/room", "video"), alice).is_err());
+    assert!(authorize(&allowlist, &announce("live/room"), bob).is_err());
+    assert!(
+      authorize(
+        &allowlist,
+        &announce("live/room"),
+        Some(Bytes::from_static(b"eve"))
+      )
+      .is_err()
+    );
+  }
+
+  #[test]
+  fn test_token_parameters() {
+    let kvp = |param: VersionParameter| -> KeyValuePair { param.try_into().unwrap() };
+    let use_alias = VersionParameter::new_auth_token_use_alias(3);
+    let message = ControlMessage::Announce(Box::new(Announce::new(
+      0,
+      Tuple::from_utf8_path("live"),
+      &[
+        kvp(VersionParameter::new_delivery_timeout(10)),
+        kvp(use_alias.clone()),
+      ],
+    )));
+    assert_eq!(token_parameters(&message).unwrap(), vec![use_alias]);
+
+    let malformed = KeyValuePair::try_new_bytes(
+      VersionSpecificParameterType::AuthorizationToken as u64,
+      Bytes::from_static(&[0x09]),
+    )
+    .unwrap();
+    let message = ControlMessage::Announce(Box::new(Announce::new(
+      0,
+      Tuple::from_utf8_path("live"),
+      &[malformed],
+    )));
+    assert!(matches!(
+      token_parameters(&message),
+      Err(AuthError::MalformedToken(_))
+    ));
+  }
+}
@@ -0,0 +1,99 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use super::Relay;
+use super::config::RelayConfig;
+use super::hooks::{
+  AllowAll, Authorizer, CacheStoreFactory, FirstMatchRouter, NamespaceRouter, RelayHooks,
+};
+use super::track_cache::MokaCacheStoreFactory;
+use crate::model::version::{VersionCodec, VersionRegistry};
+
+/// Builds a [`Relay`] with optional hooks. Hooks that are not set fall back
+/// to [`AllowAll`], [`FirstMatchRouter`] and [`MokaCacheStoreFactory`].
+/// The relay speaks draft-11 and the versions added with `with_version`.
+///
+/// # Example
+/// ```no_run
+/// # use moqtail::relay::RelayBuilder;
+/// # use moqtail::relay::auth::StaticAllowlist;
+/// # use moqtail::relay::config::RelayConfig;
+/// # async fn run() -> anyhow::Result<()> {
+/// let mut relay = RelayBuilder::new(RelayConfig::default())
+///   .with_authorizer(StaticAllowlist::from_file("allowlist.json")?)
+///   .build();
+/// relay.start().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct RelayBuilder {
+  config: RelayConfig,
+  authorizer: Option<Arc<dyn Authorizer>>,
+  namespace_router: Option<Arc<dyn NamespaceRouter>>,
+  cache_store_factory: Option<Arc<dyn CacheStoreFactory>>,
+  versions: VersionRegistry,
+}
+
+impl RelayBuilder {
+  pub fn new(config: RelayConfig) -> Self {
+    Self {
+      config,
+      authorizer: None,
+      namespace_router: None,
+      cache_store_factory: None,
+      versions: VersionRegistry::default(),
+    }
+  }
+
+  pub fn with_authorizer(mut self, authorizer: impl Authorizer + 'static) -> Self {
+    self.authorizer = Some(Arc::new(authorizer));
+    self
+  }
+
+  pub fn with_namespace_router(mut self, namespace_router: impl NamespaceRouter + 'static) -> Self {
+    self.namespace_router = Some(Arc::new(namespace_router));
+    self
+  }
+
+  pub fn with_cache_store_factory(
+    mut self,
+    cache_store_factory: impl CacheStoreFactory + 'static,
+  ) -> Self {
+    self.cache_store_factory = Some(Arc::new(cache_store_factory));
+    self
+  }
+
+  /// Adds a version the relay negotiates with clients that offer it
+  pub fn with_version(mut self, codec: impl VersionCodec + 'static) -> Self {
+    self.versions.register(Arc::new(codec));
+    self
+  }
+
+  pub fn build(self) -> Relay {
+    let config = Arc::new(self.config);
+    let hooks = RelayHooks {
+      authorizer: self.authorizer.unwrap_or_else(|| Arc::new(AllowAll)),
+      namespace_router: self
+        .namespace_router
+        .unwrap_or_else(|| Arc::new(FirstMatchRouter)),
+      cache_store_factory: self
+        .cache_store_factory
+        .unwrap_or_else(|| Arc::new(MokaCacheStoreFactory::new(config.clone()))),
+      versions: Arc::new(self.versions),
+    };
+    Relay::new(config, hooks)
+  }
+}
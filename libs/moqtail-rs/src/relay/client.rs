@@ -12,20 +12,31 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::server::{
+use crate::relay::{
+  request_credit::RequestCredit,
   stream_id::{StreamId, StreamType},
   utils,
 };
-use anyhow::Result;
-#[allow(dead_code)]
-use bytes::Bytes;
-use moqtail::{
+use crate::{
   model::{
     common::tuple::Tuple,
-    control::{client_setup::ClientSetup, control_message::ControlMessage},
+    control::{
+      client_setup::ClientSetup, control_message::ControlMessage, max_request_id::MaxRequestId,
+      requests_blocked::RequestsBlocked,
+    },
+    data::constant::StreamResetCode,
+    parameter::{auth_token::AuthTokenCache, setup_parameter::SetupParameter},
+    version::VersionCodec,
+  },
+  transport::{
+    data_stream_handler::{FetchRequest, SubscribeRequest},
+    datagram_handler::DatagramHandler,
+    moq_transport::{BoxSendStream, MoqTransport, TransportError},
   },
-  transport::data_stream_handler::{FetchRequest, SubscribeRequest},
 };
+use anyhow::Result;
+#[allow(dead_code)]
+use bytes::Bytes;
 
 use std::{
   collections::{BTreeMap, HashMap, VecDeque},
@@ -34,7 +45,6 @@ use std::{
 use tokio::sync::Notify;
 use tokio::sync::{Mutex, RwLock};
 use tracing::{debug, error, info, warn};
-use wtransport::{Connection, SendStream, error::StreamWriteError};
 
 /// Number of partitions for send stream management to reduce lock contention.
 /// Each partition contains a separate HashMap protected by its own RwLock.
@@ -42,18 +52,22 @@ use wtransport::{Connection, SendStream, error::StreamWriteError};
 /// Should be a power of 2 for optimal modulo performance.
 pub const SEND_STREAM_PARTITION_COUNT: usize = 16;
 
-pub type SendStreamMap = HashMap<String, Arc<Mutex<SendStream>>>;
+pub type SendStreamMap = HashMap<String, Arc<Mutex<BoxSendStream>>>;
 pub type SendStreamLock = Arc<RwLock<SendStreamMap>>;
 pub type SendStreamList = Vec<SendStreamLock>;
 
 #[derive(Debug, Clone)]
 pub(crate) struct MOQTClient {
   pub connection_id: usize,
-  pub connection: Arc<Connection>,
+  pub connection: Arc<dyn MoqTransport>,
+  pub datagram_handler: DatagramHandler,
+  // the wire format of the version negotiated with the client
+  pub codec: Arc<dyn VersionCodec>,
   #[allow(dead_code)]
   pub client_setup: Arc<ClientSetup>,
   pub announced_track_namespaces: Arc<RwLock<Vec<Tuple>>>, // the track namespaces the publisher announced
-  pub published_tracks: Arc<RwLock<Vec<u64>>>,             // the tracks the client is publishing
+  pub announce_subscriptions: Arc<RwLock<Vec<Tuple>>>, // the namespace prefixes from SUBSCRIBE_ANNOUNCES
+  pub published_tracks: Arc<RwLock<Vec<u64>>>,         // the tracks the client is publishing
   pub subscribers: Arc<RwLock<Vec<usize>>>, // the subscribers the client is subscribed to
 
   pub message_queue: Arc<RwLock<VecDeque<ControlMessage>>>, // the control messages the client has sent
@@ -66,24 +80,54 @@ pub(crate) struct MOQTClient {
   // this contains the requests made by the client and the corresponding request.
   // The key value is the original request id.
   pub subscribe_requests: Arc<RwLock<BTreeMap<u64, SubscribeRequest>>>,
+
+  // the request ids the relay may use towards the client, exclusive
+  pub peer_max_request_id: Arc<RwLock<u64>>,
+
+  // the next request id the relay sends to the client, odd as the relay is the server
+  pub next_request_id: Arc<Mutex<u64>>,
+
+  // the request ids the client may use towards the relay
+  pub request_credit: Arc<Mutex<RequestCredit>>,
+
+  // the auth tokens the client registered, bounded by the size in SERVER_SETUP
+  pub auth_tokens: Arc<Mutex<AuthTokenCache>>,
 }
 
 impl MOQTClient {
   pub(crate) fn new(
     connection_id: usize,
-    connection: Arc<Connection>,
+    connection: Arc<dyn MoqTransport>,
+    codec: Arc<dyn VersionCodec>,
     client_setup: Arc<ClientSetup>,
+    auth_token_cache_size: u64,
+    request_credit: RequestCredit,
   ) -> Self {
     let mut send_streams = Vec::with_capacity(SEND_STREAM_PARTITION_COUNT);
     for _ in 0..SEND_STREAM_PARTITION_COUNT {
       send_streams.push(Arc::new(RwLock::new(HashMap::new())));
     }
 
+    // a client that does not advertise MAX_REQUEST_ID is not held to a limit,
+    // the draft default of 0 would cut off publishers that omit the parameter
+    let peer_max_request_id = client_setup
+      .setup_parameters
+      .iter()
+      .filter_map(|kvp| SetupParameter::deserialize(kvp).ok())
+      .find_map(|param| match param {
+        SetupParameter::MaxRequestId { request_id } => Some(request_id),
+        _ => None,
+      })
+      .unwrap_or(u64::MAX);
+
     MOQTClient {
       connection_id,
+      datagram_handler: DatagramHandler::new(connection.clone()),
       connection,
+      codec,
       client_setup,
       announced_track_namespaces: Arc::new(RwLock::new(Vec::new())),
+      announce_subscriptions: Arc::new(RwLock::new(Vec::new())),
       published_tracks: Arc::new(RwLock::new(Vec::new())),
       subscribers: Arc::new(RwLock::new(Vec::new())),
       message_queue: Arc::new(RwLock::new(VecDeque::new())),
@@ -91,14 +135,130 @@ impl MOQTClient {
       send_streams: Arc::new(send_streams),
       fetch_requests: Arc::new(RwLock::new(BTreeMap::new())),
       subscribe_requests: Arc::new(RwLock::new(BTreeMap::new())),
+      peer_max_request_id: Arc::new(RwLock::new(peer_max_request_id)),
+      next_request_id: Arc::new(Mutex::new(1)),
+      request_credit: Arc::new(Mutex::new(request_credit)),
+      auth_tokens: Arc::new(Mutex::new(AuthTokenCache::new(auth_token_cache_size))),
+    }
+  }
+
+  /// Raises the limit for requests sent to the client. Returns false if the
+  /// client tried to lower it.
+  pub(crate) async fn update_peer_max_request_id(&self, request_id: u64) -> bool {
+    let mut peer_max_request_id = self.peer_max_request_id.write().await;
+    if request_id <= *peer_max_request_id {
+      return false;
+    }
+    *peer_max_request_id = request_id;
+    true
+  }
+
+  /// Allocates the request id of a request the relay is about to send to the
+  /// client. Returns None and reports REQUESTS_BLOCKED when the client's
+  /// MAX_REQUEST_ID leaves no credit, without using up the id.
+  pub(crate) async fn next_request_id(&self) -> Option<u64> {
+    let mut next_request_id = self.next_request_id.lock().await;
+    let peer_max_request_id = *self.peer_max_request_id.read().await;
+    if *next_request_id < peer_max_request_id {
+      let request_id = *next_request_id;
+      *next_request_id += 2;
+      return Some(request_id);
+    }
+    warn!(
+      "requests to client {} blocked at request id {}, max request id is {}",
+      self.connection_id, *next_request_id, peer_max_request_id
+    );
+    drop(next_request_id);
+    self
+      .queue_message(ControlMessage::RequestsBlocked(Box::new(RequestsBlocked {
+        maximum_request_id: peer_max_request_id,
+      })))
+      .await;
+    None
+  }
+
+  /// The request ids the client may use, exclusive
+  pub(crate) async fn max_request_id(&self) -> u64 {
+    self.request_credit.lock().await.max_request_id()
+  }
+
+  /// Records a new request of the client, which holds on to its request id
+  /// until it completes
+  pub(crate) async fn open_request(&self, request_id: u64) {
+    self.request_credit.lock().await.open(request_id);
+  }
+
+  /// Frees the request id of a completed request and issues MAX_REQUEST_ID
+  /// once enough ids were freed
+  pub(crate) async fn complete_request(&self, request_id: u64) {
+    let new_max = self.request_credit.lock().await.complete(request_id);
+    if let Some(new_max) = new_max {
+      self.send_max_request_id(new_max).await;
+    }
+  }
+
+  /// Answers REQUESTS_BLOCKED with the freed request ids unless a
+  /// MAX_REQUEST_ID is already on the way
+  pub(crate) async fn unblock_requests(&self, blocked_at: u64) {
+    let new_max = self.request_credit.lock().await.blocked(blocked_at);
+    if let Some(new_max) = new_max {
+      self.send_max_request_id(new_max).await;
     }
   }
 
+  async fn send_max_request_id(&self, request_id: u64) {
+    debug!(
+      "granting client {} request ids up to {}",
+      self.connection_id, request_id
+    );
+    self
+      .queue_message(ControlMessage::MaxRequestId(Box::new(MaxRequestId {
+        request_id,
+      })))
+      .await;
+  }
+
   pub(crate) async fn add_announced_track_namespace(&self, track_namespace: Tuple) {
     let mut announced_track_namespaces = self.announced_track_namespaces.write().await;
     announced_track_namespaces.push(track_namespace);
   }
 
+  pub(crate) async fn remove_announced_track_namespace(&self, track_namespace: &Tuple) -> bool {
+    let mut announced_track_namespaces = self.announced_track_namespaces.write().await;
+    let len = announced_track_namespaces.len();
+    announced_track_namespaces.retain(|ns| ns != track_namespace);
+    announced_track_namespaces.len() != len
+  }
+
+  /// Registers a SUBSCRIBE_ANNOUNCES prefix. Returns false if it overlaps
+  /// with a prefix the client already subscribed to.
+  pub(crate) async fn add_announce_subscription(&self, track_namespace_prefix: Tuple) -> bool {
+    let mut announce_subscriptions = self.announce_subscriptions.write().await;
+    let overlaps = announce_subscriptions.iter().any(|prefix| {
+      prefix.starts_with(&track_namespace_prefix) || track_namespace_prefix.starts_with(prefix)
+    });
+    if overlaps {
+      return false;
+    }
+    announce_subscriptions.push(track_namespace_prefix);
+    true
+  }
+
+  pub(crate) async fn remove_announce_subscription(&self, track_namespace_prefix: &Tuple) -> bool {
+    let mut announce_subscriptions = self.announce_subscriptions.write().await;
+    let len = announce_subscriptions.len();
+    announce_subscriptions.retain(|prefix| prefix != track_namespace_prefix);
+    announce_subscriptions.len() != len
+  }
+
+  /// Checks whether the client subscribed to announcements of the given namespace
+  pub(crate) async fn is_subscribed_to_announces(&self, track_namespace: &Tuple) -> bool {
+    let announce_subscriptions = self.announce_subscriptions.read().await;
+    announce_subscriptions
+      .iter()
+      .any(|prefix| track_namespace.starts_with(prefix))
+  }
+
   pub(crate) async fn add_subscriber(&self, subscriber_id: usize) {
     let mut subscribers = self.subscribers.write().await;
     subscribers.push(subscriber_id);
@@ -109,6 +269,11 @@ impl MOQTClient {
     published_tracks.push(track_alias);
   }
 
+  pub(crate) async fn remove_published_track(&self, track_alias: u64) {
+    let mut published_tracks = self.published_tracks.write().await;
+    published_tracks.retain(|alias| *alias != track_alias);
+  }
+
   pub(crate) async fn get_published_tracks(&self) -> Vec<u64> {
     let published_tracks = self.published_tracks.read().await;
     published_tracks.clone()
@@ -165,7 +330,7 @@ impl MOQTClient {
   fn get_stream_map(
     &self,
     stream_id: &StreamId,
-  ) -> Arc<RwLock<HashMap<String, Arc<Mutex<SendStream>>>>> {
+  ) -> Arc<RwLock<HashMap<String, Arc<Mutex<BoxSendStream>>>>> {
     let partition_index = self.get_partition_index(stream_id);
     debug!(
       "get_stream_map | stream_id: {} partition_index: {}",
@@ -174,7 +339,7 @@ impl MOQTClient {
     self.send_streams[partition_index].clone()
   }
 
-  pub async fn get_stream(&self, stream_id: &StreamId) -> Option<Arc<Mutex<SendStream>>> {
+  pub async fn get_stream(&self, stream_id: &StreamId) -> Option<Arc<Mutex<BoxSendStream>>> {
     let send_stream_map = self.get_stream_map(stream_id);
     let send_streams = send_stream_map.read().await;
     let send_stream = send_streams.get(stream_id.get_stream_id().as_str());
@@ -186,21 +351,17 @@ impl MOQTClient {
     stream_id: &StreamId,
     header_payload: Bytes,
     priority: i32, // Priority for the stream
-  ) -> Result<Arc<Mutex<SendStream>>> {
+  ) -> Result<Arc<Mutex<BoxSendStream>>> {
     let send_stream = {
       let send_stream_map = self.get_stream_map(stream_id);
       let mut send_streams = send_stream_map.write().await;
       match send_streams.entry(stream_id.get_stream_id().to_string()) {
         std::collections::hash_map::Entry::Vacant(entry) => {
-          let result = self
+          let send_stream = self
             .connection
             .open_uni()
             .await
-            .map_err(|e| anyhow::anyhow!("Failed to open send stream 1: {:?}", e))?;
-
-          let send_stream = result
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to open send stream 2: {:?}", e))?;
+            .map_err(|e| anyhow::anyhow!("Failed to open send stream: {:?}", e))?;
 
           send_stream.set_priority(priority);
           let s = Arc::new(Mutex::new(send_stream));
@@ -277,12 +438,31 @@ impl MOQTClient {
     }
   }
 
+  // Remove the stream from the map and reset it, dropping unsent data
+  pub async fn reset_stream(&self, stream_id: &StreamId, code: StreamResetCode) -> bool {
+    let Some(send_stream) = self.remove_stream_by_stream_id(stream_id).await else {
+      return false;
+    };
+    if let Err(e) = send_stream.lock().await.reset(u64::from(code) as u32) {
+      debug!(
+        "reset_stream | Send stream ({}) already closed: {:?} connection_id: {}",
+        stream_id, e, self.connection_id
+      );
+    } else {
+      info!(
+        "reset_stream | Reset send stream ({}) code: {:?} connection_id: {}",
+        stream_id, code, self.connection_id
+      );
+    }
+    true
+  }
+
   // Just remove the stream from the stream_map
   // The caller finishes the stream and calls this to remove it from the map
   pub async fn remove_stream_by_stream_id(
     &self,
     stream_id: &StreamId,
-  ) -> Option<Arc<Mutex<SendStream>>> {
+  ) -> Option<Arc<Mutex<BoxSendStream>>> {
     let send_stream_map = self.get_stream_map(stream_id);
     let mut send_streams = send_stream_map.write().await;
     send_streams.remove(stream_id.get_stream_id().as_str())
@@ -291,7 +471,7 @@ impl MOQTClient {
   async fn finish_stream(
     &self,
     stream_id: &StreamId,
-    send_stream: Arc<Mutex<SendStream>>,
+    send_stream: Arc<Mutex<BoxSendStream>>,
   ) -> Result<()> {
     let mut stream = send_stream.lock().await;
 
@@ -315,7 +495,7 @@ impl MOQTClient {
     stream_id: &StreamId,
     object_id: u64,
     object: Bytes,
-    the_stream: Option<Arc<Mutex<SendStream>>>,
+    the_stream: Option<Arc<Mutex<BoxSendStream>>>,
   ) -> Result<(), anyhow::Error> {
     debug!(
       "write_object_to_stream | Writing object to stream ({} - {}) connection_id: {}",
@@ -342,7 +522,7 @@ impl MOQTClient {
         Ok(..) => {}
         Err(e) => {
           match &e {
-            StreamWriteError::Closed | StreamWriteError::Stopped(_) => {
+            TransportError::StreamClosed | TransportError::Stopped(_) => {
               warn!(
                 "write_object_to_stream | Send stream is closed or stopped ({})",
                 stream_id.get_stream_id()
@@ -373,7 +553,7 @@ impl MOQTClient {
 #[cfg(test)]
 mod tests {
   use super::*;
-  use crate::server::stream_id::{StreamId, StreamType};
+  use crate::relay::stream_id::{StreamId, StreamType};
 
   /// Test helper struct that exposes the partition logic for testing
   struct PartitionTester;
@@ -0,0 +1,195 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::client::MOQTClient;
+use super::hooks::NamespaceRouter;
+use crate::model::common::tuple::Tuple;
+use crate::model::control::announce::Announce;
+use crate::model::control::control_message::ControlMessage;
+use crate::model::control::unannounce::Unannounce;
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::sync::RwLock;
+use tracing::{debug, info};
+
+pub(crate) struct ClientManager {
+  pub clients: Arc<RwLock<BTreeMap<usize, Arc<MOQTClient>>>>,
+  namespace_router: Arc<dyn NamespaceRouter>,
+}
+
+impl ClientManager {
+  pub(crate) fn new(namespace_router: Arc<dyn NamespaceRouter>) -> Self {
+    ClientManager {
+      clients: Arc::new(RwLock::new(BTreeMap::new())),
+      namespace_router,
+    }
+  }
+
+  pub(crate) async fn add(&mut self, client: Arc<MOQTClient>) {
+    let connection_id = client.connection_id;
+    let mut clients = self.clients.write().await;
+    clients.insert(connection_id, client);
+    info!("Added client connection_id: {}", connection_id);
+  }
+
+  pub(crate) async fn remove(&mut self, connection_id: usize) {
+    let mut clients = self.clients.write().await;
+    clients.remove(&connection_id);
+  }
+
+  pub(crate) async fn get(&self, connection_id: usize) -> Option<Arc<MOQTClient>> {
+    let clients = self.clients.read().await;
+    clients.get(&connection_id).cloned()
+  }
+
+  // The same namespace is only announced by several publishers under the
+  // multi-publisher conflict policy, in which case the namespace router
+  // picks one of them.
+  pub(crate) async fn get_publisher_by_announced_track_namespace(
+    &self,
+    track_namespace: &Tuple,
+  ) -> Option<Arc<MOQTClient>> {
+    let clients = self.clients.read().await;
+    let mut candidates = Vec::new();
+    for client_ref in clients.iter() {
+      debug!("checking client: {:?}", client_ref.0);
+      let client = client_ref.1;
+
+      debug!(
+        "client announced track namespaces: {:?} track namespace: {:?}",
+        client.announced_track_namespaces, track_namespace
+      );
+      let announced_track_namespaces = client.announced_track_namespaces.read().await;
+
+      for announced_track_namespace in announced_track_namespaces.iter() {
+        // Check if track_namespace is equal to or a child of announced_track_namespace
+        if track_namespace.starts_with(announced_track_namespace) {
+          candidates.push((*client_ref.0, announced_track_namespace.clone()));
+        }
+      }
+    }
+
+    let connection_id = self.namespace_router.route(track_namespace, &candidates)?;
+    clients.get(&connection_id).cloned()
+  }
+
+  /// Returns the namespaces announced by clients other than `except` that
+  /// overlap with `track_namespace`, i.e. are equal to it, a prefix of it or
+  /// fall under it, with the client that announced each
+  pub(crate) async fn get_overlapping_announcements(
+    &self,
+    track_namespace: &Tuple,
+    except: usize,
+  ) -> Vec<(Arc<MOQTClient>, Tuple)> {
+    let clients = self.clients.read().await;
+    let mut announcements = Vec::new();
+    for (connection_id, client) in clients.iter() {
+      if *connection_id == except {
+        continue;
+      }
+      let announced_track_namespaces = client.announced_track_namespaces.read().await;
+      for announced_track_namespace in announced_track_namespaces.iter() {
+        if announced_track_namespace.starts_with(track_namespace)
+          || track_namespace.starts_with(announced_track_namespace)
+        {
+          announcements.push((client.clone(), announced_track_namespace.clone()));
+        }
+      }
+    }
+    announcements
+  }
+
+  /// Returns every namespace announced by a client other than `except`
+  /// that falls under `track_namespace_prefix`
+  pub(crate) async fn get_announced_track_namespaces(
+    &self,
+    track_namespace_prefix: &Tuple,
+    except: usize,
+  ) -> Vec<Tuple> {
+    let clients = self.clients.read().await;
+    let mut track_namespaces: Vec<Tuple> = Vec::new();
+    for (connection_id, client) in clients.iter() {
+      if *connection_id == except {
+        continue;
+      }
+      let announced_track_namespaces = client.announced_track_namespaces.read().await;
+      for announced_track_namespace in announced_track_namespaces.iter() {
+        if announced_track_namespace.starts_with(track_namespace_prefix)
+          && !track_namespaces.contains(announced_track_namespace)
+        {
+          track_namespaces.push(announced_track_namespace.clone());
+        }
+      }
+    }
+    track_namespaces
+  }
+
+  /// Forwards ANNOUNCE to every client whose SUBSCRIBE_ANNOUNCES prefix
+  /// matches the namespace, except the publisher itself
+  pub(crate) async fn forward_announce(&self, publisher_id: usize, track_namespace: &Tuple) {
+    let clients = self.clients.read().await;
+    for (connection_id, client) in clients.iter() {
+      if *connection_id == publisher_id || !client.is_subscribed_to_announces(track_namespace).await
+      {
+        continue;
+      }
+      let Some(request_id) = client.next_request_id().await else {
+        continue;
+      };
+      info!(
+        "forwarding announce of {:?} to client {}",
+        track_namespace, connection_id
+      );
+      let announce = Announce::new(request_id, track_namespace.clone(), &[]);
+      client
+        .queue_message(ControlMessage::Announce(Box::new(announce)))
+        .await;
+    }
+  }
+
+  /// Forwards UNANNOUNCE to every client whose SUBSCRIBE_ANNOUNCES prefix
+  /// matches the namespace, unless another publisher still announces it
+  pub(crate) async fn forward_unannounce(&self, publisher_id: usize, track_namespace: &Tuple) {
+    let clients = self.clients.read().await;
+    for (connection_id, client) in clients.iter() {
+      if *connection_id != publisher_id
+        && client
+          .announced_track_namespaces
+          .read()
+          .await
+          .contains(track_namespace)
+      {
+        debug!(
+          "namespace {:?} is still announced by client {}",
+          track_namespace, connection_id
+        );
+        return;
+      }
+    }
+
+    for (connection_id, client) in clients.iter() {
+      if *connection_id == publisher_id || !client.is_subscribed_to_announces(track_namespace).await
+      {
+        continue;
+      }
+      info!(
+        "forwarding unannounce of {:?} to client {}",
+        track_namespace, connection_id
+      );
+      let unannounce = Unannounce::new(track_namespace.clone());
+      client
+        .queue_message(ControlMessage::Unannounce(Box::new(unannounce)))
+        .await;
+    }
+  }
+}
@@ -13,14 +13,15 @@
 // limitations under the License.
 
 use anyhow::Result;
-use clap::{Parser, ValueEnum};
-use std::sync::OnceLock;
+use std::net::{Ipv6Addr, SocketAddr};
 use std::time::Duration;
 use tracing::error;
-use wtransport::{Identity, ServerConfig};
+use wtransport::{Identity, ServerConfig, quinn};
+
+use crate::transport::quic;
 
 /// Cache expiration strategy
-#[derive(Debug, Clone, Copy, ValueEnum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CacheExpirationType {
   /// Time-to-live: entries expire after a fixed duration from creation
   Ttl,
@@ -28,82 +29,68 @@ pub enum CacheExpirationType {
   Tti,
 }
 
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-pub struct Cli {
-  /// Port to bind
-  #[arg(long, default_value_t = 4433)]
-  pub port: u16,
-  /// Host to bind
-  #[arg(long, default_value = "localhost")]
-  pub host: String,
-  /// Certificate PEM file
-  #[arg(long, default_value = "apps/relay/cert/cert.pem")]
-  pub cert_file: String,
-  /// Private key PEM file
-  #[arg(long, default_value = "apps/relay/cert/key.pem")]
-  pub key_file: String,
-  /// Number of cached subgroups/fetches per track
-  #[arg(long, default_value_t = 1000)]
-  pub cache_size: u16,
-  /// Cache grow ratio before evicting - allows cache to grow to this multiple of cache_size before evicting
-  #[arg(long, default_value_t = 7)]
-  pub max_idle_timeout: u64,
-  #[arg(long, default_value_t = 3)]
-  pub keep_alive_interval: u64,
-  #[arg(long, default_value = "/tmp")]
-  pub log_folder: String,
-  /// Cache expiration type (ttl or tti)
-  #[arg(long, value_enum, default_value = "ttl")]
-  pub cache_expiration_type: CacheExpirationType,
-  /// Cache expiration duration in minutes
-  #[arg(long, default_value_t = 30)]
-  pub cache_expiration_minutes: u64,
-  /// Enable object logging
-  #[arg(long, default_value_t = false)]
-  pub enable_object_logging: bool,
-  /// Initial maximum request ID
-  #[arg(long, default_value_t = u64::MAX / 8)]
-  pub initial_max_request_id: u64,
+/// What the relay does when a publisher announces a namespace that overlaps
+/// with one another publisher has already announced. Namespaces overlap when
+/// one is equal to or a prefix of the other, e.g. `live` and `live/room`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnounceConflictPolicy {
+  /// Keep the first publisher and reply ANNOUNCE_ERROR to the newcomer
+  Reject,
+  /// Accept the newcomer and send ANNOUNCE_CANCEL to the previous publishers
+  ReplaceLatest,
+  /// Accept every publisher and let the namespace router pick one per request
+  MultiPublisher,
 }
+
 #[derive(Debug, Clone)]
-pub struct AppConfig {
+pub struct RelayConfig {
   pub port: u16,
+  /// UDP port of the native QUIC (`moqt://`) listener, `None` disables it
+  pub quic_port: Option<u16>,
   pub host: String,
   pub cert_file: String,
   pub key_file: String,
   pub max_idle_timeout: u64,
   pub keep_alive_interval: u64,
+  /// Number of cached subgroups/fetches per track
   pub cache_size: u16,
   pub log_folder: String,
   pub cache_expiration_type: CacheExpirationType,
+  /// Cache expiration duration in minutes
   pub cache_expiration_minutes: u64,
   pub enable_object_logging: bool,
   pub initial_max_request_id: u64,
+  /// Request IDs of completed requests handed back with each MAX_REQUEST_ID, 0 keeps the initial limit
+  pub request_id_window: u64,
+  pub announce_conflict_policy: AnnounceConflictPolicy,
+  /// MAX_AUTH_TOKEN_CACHE_SIZE advertised to clients, in bytes per session
+  pub auth_token_cache_size: u64,
 }
 
-impl AppConfig {
-  pub fn load() -> &'static Self {
-    static INSTANCE: OnceLock<AppConfig> = OnceLock::new();
-    INSTANCE.get_or_init(|| {
-      let cli = Cli::parse();
-      AppConfig {
-        port: cli.port,
-        host: cli.host,
-        cert_file: cli.cert_file,
-        key_file: cli.key_file,
-        max_idle_timeout: cli.max_idle_timeout,
-        keep_alive_interval: cli.keep_alive_interval,
-        cache_size: cli.cache_size,
-        log_folder: cli.log_folder,
-        cache_expiration_type: cli.cache_expiration_type,
-        cache_expiration_minutes: cli.cache_expiration_minutes,
-        enable_object_logging: cli.enable_object_logging,
-        initial_max_request_id: cli.initial_max_request_id,
-      }
-    })
+impl Default for RelayConfig {
+  fn default() -> Self {
+    RelayConfig {
+      port: 4433,
+      quic_port: None,
+      host: "localhost".to_string(),
+      cert_file: "apps/relay/cert/cert.pem".to_string(),
+      key_file: "apps/relay/cert/key.pem".to_string(),
+      max_idle_timeout: 7,
+      keep_alive_interval: 3,
+      cache_size: 1000,
+      log_folder: "/tmp".to_string(),
+      cache_expiration_type: CacheExpirationType::Ttl,
+      cache_expiration_minutes: 30,
+      enable_object_logging: false,
+      initial_max_request_id: u64::MAX / 8,
+      request_id_window: 64,
+      announce_conflict_policy: AnnounceConflictPolicy::Reject,
+      auth_token_cache_size: 4096,
+    }
   }
+}
 
+impl RelayConfig {
   pub async fn build_server_config(&self) -> Result<ServerConfig> {
     let identity = match Identity::load_pemfiles(&self.cert_file, &self.key_file).await {
       Ok(identity) => identity,
@@ -124,19 +111,44 @@ impl AppConfig {
     Ok(config)
   }
 
+  /// Builds the native QUIC listener configuration, `None` when the
+  /// listener is disabled
+  pub async fn build_quic_server_config(
+    &self,
+  ) -> Result<Option<(SocketAddr, quinn::ServerConfig)>> {
+    let Some(quic_port) = self.quic_port else {
+      return Ok(None);
+    };
+
+    let identity = match Identity::load_pemfiles(&self.cert_file, &self.key_file).await {
+      Ok(identity) => identity,
+      Err(e) => {
+        error!("Failed to load identity from PEM files: {:?}", e);
+        return Err(e.into());
+      }
+    };
+
+    let config = quic::build_server_config(
+      identity,
+      Duration::from_secs(self.keep_alive_interval),
+      Duration::from_secs(self.max_idle_timeout),
+    )?;
+    let bind_address = SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), quic_port);
+
+    Ok(Some((bind_address, config)))
+  }
+
   /// Get cache expiration duration
   pub fn get_cache_expiration_duration(&self) -> Duration {
     Duration::from_secs(self.cache_expiration_minutes * 60)
   }
 
   /// Check if cache uses time-to-live expiration
-  #[allow(dead_code)]
   pub fn is_cache_ttl(&self) -> bool {
     matches!(self.cache_expiration_type, CacheExpirationType::Ttl)
   }
 
   /// Check if cache uses time-to-idle expiration
-  #[allow(dead_code)]
   pub fn is_cache_tti(&self) -> bool {
     matches!(self.cache_expiration_type, CacheExpirationType::Tti)
   }
@@ -148,37 +160,22 @@ mod tests {
 
   #[test]
   fn test_default_initial_max_request_id() {
-    // Test that the default value for initial_max_request_id is u64::MAX
-    let cli = Cli {
-      port: 4433,
-      host: "localhost".to_string(),
-      cert_file: "apps/relay/cert/cert.pem".to_string(),
-      key_file: "apps/relay/cert/key.pem".to_string(),
-      cache_size: 1000,
-      max_idle_timeout: 7,
-      keep_alive_interval: 3,
-      log_folder: "/tmp".to_string(),
-      cache_expiration_type: CacheExpirationType::Ttl,
-      cache_expiration_minutes: 30,
-      enable_object_logging: false,
-      initial_max_request_id: u64::MAX / 8,
-    };
+    let config = RelayConfig::default();
+    assert_eq!(config.initial_max_request_id, u64::MAX / 8);
+  }
 
-    let config = AppConfig {
-      port: cli.port,
-      host: cli.host,
-      cert_file: cli.cert_file,
-      key_file: cli.key_file,
-      max_idle_timeout: cli.max_idle_timeout,
-      keep_alive_interval: cli.keep_alive_interval,
-      cache_size: cli.cache_size,
-      log_folder: cli.log_folder,
-      cache_expiration_type: cli.cache_expiration_type,
-      cache_expiration_minutes: cli.cache_expiration_minutes,
-      enable_object_logging: cli.enable_object_logging,
-      initial_max_request_id: cli.initial_max_request_id,
-    };
+  #[test]
+  fn test_default_quic_listener_disabled() {
+    let config = RelayConfig::default();
+    assert_eq!(config.quic_port, None);
+  }
 
-    assert_eq!(config.initial_max_request_id, u64::MAX / 8);
+  #[test]
+  fn test_default_announce_conflict_policy() {
+    let config = RelayConfig::default();
+    assert_eq!(
+      config.announce_conflict_policy,
+      AnnounceConflictPolicy::Reject
+    );
   }
 }
@@ -0,0 +1,98 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::auth::{AuthError, AuthRequest};
+use super::track_cache::{CacheKey, GroupObjects};
+use crate::model::common::tuple::Tuple;
+use crate::model::version::VersionRegistry;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Decides whether a session may perform a request.
+///
+/// Called for every control message before the relay acts on it, with the
+/// token from the request's AUTHORIZATION TOKEN parameter. Rejected
+/// ANNOUNCE, SUBSCRIBE, SUBSCRIBE_ANNOUNCES and FETCH requests are answered
+/// with the matching error message and the code of the [`AuthError`], other
+/// rejected messages are dropped. See [`JwtAuthorizer`] and
+/// [`StaticAllowlist`] for token based implementations.
+///
+/// [`JwtAuthorizer`]: super::auth::JwtAuthorizer
+/// [`StaticAllowlist`]: super::auth::StaticAllowlist
+pub trait Authorizer: Send + Sync {
+  fn authorize(&self, request: &AuthRequest<'_>) -> Result<(), AuthError>;
+}
+
+/// Accepts every request.
+#[derive(Debug, Default)]
+pub struct AllowAll;
+
+impl Authorizer for AllowAll {
+  fn authorize(&self, _request: &AuthRequest<'_>) -> Result<(), AuthError> {
+    Ok(())
+  }
+}
+
+/// Picks the publisher that serves a track namespace.
+pub trait NamespaceRouter: Send + Sync {
+  /// `candidates` holds `(connection_id, announced_namespace)` pairs whose
+  /// namespace is equal to or a prefix of `track_namespace`, ordered by
+  /// connection id.
+  fn route(&self, track_namespace: &Tuple, candidates: &[(usize, Tuple)]) -> Option<usize>;
+}
+
+/// Routes to the first publisher that announced a matching namespace.
+#[derive(Debug, Default)]
+pub struct FirstMatchRouter;
+
+impl NamespaceRouter for FirstMatchRouter {
+  fn route(&self, _track_namespace: &Tuple, candidates: &[(usize, Tuple)]) -> Option<usize> {
+    candidates.first().map(|(connection_id, _)| *connection_id)
+  }
+}
+
+/// Storage behind a track's object cache. Objects are grouped per
+/// (track alias, group id).
+pub trait CacheStore: Send + Sync + Debug {
+  fn get(&self, key: CacheKey) -> BoxFuture<'_, Option<GroupObjects>>;
+  fn insert(&self, key: CacheKey, objects: GroupObjects) -> BoxFuture<'_, ()>;
+  fn contains(&self, key: CacheKey) -> bool;
+  /// Group IDs currently cached, in any order
+  fn group_ids(&self) -> Vec<u64>;
+  /// Returns (entry count, weighted size)
+  fn stats(&self) -> (u64, u64);
+  /// Caps how long groups inserted from now on stay cached, from the
+  /// MAX_CACHE_DURATION of the publisher. Stores without per-entry expiry
+  /// may ignore it.
+  fn set_max_duration(&self, _duration: Duration) {}
+}
+
+/// Creates the cache store of every new track.
+pub trait CacheStoreFactory: Send + Sync {
+  fn create(&self, track_alias: u64) -> Arc<dyn CacheStore>;
+}
+
+#[derive(Clone)]
+pub(crate) struct RelayHooks {
+  pub authorizer: Arc<dyn Authorizer>,
+  pub namespace_router: Arc<dyn NamespaceRouter>,
+  pub cache_store_factory: Arc<dyn CacheStoreFactory>,
+  pub versions: Arc<VersionRegistry>,
+}
@@ -0,0 +1,257 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::model::common::location::Location;
+use crate::model::common::reason_phrase::ReasonPhrase;
+use crate::model::control::announce_error::AnnounceError;
+use crate::model::control::constant::{
+  AnnounceErrorCode, FetchErrorCode, SubscribeAnnouncesErrorCode, SubscribeErrorCode,
+  TrackStatusCode,
+};
+use crate::model::control::fetch_error::FetchError;
+use crate::model::control::subscribe_announces_error::SubscribeAnnouncesError;
+use crate::model::control::subscribe_error::SubscribeError;
+use crate::model::control::track_status::TrackStatus;
+use crate::{
+  model::{control::control_message::ControlMessage, error::TerminationCode},
+  transport::control_stream_handler::ControlStreamHandler,
+};
+use bytes::Bytes;
+use tracing::{info, warn};
+
+use crate::relay::auth::{self, AuthError, AuthRequest};
+use crate::relay::{client::MOQTClient, session_context::SessionContext};
+use std::sync::Arc;
+mod announce_handler;
+mod fetch_handler;
+mod max_request_id_handler;
+mod subscribe_announces_handler;
+mod subscribe_handler;
+mod track_status_handler;
+use super::utils;
+
+pub struct MessageHandler {}
+
+impl MessageHandler {
+  /// Cleans up the requests the relay forwarded for a closed session
+  pub async fn handle_connection_close(context: &SessionContext) {
+    track_status_handler::abandon_forwarded_requests(context).await;
+  }
+
+  pub async fn handle(
+    client: Arc<MOQTClient>,
+    control_stream_handler: &mut ControlStreamHandler,
+    msg: ControlMessage,
+    context: Arc<SessionContext>,
+  ) -> Result<(), TerminationCode> {
+    // a request holds on to its request id until it completes
+    let request_id = Self::client_request_id(&msg);
+    if let Some(request_id) = request_id {
+      client.open_request(request_id).await;
+    }
+
+    let authorization = match auth::token_parameters(&msg) {
+      Ok(parameters) => {
+        // registrations and deletions take effect even if the request is rejected,
+        // unknown or duplicate aliases close the session
+        let mut token = None;
+        let mut auth_tokens = client.auth_tokens.lock().await;
+        for parameter in &parameters {
+          let presented = auth_tokens.apply(parameter).inspect_err(|code| {
+            warn!(
+              "auth token misuse by client {}: {:?} code: {:?}",
+              context.connection_id, parameter, code
+            )
+          })?;
+          token = token.or(presented);
+        }
+        drop(auth_tokens);
+        context.hooks.authorizer.authorize(&AuthRequest {
+          connection_id: context.connection_id,
+          message: &msg,
+          token: token.as_ref(),
+        })
+      }
+      Err(error) => Err(error),
+    };
+    if let Err(error) = authorization {
+      warn!(
+        "unauthorized request from client {}: {:?} error: {}",
+        context.connection_id,
+        msg.get_type(),
+        error
+      );
+      Self::reject_unauthorized(client.clone(), &msg, error).await;
+      if let Some(request_id) = request_id {
+        client.complete_request(request_id).await;
+      }
+      return Ok(());
+    }
+
+    let completed_request_id = Self::completed_request_id(&msg);
+
+    let handling_result = match &msg {
+      ControlMessage::Announce(_)
+      | ControlMessage::AnnounceOk(_)
+      | ControlMessage::AnnounceError(_)
+      | ControlMessage::Unannounce(_)
+      | ControlMessage::AnnounceCancel(_) => {
+        announce_handler::handle(client.clone(), control_stream_handler, msg, context.clone()).await
+      }
+      ControlMessage::MaxRequestId(_) | ControlMessage::RequestsBlocked(_) => {
+        max_request_id_handler::handle(client.clone(), control_stream_handler, msg, context.clone())
+          .await
+      }
+      ControlMessage::Subscribe(_)
+      | ControlMessage::SubscribeOk(_)
+      | ControlMessage::SubscribeUpdate(_)
+      | ControlMessage::SubscribeDone(_)
+      | ControlMessage::SubscribeError(_)
+      | ControlMessage::Unsubscribe(_) => {
+        subscribe_handler::handle(client.clone(), control_stream_handler, msg, context.clone())
+          .await
+      }
+      ControlMessage::SubscribeAnnounces(_) | ControlMessage::UnsubscribeAnnounces(_) => {
+        subscribe_announces_handler::handle(
+          client.clone(),
+          control_stream_handler,
+          msg,
+          context.clone(),
+        )
+        .await
+      }
+      ControlMessage::Fetch(_)
+      | ControlMessage::FetchOk(_)
+      | ControlMessage::FetchError(_)
+      | ControlMessage::FetchCancel(_) => {
+        fetch_handler::handle(client.clone(), control_stream_handler, msg, context.clone()).await
+      }
+      ControlMessage::TrackStatusRequest(_) | ControlMessage::TrackStatus(_) => {
+        track_status_handler::handle(client.clone(), control_stream_handler, msg, context.clone())
+          .await
+      }
+
+      m => {
+        info!("some message received");
+        let a = m.serialize().unwrap();
+        let buf = Bytes::from_iter(a);
+        utils::print_bytes(&buf);
+        Ok(())
+      }
+    }; // end of if
+
+    if let Err(termination_code) = handling_result {
+      Err(termination_code)
+    } else {
+      if let Some(request_id) = completed_request_id {
+        client.complete_request(request_id).await;
+      }
+      Ok(())
+    }
+  }
+
+  /// The request id of a new request from the client, which uses up credit
+  fn client_request_id(msg: &ControlMessage) -> Option<u64> {
+    match msg {
+      ControlMessage::Announce(m) => Some(m.request_id),
+      ControlMessage::Subscribe(m) => Some(m.request_id),
+      ControlMessage::SubscribeAnnounces(m) => Some(m.request_id),
+      ControlMessage::Fetch(m) => Some(m.request_id),
+      ControlMessage::TrackStatusRequest(m) => Some(m.request_id),
+      _ => None,
+    }
+  }
+
+  /// The request id of a request that is over once the message is handled,
+  /// which frees its credit. Announcements are answered right away and later
+  /// messages refer to their namespace, not to the request.
+  fn completed_request_id(msg: &ControlMessage) -> Option<u64> {
+    match msg {
+      ControlMessage::Announce(m) => Some(m.request_id),
+      ControlMessage::SubscribeAnnounces(m) => Some(m.request_id),
+      ControlMessage::Unsubscribe(m) => Some(m.request_id),
+      ControlMessage::FetchCancel(m) => Some(m.request_id),
+      _ => None,
+    }
+  }
+
+  async fn reject_unauthorized(client: Arc<MOQTClient>, msg: &ControlMessage, error: AuthError) {
+    let reason_phrase = ReasonPhrase::try_new(error.reason().to_string())
+      .unwrap_or_else(|_| ReasonPhrase::try_new(String::from("Unauthorized")).unwrap());
+    let response = match msg {
+      ControlMessage::Announce(m) => {
+        let code = match error {
+          AuthError::Unauthorized(_) => AnnounceErrorCode::Unauthorized,
+          AuthError::MalformedToken(_) => AnnounceErrorCode::MalformedAuthToken,
+          AuthError::ExpiredToken(_) => AnnounceErrorCode::ExpiredAuthToken,
+          AuthError::UnknownTokenAlias(_) => AnnounceErrorCode::UnknownAuthTokenAlias,
+        };
+        ControlMessage::AnnounceError(Box::new(AnnounceError::new(
+          m.request_id,
+          code,
+          reason_phrase,
+        )))
+      }
+      ControlMessage::Subscribe(m) => {
+        let code = match error {
+          AuthError::Unauthorized(_) => SubscribeErrorCode::Unauthorized,
+          AuthError::MalformedToken(_) => SubscribeErrorCode::MalformedAuthToken,
+          AuthError::ExpiredToken(_) => SubscribeErrorCode::ExpiredAuthToken,
+          AuthError::UnknownTokenAlias(_) => SubscribeErrorCode::UnknownAuthTokenAlias,
+        };
+        ControlMessage::SubscribeError(Box::new(SubscribeError::new(
+          m.request_id,
+          code,
+          reason_phrase,
+          m.track_alias,
+        )))
+      }
+      ControlMessage::Fetch(m) => {
+        // FETCH_ERROR has no code for unknown aliases
+        let code = match error {
+          AuthError::Unauthorized(_) => FetchErrorCode::Unauthorized,
+          AuthError::MalformedToken(_) | AuthError::UnknownTokenAlias(_) => {
+            FetchErrorCode::MalformedAuthToken
+          }
+          AuthError::ExpiredToken(_) => FetchErrorCode::ExpiredAuthToken,
+        };
+        ControlMessage::FetchError(Box::new(FetchError::new(m.request_id, code, reason_phrase)))
+      }
+      ControlMessage::SubscribeAnnounces(m) => {
+        let code = match error {
+          AuthError::Unauthorized(_) => SubscribeAnnouncesErrorCode::Unauthorized,
+          AuthError::MalformedToken(_) => SubscribeAnnouncesErrorCode::MalformedAuthToken,
+          AuthError::ExpiredToken(_) => SubscribeAnnouncesErrorCode::ExpiredAuthToken,
+          AuthError::UnknownTokenAlias(_) => SubscribeAnnouncesErrorCode::UnknownAuthTokenAlias,
+        };
+        ControlMessage::SubscribeAnnouncesError(Box::new(SubscribeAnnouncesError::new(
+          m.request_id,
+          code,
+          reason_phrase,
+        )))
+      }
+      ControlMessage::TrackStatusRequest(m) => {
+        // TRACK_STATUS has no error codes, the track does not exist for the client
+        ControlMessage::TrackStatus(Box::new(TrackStatus::new(
+          m.request_id,
+          TrackStatusCode::DoesNotExist,
+          Location::new(0, 0),
+          vec![],
+        )))
+      }
+      _ => return,
+    };
+    client.queue_message(response).await;
+  }
+}
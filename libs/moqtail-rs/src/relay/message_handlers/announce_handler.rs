@@ -0,0 +1,187 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::model::common::reason_phrase::ReasonPhrase;
+use crate::model::control::announce_error::AnnounceError;
+use crate::model::control::constant::AnnounceErrorCode;
+use crate::model::control::{announce_ok::AnnounceOk, control_message::ControlMessage};
+use crate::model::error::TerminationCode;
+use crate::relay::client::MOQTClient;
+use crate::relay::config::AnnounceConflictPolicy;
+use crate::relay::namespace;
+use crate::relay::session_context::SessionContext;
+use crate::transport::control_stream_handler::ControlStreamHandler;
+use core::result::Result;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+pub async fn handle(
+  client: Arc<MOQTClient>,
+  control_stream_handler: &mut ControlStreamHandler,
+  msg: ControlMessage,
+  context: Arc<SessionContext>,
+) -> Result<(), TerminationCode> {
+  match msg {
+    ControlMessage::Announce(m) => {
+      info!("received Announce message");
+      let request_id = m.request_id;
+
+      // check request id
+      {
+        let max_request_id = client.max_request_id().await;
+        if request_id >= max_request_id {
+          warn!(
+            "request id ({}) is greater than max request id ({})",
+            request_id, max_request_id
+          );
+          return Err(TerminationCode::TooManyRequests);
+        }
+      }
+
+      if client
+        .announced_track_namespaces
+        .read()
+        .await
+        .contains(&m.track_namespace)
+      {
+        warn!(
+          "client {} announced {:?} twice",
+          context.connection_id, m.track_namespace
+        );
+        return send_announce_error(
+          control_stream_handler,
+          request_id,
+          AnnounceErrorCode::InternalError,
+          "Track namespace already announced",
+        )
+        .await;
+      }
+
+      let overlapping = context
+        .client_manager
+        .read()
+        .await
+        .get_overlapping_announcements(&m.track_namespace, context.connection_id)
+        .await;
+      if !overlapping.is_empty() {
+        match context.server_config.announce_conflict_policy {
+          AnnounceConflictPolicy::Reject => {
+            warn!(
+              "rejecting announce of {:?} from client {}, overlaps with {:?}",
+              m.track_namespace,
+              context.connection_id,
+              overlapping.iter().map(|(_, ns)| ns).collect::<Vec<_>>()
+            );
+            return send_announce_error(
+              control_stream_handler,
+              request_id,
+              AnnounceErrorCode::Unauthorized,
+              "Track namespace announced by another publisher",
+            )
+            .await;
+          }
+          AnnounceConflictPolicy::ReplaceLatest => {
+            for (publisher, track_namespace) in overlapping {
+              namespace::revoke_namespace(
+                &context.client_manager,
+                &context.tracks,
+                &context.relay_subscribe_requests,
+                &publisher,
+                &track_namespace,
+                AnnounceErrorCode::Unauthorized,
+                "Track namespace announced by another publisher",
+              )
+              .await;
+            }
+          }
+          AnnounceConflictPolicy::MultiPublisher => {}
+        }
+      }
+
+      // this is a publisher, add it to the client manager
+      // send announce_ok
+      client
+        .add_announced_track_namespace(m.track_namespace.clone())
+        .await;
+
+      let announce_ok = Box::new(AnnounceOk {
+        request_id: m.request_id,
+      });
+      control_stream_handler
+        .send(&ControlMessage::AnnounceOk(announce_ok))
+        .await?;
+
+      context
+        .client_manager
+        .read()
+        .await
+        .forward_announce(context.connection_id, &m.track_namespace)
+        .await;
+      Ok(())
+    }
+    ControlMessage::AnnounceOk(m) => {
+      // reply to an announce forwarded to a SUBSCRIBE_ANNOUNCES subscriber
+      info!("received AnnounceOk message: {:?}", m);
+      Ok(())
+    }
+    ControlMessage::AnnounceError(m) => {
+      warn!("received AnnounceError message: {:?}", m);
+      Ok(())
+    }
+    ControlMessage::Unannounce(m) => {
+      info!("received Unannounce message: {:?}", m);
+      let withdrawn = namespace::withdraw_namespace(
+        &context.client_manager,
+        &context.tracks,
+        &context.relay_subscribe_requests,
+        &client,
+        &m.track_namespace,
+        "Track namespace unannounced",
+      )
+      .await;
+      if !withdrawn {
+        warn!(
+          "client {} unannounced a namespace it did not announce: {:?}",
+          context.connection_id, m.track_namespace
+        );
+      }
+      Ok(())
+    }
+    ControlMessage::AnnounceCancel(m) => {
+      // a SUBSCRIBE_ANNOUNCES subscriber cancelled an announce we forwarded
+      info!("received AnnounceCancel message: {:?}", m);
+      Ok(())
+    }
+    _ => {
+      // no-op
+      Ok(())
+    }
+  }
+}
+
+async fn send_announce_error(
+  control_stream_handler: &mut ControlStreamHandler,
+  request_id: u64,
+  error_code: AnnounceErrorCode,
+  reason: &str,
+) -> Result<(), TerminationCode> {
+  let announce_error = AnnounceError::new(
+    request_id,
+    error_code,
+    ReasonPhrase::try_new(reason.to_string()).unwrap(),
+  );
+  control_stream_handler
+    .send(&ControlMessage::AnnounceError(Box::new(announce_error)))
+    .await
+}
@@ -0,0 +1,1018 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::model::common::location::Location;
+use crate::model::control::constant::FetchErrorCode;
+use crate::model::control::constant::GroupOrder;
+use crate::model::control::control_message::ControlMessage;
+use crate::model::control::fetch::{Fetch, StandAloneFetchProps};
+use crate::model::control::fetch_cancel::FetchCancel;
+use crate::model::control::fetch_error::FetchError;
+use crate::model::control::fetch_ok::FetchOk;
+use crate::model::data::constant::StreamResetCode;
+use crate::model::data::fetch_header::FetchHeader;
+use crate::model::data::fetch_object::FetchObject;
+use crate::model::error::TerminationCode;
+use crate::model::parameter::version_parameter::VersionParameters;
+use crate::model::{common::reason_phrase::ReasonPhrase, control::constant::FetchType};
+use crate::relay::client::MOQTClient;
+use crate::relay::priority;
+use crate::relay::session_context::{RelayRequestKey, SessionContext};
+use crate::relay::stream_id::StreamId;
+use crate::relay::track::Track;
+use crate::relay::track_cache::{CacheConsumeEvent, CacheSpan, FillEvent};
+use crate::relay::utils::build_stream_id;
+use crate::transport::control_stream_handler::ControlStreamHandler;
+use crate::transport::data_stream_handler::{FetchRequest, HeaderInfo};
+use core::result::Result::{Err, Ok};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{Receiver, UnboundedReceiver, channel, unbounded_channel};
+use tokio::time::timeout;
+use tracing::{error, info, warn};
+
+/// Track aliases the relay picks for tracks it only knows from fetches. They
+/// are kept apart from the aliases chosen by subscribers.
+const FETCH_TRACK_ALIAS_BASE: u64 = 1 << 61;
+
+/// How long a gap fill may stay silent before the merge gives up on it
+const FETCH_FILL_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub async fn handle(
+  client: Arc<MOQTClient>,
+  _control_stream_handler: &mut ControlStreamHandler,
+  msg: ControlMessage,
+  context: Arc<SessionContext>,
+) -> Result<(), TerminationCode> {
+  match msg {
+    ControlMessage::Fetch(m) => {
+      info!("received Fetch message: {:?}", m);
+      let fetch = *m;
+      let request_id = fetch.clone().request_id;
+
+      // check request id
+      {
+        let max_request_id = client.max_request_id().await;
+        if request_id >= max_request_id {
+          warn!(
+            "request id ({}) is greater than max request id ({})",
+            request_id, max_request_id
+          );
+          return Err(TerminationCode::TooManyRequests);
+        }
+      }
+
+      // a draining relay has sent GOAWAY and takes no new fetches either
+      if context.drain.read().await.is_some() {
+        info!("relay is draining, refusing fetch {}", request_id);
+        send_fetch_error(
+          client.clone(),
+          request_id,
+          FetchErrorCode::NotSupported,
+          ReasonPhrase::try_new(String::from("Relay is going away")).unwrap(),
+        )
+        .await;
+        return Ok(());
+      }
+
+      let fn_ = async {
+        if let Some(joining_fetch_props) = fetch.clone().joining_fetch_props {
+          let sub_request_id = joining_fetch_props.joining_request_id;
+          let sub_requests = context.client_subscribe_requests.read().await;
+          // the original request id is the request id of the subscribe request that created the subscription
+          let existing_sub = sub_requests
+            .iter()
+            .find(|e| e.1.original_request_id == sub_request_id);
+          if existing_sub.is_none() {
+            error!(
+              "handle_fetch_messages | Joining fetch request id not found: {:?} {:?}",
+              sub_request_id, sub_requests
+            );
+            // return Err(TerminationCode::InternalError);
+            return Some((None, None, None));
+          }
+          let existing_sub = existing_sub.unwrap().1;
+
+          let tracks = context.tracks.read().await;
+          let track = tracks.get(&existing_sub.subscribe_request.track_alias);
+
+          if let Some(track) = track {
+            let largest_location = track.largest_location.read().await;
+
+            // TODO: validate the range
+            if largest_location.group < joining_fetch_props.joining_start {
+              error!(
+                "handle_fetch_messages | Joining fetch start location is larger than the track's largest location: {:?} {:?}",
+                largest_location, joining_fetch_props.joining_start
+              );
+              send_fetch_error(
+                client.clone(),
+                request_id,
+                FetchErrorCode::InvalidRange,
+                ReasonPhrase::try_new(String::from("Invalid range")).unwrap(),
+              )
+              .await;
+              return None;
+            }
+
+            let start_group = if fetch.fetch_type == FetchType::RelativeFetch {
+              largest_location.group - joining_fetch_props.joining_start
+            } else {
+              joining_fetch_props.joining_start
+            };
+
+            let start_location = Location::new(start_group, 0);
+            // up to the largest object, the subscription delivers the rest
+            let end_location = Location::new(largest_location.group, largest_location.object + 1);
+            Some((
+              Some(track.clone()),
+              Some(start_location),
+              Some(end_location),
+            ))
+          } else {
+            Some((None, None, None))
+          }
+        } else {
+          // standalone fetch
+          let props = fetch.standalone_fetch_props.clone().unwrap();
+
+          // let's see whether the track is in the cache
+          let track = {
+            let tracks = context.tracks.read().await;
+            tracks
+              .iter()
+              .find(|e| {
+                e.1.track_namespace == props.track_namespace && e.1.track_name == props.track_name
+              })
+              .map(|track| track.1.clone())
+          };
+
+          if let Some(track) = track {
+            Some((
+              Some(track),
+              Some(props.start_location.clone()),
+              Some(props.end_location.clone()),
+            ))
+          } else {
+            Some((None, None, None))
+          }
+        }
+      };
+
+      // None means the fetch has already been answered with an error
+      let Some((track, start_location, end_location)) = fn_.await else {
+        return Ok(());
+      };
+
+      let spans = match (&track, &start_location, &end_location) {
+        (Some(track), Some(start_location), Some(end_location)) => {
+          track.cache.spans(start_location, end_location).await
+        }
+        _ => vec![],
+      };
+      let cache_hit = spans.iter().any(|s| matches!(s, CacheSpan::Cached(..)));
+
+      if !cache_hit {
+        if fetch.joining_fetch_props.is_some() && track.is_none() {
+          send_fetch_error(
+            client.clone(),
+            request_id,
+            FetchErrorCode::TrackDoesNotExist,
+            ReasonPhrase::try_new(String::from("Track does not exist")).unwrap(),
+          )
+          .await;
+          return Ok(());
+        }
+        return forward_fetch(client, fetch, track, context).await;
+      }
+
+      info!(
+        "handle_fetch_messages | Fetching objects from {:?} to {:?}",
+        start_location.clone().unwrap(),
+        end_location.clone().unwrap()
+      );
+
+      let track = track.unwrap();
+      let start_location = start_location.unwrap();
+      let end_location = end_location.unwrap();
+
+      let group_order =
+        priority::resolve_group_order(fetch.group_order, *track.publisher_group_order.read().await);
+
+      // a partial hit fetches the missing objects upstream
+      let sources = if spans.iter().any(|s| matches!(s, CacheSpan::Missing(..))) {
+        match request_fetch_fills(&fetch, &track, spans, group_order, context.clone()).await {
+          Some(sources) => sources,
+          None => {
+            send_fetch_error(
+              client,
+              request_id,
+              FetchErrorCode::InternalError,
+              ReasonPhrase::try_new(String::from("Cannot fetch the uncached objects")).unwrap(),
+            )
+            .await;
+            return Ok(());
+          }
+        }
+      } else {
+        vec![]
+      };
+
+      // the task is registered before it can finish and deregister itself
+      let task_stream_id = StreamId::new_fetch(track.track_alias, request_id);
+      let fetch_tasks = context.fetch_tasks.clone();
+      let mut tasks = fetch_tasks.write().await;
+      let task = tokio::spawn(async move {
+        let result = async {
+          let mut object_rx = if sources.is_empty() {
+            track
+              .cache
+              .read_objects(start_location, end_location, group_order)
+              .await
+          } else {
+            merge_fetch_fills(track.clone(), group_order, sources)
+          };
+
+          let subscriber_priority = fetch.subscriber_priority;
+          let fetch_header = FetchHeader::new(request_id);
+          let header_info = HeaderInfo::Fetch {
+            header: fetch_header,
+            fetch_request: fetch,
+          };
+
+          let stream_id = build_stream_id(track.track_alias, &header_info);
+
+          let stream_fn = async move |client: Arc<MOQTClient>,
+                                      stream_id: &StreamId,
+                                      publisher_priority: u8,
+                                      group_id: u64,
+                                      newest_group: u64| {
+            let priority = priority::send_order(
+              subscriber_priority,
+              publisher_priority,
+              group_order,
+              group_id,
+              newest_group,
+            );
+            let stream_result = client
+              .open_stream(
+                stream_id,
+                client.codec.serialize_fetch_header(&fetch_header).unwrap(),
+                priority,
+              )
+              .await;
+
+            match stream_result {
+              Ok(send_stream) => Some(send_stream),
+              Err(e) => {
+                error!("handle_fetch_messages | Error opening stream: {:?}", e);
+                None
+              }
+            }
+          };
+
+          let mut object_count = 0;
+          let mut send_stream = None;
+          let mut fetch_ok = None;
+          loop {
+            match object_rx.recv().await {
+              Some(event) => match event {
+                CacheConsumeEvent::NoObject => {
+                  // there is no object found
+                  break;
+                }
+                CacheConsumeEvent::EndLocation(end_location) => {
+                  // TODO: end of track is correct?
+                  let largest_location = track.largest_location.read().await;
+                  let end_of_track = largest_location.group == end_location.group;
+                  fetch_ok = Some(match group_order {
+                    GroupOrder::Descending => {
+                      FetchOk::new_descending(request_id, end_of_track, end_location, vec![])
+                    }
+                    _ => FetchOk::new_ascending(request_id, end_of_track, end_location, vec![]),
+                  });
+                }
+                CacheConsumeEvent::Failed => {
+                  if object_count == 0 {
+                    send_fetch_error(
+                      client.clone(),
+                      request_id,
+                      FetchErrorCode::InternalError,
+                      ReasonPhrase::try_new(String::from("Upstream fetch failed")).unwrap(),
+                    )
+                    .await;
+                  } else {
+                    // the requester must not take the objects so far as the whole range
+                    warn!(
+                      "handle_fetch_messages | gap fill failed, resetting stream {:?}",
+                      &stream_id
+                    );
+                    client
+                      .reset_stream(&stream_id, StreamResetCode::InternalError)
+                      .await;
+                  }
+                  return Ok(());
+                }
+                CacheConsumeEvent::Object(object) => {
+                  if object_count == 0 {
+                    // FETCH_OK is only sent once there is an object to back it
+                    if let Some(fetch_ok) = fetch_ok.take() {
+                      info!(
+                        "handle_fetch_messages | sending fetch_ok | actual end_location: {:?}",
+                        &fetch_ok.end_location
+                      );
+                      client
+                        .queue_message(ControlMessage::FetchOk(Box::new(fetch_ok)))
+                        .await;
+                    }
+                    info!("handle_fetch_messages | starting stream {:?}", &stream_id);
+                    let newest_group = track.largest_location.read().await.group;
+                    send_stream = match stream_fn(
+                      client.clone(),
+                      &stream_id,
+                      object.publisher_priority,
+                      object.group_id,
+                      newest_group.max(object.group_id),
+                    )
+                    .await
+                    {
+                      Some(ss) => Some(ss),
+                      None => return Err(TerminationCode::InternalError),
+                    };
+                  }
+                  let object_id = object.object_id;
+                  let is_sent = if let Err(e) = client
+                    .write_object_to_stream(
+                      &stream_id,
+                      object_id,
+                      object.serialize().unwrap(),
+                      send_stream.as_ref().cloned(),
+                    )
+                    .await
+                  {
+                    error!(
+                      "handle_fetch_messages | Error writing object to stream: {:?}",
+                      e
+                    );
+                    false
+                  } else {
+                    true
+                  };
+
+                  if !is_sent {
+                    return Err(TerminationCode::InternalError);
+                  }
+
+                  // Log fetch stream object if enabled
+                  if context.server_config.enable_object_logging {
+                    let sending_time = crate::relay::utils::passed_time_since_start();
+                    let fetch_object = crate::model::data::object::Object {
+                      track_alias: track.track_alias,
+                      location: crate::model::common::location::Location::new(
+                        object.group_id,
+                        object.object_id,
+                      ),
+                      publisher_priority: object.publisher_priority,
+                      forwarding_preference:
+                        crate::model::data::constant::ObjectForwardingPreference::Subgroup,
+                      subgroup_id: Some(object.subgroup_id),
+                      status: object
+                        .object_status
+                        .unwrap_or(crate::model::data::constant::ObjectStatus::Normal),
+                      extensions: object.extension_headers.clone(),
+                      payload: object.payload.clone(),
+                    };
+                    track
+                      .object_logger
+                      .log_fetch_object(
+                        track.track_alias,
+                        context.connection_id,
+                        request_id,
+                        &fetch_object,
+                        is_sent,
+                        sending_time,
+                      )
+                      .await;
+                  }
+                  info!(
+                    "handle_fetch_messages | Wrote object to stream: {} object_id: {}",
+                    &stream_id, object_id
+                  );
+                  object_count += 1;
+                }
+              },
+              None => {
+                warn!("handle_fetch_messages | No object.");
+                break;
+              }
+            }
+          }
+
+          if object_count == 0 {
+            send_fetch_error(
+              client.clone(),
+              request_id,
+              FetchErrorCode::NoObjects,
+              ReasonPhrase::try_new(String::from("No objects available")).unwrap(),
+            )
+            .await;
+          } else {
+            // close the stream instantly
+            if let Some(the_stream) = send_stream {
+              // gracefully finish the stream here
+              if let Err(e) = the_stream.lock().await.finish().await {
+                error!("handle_fetch_messages | Error closing stream: {:?}", e);
+                // return Err(TerminationCode::InternalError);
+              } else {
+                info!("finished fetch stream: {:?}", &stream_id);
+              }
+              client.remove_stream_by_stream_id(&stream_id).await;
+              info!("removed stream from the map {}", stream_id);
+            }
+          }
+          Ok(())
+        }
+        .await;
+        context.fetch_tasks.write().await.remove(&request_id);
+        client.complete_request(request_id).await;
+        result
+      });
+      tasks.insert(request_id, (task.abort_handle(), task_stream_id));
+
+      Ok(())
+    }
+    ControlMessage::FetchOk(m) => {
+      info!("received FetchOk message: {:?}", m);
+      let mut msg = *m;
+
+      // this comes from the publisher, for a forwarded fetch or a gap fill
+      let key = (context.connection_id, msg.request_id);
+      let request = context.relay_fetch_requests.read().await.get(&key).cloned();
+      let request = match request {
+        Some(request) => request,
+        None => {
+          warn!(
+            "handle_fetch_messages | FetchOk | request_id does not exist: {}",
+            msg.request_id
+          );
+          return Ok(());
+        }
+      };
+
+      // objects of either are cached
+      if let Some(max_cache_duration) = msg.max_cache_duration()
+        && let Some(track) = context.tracks.read().await.get(&request.track_alias)
+      {
+        track.cache.set_max_duration(max_cache_duration);
+      }
+
+      // a gap fill is answered to the requester by the merging fetch
+      if let Some(fill) = context.relay_fetch_fills.read().await.get(&key) {
+        let _ = fill.send(FillEvent::FetchOk(msg.end_location));
+        return Ok(());
+      }
+
+      let requester = {
+        let mngr = context.client_manager.read().await;
+        mngr.get(request.requested_by).await
+      };
+      match requester {
+        Some(requester) => {
+          msg.request_id = request.original_request_id;
+          requester
+            .queue_message(ControlMessage::FetchOk(Box::new(msg)))
+            .await;
+        }
+        None => warn!("requester not found: {:?}", request.requested_by),
+      }
+      Ok(())
+    }
+    ControlMessage::FetchError(m) => {
+      info!("received FetchError message: {:?}", m);
+      let mut msg = *m;
+
+      // the fetch will not produce a stream, forget it
+      client.fetch_requests.write().await.remove(&msg.request_id);
+      let key = (context.connection_id, msg.request_id);
+      let fill = context.relay_fetch_fills.write().await.remove(&key);
+      if let Some(fill) = fill {
+        context.relay_fetch_requests.write().await.remove(&key);
+        // a gap the publisher has no objects for is simply empty
+        if msg.error_code != FetchErrorCode::NoObjects {
+          warn!(
+            "handle_fetch_messages | FetchError | gap fill failed: {:?}",
+            msg
+          );
+          let _ = fill.send(FillEvent::Failed);
+        }
+        return Ok(());
+      }
+      let request = context.relay_fetch_requests.write().await.remove(&key);
+      let request = match request {
+        Some(request) => request,
+        None => {
+          warn!(
+            "handle_fetch_messages | FetchError | request_id does not exist: {}",
+            msg.request_id
+          );
+          return Ok(());
+        }
+      };
+
+      let requester = {
+        let mngr = context.client_manager.read().await;
+        mngr.get(request.requested_by).await
+      };
+      match requester {
+        Some(requester) => {
+          msg.request_id = request.original_request_id;
+          requester
+            .queue_message(ControlMessage::FetchError(Box::new(msg)))
+            .await;
+          requester
+            .complete_request(request.original_request_id)
+            .await;
+        }
+        None => warn!("requester not found: {:?}", request.requested_by),
+      }
+      Ok(())
+    }
+    ControlMessage::FetchCancel(m) => {
+      info!("received FetchCancel message: {:?}", m);
+      cancel_fetch(client, m.request_id, context).await;
+      Ok(())
+    }
+    _ => {
+      // no-op
+      Ok(())
+    }
+  }
+}
+
+/// Stops everything serving the client's fetch `request_id`: the task
+/// reading the cache, the upstream fetches and gap fills, and the fetch
+/// stream to the client.
+async fn cancel_fetch(client: Arc<MOQTClient>, request_id: u64, context: Arc<SessionContext>) {
+  let mut stream_ids = Vec::new();
+
+  if let Some((task, stream_id)) = context.fetch_tasks.write().await.remove(&request_id) {
+    task.abort();
+    stream_ids.push(stream_id);
+  }
+
+  let upstream: Vec<(RelayRequestKey, FetchRequest)> = {
+    let mut requests = context.relay_fetch_requests.write().await;
+    let keys: Vec<RelayRequestKey> = requests
+      .iter()
+      .filter(|(_, r)| {
+        r.original_request_id == request_id && r.requested_by == context.connection_id
+      })
+      .map(|(key, _)| *key)
+      .collect();
+    keys
+      .into_iter()
+      .filter_map(|key| requests.remove(&key).map(|r| (key, r)))
+      .collect()
+  };
+
+  for (key, request) in upstream {
+    context.relay_fetch_fills.write().await.remove(&key);
+
+    let stream_id = StreamId::new_fetch(request.track_alias, request_id);
+    if !stream_ids.contains(&stream_id) {
+      stream_ids.push(stream_id);
+    }
+
+    let (publisher_id, relay_request_id) = key;
+    let publisher = {
+      let mngr = context.client_manager.read().await;
+      mngr.get(publisher_id).await
+    };
+    if let Some(publisher) = publisher {
+      publisher
+        .fetch_requests
+        .write()
+        .await
+        .remove(&relay_request_id);
+      info!(
+        "handle_fetch_messages | cancelling upstream fetch {} at publisher {}",
+        relay_request_id, publisher.connection_id
+      );
+      publisher
+        .queue_message(ControlMessage::FetchCancel(Box::new(FetchCancel::new(
+          relay_request_id,
+        ))))
+        .await;
+    }
+  }
+
+  if stream_ids.is_empty() {
+    warn!(
+      "handle_fetch_messages | FetchCancel | request_id does not exist: {}",
+      request_id
+    );
+    return;
+  }
+  for stream_id in stream_ids {
+    client
+      .reset_stream(&stream_id, StreamResetCode::Cancelled)
+      .await;
+  }
+}
+
+/// Forwards a FETCH the cache cannot serve to the publisher of the track.
+/// The fetch stream that comes back is piped to the requester by the session
+/// and stored in the track cache.
+async fn forward_fetch(
+  client: Arc<MOQTClient>,
+  fetch: Fetch,
+  track: Option<Track>,
+  context: Arc<SessionContext>,
+) -> Result<(), TerminationCode> {
+  let request_id = fetch.request_id;
+
+  let publisher = {
+    let mngr = context.client_manager.read().await;
+    match (&track, &fetch.standalone_fetch_props) {
+      (Some(track), _) => mngr.get(track.publisher_connection_id).await,
+      (None, Some(props)) => {
+        mngr
+          .get_publisher_by_announced_track_namespace(&props.track_namespace)
+          .await
+      }
+      (None, None) => None,
+    }
+  };
+  let publisher = match publisher {
+    Some(publisher) => publisher,
+    None => {
+      send_fetch_error(
+        client,
+        request_id,
+        FetchErrorCode::TrackDoesNotExist,
+        ReasonPhrase::try_new(String::from("Track does not exist")).unwrap(),
+      )
+      .await;
+      return Ok(());
+    }
+  };
+
+  let Some(relay_request_id) = publisher.next_request_id().await else {
+    send_fetch_error(
+      client,
+      request_id,
+      FetchErrorCode::InternalError,
+      ReasonPhrase::try_new(String::from("Publisher is out of request ids")).unwrap(),
+    )
+    .await;
+    return Ok(());
+  };
+  let mut upstream_fetch = fetch.clone();
+  upstream_fetch.request_id = relay_request_id;
+  upstream_fetch.parameters = fetch.forwarded_parameters();
+
+  // a joining fetch joins the relay's own upstream subscription
+  if let (Some(props), Some(track)) = (upstream_fetch.joining_fetch_props.as_mut(), &track) {
+    let upstream_request_id = context
+      .relay_subscribe_requests
+      .read()
+      .await
+      .iter()
+      .find(|((publisher_id, _), r)| {
+        *publisher_id == publisher.connection_id
+          && r.subscribe_request.track_alias == track.track_alias
+      })
+      .map(|((_, relay_request_id), _)| *relay_request_id);
+    match upstream_request_id {
+      Some(upstream_request_id) => props.joining_request_id = upstream_request_id,
+      None => {
+        send_fetch_error(
+          client,
+          request_id,
+          FetchErrorCode::TrackDoesNotExist,
+          ReasonPhrase::try_new(String::from("Track does not exist")).unwrap(),
+        )
+        .await;
+        return Ok(());
+      }
+    }
+  }
+
+  let track_alias = match track {
+    Some(track) => track.track_alias,
+    None => {
+      // keep a track for the fetched objects so later fetches hit the cache
+      let props = fetch.standalone_fetch_props.as_ref().unwrap();
+      let mut tracks = context.tracks.write().await;
+      let track_alias = tracks
+        .range(FETCH_TRACK_ALIAS_BASE..)
+        .next_back()
+        .map_or(FETCH_TRACK_ALIAS_BASE, |(track_alias, _)| track_alias + 1);
+      let track = Track::new(
+        track_alias,
+        props.track_namespace.clone(),
+        props.track_name.clone(),
+        publisher.connection_id,
+        context.server_config.clone(),
+        context.hooks.cache_store_factory.create(track_alias),
+      );
+      tracks.insert(track_alias, track);
+      drop(tracks);
+      publisher.add_published_track(track_alias).await;
+      track_alias
+    }
+  };
+
+  let request = FetchRequest::new(
+    request_id,
+    context.connection_id,
+    upstream_fetch.clone(),
+    track_alias,
+  );
+  publisher
+    .fetch_requests
+    .write()
+    .await
+    .insert(relay_request_id, request.clone());
+  context
+    .relay_fetch_requests
+    .write()
+    .await
+    .insert((publisher.connection_id, relay_request_id), request);
+
+  info!(
+    "handle_fetch_messages | forwarding fetch to publisher {} with relay's request id: {}",
+    publisher.connection_id, relay_request_id
+  );
+  publisher
+    .queue_message(ControlMessage::Fetch(Box::new(upstream_fetch)))
+    .await;
+  Ok(())
+}
+
+/// Where a part of a fetch that is partly served from the cache comes from
+enum FetchSource {
+  Cache(Location, Location),
+  Fill(Fill),
+}
+
+/// A gap fill, buffering the objects that arrive before it is their turn
+struct Fill {
+  events: UnboundedReceiver<FillEvent>,
+  buffered: VecDeque<FetchObject>,
+}
+
+impl Fill {
+  /// Waits for the FETCH_OK of the fill and returns its end location, or
+  /// None if the fill has no objects. Fails if the fill fails or stalls.
+  async fn end_location(&mut self) -> Result<Option<Location>, ()> {
+    loop {
+      match timeout(FETCH_FILL_TIMEOUT, self.events.recv()).await {
+        Ok(Some(FillEvent::FetchOk(end_location))) => return Ok(Some(end_location)),
+        Ok(Some(FillEvent::Object(object))) => self.buffered.push_back(object),
+        Ok(Some(FillEvent::Failed)) | Err(_) => return Err(()),
+        Ok(None) => {
+          return Ok(
+            self
+              .buffered
+              .iter()
+              .map(|object| Location::new(object.group_id, object.object_id))
+              .max(),
+          );
+        }
+      }
+    }
+  }
+
+  /// The next object of the fill, None once it is done
+  async fn next_object(&mut self) -> Result<Option<FetchObject>, ()> {
+    if let Some(object) = self.buffered.pop_front() {
+      return Ok(Some(object));
+    }
+    loop {
+      match timeout(FETCH_FILL_TIMEOUT, self.events.recv()).await {
+        Ok(Some(FillEvent::Object(object))) => return Ok(Some(object)),
+        Ok(Some(FillEvent::FetchOk(_))) => continue,
+        Ok(Some(FillEvent::Failed)) | Err(_) => return Err(()),
+        Ok(None) => return Ok(None),
+      }
+    }
+  }
+}
+
+/// Sends a standalone FETCH upstream for every missing span of a partial
+/// cache hit. Returns the cached spans and the fills in ascending order, or
+/// None if a missing span cannot be requested, in which case the fills
+/// already sent are cancelled.
+async fn request_fetch_fills(
+  fetch: &Fetch,
+  track: &Track,
+  spans: Vec<CacheSpan>,
+  group_order: GroupOrder,
+  context: Arc<SessionContext>,
+) -> Option<Vec<FetchSource>> {
+  let publisher = {
+    let mngr = context.client_manager.read().await;
+    mngr.get(track.publisher_connection_id).await
+  };
+  let Some(publisher) = publisher else {
+    warn!(
+      "handle_fetch_messages | publisher not found: {}",
+      track.publisher_connection_id
+    );
+    return None;
+  };
+
+  let mut sources = Vec::new();
+  let mut requested = Vec::new();
+  for span in spans {
+    let (start_location, end_location) = match span {
+      CacheSpan::Cached(start, end) => {
+        sources.push(FetchSource::Cache(start, end));
+        continue;
+      }
+      CacheSpan::Missing(start, end) => (start, end),
+    };
+
+    let Some(relay_request_id) = publisher.next_request_id().await else {
+      cancel_fetch_fills(&publisher, &requested, &context).await;
+      return None;
+    };
+    let upstream_fetch = Fetch::new_standalone(
+      relay_request_id,
+      fetch.subscriber_priority,
+      group_order,
+      StandAloneFetchProps {
+        track_namespace: track.track_namespace.clone(),
+        track_name: track.track_name.clone(),
+        start_location: start_location.clone(),
+        end_location: end_location.clone(),
+      },
+      vec![],
+    );
+
+    let (tx, rx) = unbounded_channel();
+    context
+      .relay_fetch_fills
+      .write()
+      .await
+      .insert((publisher.connection_id, relay_request_id), tx);
+    let request = FetchRequest::new(
+      fetch.request_id,
+      context.connection_id,
+      upstream_fetch.clone(),
+      track.track_alias,
+    );
+    publisher
+      .fetch_requests
+      .write()
+      .await
+      .insert(relay_request_id, request.clone());
+    context
+      .relay_fetch_requests
+      .write()
+      .await
+      .insert((publisher.connection_id, relay_request_id), request);
+
+    info!(
+      "handle_fetch_messages | filling {:?}..{:?} from publisher {} with relay's request id: {}",
+      start_location, end_location, publisher.connection_id, relay_request_id
+    );
+    publisher
+      .queue_message(ControlMessage::Fetch(Box::new(upstream_fetch)))
+      .await;
+    requested.push(relay_request_id);
+    sources.push(FetchSource::Fill(Fill {
+      events: rx,
+      buffered: VecDeque::new(),
+    }));
+  }
+  Some(sources)
+}
+
+async fn cancel_fetch_fills(
+  publisher: &MOQTClient,
+  relay_request_ids: &[u64],
+  context: &SessionContext,
+) {
+  for relay_request_id in relay_request_ids {
+    let key = (publisher.connection_id, *relay_request_id);
+    context.relay_fetch_fills.write().await.remove(&key);
+    context.relay_fetch_requests.write().await.remove(&key);
+    publisher
+      .fetch_requests
+      .write()
+      .await
+      .remove(relay_request_id);
+    publisher
+      .queue_message(ControlMessage::FetchCancel(Box::new(FetchCancel::new(
+        *relay_request_id,
+      ))))
+      .await;
+  }
+}
+
+/// Streams the cached spans and gap fills in the requested group order, in
+/// the same shape as [`TrackCache::read_objects`](crate::relay::track_cache::TrackCache::read_objects).
+/// Each part is sent as soon as its turn comes. The end location waits for
+/// the FETCH_OK of a trailing fill, and a failed fill ends the stream with
+/// [`CacheConsumeEvent::Failed`].
+fn merge_fetch_fills(
+  track: Track,
+  group_order: GroupOrder,
+  mut sources: Vec<FetchSource>,
+) -> Receiver<CacheConsumeEvent> {
+  let (tx, rx) = channel(32);
+
+  tokio::spawn(async move {
+    // the end location is the largest object whatever the order
+    let mut end_location = None;
+    for source in sources.iter_mut().rev() {
+      end_location = match source {
+        FetchSource::Cache(start, end) => track.cache.end_location(start, end).await,
+        FetchSource::Fill(fill) => match fill.end_location().await {
+          Ok(end_location) => end_location,
+          Err(()) => {
+            warn!("merge_fetch_fills | gap fill failed before FETCH_OK");
+            let _ = tx.send(CacheConsumeEvent::Failed).await;
+            return;
+          }
+        },
+      };
+      if end_location.is_some() {
+        break;
+      }
+    }
+    let Some(end_location) = end_location else {
+      let _ = tx.send(CacheConsumeEvent::NoObject).await;
+      return;
+    };
+    if tx
+      .send(CacheConsumeEvent::EndLocation(end_location))
+      .await
+      .is_err()
+    {
+      return;
+    }
+
+    if group_order == GroupOrder::Descending {
+      sources.reverse();
+    }
+    for source in sources {
+      match source {
+        FetchSource::Cache(start, end) => {
+          let mut cached = track.cache.read_objects(start, end, group_order).await;
+          while let Some(event) = cached.recv().await {
+            if let CacheConsumeEvent::Object(object) = event
+              && tx.send(CacheConsumeEvent::Object(object)).await.is_err()
+            {
+              return;
+            }
+          }
+        }
+        FetchSource::Fill(mut fill) => loop {
+          match fill.next_object().await {
+            Ok(Some(object)) => {
+              if tx.send(CacheConsumeEvent::Object(object)).await.is_err() {
+                return;
+              }
+            }
+            Ok(None) => break,
+            Err(()) => {
+              warn!("merge_fetch_fills | gap fill failed or timed out");
+              let _ = tx.send(CacheConsumeEvent::Failed).await;
+              return;
+            }
+          }
+        },
+      }
+    }
+  });
+
+  rx
+}
+
+async fn send_fetch_error(
+  client: Arc<MOQTClient>,
+  request_id: u64,
+  error_code: FetchErrorCode,
+  reason_phrase: ReasonPhrase,
+) {
+  let fetch_error = FetchError::new(request_id, error_code, reason_phrase);
+  client
+    .queue_message(ControlMessage::FetchError(Box::new(fetch_error)))
+    .await;
+  client.complete_request(request_id).await;
+}
@@ -12,38 +12,40 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::server::client::MOQTClient;
-use crate::server::session_context::SessionContext;
+use crate::model::control::control_message::ControlMessage;
+use crate::model::error::TerminationCode;
+use crate::relay::client::MOQTClient;
+use crate::relay::session_context::SessionContext;
+use crate::transport::control_stream_handler::ControlStreamHandler;
 use core::result::Result;
-use moqtail::model::control::control_message::ControlMessage;
-use moqtail::model::error::TerminationCode;
-use moqtail::transport::control_stream_handler::ControlStreamHandler;
 use std::sync::Arc;
 use tracing::{info, warn};
 
 pub async fn handle(
-  _client: Arc<MOQTClient>,
+  client: Arc<MOQTClient>,
   _control_stream_handler: &mut ControlStreamHandler,
   msg: ControlMessage,
   context: Arc<SessionContext>,
 ) -> Result<(), TerminationCode> {
   match msg {
     ControlMessage::MaxRequestId(m) => {
-      // TODO: the namespace is already announced, return error
       info!("received MaxRequestId message");
 
-      let new_max_request_id = m.request_id;
-
-      {
-        let current_max_request_id = context.max_request_id.read().await;
-        if *current_max_request_id >= new_max_request_id {
-          warn!("received MaxRequestId message with lower request id than previously announced");
-          return Err(TerminationCode::ProtocolViolation);
-        }
+      // the limit applies to the requests the relay sends to this client
+      if !client.update_peer_max_request_id(m.request_id).await {
+        warn!("received MaxRequestId message with lower request id than previously announced");
+        return Err(TerminationCode::ProtocolViolation);
       }
 
-      info!("setting new maxrequest id to {}", new_max_request_id);
-      *context.max_request_id.write().await = new_max_request_id;
+      info!("setting new peer max request id to {}", m.request_id);
+      Ok(())
+    }
+    ControlMessage::RequestsBlocked(m) => {
+      info!(
+        "client {} blocked at max request id {}",
+        context.connection_id, m.maximum_request_id
+      );
+      client.unblock_requests(m.maximum_request_id).await;
       Ok(())
     }
     _ => {
@@ -0,0 +1,119 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::model::common::reason_phrase::ReasonPhrase;
+use crate::model::control::announce::Announce;
+use crate::model::control::constant::SubscribeAnnouncesErrorCode;
+use crate::model::control::control_message::ControlMessage;
+use crate::model::control::subscribe_announces_error::SubscribeAnnouncesError;
+use crate::model::control::subscribe_announces_ok::SubscribeAnnouncesOk;
+use crate::model::error::TerminationCode;
+use crate::relay::client::MOQTClient;
+use crate::relay::session_context::SessionContext;
+use crate::transport::control_stream_handler::ControlStreamHandler;
+use core::result::Result;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+pub async fn handle(
+  client: Arc<MOQTClient>,
+  control_stream_handler: &mut ControlStreamHandler,
+  msg: ControlMessage,
+  context: Arc<SessionContext>,
+) -> Result<(), TerminationCode> {
+  match msg {
+    ControlMessage::SubscribeAnnounces(m) => {
+      info!("received SubscribeAnnounces message: {:?}", m);
+      let request_id = m.request_id;
+
+      // check request id
+      {
+        let max_request_id = client.max_request_id().await;
+        if request_id >= max_request_id {
+          warn!(
+            "request id ({}) is greater than max request id ({})",
+            request_id, max_request_id
+          );
+          return Err(TerminationCode::TooManyRequests);
+        }
+      }
+
+      if !client
+        .add_announce_subscription(m.track_namespace_prefix.clone())
+        .await
+      {
+        warn!(
+          "namespace prefix {:?} overlaps with an existing subscription of client {}",
+          m.track_namespace_prefix, context.connection_id
+        );
+        let subscribe_announces_error = SubscribeAnnouncesError::new(
+          request_id,
+          SubscribeAnnouncesErrorCode::NamespacePrefixOverlap,
+          ReasonPhrase::try_new("Namespace prefix overlap".to_string()).unwrap(),
+        );
+        return control_stream_handler
+          .send_impl(&subscribe_announces_error)
+          .await;
+      }
+
+      control_stream_handler
+        .send_impl(&SubscribeAnnouncesOk::new(request_id))
+        .await?;
+
+      // let the subscriber know about the namespaces that are already announced
+      let track_namespaces = context
+        .client_manager
+        .read()
+        .await
+        .get_announced_track_namespaces(&m.track_namespace_prefix, context.connection_id)
+        .await;
+
+      for track_namespace in track_namespaces {
+        let Some(request_id) = client.next_request_id().await else {
+          warn!(
+            "not sending existing announce of {:?} to client {}",
+            track_namespace, context.connection_id
+          );
+          continue;
+        };
+        info!(
+          "sending existing announce of {:?} to client {}",
+          track_namespace, context.connection_id
+        );
+        let announce = Announce::new(request_id, track_namespace, &[]);
+        client
+          .queue_message(ControlMessage::Announce(Box::new(announce)))
+          .await;
+      }
+      Ok(())
+    }
+    ControlMessage::UnsubscribeAnnounces(m) => {
+      info!("received UnsubscribeAnnounces message: {:?}", m);
+      if !client
+        .remove_announce_subscription(&m.track_namespace_prefix)
+        .await
+      {
+        warn!(
+          "client {} is not subscribed to namespace prefix {:?}",
+          context.connection_id, m.track_namespace_prefix
+        );
+      }
+      Ok(())
+    }
+    _ => {
+      // no-op
+      Ok(())
+    }
+  }
+}
@@ -0,0 +1,586 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::model::control::constant::{FilterType, GroupOrder, SubscribeErrorCode};
+use crate::model::control::subscribe::Subscribe;
+use crate::model::control::subscribe_error::SubscribeError;
+use crate::model::control::subscribe_ok::SubscribeOk;
+use crate::model::control::unsubscribe::Unsubscribe;
+use crate::model::error::TerminationCode;
+use crate::model::parameter::version_parameter::VersionParameters;
+use crate::model::{common::reason_phrase::ReasonPhrase, control::control_message::ControlMessage};
+use crate::relay::client::MOQTClient;
+use crate::relay::session_context::SessionContext;
+use crate::relay::subscription::SubscriptionParams;
+use crate::relay::track::Track;
+use crate::transport::control_stream_handler::ControlStreamHandler;
+use crate::transport::data_stream_handler::SubscribeRequest;
+use core::result::Result;
+use std::sync::Arc;
+use tracing::{debug, error, info, warn};
+
+pub async fn handle(
+  client: Arc<MOQTClient>,
+  control_stream_handler: &mut ControlStreamHandler,
+  msg: ControlMessage,
+  context: Arc<SessionContext>,
+) -> Result<(), TerminationCode> {
+  match msg {
+    ControlMessage::Subscribe(m) => {
+      info!("received Subscribe message: {:?}", m);
+      let sub = *m;
+      let track_namespace = sub.track_namespace.clone();
+      let request_id = sub.request_id;
+
+      // check request id
+      {
+        let max_request_id = client.max_request_id().await;
+        if request_id >= max_request_id {
+          warn!(
+            "request id ({}) is greater than max request id ({})",
+            request_id, max_request_id
+          );
+          return Err(TerminationCode::TooManyRequests);
+        }
+      }
+
+      // a draining relay has sent GOAWAY and takes no new subscriptions
+      if context.drain.read().await.is_some() {
+        info!("relay is draining, refusing subscribe {}", request_id);
+        return send_subscribe_error(
+          &client,
+          control_stream_handler,
+          &sub,
+          SubscribeErrorCode::NotSupported,
+          "Relay is going away",
+        )
+        .await;
+      }
+
+      if let (FilterType::AbsoluteRange, Some(start_location), Some(end_group)) =
+        (sub.filter_type, &sub.start_location, sub.end_group)
+        && end_group < start_location.group
+      {
+        warn!(
+          "invalid range, end group ({}) is before start location ({:?})",
+          end_group, start_location
+        );
+        return send_subscribe_error(
+          &client,
+          control_stream_handler,
+          &sub,
+          SubscribeErrorCode::InvalidRange,
+          "End group is before start location",
+        )
+        .await;
+      }
+
+      // find who is the publisher
+      let publisher = {
+        debug!("trying to get the publisher");
+        let m = context.client_manager.read().await;
+        debug!(
+          "client manager obtained, current client id: {}",
+          context.connection_id
+        );
+        m.get_publisher_by_announced_track_namespace(&track_namespace)
+          .await
+      };
+
+      let publisher = if let Some(publisher) = publisher {
+        publisher.clone()
+      } else {
+        error!(
+          "no publisher found for track namespace: {:?}",
+          track_namespace
+        );
+        // send SubscribeError
+        send_subscribe_error(
+          &client,
+          control_stream_handler,
+          &sub,
+          SubscribeErrorCode::TrackDoesNotExist,
+          "Unknown track namespace",
+        )
+        .await
+        .unwrap();
+        return Ok(());
+      };
+
+      {
+        publisher.add_subscriber(context.connection_id).await;
+      }
+      info!(
+        "Subscriber ({}) added to the publisher ({})",
+        context.connection_id, publisher.connection_id
+      );
+
+      let original_request_id = sub.request_id;
+
+      let res: Result<(), TerminationCode> =
+        if !context.tracks.read().await.contains_key(&sub.track_alias) {
+          info!("Track not found, creating new track: {:?}", sub.track_alias);
+          let Some(relay_request_id) = publisher.next_request_id().await else {
+            return send_subscribe_error(
+              &client,
+              control_stream_handler,
+              &sub,
+              SubscribeErrorCode::InternalError,
+              "Publisher is out of request ids",
+            )
+            .await;
+          };
+          // subscribed_tracks.insert(sub.track_alias, Track::new(sub.track_alias, track_namespace.clone(), sub.track_name.clone()));
+          let mut track = Track::new(
+            sub.track_alias,
+            sub.track_namespace.clone(),
+            sub.track_name.clone(),
+            publisher.connection_id,
+            context.server_config.clone(),
+            context.hooks.cache_store_factory.create(sub.track_alias),
+          );
+          {
+            context
+              .tracks
+              .write()
+              .await
+              .insert(sub.track_alias, track.clone());
+          }
+
+          let _ = track.add_subscription(client.clone(), sub.clone()).await;
+
+          // send the subscribe message to the publisher
+          let mut new_sub = sub.clone();
+          new_sub.request_id = relay_request_id;
+          new_sub.subscribe_parameters = sub.forwarded_parameters();
+          track
+            .set_requested_delivery_timeout(new_sub.delivery_timeout())
+            .await;
+
+          publisher
+            .queue_message(ControlMessage::Subscribe(Box::new(new_sub.clone())))
+            .await;
+
+          // add the track to the publisher's published tracks
+          publisher.add_published_track(track.track_alias).await;
+
+          // insert this request id into the relay's subscribe requests
+          // TODO: we need to add a timeout here or another loop to control expired requests
+          let req =
+            SubscribeRequest::new(original_request_id, context.connection_id, new_sub.clone());
+          let mut requests = context.relay_subscribe_requests.write().await;
+          requests.insert((publisher.connection_id, new_sub.request_id), req.clone());
+          info!(
+            "inserted request into relay's subscribe requests: {:?} with relay's request id: {:?}",
+            req, new_sub.request_id
+          );
+          Ok(())
+        } else {
+          info!("track already exists, sending SubscribeOk");
+          let mut tracks = context.tracks.write().await;
+          let track = tracks.get_mut(&sub.track_alias).unwrap();
+          let _ = track.add_subscription(client.clone(), sub.clone()).await;
+          let largest_location = track.get_largest_location().await;
+          let group_order = match *track.publisher_group_order.read().await {
+            // the publisher has not answered yet
+            GroupOrder::Original => GroupOrder::Ascending,
+            group_order => group_order,
+          };
+          drop(tracks);
+          update_upstream_subscription(&context, sub.track_alias).await;
+
+          let subscribe_ok = SubscribeOk {
+            request_id: sub.request_id,
+            expires: 0,
+            group_order,
+            content_exists: largest_location.is_some(),
+            largest_location,
+            subscribe_parameters: None,
+          };
+
+          control_stream_handler.send_impl(&subscribe_ok).await
+        };
+
+      // return if there's an error
+      if res.is_ok() {
+        // insert this request id into the clients subscribe requests
+        let req = SubscribeRequest::new(original_request_id, context.connection_id, sub.clone());
+        let mut requests = context.client_subscribe_requests.write().await;
+        requests.insert(sub.request_id, req.clone());
+        info!(
+          "inserted request into client's subscribe requests: {:?} with subscriber's request id: {:?}",
+          req, sub.request_id
+        );
+
+        // also insert the request to the client's subscribe requests
+        let mut requests = client.subscribe_requests.write().await;
+        let orig_req =
+          SubscribeRequest::new(original_request_id, context.connection_id, sub.clone());
+        requests.insert(original_request_id, orig_req.clone());
+        debug!(
+          "inserted request into client's subscribe requests: {:?}",
+          orig_req
+        );
+      } else {
+        error!("error in adding subscription: {:?}", res);
+      }
+      res
+    }
+    ControlMessage::SubscribeOk(m) => {
+      info!("received SubscribeOk message: {:?}", m);
+      let msg = *m;
+
+      // this comes from the publisher
+      // it should be sent to the subscriber
+      let request_id = msg.request_id;
+
+      let mut sub_request = {
+        let mut requests = context.relay_subscribe_requests.write().await;
+        // print out every request
+        debug!("current requests: {:?}", requests);
+        match requests.get_mut(&(context.connection_id, request_id)) {
+          Some(m) => {
+            info!("request id is verified: {:?}", request_id);
+            let sub_request = m.clone();
+            m.answered = true;
+            sub_request
+          }
+          None => {
+            warn!("request id is not verified: {:?}", request_id);
+            return Ok(());
+          }
+        }
+      };
+
+      // replace the request id with the original request id
+      sub_request.subscribe_request.request_id = sub_request.original_request_id;
+
+      // the publisher's group order decides for subscribers that asked for
+      // the original order
+      if let Some(track) = context
+        .tracks
+        .read()
+        .await
+        .get(&sub_request.subscribe_request.track_alias)
+      {
+        *track.publisher_group_order.write().await = msg.group_order;
+        if let Some(max_cache_duration) = msg.max_cache_duration() {
+          track.cache.set_max_duration(max_cache_duration);
+        }
+        track
+          .set_publisher_delivery_timeout(msg.delivery_timeout())
+          .await;
+      }
+
+      // a re-subscription for a wider range was already answered downstream
+      if sub_request.answered {
+        return Ok(());
+      }
+
+      // now we're ready to send the subscribe_ok message to the subscriber,
+      // with the publisher's values and without its parameters
+      let subscribe_ok = SubscribeOk {
+        request_id: sub_request.original_request_id,
+        subscribe_parameters: None,
+        ..msg
+      };
+      // send the subscribe_ok message to the subscriber
+      let subscriber = {
+        let mngr = context.client_manager.read().await;
+        mngr.get(sub_request.requested_by).await
+      };
+
+      if subscriber.is_none() {
+        warn!("subscriber not found");
+        return Ok(());
+      }
+
+      debug!("subscriber found: {:?}", sub_request.requested_by);
+      let subscriber = subscriber.unwrap();
+
+      info!(
+        "sending SubscribeOk to subscriber: {:?}, msg: {:?}",
+        sub_request.requested_by, &subscribe_ok
+      );
+
+      subscriber
+        .queue_message(ControlMessage::SubscribeOk(Box::new(subscribe_ok)))
+        .await;
+      drop(subscriber);
+
+      /*
+      TODO: do we need to update the subscription with the incoming subscribe_ok message?
+      */
+
+      // remove the request from the publisher
+      debug!("removing request from publisher: {:?}", request_id);
+
+      // add the track to the subscribed tracks
+      info!(
+        "adding track to subscribed tracks: {:?}",
+        sub_request.subscribe_request.track_alias
+      );
+      Ok(())
+    }
+    ControlMessage::SubscribeDone(m) => {
+      // the publisher ended the relay's subscription, e.g. at the end of the
+      // track, so every subscriber of the track is done as well
+      info!("received SubscribeDone message: {:?}", m);
+      let request = context
+        .relay_subscribe_requests
+        .write()
+        .await
+        .remove(&(context.connection_id, m.request_id));
+      let Some(request) = request else {
+        // e.g. the subscription was replaced by a wider one
+        debug!("request not found for request id: {:?}", m.request_id);
+        return Ok(());
+      };
+
+      let track_alias = request.subscribe_request.track_alias;
+      let track = context.tracks.write().await.remove(&track_alias);
+      if let Some(track) = track
+        && let Err(e) = track
+          .notify_track_ended(m.status_code, "Publisher ended the subscription")
+          .await
+      {
+        error!(
+          "Failed to notify subscribers for track {}: {:?}",
+          track_alias, e
+        );
+      }
+      if let Some(client) = context.get_client().await {
+        client.remove_published_track(track_alias).await;
+      }
+      Ok(())
+    }
+    ControlMessage::Unsubscribe(m) => {
+      info!("received Unsubscribe message: {:?}", m);
+      // stop sending objects for the track for the subscriber
+      // by removing the subscription
+
+      // get the client
+      let client = context.get_client().await;
+      let client = match client {
+        Some(c) => c,
+        None => {
+          warn!(
+            "client not found for connection id: {:?}",
+            context.connection_id
+          );
+          return Err(TerminationCode::InternalError);
+        }
+      };
+
+      // find the track alias by using the request id
+      let requests = client.subscribe_requests.read().await;
+      let request = requests.get(&m.request_id);
+      if request.is_none() {
+        // a warning is enough
+        warn!("request not found for request id: {:?}", m.request_id);
+        return Ok(());
+      }
+      let request = request.unwrap();
+      let track_alias = request.subscribe_request.track_alias;
+
+      // remove the subscription from the track
+      let mut tracks = context.tracks.write().await;
+      let track = tracks.get_mut(&track_alias).unwrap();
+      track.remove_subscription(context.connection_id).await;
+      drop(tracks);
+      drop(requests);
+
+      update_upstream_subscription(&context, track_alias).await;
+      Ok(())
+    }
+    ControlMessage::SubscribeUpdate(m) => {
+      info!("received SubscribeUpdate message: {:?}", m);
+
+      let client = match context.get_client().await {
+        Some(c) => c,
+        None => return Err(TerminationCode::InternalError),
+      };
+
+      // the request id is the one of the subscriber's SUBSCRIBE
+      let track_alias = match client.subscribe_requests.read().await.get(&m.request_id) {
+        Some(request) => request.subscribe_request.track_alias,
+        None => {
+          warn!("request not found for request id: {:?}", m.request_id);
+          return Ok(());
+        }
+      };
+
+      let updated = {
+        let tracks = context.tracks.read().await;
+        match tracks.get(&track_alias) {
+          Some(track) => track.update_subscription(context.connection_id, &m).await,
+          None => None,
+        }
+      };
+
+      match updated {
+        Some(true) => {
+          update_upstream_subscription(&context, track_alias).await;
+          Ok(())
+        }
+        Some(false) => {
+          warn!(
+            "SubscribeUpdate widens the subscription of request id: {:?}",
+            m.request_id
+          );
+          Err(TerminationCode::ProtocolViolation)
+        }
+        None => {
+          warn!(
+            "no subscription found for track: {:?} subscriber: {}",
+            track_alias, context.connection_id
+          );
+          Ok(())
+        }
+      }
+    }
+    _ => {
+      // no-op
+      Ok(())
+    }
+  }
+}
+
+/// Brings the relay's upstream subscription to a track in line with the
+/// combined needs of its downstream subscriptions. SUBSCRIBE_UPDATE cannot
+/// widen a subscription or drop its delivery timeout, so either re-subscribes
+/// upstream. Otherwise forwarding, priority and delivery timeout are updated,
+/// e.g. when every subscriber paused, and the subscribed range is kept for
+/// later subscribers.
+async fn update_upstream_subscription(context: &SessionContext, track_alias: u64) {
+  let (desired, largest_location, publisher_connection_id) = {
+    let tracks = context.tracks.read().await;
+    let Some(track) = tracks.get(&track_alias) else {
+      return;
+    };
+    let desired = track.aggregate_subscription_params().await;
+    // without subscribers nothing has to arrive in time
+    track
+      .set_requested_delivery_timeout(desired.as_ref().and_then(|d| d.delivery_timeout))
+      .await;
+    let Some(desired) = desired else {
+      return;
+    };
+    (
+      desired,
+      track.get_largest_location().await,
+      track.publisher_connection_id,
+    )
+  };
+
+  let publisher = {
+    let mngr = context.client_manager.read().await;
+    mngr.get(publisher_connection_id).await
+  };
+  let Some(publisher) = publisher else {
+    warn!("publisher not found: {:?}", publisher_connection_id);
+    return;
+  };
+
+  let mut requests = context.relay_subscribe_requests.write().await;
+  let Some((&(_, relay_request_id), request)) =
+    requests.iter_mut().find(|((publisher_id, _), r)| {
+      *publisher_id == publisher_connection_id && r.subscribe_request.track_alias == track_alias
+    })
+  else {
+    return;
+  };
+
+  let upstream = &mut request.subscribe_request;
+  let current = SubscriptionParams::from_subscribe(upstream, None);
+  let drops_delivery_timeout =
+    current.delivery_timeout.is_some() && desired.delivery_timeout.is_none();
+  if current.covers(&desired) && !drops_delivery_timeout {
+    if current.forward == desired.forward
+      && current.subscriber_priority == desired.subscriber_priority
+      && current.delivery_timeout == desired.delivery_timeout
+    {
+      return;
+    }
+    let updated = SubscriptionParams {
+      forward: desired.forward,
+      subscriber_priority: desired.subscriber_priority,
+      delivery_timeout: desired.delivery_timeout,
+      ..current
+    };
+    upstream.forward = desired.forward;
+    upstream.subscriber_priority = desired.subscriber_priority;
+    updated.apply_delivery_timeout_to(upstream);
+    let subscribe_update = updated.to_subscribe_update(relay_request_id);
+    drop(requests);
+
+    info!(
+      "sending SubscribeUpdate upstream for track: {:?} msg: {:?}",
+      track_alias, subscribe_update
+    );
+    publisher
+      .queue_message(ControlMessage::SubscribeUpdate(Box::new(subscribe_update)))
+      .await;
+    return;
+  }
+
+  let Some(new_relay_request_id) = publisher.next_request_id().await else {
+    warn!(
+      "cannot widen the upstream subscription of track {:?}, publisher is out of request ids",
+      track_alias
+    );
+    return;
+  };
+  let Some(mut request) = requests.remove(&(publisher_connection_id, relay_request_id)) else {
+    return;
+  };
+  desired.apply_to(&mut request.subscribe_request, largest_location.as_ref());
+  request.subscribe_request.request_id = new_relay_request_id;
+  let subscribe = request.subscribe_request.clone();
+  requests.insert((publisher_connection_id, new_relay_request_id), request);
+  drop(requests);
+
+  // the track alias is reused, so the old subscription has to go first
+  info!(
+    "re-subscribing upstream for track: {:?} with relay's request id: {:?} msg: {:?}",
+    track_alias, new_relay_request_id, subscribe
+  );
+  publisher
+    .queue_message(ControlMessage::Unsubscribe(Box::new(Unsubscribe::new(
+      relay_request_id,
+    ))))
+    .await;
+  publisher
+    .queue_message(ControlMessage::Subscribe(Box::new(subscribe)))
+    .await;
+}
+
+/// Refuses a SUBSCRIBE of the client, which completes the request
+async fn send_subscribe_error(
+  client: &MOQTClient,
+  control_stream_handler: &mut ControlStreamHandler,
+  sub: &Subscribe,
+  error_code: SubscribeErrorCode,
+  reason: &str,
+) -> Result<(), TerminationCode> {
+  let subscribe_error = SubscribeError::new(
+    sub.request_id,
+    error_code,
+    ReasonPhrase::try_new(reason.to_string()).unwrap(),
+    sub.track_alias,
+  );
+  control_stream_handler.send_impl(&subscribe_error).await?;
+  client.complete_request(sub.request_id).await;
+  Ok(())
+}
@@ -0,0 +1,247 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::model::common::location::Location;
+use crate::model::control::constant::TrackStatusCode;
+use crate::model::control::control_message::ControlMessage;
+use crate::model::control::track_status::TrackStatus;
+use crate::model::control::track_status_request::TrackStatusRequest;
+use crate::model::error::TerminationCode;
+use crate::model::parameter::version_parameter::VersionParameters;
+use crate::relay::client::MOQTClient;
+use crate::relay::session_context::{RelayRequestKey, SessionContext, TrackStatusForward};
+use crate::transport::control_stream_handler::ControlStreamHandler;
+use core::result::Result;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+pub async fn handle(
+  client: Arc<MOQTClient>,
+  control_stream_handler: &mut ControlStreamHandler,
+  msg: ControlMessage,
+  context: Arc<SessionContext>,
+) -> Result<(), TerminationCode> {
+  match msg {
+    ControlMessage::TrackStatusRequest(m) => {
+      info!("received TrackStatusRequest message: {:?}", m);
+      let request_id = m.request_id;
+
+      // check request id
+      {
+        let max_request_id = client.max_request_id().await;
+        if request_id >= max_request_id {
+          warn!(
+            "request id ({}) is greater than max request id ({})",
+            request_id, max_request_id
+          );
+          return Err(TerminationCode::TooManyRequests);
+        }
+      }
+
+      // answer from the live track if the relay is already subscribed to it
+      let live_track = {
+        let tracks = context.tracks.read().await;
+        match tracks
+          .values()
+          .find(|t| t.track_namespace == m.track_namespace && t.track_name == m.track_name)
+        {
+          Some(track) => Some(track.get_largest_location().await),
+          None => None,
+        }
+      };
+
+      if let Some(largest_location) = live_track {
+        debug!(
+          "answering track status from live track, largest location: {:?}",
+          largest_location
+        );
+        let track_status = match largest_location {
+          Some(largest_location) => TrackStatus::new(
+            request_id,
+            TrackStatusCode::InProgress,
+            largest_location,
+            vec![],
+          ),
+          // subscribed, but the publisher has not sent anything yet
+          None => TrackStatus::new(
+            request_id,
+            TrackStatusCode::NotYetBegun,
+            Location::new(0, 0),
+            vec![],
+          ),
+        };
+        return send_track_status(&client, control_stream_handler, track_status).await;
+      }
+
+      // otherwise ask the publisher
+      let publisher = {
+        let mngr = context.client_manager.read().await;
+        mngr
+          .get_publisher_by_announced_track_namespace(&m.track_namespace)
+          .await
+      };
+
+      let publisher = match publisher {
+        Some(publisher) => publisher,
+        None => {
+          warn!(
+            "no publisher found for track namespace: {:?}",
+            m.track_namespace
+          );
+          let track_status = TrackStatus::new(
+            request_id,
+            TrackStatusCode::DoesNotExist,
+            Location::new(0, 0),
+            vec![],
+          );
+          return send_track_status(&client, control_stream_handler, track_status).await;
+        }
+      };
+
+      let Some(relay_request_id) = publisher.next_request_id().await else {
+        let track_status = TrackStatus::new(
+          request_id,
+          TrackStatusCode::RelayUnavailable,
+          Location::new(0, 0),
+          vec![],
+        );
+        return send_track_status(&client, control_stream_handler, track_status).await;
+      };
+      context.relay_track_status_requests.write().await.insert(
+        (publisher.connection_id, relay_request_id),
+        TrackStatusForward {
+          original_request_id: request_id,
+          requested_by: context.connection_id,
+          forwarded_to: publisher.connection_id,
+        },
+      );
+
+      let track_status_request = TrackStatusRequest::new(
+        relay_request_id,
+        m.track_namespace.clone(),
+        m.track_name.clone(),
+        m.forwarded_parameters(),
+      );
+      info!(
+        "forwarding TrackStatusRequest to publisher {} with relay's request id: {}",
+        publisher.connection_id, relay_request_id
+      );
+      publisher
+        .queue_message(ControlMessage::TrackStatusRequest(Box::new(
+          track_status_request,
+        )))
+        .await;
+      Ok(())
+    }
+    ControlMessage::TrackStatus(m) => {
+      // this comes from the publisher, relay it to the requester
+      info!("received TrackStatus message: {:?}", m);
+      let mut track_status = *m;
+
+      let request = context
+        .relay_track_status_requests
+        .write()
+        .await
+        .remove(&(context.connection_id, track_status.request_id));
+      let request = match request {
+        Some(request) => request,
+        None => {
+          warn!("request id is not verified: {:?}", track_status.request_id);
+          return Ok(());
+        }
+      };
+
+      let requester = {
+        let mngr = context.client_manager.read().await;
+        mngr.get(request.requested_by).await
+      };
+      match requester {
+        Some(requester) => {
+          track_status.request_id = request.original_request_id;
+          requester
+            .queue_message(ControlMessage::TrackStatus(Box::new(track_status)))
+            .await;
+          requester
+            .complete_request(request.original_request_id)
+            .await;
+        }
+        None => warn!("requester not found: {:?}", request.requested_by),
+      }
+      Ok(())
+    }
+    _ => {
+      // no-op
+      Ok(())
+    }
+  }
+}
+
+/// Cleans up the forwarded TRACK_STATUS_REQUESTs of a closed session.
+/// Requests it forwarded get RELAY_UNAVAILABLE, as the publisher will never
+/// answer them, and requests it made are dropped.
+pub(crate) async fn abandon_forwarded_requests(context: &SessionContext) {
+  let abandoned: Vec<TrackStatusForward> = {
+    let mut requests = context.relay_track_status_requests.write().await;
+    let keys: Vec<RelayRequestKey> = requests
+      .iter()
+      .filter(|(_, r)| {
+        r.forwarded_to == context.connection_id || r.requested_by == context.connection_id
+      })
+      .map(|(key, _)| *key)
+      .collect();
+    keys
+      .iter()
+      .filter_map(|key| requests.remove(key))
+      .filter(|r| r.requested_by != context.connection_id)
+      .collect()
+  };
+
+  for request in abandoned {
+    let requester = {
+      let mngr = context.client_manager.read().await;
+      mngr.get(request.requested_by).await
+    };
+    let Some(requester) = requester else {
+      continue;
+    };
+    info!(
+      "publisher {} closed before answering track status request {} of client {}",
+      context.connection_id, request.original_request_id, request.requested_by
+    );
+    let track_status = TrackStatus::new(
+      request.original_request_id,
+      TrackStatusCode::RelayUnavailable,
+      Location::new(0, 0),
+      vec![],
+    );
+    requester
+      .queue_message(ControlMessage::TrackStatus(Box::new(track_status)))
+      .await;
+    requester
+      .complete_request(request.original_request_id)
+      .await;
+  }
+}
+
+/// Answers a TRACK_STATUS_REQUEST of the client, which completes the request
+async fn send_track_status(
+  client: &MOQTClient,
+  control_stream_handler: &mut ControlStreamHandler,
+  track_status: TrackStatus,
+) -> Result<(), TerminationCode> {
+  let request_id = track_status.request_id;
+  control_stream_handler.send_impl(&track_status).await?;
+  client.complete_request(request_id).await;
+  Ok(())
+}
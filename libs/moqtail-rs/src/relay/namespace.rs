@@ -0,0 +1,162 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::client::MOQTClient;
+use super::client_manager::ClientManager;
+use super::session_context::RelayRequestKey;
+use super::track::Track;
+use crate::model::common::reason_phrase::ReasonPhrase;
+use crate::model::common::tuple::Tuple;
+use crate::model::control::announce_cancel::AnnounceCancel;
+use crate::model::control::constant::{AnnounceErrorCode, SubscribeDoneStatusCode};
+use crate::model::control::control_message::ControlMessage;
+use crate::model::control::unsubscribe::Unsubscribe;
+use crate::transport::data_stream_handler::SubscribeRequest;
+use std::collections::BTreeMap;
+use tokio::sync::RwLock;
+use tracing::{error, info};
+
+/// Withdraws a namespace announced by `publisher`. Subscribers of its
+/// announcements get UNANNOUNCE, every track under it is ended with
+/// SUBSCRIBE_DONE and the relay's subscriptions to those tracks are ended
+/// with UNSUBSCRIBE. Returns false if the publisher had not announced it.
+pub(crate) async fn withdraw_namespace(
+  client_manager: &RwLock<ClientManager>,
+  tracks: &RwLock<BTreeMap<u64, Track>>,
+  relay_subscribe_requests: &RwLock<BTreeMap<RelayRequestKey, SubscribeRequest>>,
+  publisher: &MOQTClient,
+  track_namespace: &Tuple,
+  reason: &str,
+) -> bool {
+  if !publisher
+    .remove_announced_track_namespace(track_namespace)
+    .await
+  {
+    return false;
+  }
+
+  client_manager
+    .read()
+    .await
+    .forward_unannounce(publisher.connection_id, track_namespace)
+    .await;
+
+  // tracks that are still covered by another namespace of the publisher stay
+  let remaining_namespaces = publisher.announced_track_namespaces.read().await.clone();
+  let mut tracks = tracks.write().await;
+  let ended_tracks: Vec<u64> = tracks
+    .values()
+    .filter(|track| {
+      track.publisher_connection_id == publisher.connection_id
+        && track.track_namespace.starts_with(track_namespace)
+        && !remaining_namespaces
+          .iter()
+          .any(|ns| track.track_namespace.starts_with(ns))
+    })
+    .map(|track| track.track_alias)
+    .collect();
+
+  for track_alias in ended_tracks {
+    if let Some(track) = tracks.remove(&track_alias) {
+      info!(
+        "ending track {} as namespace {:?} is withdrawn",
+        track_alias, track_namespace
+      );
+      if let Err(e) = track
+        .notify_track_ended(SubscribeDoneStatusCode::TrackEnded, reason)
+        .await
+      {
+        error!(
+          "Failed to notify subscribers for track {}: {:?}",
+          track_alias, e
+        );
+      }
+    }
+    publisher.remove_published_track(track_alias).await;
+    unsubscribe_upstream(relay_subscribe_requests, publisher, track_alias).await;
+  }
+  true
+}
+
+/// Forgets the relay's subscription to a track and sends UNSUBSCRIBE to
+/// its publisher
+async fn unsubscribe_upstream(
+  relay_subscribe_requests: &RwLock<BTreeMap<RelayRequestKey, SubscribeRequest>>,
+  publisher: &MOQTClient,
+  track_alias: u64,
+) {
+  let relay_request_keys: Vec<RelayRequestKey> = {
+    let mut requests = relay_subscribe_requests.write().await;
+    let relay_request_keys: Vec<RelayRequestKey> = requests
+      .iter()
+      .filter(|((publisher_id, _), r)| {
+        *publisher_id == publisher.connection_id && r.subscribe_request.track_alias == track_alias
+      })
+      .map(|(key, _)| *key)
+      .collect();
+    for key in &relay_request_keys {
+      requests.remove(key);
+    }
+    relay_request_keys
+  };
+
+  for (_, relay_request_id) in relay_request_keys {
+    info!(
+      "unsubscribing from track {} of client {}",
+      track_alias, publisher.connection_id
+    );
+    publisher
+      .queue_message(ControlMessage::Unsubscribe(Box::new(Unsubscribe::new(
+        relay_request_id,
+      ))))
+      .await;
+  }
+}
+
+/// Revokes a namespace on the relay's own initiative: tears it down like an
+/// UNANNOUNCE and tells the publisher with ANNOUNCE_CANCEL.
+pub(crate) async fn revoke_namespace(
+  client_manager: &RwLock<ClientManager>,
+  tracks: &RwLock<BTreeMap<u64, Track>>,
+  relay_subscribe_requests: &RwLock<BTreeMap<RelayRequestKey, SubscribeRequest>>,
+  publisher: &MOQTClient,
+  track_namespace: &Tuple,
+  error_code: AnnounceErrorCode,
+  reason: &str,
+) -> bool {
+  if !withdraw_namespace(
+    client_manager,
+    tracks,
+    relay_subscribe_requests,
+    publisher,
+    track_namespace,
+    reason,
+  )
+  .await
+  {
+    return false;
+  }
+
+  info!(
+    "revoking namespace {:?} of client {}: {}",
+    track_namespace, publisher.connection_id, reason
+  );
+  let reason_phrase = ReasonPhrase::try_new(reason.to_string())
+    .unwrap_or_else(|_| ReasonPhrase::try_new(String::from("Announce cancelled")).unwrap());
+  let announce_cancel = AnnounceCancel::new(track_namespace.clone(), error_code, reason_phrase);
+  publisher
+    .queue_message(ControlMessage::AnnounceCancel(Box::new(announce_cancel)))
+    .await;
+  true
+}
@@ -0,0 +1,144 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::model::control::constant::GroupOrder;
+
+/// Number of bits of the group term in the send order. The term is the
+/// distance of a group from the newest group of its track, so it does not
+/// depend on how large group IDs grow. Groups further behind than this all
+/// rank as the oldest.
+const GROUP_BITS: u32 = 16;
+const GROUP_MASK: u64 = (1 << GROUP_BITS) - 1;
+
+/// Resolves the group order requested by a subscriber. `Original` defers to
+/// the order the publisher announced in SUBSCRIBE_OK, which in turn defaults
+/// to ascending.
+pub(crate) fn resolve_group_order(requested: GroupOrder, publisher: GroupOrder) -> GroupOrder {
+  match (requested, publisher) {
+    (GroupOrder::Original, GroupOrder::Descending) => GroupOrder::Descending,
+    (GroupOrder::Original, _) => GroupOrder::Ascending,
+    (requested, _) => requested,
+  }
+}
+
+/// Computes the send order of a data stream. The transport sends streams with
+/// a higher value first, and the order is comparable across every
+/// subscription and fetch of a session.
+///
+/// Per the draft's priority rules, the subscriber priority decides first, then
+/// the publisher priority (for both, lower values are more important), and
+/// then the group order between groups of the same priorities. `newest_group`
+/// is the newest group known on the track, which moves as the track grows.
+pub(crate) fn send_order(
+  subscriber_priority: u8,
+  publisher_priority: u8,
+  group_order: GroupOrder,
+  group_id: u64,
+  newest_group: u64,
+) -> i32 {
+  let age = newest_group.saturating_sub(group_id).min(GROUP_MASK);
+  let group = match group_order {
+    GroupOrder::Descending => GROUP_MASK - age,
+    _ => age,
+  };
+  let order = (u64::from(u8::MAX - subscriber_priority) << (GROUP_BITS + 8))
+    | (u64::from(u8::MAX - publisher_priority) << GROUP_BITS)
+    | group;
+  // shift the unsigned order into the i32 range without changing it
+  (order as i64 + i64::from(i32::MIN)) as i32
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_subscriber_priority_wins() {
+    let audio = send_order(0, 200, GroupOrder::Ascending, 1, 1);
+    let video = send_order(1, 0, GroupOrder::Descending, 100, 100);
+    assert!(audio > video);
+  }
+
+  #[test]
+  fn test_publisher_priority_breaks_ties() {
+    let audio = send_order(128, 1, GroupOrder::Ascending, 10, 10);
+    let video = send_order(128, 2, GroupOrder::Ascending, 1, 10);
+    assert!(audio > video);
+  }
+
+  #[test]
+  fn test_group_order() {
+    let old = send_order(128, 128, GroupOrder::Descending, 10, 11);
+    let new = send_order(128, 128, GroupOrder::Descending, 11, 11);
+    assert!(new > old);
+
+    let old = send_order(128, 128, GroupOrder::Ascending, 10, 11);
+    let new = send_order(128, 128, GroupOrder::Ascending, 11, 11);
+    assert!(old > new);
+  }
+
+  #[test]
+  fn test_group_order_across_wrap_boundary() {
+    let newest = GROUP_MASK + 10;
+    let old = send_order(128, 128, GroupOrder::Descending, GROUP_MASK, newest);
+    let new = send_order(128, 128, GroupOrder::Descending, GROUP_MASK + 1, newest);
+    assert!(new > old);
+
+    let old = send_order(128, 128, GroupOrder::Ascending, GROUP_MASK, newest);
+    let new = send_order(128, 128, GroupOrder::Ascending, GROUP_MASK + 1, newest);
+    assert!(old > new);
+  }
+
+  #[test]
+  fn test_groups_beyond_window_rank_as_oldest() {
+    let newest = 3 * GROUP_MASK;
+    let oldest = send_order(128, 128, GroupOrder::Descending, 0, newest);
+    let old = send_order(128, 128, GroupOrder::Descending, GROUP_MASK, newest);
+    let new = send_order(128, 128, GroupOrder::Descending, newest, newest);
+    assert_eq!(oldest, old);
+    assert!(new > old);
+
+    let oldest = send_order(128, 128, GroupOrder::Ascending, 0, newest);
+    let new = send_order(128, 128, GroupOrder::Ascending, newest, newest);
+    assert!(oldest > new);
+  }
+
+  #[test]
+  fn test_extremes_fit_in_i32() {
+    assert_eq!(
+      send_order(u8::MAX, u8::MAX, GroupOrder::Ascending, 0, 0),
+      i32::MIN
+    );
+    assert_eq!(
+      send_order(0, 0, GroupOrder::Descending, u64::MAX, u64::MAX),
+      i32::MAX
+    );
+  }
+
+  #[test]
+  fn test_resolve_group_order() {
+    assert_eq!(
+      resolve_group_order(GroupOrder::Original, GroupOrder::Descending),
+      GroupOrder::Descending
+    );
+    assert_eq!(
+      resolve_group_order(GroupOrder::Original, GroupOrder::Original),
+      GroupOrder::Ascending
+    );
+    assert_eq!(
+      resolve_group_order(GroupOrder::Ascending, GroupOrder::Descending),
+      GroupOrder::Ascending
+    );
+  }
+}
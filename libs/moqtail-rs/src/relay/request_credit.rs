@@ -0,0 +1,169 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Credit policy for the request IDs a peer may use towards the relay.
+//
+// Every request a peer sends holds on to its request ID until the request
+// completes, e.g. with SUBSCRIBE_DONE, UNSUBSCRIBE or an error reply. The
+// IDs of completed requests are handed back in windows: once a window's
+// worth is freed a new MAX_REQUEST_ID is issued. A peer that sent
+// REQUESTS_BLOCKED gets every freed ID right away. A window of zero
+// disables the policy and keeps the initial limit for the whole session.
+
+use std::collections::BTreeSet;
+
+/// Request IDs a single request uses up, peers number their requests in steps of 2
+const REQUEST_ID_STEP: u64 = 2;
+
+#[derive(Debug)]
+pub(crate) struct RequestCredit {
+  max_request_id: u64,
+  window: u64,
+  open_requests: BTreeSet<u64>,
+  freed: u64,
+  blocked: bool,
+}
+
+impl RequestCredit {
+  pub(crate) fn new(initial_max_request_id: u64, window: u64) -> Self {
+    RequestCredit {
+      max_request_id: initial_max_request_id,
+      window,
+      open_requests: BTreeSet::new(),
+      freed: 0,
+      blocked: false,
+    }
+  }
+
+  /// The request IDs the peer may use, exclusive
+  pub(crate) fn max_request_id(&self) -> u64 {
+    self.max_request_id
+  }
+
+  /// Records a new request of the peer, which holds its ID until it completes
+  pub(crate) fn open(&mut self, request_id: u64) {
+    self.open_requests.insert(request_id);
+  }
+
+  /// Frees the ID of a completed request. Returns the new maximum once
+  /// enough IDs were freed, or `None` when the peer has to wait for more.
+  /// A request completes once, later calls for the same ID are ignored.
+  pub(crate) fn complete(&mut self, request_id: u64) -> Option<u64> {
+    if !self.open_requests.remove(&request_id) || self.window == 0 {
+      return None;
+    }
+    self.freed = self.freed.saturating_add(REQUEST_ID_STEP);
+    if self.freed < self.window && !self.blocked {
+      return None;
+    }
+    self.grant()
+  }
+
+  /// Handles REQUESTS_BLOCKED at `blocked_at`. A peer blocked at an older
+  /// maximum already has a MAX_REQUEST_ID on the way, so it does not get
+  /// more credit. Otherwise the freed IDs are granted, or the next
+  /// completed request is.
+  pub(crate) fn blocked(&mut self, blocked_at: u64) -> Option<u64> {
+    if self.window == 0 || blocked_at < self.max_request_id {
+      return None;
+    }
+    if self.freed == 0 {
+      self.blocked = true;
+      return None;
+    }
+    self.grant()
+  }
+
+  fn grant(&mut self) -> Option<u64> {
+    self.max_request_id = self.max_request_id.saturating_add(self.freed);
+    self.freed = 0;
+    self.blocked = false;
+    Some(self.max_request_id)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn credit_with_open_requests(max_request_id: u64, window: u64) -> RequestCredit {
+    let mut credit = RequestCredit::new(max_request_id, window);
+    for request_id in (0..max_request_id).step_by(2) {
+      credit.open(request_id);
+    }
+    credit
+  }
+
+  #[test]
+  fn test_open_requests_keep_limit() {
+    let mut credit = credit_with_open_requests(64, 8);
+    assert_eq!(credit.max_request_id(), 64);
+    assert_eq!(credit.complete(0), None);
+    assert_eq!(credit.complete(2), None);
+    assert_eq!(credit.complete(4), None);
+    assert_eq!(credit.max_request_id(), 64);
+  }
+
+  #[test]
+  fn test_completed_requests_grant_a_window() {
+    let mut credit = credit_with_open_requests(64, 8);
+    for request_id in [0, 2, 4] {
+      assert_eq!(credit.complete(request_id), None);
+    }
+    assert_eq!(credit.complete(6), Some(72));
+    assert_eq!(credit.max_request_id(), 72);
+  }
+
+  #[test]
+  fn test_request_completes_once() {
+    let mut credit = credit_with_open_requests(64, 4);
+    assert_eq!(credit.complete(0), None);
+    assert_eq!(credit.complete(0), None);
+    // never opened
+    assert_eq!(credit.complete(100), None);
+    assert_eq!(credit.complete(2), Some(68));
+  }
+
+  #[test]
+  fn test_blocked_peer_gets_freed_ids() {
+    let mut credit = credit_with_open_requests(64, 8);
+    assert_eq!(credit.complete(0), None);
+    assert_eq!(credit.blocked(64), Some(66));
+  }
+
+  #[test]
+  fn test_blocked_peer_waits_for_a_completed_request() {
+    let mut credit = credit_with_open_requests(64, 8);
+    assert_eq!(credit.blocked(64), None);
+    assert_eq!(credit.complete(10), Some(66));
+    // the next request waits for a window again
+    assert_eq!(credit.complete(12), None);
+  }
+
+  #[test]
+  fn test_blocked_at_older_limit() {
+    let mut credit = credit_with_open_requests(64, 2);
+    assert_eq!(credit.complete(0), Some(66));
+    assert_eq!(credit.complete(2), Some(68));
+    assert_eq!(credit.blocked(64), None);
+  }
+
+  #[test]
+  fn test_disabled_window_keeps_initial_limit() {
+    let mut credit = credit_with_open_requests(64, 0);
+    assert_eq!(credit.complete(0), None);
+    assert_eq!(credit.blocked(64), None);
+    assert_eq!(credit.max_request_id(), 64);
+  }
+}
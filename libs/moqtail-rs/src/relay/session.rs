@@ -12,34 +12,41 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use anyhow::Result;
-use moqtail::model::{
-  control::{constant, control_message::ControlMessage, server_setup::ServerSetup},
+use crate::model::{
+  control::{constant::GroupOrder, control_message::ControlMessage, server_setup::ServerSetup},
+  data::{constant::StreamResetCode, fetch_header::FetchHeader, object::Object},
   error::TerminationCode,
+  parameter::setup_parameter::SetupParameter,
 };
-use moqtail::transport::{
+use crate::transport::{
   control_stream_handler::ControlStreamHandler,
   data_stream_handler::{HeaderInfo, RecvDataStream},
+  datagram_handler::DatagramHandler,
+  moq_transport::{BoxRecvStream, BoxSendStream, MoqTransport},
 };
+use anyhow::Result;
+use bytes::Bytes;
 use std::{collections::BTreeMap, sync::Arc};
 use tokio::sync::RwLock;
 use tracing::{Instrument, debug, error, info, info_span, warn};
-use wtransport::{RecvStream, SendStream, endpoint::IncomingSession};
+use wtransport::endpoint::IncomingSession;
 
-use crate::server::{Server, stream_id::StreamId};
+use crate::relay::{Relay, stream_id::StreamId};
 
 use super::{
   client::MOQTClient,
-  message_handlers,
+  message_handlers, priority,
+  request_credit::RequestCredit,
   session_context::{RequestMaps, SessionContext},
   track::Track,
+  track_cache::FillEvent,
   utils,
 };
 
 pub struct Session {}
 
 impl Session {
-  pub async fn new(incoming_session: IncomingSession, server: Server) -> Result<Session> {
+  pub async fn new(incoming_session: IncomingSession, server: Relay) -> Result<Session> {
     let session_request = incoming_session.await?;
 
     info!(
@@ -48,30 +55,41 @@ impl Session {
       session_request.path(),
     );
 
+    let connection = session_request.accept().await?;
+    Self::start(Arc::new(connection), server)
+  }
+
+  /// Runs a session over an established connection, whichever transport
+  /// carries it
+  pub fn start(connection: Arc<dyn MoqTransport>, server: Relay) -> Result<Session> {
     let client_manager = server.client_manager.clone();
     let tracks = server.tracks.clone();
-    let server_config = server.app_config;
+    let server_config = server.config.clone();
+    let hooks = server.hooks.clone();
     let relay_fetch_requests = server.relay_fetch_requests.clone();
     let client_fetch_requests = Arc::new(RwLock::new(BTreeMap::new()));
     let relay_subscribe_requests = server.relay_subscribe_requests.clone();
     let client_subscribe_requests = Arc::new(RwLock::new(BTreeMap::new()));
-    let relay_next_request_id = server.relay_next_request_id.clone();
-    let connection = session_request.accept().await?;
+    let relay_track_status_requests = server.relay_track_status_requests.clone();
+    let relay_fetch_fills = server.relay_fetch_fills.clone();
 
     let request_maps = RequestMaps {
       relay_fetch_requests,
       client_fetch_requests,
       relay_subscribe_requests,
       client_subscribe_requests,
+      relay_track_status_requests,
+      relay_fetch_fills,
     };
 
     let context = Arc::new(SessionContext::new(
       server_config,
+      hooks,
       client_manager,
       tracks,
       request_maps,
       connection,
-      relay_next_request_id,
+      server.drain.clone(),
     ));
 
     tokio::spawn(Self::handle_connection_close(context.clone()));
@@ -123,8 +141,8 @@ impl Session {
 
   async fn handle_control_messages(
     context: Arc<SessionContext>,
-    send_stream: SendStream,
-    recv_stream: RecvStream,
+    send_stream: BoxSendStream,
+    recv_stream: BoxRecvStream,
   ) -> core::result::Result<(), TerminationCode> {
     info!("new control message stream");
     let mut control_stream_handler = ControlStreamHandler::new(send_stream, recv_stream);
@@ -136,7 +154,7 @@ impl Session {
     {
       Ok(client) => client,
       Err(_) => {
-        context.connection.close(0u32.into(), b"Negotiation failed");
+        context.connection.close(0, b"Negotiation failed");
         return Err(TerminationCode::VersionNegotiationFailed);
       }
     };
@@ -144,6 +162,13 @@ impl Session {
     // Set the client in the context
     context.set_client(client.clone()).await;
 
+    // a session opened while draining is sent away right after setup
+    if let Some(goaway) = context.drain.read().await.clone() {
+      client
+        .queue_message(ControlMessage::Goaway(Box::new(goaway)))
+        .await;
+    }
+
     // start waiting for unistreams
     let session_context = context.clone();
     tokio::spawn(async move {
@@ -218,7 +243,7 @@ impl Session {
   fn close_session(context: Arc<SessionContext>, error_code: TerminationCode, msg: &str) {
     context
       .connection
-      .close(error_code.to_u32().into(), msg.as_bytes());
+      .close(error_code.to_u32(), msg.as_bytes());
   }
 
   // TODO: in an error close the connection
@@ -265,10 +290,25 @@ impl Session {
           });
         }
 
-        _ = context.connection.receive_datagram() => {
-          let connection_id = context.connection_id;
+        datagram = context.connection.receive_datagram() => {
+          let datagram = match datagram {
+            Ok(datagram) => datagram,
+            // only the connection fails here, malformed datagrams are
+            // dropped one by one in handle_datagram
+            Err(e) => {
+              info!(
+                "Connection lost, stopping stream acceptance for connection {}: {:?}",
+                context.connection_id, e
+              );
+              return Ok(());
+            }
+          };
           tokio::spawn(async move {
-            debug!("Received (dgram) from client {}", connection_id);
+            Self::handle_datagram(session_context, datagram)
+              .await
+              .unwrap_or_else(|e| {
+                error!("Error processing datagram: {:?}", e);
+              });
           });
         }
       }
@@ -354,9 +394,22 @@ impl Session {
       }
     }
 
+    // Withdraw the namespaces of the disconnected publisher from announce subscribers
+    if let Some(client) = context.get_client().await {
+      let announced_track_namespaces = client.announced_track_namespaces.read().await.clone();
+      let cm = client_manager_cleanup.read().await;
+      for track_namespace in announced_track_namespaces.iter() {
+        cm.forward_unannounce(context.connection_id, track_namespace)
+          .await;
+      }
+    }
+
     // Remove client from client_manager
     let mut cm = client_manager_cleanup.write().await;
     cm.remove(context.connection_id).await;
+    drop(cm);
+
+    message_handlers::MessageHandler::handle_connection_close(&context).await;
 
     // Remove client from all remaining tracks (as a subscriber)
     for (_, track) in tracks_cleanup.write().await.iter_mut() {
@@ -370,7 +423,7 @@ impl Session {
     Ok(())
   }
 
-  async fn handle_uni_stream(context: Arc<SessionContext>, stream: RecvStream) -> Result<()> {
+  async fn handle_uni_stream(context: Arc<SessionContext>, stream: BoxRecvStream) -> Result<()> {
     debug!("accepted unidirectional stream");
     let client = context.get_client().await;
     let client = match client {
@@ -380,17 +433,42 @@ impl Session {
 
     debug!("client is {}", client.connection_id);
 
-    let mut stream_handler = &RecvDataStream::new(stream, client.fetch_requests.clone());
+    let mut stream_handler =
+      &RecvDataStream::new_with_codec(stream, client.fetch_requests.clone(), client.codec.clone());
 
     let mut first_object = true;
     let mut track_alias = 0u64;
+    let mut group_id = 0u64;
     let mut stream_id: Option<StreamId> = None;
     let mut current_track: Option<Track> = None;
-
     let mut object_count = 0;
 
     loop {
-      let next = stream_handler.next_object().await;
+      // subscribers may change the timeout while the stream is open
+      let delivery_timeout = match &current_track {
+        Some(track) => track.delivery_timeout().await,
+        None => None,
+      };
+      let next = match delivery_timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, stream_handler.next_object()).await {
+          Ok(next) => next,
+          Err(_) => {
+            // a stalled group is only given up once a newer one has started
+            let track = current_track.as_ref().unwrap();
+            if track.largest_location.read().await.group <= group_id {
+              continue;
+            }
+            let stream_id = stream_id.unwrap();
+            warn!(
+              "delivery timeout exceeded, stopping stream client: {} track: {} stream_id: {} objects: {}",
+              context.connection_id, track_alias, stream_id, object_count
+            );
+            stream_handler.stop(StreamResetCode::DeliveryTimeout).await;
+            return track.stream_timed_out(&stream_id).await;
+          }
+        },
+        None => stream_handler.next_object().await,
+      };
 
       match next {
         (handler, Some(object)) => {
@@ -412,19 +490,22 @@ impl Session {
               HeaderInfo::Subgroup { header } => {
                 debug!("received Subgroup header: {:?}", header);
                 track_alias = header.track_alias;
+                group_id = header.group_id;
               }
               HeaderInfo::Fetch {
                 header,
                 fetch_request: _,
               } => {
                 debug!("received Fetch header: {:?}", header);
-                let fetch_request_id = header.request_id;
-                track_alias = client
-                  .fetch_requests
-                  .read()
-                  .await
-                  .get(&fetch_request_id)
-                  .map_or(0, |r| r.track_alias);
+                // a fetch the relay forwarded to this publisher
+                return Self::forward_fetch_stream(
+                  context.clone(),
+                  client.clone(),
+                  handler,
+                  header.request_id,
+                  object,
+                )
+                .await;
               }
             }
 
@@ -484,11 +565,203 @@ impl Session {
     Ok(())
   }
 
-  pub(crate) async fn get_next_relay_request_id(relay_next_request_id: Arc<RwLock<u64>>) -> u64 {
-    let current_request_id = *relay_next_request_id.read().await;
-    // increment by 2 for the next request
-    *relay_next_request_id.write().await = current_request_id + 2;
-    current_request_id
+  /// Feeds an OBJECT_DATAGRAM or OBJECT_DATAGRAM_STATUS from a publisher
+  /// into its track
+  async fn handle_datagram(context: Arc<SessionContext>, payload: Bytes) -> Result<()> {
+    let object = DatagramHandler::parse(payload)?;
+    debug!(
+      "Received (dgram) from client {} track: {} location: {:?}",
+      context.connection_id, object.track_alias, object.location
+    );
+
+    let track = context
+      .tracks
+      .read()
+      .await
+      .get(&object.track_alias)
+      .cloned();
+    let Some(track) = track else {
+      // a datagram for a track nobody subscribed to yet is dropped
+      warn!(
+        "track not found for datagram: {:?} client: {}",
+        object.track_alias, context.connection_id
+      );
+      return Ok(());
+    };
+    if track.publisher_connection_id != context.connection_id {
+      warn!(
+        "datagram for track {} from client {} which is not its publisher",
+        object.track_alias, context.connection_id
+      );
+      return Ok(());
+    }
+    track.new_datagram(&object).await
+  }
+
+  /// Pipes a fetch stream from a publisher to the client that requested the
+  /// fetch, storing the objects in the track cache on the way
+  async fn forward_fetch_stream(
+    context: Arc<SessionContext>,
+    client: Arc<MOQTClient>,
+    stream_handler: &RecvDataStream,
+    fetch_request_id: u64,
+    first_object: Object,
+  ) -> Result<()> {
+    let request = client
+      .fetch_requests
+      .read()
+      .await
+      .get(&fetch_request_id)
+      .cloned();
+    let request = match request {
+      Some(request) => request,
+      None => {
+        error!("fetch request not found: {:?}", fetch_request_id);
+        return Err(anyhow::Error::msg(TerminationCode::InternalError.to_json()));
+      }
+    };
+
+    // a gap fill goes to the merging fetch instead of the requester
+    let fill = context
+      .relay_fetch_fills
+      .read()
+      .await
+      .get(&(context.connection_id, fetch_request_id))
+      .cloned();
+    let requester = if fill.is_some() {
+      None
+    } else {
+      let mngr = context.client_manager.read().await;
+      mngr.get(request.requested_by).await
+    };
+    let track = context
+      .tracks
+      .read()
+      .await
+      .get(&request.track_alias)
+      .cloned();
+
+    let fetch_header = FetchHeader::new(request.original_request_id);
+    let mut fetch = request.fetch_request.clone();
+    fetch.request_id = request.original_request_id;
+    let header_info = HeaderInfo::Fetch {
+      header: fetch_header,
+      fetch_request: fetch.clone(),
+    };
+    let stream_id = utils::build_stream_id(request.track_alias, &header_info);
+
+    let mut send_stream = None;
+    if let Some(requester) = &requester {
+      let (publisher_group_order, newest_group) = match &track {
+        Some(track) => (
+          *track.publisher_group_order.read().await,
+          track.largest_location.read().await.group,
+        ),
+        None => (GroupOrder::Original, 0),
+      };
+      let priority = priority::send_order(
+        fetch.subscriber_priority,
+        first_object.publisher_priority,
+        priority::resolve_group_order(fetch.group_order, publisher_group_order),
+        first_object.location.group,
+        newest_group.max(first_object.location.group),
+      );
+      match requester
+        .open_stream(
+          &stream_id,
+          requester.codec.serialize_fetch_header(&fetch_header)?,
+          priority,
+        )
+        .await
+      {
+        Ok(stream) => send_stream = Some(stream),
+        Err(e) => error!("failed to open fetch stream {}: {:?}", stream_id, e),
+      }
+    } else if fill.is_none() {
+      warn!("requester not found: {:?}", request.requested_by);
+    }
+
+    let mut object_count = 0;
+    let mut cancelled = false;
+    let mut next_object = Some(first_object);
+    while let Some(object) = next_object {
+      // FETCH_CANCEL from the requester drops the request
+      if !context
+        .relay_fetch_requests
+        .read()
+        .await
+        .contains_key(&(context.connection_id, fetch_request_id))
+      {
+        info!(
+          "fetch {} was cancelled, stopping the stream",
+          fetch_request_id
+        );
+        stream_handler.stop(StreamResetCode::Cancelled).await;
+        cancelled = true;
+        break;
+      }
+      match object.try_into_fetch() {
+        Ok(fetch_object) => {
+          if let Some(track) = &track {
+            track.cache.add_object(fetch_object.clone()).await;
+          }
+          if let Some(fill) = &fill {
+            let _ = fill.send(FillEvent::Object(fetch_object.clone()));
+          }
+          if let (Some(requester), Some(send_stream)) = (&requester, &send_stream) {
+            let object_id = fetch_object.object_id;
+            if let Err(e) = requester
+              .write_object_to_stream(
+                &stream_id,
+                object_id,
+                fetch_object.serialize()?,
+                Some(send_stream.clone()),
+              )
+              .await
+            {
+              error!("failed to write fetch object to {}: {:?}", stream_id, e);
+            }
+          }
+          object_count += 1;
+        }
+        Err(e) => error!("invalid fetch object: {:?}", e),
+      }
+      next_object = stream_handler.next_object().await.1;
+    }
+
+    info!(
+      "fetch stream finished client: {} stream_id: {} objects: {}",
+      context.connection_id, stream_id, object_count
+    );
+    // a cancelled stream has already been reset by the requester's session
+    if let (false, Some(requester), Some(send_stream)) = (cancelled, &requester, send_stream) {
+      if let Err(e) = send_stream.lock().await.finish().await {
+        error!("failed to finish fetch stream {}: {:?}", stream_id, e);
+      }
+      requester.remove_stream_by_stream_id(&stream_id).await;
+    }
+    if let Some(requester) = &requester {
+      requester
+        .complete_request(request.original_request_id)
+        .await;
+    }
+    client
+      .fetch_requests
+      .write()
+      .await
+      .remove(&fetch_request_id);
+    context
+      .relay_fetch_requests
+      .write()
+      .await
+      .remove(&(context.connection_id, fetch_request_id));
+    // dropping the last sender tells the merging fetch that the fill is done
+    context
+      .relay_fetch_fills
+      .write()
+      .await
+      .remove(&(context.connection_id, fetch_request_id));
+    Ok(())
   }
 
   async fn negotiate(
@@ -516,44 +789,64 @@ impl Session {
 
     utils::print_msg_bytes(&client_setup);
 
-    let max_request_id_param = {
-      let max_request_id = context.max_request_id.read().await;
-      moqtail::model::parameter::setup_parameter::SetupParameter::new_max_request_id(
-        *max_request_id + 1,
-      )
-      .try_into()
-      .unwrap()
-    };
-
-    let server_setup = ServerSetup::new(constant::DRAFT_11, vec![max_request_id_param]);
+    // request ids at or above the maximum are refused, see the handlers
+    let max_request_id_param =
+      SetupParameter::new_max_request_id(context.server_config.initial_max_request_id)
+        .try_into()
+        .unwrap();
 
     debug!("client setup: {:?}", client_setup.supported_versions);
+
+    // the highest version both ends speak
+    let Some(codec) = context
+      .hooks
+      .versions
+      .negotiate(&client_setup.supported_versions)
+    else {
+      warn!(
+        "no mutually supported version, client: {:?} relay: {:?}",
+        client_setup.supported_versions,
+        context.hooks.versions.supported_versions()
+      );
+      return Err(anyhow::Error::msg(
+        TerminationCode::VersionNegotiationFailed.to_json(),
+      ));
+    };
+
+    let auth_token_cache_size = context.server_config.auth_token_cache_size;
+    let auth_token_cache_size_param =
+      SetupParameter::new_max_auth_token_cache_size(auth_token_cache_size)
+        .try_into()
+        .unwrap();
+    let server_setup = ServerSetup::new(
+      codec.version(),
+      vec![max_request_id_param, auth_token_cache_size_param],
+    );
     debug!("server setup: {:?}", server_setup);
 
-    let client = if client_setup
-      .supported_versions
-      .contains(&constant::DRAFT_11)
-    {
+    let client = {
       let mut m = context.client_manager.write().await;
 
       let client = MOQTClient::new(
         context.connection_id,
-        Arc::new(context.connection.clone()),
+        context.connection.clone(),
+        codec.clone(),
         Arc::new(client_setup),
+        auth_token_cache_size,
+        RequestCredit::new(
+          context.server_config.initial_max_request_id,
+          context.server_config.request_id_window,
+        ),
       );
       let client = Arc::new(client);
       m.add(client.clone()).await;
       client
-    } else {
-      warn!("unsupported version");
-      return Err(anyhow::Error::msg(
-        TerminationCode::VersionNegotiationFailed.to_json(),
-      ));
     };
 
     match control_stream_handler.send_impl(&server_setup).await {
       Ok(_) => {
         debug!("Sent server setup to client");
+        control_stream_handler.set_codec(codec);
         Ok(client)
       }
       Err(e) => {
@@ -0,0 +1,108 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::sync::{RwLock, mpsc::UnboundedSender};
+use tokio::task::AbortHandle;
+
+use crate::model::control::goaway::GoAway;
+use crate::transport::data_stream_handler::{FetchRequest, SubscribeRequest};
+use crate::transport::moq_transport::MoqTransport;
+
+use super::{
+  client::MOQTClient, client_manager::ClientManager, config::RelayConfig, hooks::RelayHooks,
+  stream_id::StreamId, track::Track, track_cache::FillEvent,
+};
+
+/// A request the relay sent upstream: the publisher's connection id and the
+/// relay's request id on that connection
+pub type RelayRequestKey = (usize, u64);
+
+/// A TRACK_STATUS_REQUEST the relay forwarded to a publisher
+#[derive(Debug, Clone)]
+pub struct TrackStatusForward {
+  pub original_request_id: u64,
+  pub requested_by: usize, // connection id
+  pub forwarded_to: usize, // connection id of the publisher
+}
+
+pub struct RequestMaps {
+  pub relay_fetch_requests: Arc<RwLock<BTreeMap<RelayRequestKey, FetchRequest>>>,
+  pub client_fetch_requests: Arc<RwLock<BTreeMap<u64, FetchRequest>>>,
+  pub relay_subscribe_requests: Arc<RwLock<BTreeMap<RelayRequestKey, SubscribeRequest>>>,
+  pub client_subscribe_requests: Arc<RwLock<BTreeMap<u64, SubscribeRequest>>>,
+  pub relay_track_status_requests: Arc<RwLock<BTreeMap<RelayRequestKey, TrackStatusForward>>>,
+  pub relay_fetch_fills: Arc<RwLock<BTreeMap<RelayRequestKey, UnboundedSender<FillEvent>>>>,
+}
+
+pub struct SessionContext {
+  pub(crate) client_manager: Arc<RwLock<ClientManager>>,
+  pub(crate) tracks: Arc<RwLock<BTreeMap<u64, Track>>>, // the tracks the relay is subscribed to, key is the track alias
+  pub(crate) relay_fetch_requests: Arc<RwLock<BTreeMap<RelayRequestKey, FetchRequest>>>,
+  pub(crate) _client_fetch_requests: Arc<RwLock<BTreeMap<u64, FetchRequest>>>,
+  pub(crate) relay_subscribe_requests: Arc<RwLock<BTreeMap<RelayRequestKey, SubscribeRequest>>>,
+  pub(crate) client_subscribe_requests: Arc<RwLock<BTreeMap<u64, SubscribeRequest>>>,
+  pub(crate) relay_track_status_requests:
+    Arc<RwLock<BTreeMap<RelayRequestKey, TrackStatusForward>>>,
+  pub(crate) relay_fetch_fills: Arc<RwLock<BTreeMap<RelayRequestKey, UnboundedSender<FillEvent>>>>, // upstream fetches filling a partial cache hit
+  pub(crate) fetch_tasks: Arc<RwLock<BTreeMap<u64, (AbortHandle, StreamId)>>>, // fetches served from the cache, key is the client's request id
+  pub(crate) connection_id: usize,
+  pub(crate) client: Arc<RwLock<Option<Arc<MOQTClient>>>>, // the client that is connected to this session
+  pub(crate) connection: Arc<dyn MoqTransport>,
+  pub(crate) server_config: Arc<RelayConfig>,
+  pub(crate) hooks: RelayHooks,
+  pub(crate) is_connection_closed: Arc<RwLock<bool>>,
+  pub(crate) drain: Arc<RwLock<Option<GoAway>>>, // the GOAWAY of a draining relay
+}
+
+impl SessionContext {
+  #[allow(clippy::too_many_arguments)]
+  pub fn new(
+    server_config: Arc<RelayConfig>,
+    hooks: RelayHooks,
+    client_manager: Arc<RwLock<ClientManager>>,
+    tracks: Arc<RwLock<BTreeMap<u64, Track>>>,
+    request_maps: RequestMaps,
+    connection: Arc<dyn MoqTransport>,
+    drain: Arc<RwLock<Option<GoAway>>>,
+  ) -> Self {
+    Self {
+      client_manager,
+      tracks,
+      relay_fetch_requests: request_maps.relay_fetch_requests,
+      _client_fetch_requests: request_maps.client_fetch_requests,
+      relay_subscribe_requests: request_maps.relay_subscribe_requests,
+      client_subscribe_requests: request_maps.client_subscribe_requests,
+      relay_track_status_requests: request_maps.relay_track_status_requests,
+      relay_fetch_fills: request_maps.relay_fetch_fills,
+      fetch_tasks: Arc::new(RwLock::new(BTreeMap::new())),
+      connection_id: connection.stable_id(),
+      client: Arc::new(RwLock::new(None)), // initially no client is set
+      connection,
+      server_config,
+      hooks,
+      is_connection_closed: Arc::new(RwLock::new(false)),
+      drain,
+    }
+  }
+
+  pub async fn set_client(&self, client: Arc<MOQTClient>) {
+    let mut guard = self.client.write().await;
+    *guard = Some(client);
+  }
+
+  pub async fn get_client(&self) -> Option<Arc<MOQTClient>> {
+    self.client.read().await.clone()
+  }
+}
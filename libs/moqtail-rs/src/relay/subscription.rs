@@ -0,0 +1,1240 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::model::common::location::Location;
+use crate::model::common::pair::KeyValuePair;
+use crate::model::common::reason_phrase::ReasonPhrase;
+use crate::model::control::constant::{FilterType, GroupOrder, SubscribeDoneStatusCode};
+use crate::model::control::control_message::ControlMessage;
+use crate::model::control::subscribe::Subscribe;
+use crate::model::control::subscribe_done::SubscribeDone;
+use crate::model::control::subscribe_update::SubscribeUpdate;
+use crate::model::data::constant::{ObjectStatus, StreamResetCode};
+use crate::model::data::object::Object;
+use crate::model::parameter::version_parameter::{VersionParameter, VersionParameters};
+use crate::relay::client::MOQTClient;
+use crate::relay::config::RelayConfig;
+use crate::relay::object_logger::ObjectLogger;
+use crate::relay::priority;
+use crate::relay::stream_id::StreamId;
+use crate::relay::track::TrackEvent;
+use crate::relay::track_cache::TrackCache;
+use crate::relay::utils;
+use crate::transport::data_stream_handler::HeaderInfo;
+use crate::transport::moq_transport::BoxSendStream;
+use anyhow::Result;
+use bytes::Bytes;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::sync::RwLock;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tracing::warn;
+use tracing::{debug, error, info};
+
+/// The properties of a subscription that SUBSCRIBE_UPDATE can change
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriptionParams {
+  pub start_location: Option<Location>,
+  /// Last group to deliver, inclusive. `None` means open-ended
+  pub end_group: Option<u64>,
+  pub subscriber_priority: u8,
+  pub forward: bool,
+  /// How long an object may wait in the relay before its stream is reset
+  pub delivery_timeout: Option<Duration>,
+  /// NextGroupStart on a track without objects yet, the start is resolved
+  /// by [`SubscriptionParams::resolve_start`]
+  pub awaits_next_group: bool,
+}
+
+impl SubscriptionParams {
+  /// Resolves the filter of a SUBSCRIBE against the largest location the
+  /// track has seen so far, if any
+  pub fn from_subscribe(subscribe: &Subscribe, largest_location: Option<&Location>) -> Self {
+    let start_location = match (&subscribe.start_location, subscribe.filter_type) {
+      (Some(start_location), _) => Some(start_location.clone()),
+      (None, FilterType::NextGroupStart) => {
+        largest_location.map(|largest| Location::new(largest.group + 1, 0))
+      }
+      (None, _) => None,
+    };
+    Self {
+      awaits_next_group: start_location.is_none()
+        && subscribe.filter_type == FilterType::NextGroupStart,
+      start_location,
+      end_group: subscribe.end_group,
+      subscriber_priority: subscribe.subscriber_priority,
+      forward: subscribe.forward,
+      delivery_timeout: subscribe.delivery_timeout(),
+    }
+  }
+
+  /// Resolves a NextGroupStart filter at the first object seen. Its group
+  /// may have started before the subscription, so delivery starts with the
+  /// next group.
+  pub fn resolve_start(&mut self, group: u64) {
+    if self.awaits_next_group && self.start_location.is_none() {
+      self.start_location = Some(Location::new(group + 1, 0));
+    }
+    self.awaits_next_group = false;
+  }
+
+  /// Applies a SUBSCRIBE_UPDATE. The range can only be narrowed, so an
+  /// update that moves the start backwards or the end forwards is rejected.
+  pub fn apply_update(&mut self, update: &SubscribeUpdate) -> bool {
+    // SUBSCRIBE_UPDATE carries the end group plus one, zero is open-ended
+    let end_group = update.end_group.checked_sub(1);
+
+    if let Some(start_location) = &self.start_location
+      && update.start_location < *start_location
+    {
+      return false;
+    }
+    if let Some(current_end_group) = self.end_group {
+      match end_group {
+        Some(end_group) if end_group <= current_end_group => {}
+        _ => return false,
+      }
+    }
+
+    self.start_location = Some(update.start_location.clone());
+    self.end_group = end_group;
+    self.subscriber_priority = update.subscriber_priority;
+    self.forward = update.forward;
+    if let Some(delivery_timeout) = update.delivery_timeout() {
+      self.delivery_timeout = Some(delivery_timeout);
+    }
+    true
+  }
+
+  /// Whether any object of the group can be delivered
+  pub fn includes_group(&self, group: u64) -> bool {
+    self
+      .start_location
+      .as_ref()
+      .is_none_or(|start| group >= start.group)
+      && self.end_group.is_none_or(|end_group| group <= end_group)
+  }
+
+  pub fn includes(&self, location: &Location) -> bool {
+    self
+      .start_location
+      .as_ref()
+      .is_none_or(|start| location >= start)
+      && self
+        .end_group
+        .is_none_or(|end_group| location.group <= end_group)
+  }
+
+  pub fn is_past_end(&self, group: u64) -> bool {
+    self.end_group.is_some_and(|end_group| group > end_group)
+  }
+
+  /// Whether the group is the last one of the range
+  pub fn is_end_group(&self, group: u64) -> bool {
+    self.end_group == Some(group)
+  }
+
+  fn delivery_timeout_parameter(&self) -> Option<KeyValuePair> {
+    self.delivery_timeout.and_then(|timeout| {
+      let millis = u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX);
+      VersionParameter::new_delivery_timeout(millis)
+        .try_into()
+        .ok()
+    })
+  }
+
+  pub fn to_subscribe_update(&self, request_id: u64) -> SubscribeUpdate {
+    let parameters = self.delivery_timeout_parameter().into_iter().collect();
+    SubscribeUpdate::new(
+      request_id,
+      self
+        .start_location
+        .clone()
+        .unwrap_or_else(|| Location::new(0, 0)),
+      self.end_group.map_or(0, |end_group| end_group + 1),
+      self.subscriber_priority,
+      self.forward,
+      parameters,
+    )
+  }
+
+  /// Combines the parameters of every downstream subscription of a track
+  /// into what the relay needs from upstream: the widest range, the highest
+  /// priority (lowest value), forwarding if anyone wants objects, and the
+  /// longest delivery timeout.
+  pub fn aggregate<'a>(params: impl IntoIterator<Item = &'a SubscriptionParams>) -> Option<Self> {
+    let mut params = params.into_iter();
+    let mut aggregated = params.next()?.clone();
+    for p in params {
+      aggregated.start_location = match (&aggregated.start_location, &p.start_location) {
+        (Some(a), Some(b)) => Some(a.clone().min(b.clone())),
+        _ => None,
+      };
+      aggregated.end_group = match (aggregated.end_group, p.end_group) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        _ => None,
+      };
+      aggregated.subscriber_priority = aggregated.subscriber_priority.min(p.subscriber_priority);
+      aggregated.forward |= p.forward;
+      aggregated.delivery_timeout = match (aggregated.delivery_timeout, p.delivery_timeout) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        _ => None,
+      };
+    }
+    Some(aggregated)
+  }
+
+  /// Whether an upstream subscription with these parameters delivers every
+  /// group `desired` asks for. A subscription without a start location
+  /// delivers from the latest object on, which covers any start.
+  pub fn covers(&self, desired: &SubscriptionParams) -> bool {
+    let start_covered = match (&self.start_location, &desired.start_location) {
+      (Some(current), Some(desired)) => desired >= current,
+      _ => true,
+    };
+    let end_covered = match (self.end_group, desired.end_group) {
+      (None, _) => true,
+      (Some(_), None) => false,
+      (Some(current), Some(desired)) => desired <= current,
+    };
+    start_covered && end_covered
+  }
+
+  /// Writes the range, priority, forwarding and delivery timeout into a
+  /// SUBSCRIBE. A range that ends but has no start begins at
+  /// `largest_location`.
+  pub fn apply_to(&self, subscribe: &mut Subscribe, largest_location: Option<&Location>) {
+    let start_location = self.start_location.clone().or_else(|| {
+      self.end_group.map(|_| {
+        largest_location
+          .cloned()
+          .unwrap_or_else(|| Location::new(0, 0))
+      })
+    });
+    subscribe.filter_type = match (&start_location, self.end_group) {
+      (Some(_), Some(_)) => FilterType::AbsoluteRange,
+      (Some(_), None) => FilterType::AbsoluteStart,
+      (None, _) => FilterType::LatestObject,
+    };
+    subscribe.start_location = start_location;
+    subscribe.end_group = self.end_group;
+    subscribe.subscriber_priority = self.subscriber_priority;
+    subscribe.forward = self.forward;
+    self.apply_delivery_timeout_to(subscribe);
+  }
+
+  /// Replaces the DELIVERY_TIMEOUT parameter of a SUBSCRIBE
+  pub fn apply_delivery_timeout_to(&self, subscribe: &mut Subscribe) {
+    subscribe.subscribe_parameters.retain(|kvp| {
+      !matches!(
+        VersionParameter::deserialize(kvp),
+        Ok(VersionParameter::DeliveryTimeout { .. })
+      )
+    });
+    subscribe
+      .subscribe_parameters
+      .extend(self.delivery_timeout_parameter());
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct Subscription {
+  pub subscribe_message: Subscribe,
+  params: Arc<RwLock<SubscriptionParams>>,
+  subscriber: Arc<MOQTClient>,
+  event_rx: Arc<Mutex<Option<UnboundedReceiver<TrackEvent>>>>,
+  send_stream_ids: Arc<RwLock<Vec<StreamId>>>,
+  /// Streams opened so far, reported in SUBSCRIBE_DONE
+  stream_count: Arc<AtomicU64>,
+  /// Newest group seen on the track, the base of the group term of send orders
+  newest_group: Arc<AtomicU64>,
+  /// Streams reset for exceeding the delivery timeout, whose remaining
+  /// objects are dropped
+  timed_out_stream_ids: Arc<RwLock<Vec<StreamId>>>,
+  finished: Arc<RwLock<bool>>, // Indicates if the subscription is finished
+  #[allow(dead_code)]
+  cache: TrackCache,
+  client_connection_id: usize,
+  /// Group order from the publisher's SUBSCRIBE_OK, shared with the track
+  publisher_group_order: Arc<RwLock<GroupOrder>>,
+  object_logger: ObjectLogger,
+  config: Arc<RelayConfig>,
+}
+
+impl Subscription {
+  fn create_instance(
+    subscribe_message: Subscribe,
+    subscriber: Arc<MOQTClient>,
+    event_rx: Arc<Mutex<Option<UnboundedReceiver<TrackEvent>>>>,
+    cache: TrackCache,
+    publisher_group_order: Arc<RwLock<GroupOrder>>,
+    largest_location: Option<Location>,
+    config: Arc<RelayConfig>,
+  ) -> Self {
+    Self {
+      params: Arc::new(RwLock::new(SubscriptionParams::from_subscribe(
+        &subscribe_message,
+        largest_location.as_ref(),
+      ))),
+      subscribe_message,
+      client_connection_id: subscriber.connection_id,
+      subscriber,
+      event_rx,
+      send_stream_ids: Arc::new(RwLock::new(Vec::new())),
+      stream_count: Arc::new(AtomicU64::new(0)),
+      newest_group: Arc::new(AtomicU64::new(
+        largest_location.as_ref().map_or(0, |largest| largest.group),
+      )),
+      timed_out_stream_ids: Arc::new(RwLock::new(Vec::new())),
+      finished: Arc::new(RwLock::new(false)),
+      cache,
+      publisher_group_order,
+      object_logger: ObjectLogger::new(config.log_folder.clone()),
+      config,
+    }
+  }
+  pub fn new(
+    subscribe_message: Subscribe,
+    subscriber: Arc<MOQTClient>,
+    event_rx: UnboundedReceiver<TrackEvent>,
+    cache: TrackCache,
+    publisher_group_order: Arc<RwLock<GroupOrder>>,
+    largest_location: Option<Location>,
+    config: Arc<RelayConfig>,
+  ) -> Self {
+    let event_rx = Arc::new(Mutex::new(Some(event_rx)));
+    let sub = Self::create_instance(
+      subscribe_message,
+      subscriber,
+      event_rx,
+      cache,
+      publisher_group_order,
+      largest_location,
+      config,
+    );
+
+    let mut instance = sub.clone();
+    tokio::spawn(async move {
+      loop {
+        {
+          let is_finished = instance.finished.read().await;
+          if *is_finished {
+            break;
+          }
+        }
+        tokio::select! {
+          biased;
+          _ = instance.receive() => {
+            continue;
+          }
+          // 1 second timeout to check if the subscription is still valid
+          _ = tokio::time::sleep(tokio::time::Duration::from_secs(1)) => {
+            // TODO: implement max timeout here
+            continue;
+          }
+        }
+      }
+      // release the event receiver and the send streams
+      instance.finish().await;
+    });
+
+    sub
+  }
+
+  pub async fn params(&self) -> SubscriptionParams {
+    self.params.read().await.clone()
+  }
+
+  /// The parameters to filter an object of `group` with
+  async fn resolved_params(&self, group: u64) -> SubscriptionParams {
+    let mut params = self.params.write().await;
+    params.resolve_start(group);
+    params.clone()
+  }
+
+  /// Applies a SUBSCRIBE_UPDATE from the subscriber. Returns false if the
+  /// update would widen the subscription.
+  pub async fn update(&self, update: &SubscribeUpdate) -> bool {
+    let mut params = self.params.write().await;
+    let updated = params.apply_update(update);
+    if updated {
+      info!(
+        "Subscription updated for subscriber: {} track: {} params: {:?}",
+        self.client_connection_id, self.subscribe_message.track_alias, params
+      );
+    }
+    updated
+  }
+
+  pub async fn finish(&mut self) {
+    let mut is_finished = self.finished.write().await;
+    *is_finished = true;
+    drop(is_finished); // Explicitly drop the lock to allow other tasks to proceed
+
+    let mut receiver_guard = self.event_rx.lock().await;
+    let receiver = receiver_guard.take(); // This replaces the Some(receiver) with None
+    drop(receiver_guard); // Release the lock
+    if receiver.is_none() {
+      // already finished
+      return;
+    }
+
+    info!(
+      "Subscription finished for subscriber: {} and track: {}",
+      self.client_connection_id, self.subscribe_message.track_alias
+    );
+
+    // Close all send streams asynchronously to avoid blocking subscription cleanup
+    let stream_ids = {
+      let mut send_stream_ids = self.send_stream_ids.write().await;
+      let ids = send_stream_ids.clone();
+      send_stream_ids.clear();
+      ids
+    };
+
+    if !stream_ids.is_empty() {
+      let subscriber = self.subscriber.clone();
+      let connection_id = self.client_connection_id;
+      let track_alias = self.subscribe_message.track_alias;
+
+      // Spawn background task for graceful stream cleanup
+      tokio::spawn(async move {
+        info!(
+          "Starting background cleanup of {} streams for subscriber: {} track: {}",
+          stream_ids.len(),
+          connection_id,
+          track_alias
+        );
+
+        for stream_id in stream_ids.iter() {
+          if let Err(e) = subscriber.close_stream(stream_id).await {
+            warn!(
+              "Background stream cleanup error for subscriber: {} stream_id: {} track: {} error: {:?}",
+              connection_id, stream_id, track_alias, e
+            );
+          } else {
+            debug!(
+              "Background stream cleanup successful for subscriber: {} stream_id: {} track: {}",
+              connection_id, stream_id, track_alias
+            );
+          }
+        }
+
+        info!(
+          "Background cleanup completed for subscriber: {} track: {} ({} streams)",
+          connection_id,
+          track_alias,
+          stream_ids.len()
+        );
+      });
+    }
+  }
+
+  async fn receive(&mut self) {
+    let mut event_rx_guard = self.event_rx.lock().await;
+
+    if let Some(ref mut event_rx) = *event_rx_guard {
+      match event_rx.recv().await {
+        Some(event) => {
+          if *self.finished.read().await {
+            return;
+          }
+
+          match event {
+            TrackEvent::Object {
+              object,
+              stream_id,
+              header_info,
+              received_at,
+            } => {
+              let params = self.resolved_params(object.location.group).await;
+              if params.is_past_end(object.location.group) {
+                self.end_range(&object.location).await;
+                return;
+              }
+
+              if !params.forward || !params.includes_group(object.location.group) {
+                debug!(
+                  "Object filtered out: subscriber: {} track: {} object: {:?} forward: {}",
+                  self.client_connection_id,
+                  self.subscribe_message.track_alias,
+                  object.location,
+                  params.forward
+                );
+                return;
+              }
+
+              if self.timed_out_stream_ids.read().await.contains(&stream_id) {
+                debug!(
+                  "Object of a timed out stream dropped: subscriber: {} stream_id: {} track: {} object: {:?}",
+                  self.client_connection_id,
+                  stream_id,
+                  self.subscribe_message.track_alias,
+                  object.location
+                );
+                return;
+              }
+              if params
+                .delivery_timeout
+                .is_some_and(|timeout| received_at.elapsed() > timeout)
+              {
+                self
+                  .reset_timed_out_stream(&stream_id, &object.location)
+                  .await;
+                return;
+              }
+
+              let object_received_time = utils::passed_time_since_start();
+
+              // Handle header info if this is the first object
+              let send_stream = if let Some(header) = header_info {
+                if let HeaderInfo::Subgroup {
+                  header: _subgroup_header,
+                } = header
+                {
+                  info!(
+                    "Creating stream - subscriber: {} track: {} now: {} received time: {} object: {:?}",
+                    self.client_connection_id,
+                    self.subscribe_message.track_alias,
+                    utils::passed_time_since_start(),
+                    object_received_time,
+                    object.location
+                  );
+                  if let Ok((stream_id, send_stream)) = self.handle_header(header.clone()).await {
+                    self.send_stream_ids.write().await.push(stream_id.clone());
+                    self.stream_count.fetch_add(1, Ordering::Relaxed);
+                    info!(
+                      "Stream created - subscriber: {} stream_id: {} track: {} now: {} received time: {} object: {:?}",
+                      self.client_connection_id,
+                      stream_id,
+                      self.subscribe_message.track_alias,
+                      utils::passed_time_since_start(),
+                      object_received_time,
+                      object.location
+                    );
+                    Some(send_stream)
+                  } else {
+                    // TODO: maybe log error here?
+                    None
+                  }
+                } else {
+                  error!(
+                    "Received Object event with non-subgroup header: {:?}",
+                    header
+                  );
+                  None
+                }
+              } else {
+                self.subscriber.get_stream(&stream_id).await
+              };
+
+              // the stream is opened for the whole group, but objects
+              // before the start location are not delivered
+              if !params.includes(&object.location) {
+                debug!(
+                  "Object before start location: subscriber: {} track: {} object: {:?}",
+                  self.client_connection_id, self.subscribe_message.track_alias, object.location
+                );
+                return;
+              }
+
+              if let Some(send_stream) = send_stream {
+                debug!(
+                  "Received Object event: subscriber: {} stream_id: {} track: {}",
+                  self.client_connection_id, stream_id, self.subscribe_message.track_alias
+                );
+
+                // Log object properties with send status if enabled
+                let write_result = self
+                  .handle_object(object.clone(), &stream_id, send_stream.clone())
+                  .await;
+                let send_status = write_result.is_ok();
+
+                if self.config.enable_object_logging {
+                  self
+                    .object_logger
+                    .log_subscription_object(
+                      self.subscribe_message.track_alias,
+                      self.client_connection_id,
+                      &object,
+                      send_status,
+                      object_received_time,
+                    )
+                    .await;
+                }
+
+                let _ = write_result;
+
+                // nothing follows the end of the last group or of the track
+                if object.status == ObjectStatus::EndOfTrack
+                  || (object.status == ObjectStatus::EndOfGroup
+                    && params.is_end_group(object.location.group))
+                {
+                  self.end_range(&object.location).await;
+                }
+              } else {
+                error!(
+                  "Received Object event without a valid send stream for subscriber: {} stream_id: {} track: {} object: {:?} now: {} received time: {}",
+                  self.client_connection_id,
+                  stream_id,
+                  self.subscribe_message.track_alias,
+                  object.location,
+                  utils::passed_time_since_start(),
+                  object_received_time
+                );
+              }
+            }
+            TrackEvent::Datagram { object } => {
+              let params = self.resolved_params(object.location.group).await;
+              if params.is_past_end(object.location.group) {
+                self.end_range(&object.location).await;
+                return;
+              }
+
+              if !params.forward || !params.includes(&object.location) {
+                debug!(
+                  "Datagram filtered out: subscriber: {} track: {} object: {:?} forward: {}",
+                  self.client_connection_id,
+                  self.subscribe_message.track_alias,
+                  object.location,
+                  params.forward
+                );
+                return;
+              }
+
+              let object_received_time = utils::passed_time_since_start();
+              let send_result = self.handle_datagram(object.clone());
+              if let Err(e) = &send_result {
+                warn!(
+                  "Failed to send datagram: subscriber: {} track: {} object: {:?} error: {:?}",
+                  self.client_connection_id, self.subscribe_message.track_alias, object.location, e
+                );
+              }
+
+              if self.config.enable_object_logging {
+                self
+                  .object_logger
+                  .log_subscription_object(
+                    self.subscribe_message.track_alias,
+                    self.client_connection_id,
+                    &object,
+                    send_result.is_ok(),
+                    object_received_time,
+                  )
+                  .await;
+              }
+            }
+            TrackEvent::StreamClosed { stream_id } => {
+              info!(
+                "Received StreamClosed event: subscriber: {} stream_id: {} track: {}",
+                self.client_connection_id, stream_id, self.subscribe_message.track_alias
+              );
+              let mut timed_out_stream_ids = self.timed_out_stream_ids.write().await;
+              if let Some(index) = timed_out_stream_ids.iter().position(|id| *id == stream_id) {
+                // already reset
+                timed_out_stream_ids.remove(index);
+                return;
+              }
+              drop(timed_out_stream_ids);
+              let _ = self.handle_stream_closed(&stream_id).await;
+
+              // the range is complete once every stream of its last group is
+              if let Some(group_id) = stream_id.group_id
+                && self.params.read().await.is_end_group(group_id)
+                && !self
+                  .send_stream_ids
+                  .read()
+                  .await
+                  .iter()
+                  .any(|id| id.group_id == Some(group_id))
+              {
+                self.end_range(&Location::new(group_id, 0)).await;
+              }
+            }
+            TrackEvent::StreamTimedOut { stream_id } => {
+              info!(
+                "Received StreamTimedOut event: subscriber: {} stream_id: {} track: {}",
+                self.client_connection_id, stream_id, self.subscribe_message.track_alias
+              );
+              // the publisher's stream is gone, no StreamClosed follows
+              self
+                .timed_out_stream_ids
+                .write()
+                .await
+                .retain(|id| *id != stream_id);
+              self
+                .send_stream_ids
+                .write()
+                .await
+                .retain(|id| *id != stream_id);
+              self
+                .subscriber
+                .reset_stream(&stream_id, StreamResetCode::DeliveryTimeout)
+                .await;
+            }
+            TrackEvent::TrackEnded {
+              status_code,
+              reason,
+            } => {
+              info!(
+                "Received TrackEnded event: subscriber: {}, reason: {} track: {}",
+                self.client_connection_id, reason, self.subscribe_message.track_alias
+              );
+
+              // Send SubscribeDone message and finish the subscription
+              if let Err(e) = self.send_subscribe_done(status_code, &reason).await {
+                error!(
+                  "Failed to send SubscribeDone for ended track: subscriber: {} track: {} error: {:?}",
+                  self.client_connection_id, self.subscribe_message.track_alias, e
+                );
+              }
+
+              // Finish the subscription since the track is gone
+              let mut is_finished = self.finished.write().await;
+              *is_finished = true;
+            }
+          }
+        }
+        None => {
+          // For unbounded receivers, recv() returns None when the channel is closed
+          // The channel is closed, we should finish the subscription
+          info!(
+            "Event receiver closed for subscriber: {} track: {}, finishing subscription",
+            self.client_connection_id, self.subscribe_message.track_alias
+          );
+          let mut is_finished = self.finished.write().await;
+          *is_finished = true;
+        }
+      }
+    } else {
+      // No receiver available, subscription has been finished
+      let mut is_finished = self.finished.write().await;
+      *is_finished = true;
+    }
+  }
+
+  /// Sends SUBSCRIBE_DONE once the end group is complete, or an object past
+  /// it arrives, and finishes the subscription
+  async fn end_range(&self, location: &Location) {
+    info!(
+      "Subscription range ended: subscriber: {} track: {} object: {:?}",
+      self.client_connection_id, self.subscribe_message.track_alias, location
+    );
+    if let Err(e) = self
+      .send_subscribe_done(
+        SubscribeDoneStatusCode::SubscriptionEnded,
+        "Subscription range ended",
+      )
+      .await
+    {
+      error!(
+        "Failed to send SubscribeDone for ended range: subscriber: {} track: {} error: {:?}",
+        self.client_connection_id, self.subscribe_message.track_alias, e
+      );
+    }
+    let mut is_finished = self.finished.write().await;
+    *is_finished = true;
+  }
+
+  /// Resets a subgroup stream whose object waited longer than the delivery
+  /// timeout. The rest of its group is skipped, so the subscriber moves on
+  /// to the next group instead of receiving stale objects.
+  async fn reset_timed_out_stream(&self, stream_id: &StreamId, location: &Location) {
+    warn!(
+      "Delivery timeout exceeded, resetting stream: subscriber: {} stream_id: {} track: {} object: {:?}",
+      self.client_connection_id, stream_id, self.subscribe_message.track_alias, location
+    );
+    self
+      .timed_out_stream_ids
+      .write()
+      .await
+      .push(stream_id.clone());
+    self
+      .send_stream_ids
+      .write()
+      .await
+      .retain(|id| id != stream_id);
+    self
+      .subscriber
+      .reset_stream(stream_id, StreamResetCode::DeliveryTimeout)
+      .await;
+  }
+
+  fn handle_datagram(&self, object: Object) -> Result<()> {
+    self
+      .subscriber
+      .datagram_handler
+      .send_object(&object, self.subscribe_message.track_alias)?;
+    Ok(())
+  }
+
+  async fn handle_header(
+    &self,
+    header_info: HeaderInfo,
+  ) -> Result<(StreamId, Arc<Mutex<BoxSendStream>>)> {
+    // Handle the header information
+    debug!("Handling header: {:?}", header_info);
+    let stream_id = self.get_stream_id(&header_info);
+
+    if let Ok(header_payload) = self.get_header_payload(&header_info).await {
+      let priority = self.get_send_order(&header_info).await;
+
+      let send_stream = match self
+        .subscriber
+        .open_stream(&stream_id, header_payload, priority)
+        .await
+      {
+        Ok(send_stream) => send_stream,
+        Err(e) => {
+          error!(
+            "Failed to open stream {}: {:?} subscriber: {} track: {}",
+            stream_id, e, self.client_connection_id, self.subscribe_message.track_alias
+          );
+          return Err(e);
+        }
+      };
+
+      info!("Created stream: {}", stream_id.get_stream_id());
+
+      Ok((stream_id, send_stream.clone()))
+    } else {
+      error!(
+        "Failed to serialize header payload for stream {} subscriber: {} track: {}",
+        stream_id, self.client_connection_id, self.subscribe_message.track_alias
+      );
+      Err(anyhow::anyhow!(
+        "Failed to serialize header payload for stream {} subscriber: {} track: {}",
+        stream_id,
+        self.client_connection_id,
+        self.subscribe_message.track_alias
+      ))
+    }
+  }
+
+  async fn handle_object(
+    &self,
+    object: Object,
+    stream_id: &StreamId,
+    send_stream: Arc<Mutex<BoxSendStream>>,
+  ) -> Result<()> {
+    debug!(
+      "Handling object track: {} location: {:?} stream_id: {} diff_ms: {}",
+      object.track_alias,
+      object.location,
+      stream_id,
+      utils::passed_time_since_start()
+    );
+
+    // This loop will keep the stream open and process incoming objects
+    // TODO: revisit this logic to handle also fetch requests
+    if let Ok(sub_object) = object.try_into_subgroup() {
+      let has_extensions = sub_object.extension_headers.is_some();
+      let object_bytes = match sub_object.serialize(has_extensions) {
+        Ok(data) => data,
+        Err(e) => {
+          error!(
+            "Error in serializing object before writing to stream for subscriber {} track: {}, error: {:?}",
+            self.client_connection_id, self.subscribe_message.track_alias, e
+          );
+          return Err(e.into());
+        }
+      };
+
+      self
+        .subscriber
+        .write_object_to_stream(
+          stream_id,
+          sub_object.object_id,
+          object_bytes,
+          Some(send_stream.clone()),
+        )
+        .await
+        .map_err(|open_stream_err| {
+          error!(
+            "Error writing object to stream for subscriber {} track: {}, error: {:?}",
+            self.client_connection_id, self.subscribe_message.track_alias, open_stream_err
+          );
+          open_stream_err
+        })
+    } else {
+      debug!(
+        "Could not convert object to subgroup. stream_id: {:?} subscriber: {} track: {}",
+        stream_id, self.client_connection_id, self.subscribe_message.track_alias
+      );
+      Err(anyhow::anyhow!(
+        "Could not convert object to subgroup. stream_id: {:?} subscriber: {} track: {}",
+        stream_id,
+        self.client_connection_id,
+        self.subscribe_message.track_alias
+      ))
+    }
+  }
+
+  async fn handle_stream_closed(&self, stream_id: &StreamId) -> Result<()> {
+    // Handle the stream closed event
+    debug!("Stream closed: {}", stream_id.get_stream_id());
+
+    // remove the stream id from stream ids array immediately
+    let mut stream_ids = self.send_stream_ids.write().await;
+    stream_ids.retain(|x| *x != *stream_id);
+    drop(stream_ids); // Release the lock immediately
+
+    // Perform graceful stream closure in a separate task to avoid blocking
+    // the main subscription event loop. This is critical for real-time media streaming
+    // where blocking operations can disrupt video flow timing (25fps = ~40ms intervals)
+    let subscriber = self.subscriber.clone();
+    let stream_id_clone = stream_id.clone();
+    let connection_id = self.client_connection_id;
+    let track_alias = self.subscribe_message.track_alias;
+
+    tokio::spawn(async move {
+      debug!(
+        "Starting graceful stream closure in background: subscriber: {} stream_id: {} track: {}",
+        connection_id, stream_id_clone, track_alias
+      );
+
+      if let Err(e) = subscriber.close_stream(&stream_id_clone).await {
+        // Log the error but don't propagate it since this is background cleanup
+        debug!(
+          "Background stream closure completed with error: subscriber: {} stream_id: {} track: {} error: {:?}",
+          connection_id, stream_id_clone, track_alias, e
+        );
+      } else {
+        debug!(
+          "Background stream closure completed successfully: subscriber: {} stream_id: {} track: {}",
+          connection_id, stream_id_clone, track_alias
+        );
+      }
+    });
+
+    // Return immediately to avoid blocking the event loop
+    Ok(())
+  }
+
+  async fn get_header_payload(&self, header_info: &HeaderInfo) -> Result<Bytes> {
+    let connection_id = self.client_connection_id;
+    match header_info {
+      HeaderInfo::Subgroup { header } => self
+        .subscriber
+        .codec
+        .serialize_subgroup_header(header)
+        .map_err(|e| {
+          error!(
+            "Error serializing subgroup header: {:?} subscriber: {} track: {}",
+            e, connection_id, self.subscribe_message.track_alias
+          );
+          e.into()
+        }),
+      HeaderInfo::Fetch {
+        header,
+        fetch_request: _,
+      } => self
+        .subscriber
+        .codec
+        .serialize_fetch_header(header)
+        .map_err(|e| {
+          error!(
+            "Error serializing fetch header: {:?} subscriber: {} track: {}",
+            e, connection_id, self.subscribe_message.track_alias
+          );
+          e.into()
+        }),
+    }
+  }
+
+  async fn get_send_order(&self, header_info: &HeaderInfo) -> i32 {
+    let (group_id, publisher_priority) = match header_info {
+      HeaderInfo::Subgroup { header } => (header.group_id, header.publisher_priority),
+      // fetches are served from the cache, not through subscriptions
+      HeaderInfo::Fetch { .. } => (0, u8::MAX),
+    };
+    let group_order = priority::resolve_group_order(
+      self.subscribe_message.group_order,
+      *self.publisher_group_order.read().await,
+    );
+    let newest_group = self
+      .newest_group
+      .fetch_max(group_id, Ordering::Relaxed)
+      .max(group_id);
+    priority::send_order(
+      self.params.read().await.subscriber_priority,
+      publisher_priority,
+      group_order,
+      group_id,
+      newest_group,
+    )
+  }
+
+  fn get_stream_id(&self, header_info: &HeaderInfo) -> StreamId {
+    utils::build_stream_id(self.subscribe_message.track_alias, header_info)
+  }
+
+  /// Send SubscribeDone message to this subscriber
+  pub async fn send_subscribe_done(
+    &self,
+    status_code: SubscribeDoneStatusCode,
+    reason: &str,
+  ) -> Result<(), anyhow::Error> {
+    let reason_phrase = ReasonPhrase::try_new(reason.to_string())
+      .map_err(|e| anyhow::anyhow!("Failed to create reason phrase: {:?}", e))?;
+
+    let subscribe_done = SubscribeDone::new(
+      self.subscribe_message.request_id,
+      status_code,
+      self.stream_count.load(Ordering::Relaxed),
+      reason_phrase,
+    );
+
+    self
+      .subscriber
+      .queue_message(ControlMessage::SubscribeDone(Box::new(subscribe_done)))
+      .await;
+    self
+      .subscriber
+      .complete_request(self.subscribe_message.request_id)
+      .await;
+
+    info!(
+      "Sent SubscribeDone to subscriber {} track: {} for request_id {}",
+      self.client_connection_id,
+      self.subscribe_message.track_alias,
+      self.subscribe_message.request_id
+    );
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::common::tuple::Tuple;
+  use crate::model::control::constant::GroupOrder;
+
+  fn params(
+    start: Option<(u64, u64)>,
+    end_group: Option<u64>,
+    forward: bool,
+  ) -> SubscriptionParams {
+    SubscriptionParams {
+      start_location: start.map(|(group, object)| Location::new(group, object)),
+      end_group,
+      subscriber_priority: 1,
+      forward,
+      delivery_timeout: None,
+      awaits_next_group: false,
+    }
+  }
+
+  #[test]
+  fn test_apply_update_narrows_range() {
+    let mut p = params(Some((5, 0)), Some(20), true);
+    let update = SubscribeUpdate::new(1, Location::new(8, 0), 11, 0, false, vec![]);
+    assert!(p.apply_update(&update));
+    assert_eq!(p.start_location, Some(Location::new(8, 0)));
+    assert_eq!(p.end_group, Some(10));
+    assert_eq!(p.subscriber_priority, 0);
+    assert!(!p.forward);
+  }
+
+  #[test]
+  fn test_apply_update_rejects_widening() {
+    let mut p = params(Some((5, 0)), Some(20), true);
+    let earlier_start = SubscribeUpdate::new(1, Location::new(4, 0), 11, 1, true, vec![]);
+    assert!(!p.apply_update(&earlier_start));
+    let open_ended = SubscribeUpdate::new(1, Location::new(5, 0), 0, 1, true, vec![]);
+    assert!(!p.apply_update(&open_ended));
+    assert_eq!(p, params(Some((5, 0)), Some(20), true));
+  }
+
+  #[test]
+  fn test_apply_update_delivery_timeout() {
+    let mut p = params(None, None, true);
+    let update = SubscribeUpdate::new(1, Location::new(0, 0), 0, 1, true, vec![]);
+    assert!(p.apply_update(&update));
+    assert_eq!(p.delivery_timeout, None);
+
+    let update = SubscribeUpdate::new(1, Location::new(0, 0), 0, 1, true, vec![])
+      .with_delivery_timeout(Duration::from_millis(300))
+      .unwrap();
+    assert!(p.apply_update(&update));
+    assert_eq!(p.delivery_timeout, Some(Duration::from_millis(300)));
+    assert_eq!(
+      p.to_subscribe_update(1).delivery_timeout(),
+      Some(Duration::from_millis(300))
+    );
+  }
+
+  #[test]
+  fn test_aggregate_takes_widest_range() {
+    let a = params(Some((5, 0)), Some(20), false);
+    let mut b = params(Some((3, 2)), Some(10), true);
+    b.subscriber_priority = 0;
+    let aggregated = SubscriptionParams::aggregate([&a, &b]).unwrap();
+    assert_eq!(aggregated.start_location, Some(Location::new(3, 2)));
+    assert_eq!(aggregated.end_group, Some(20));
+    assert_eq!(aggregated.subscriber_priority, 0);
+    assert!(aggregated.forward);
+
+    let c = params(None, None, false);
+    let aggregated = SubscriptionParams::aggregate([&a, &c]).unwrap();
+    assert_eq!(aggregated.start_location, None);
+    assert_eq!(aggregated.end_group, None);
+    assert!(!aggregated.forward);
+  }
+
+  #[test]
+  fn test_aggregate_takes_longest_delivery_timeout() {
+    let mut a = params(None, None, true);
+    a.delivery_timeout = Some(Duration::from_millis(100));
+    let mut b = params(None, None, true);
+    b.delivery_timeout = Some(Duration::from_millis(400));
+    let aggregated = SubscriptionParams::aggregate([&a, &b]).unwrap();
+    assert_eq!(
+      aggregated.delivery_timeout,
+      Some(Duration::from_millis(400))
+    );
+
+    // a subscriber without a timeout waits for every object
+    let c = params(None, None, true);
+    let aggregated = SubscriptionParams::aggregate([&a, &c]).unwrap();
+    assert_eq!(aggregated.delivery_timeout, None);
+  }
+
+  #[test]
+  fn test_apply_delivery_timeout_replaces_parameter() {
+    let timeout: KeyValuePair = VersionParameter::new_delivery_timeout(100)
+      .try_into()
+      .unwrap();
+    let mut subscribe = Subscribe::new_latest_object(
+      1,
+      0,
+      Tuple::from_utf8_path("live/room"),
+      "video".to_string(),
+      1,
+      GroupOrder::Ascending,
+      true,
+      vec![timeout],
+    );
+    let mut p = params(None, None, true);
+    p.delivery_timeout = Some(Duration::from_millis(300));
+    p.apply_delivery_timeout_to(&mut subscribe);
+    assert_eq!(subscribe.subscribe_parameters.len(), 1);
+    assert_eq!(
+      subscribe.delivery_timeout(),
+      Some(Duration::from_millis(300))
+    );
+
+    p.delivery_timeout = None;
+    p.apply_delivery_timeout_to(&mut subscribe);
+    assert!(subscribe.subscribe_parameters.is_empty());
+  }
+
+  #[test]
+  fn test_from_subscribe_next_group_start() {
+    let subscribe = Subscribe::new_next_group_start(
+      0,
+      1,
+      Tuple::from_utf8_path("moqtail"),
+      "demo".to_string(),
+      1,
+      GroupOrder::Ascending,
+      true,
+      vec![],
+    );
+    let p = SubscriptionParams::from_subscribe(&subscribe, Some(&Location::new(7, 3)));
+    assert_eq!(p.start_location, Some(Location::new(8, 0)));
+    assert!(!p.includes_group(7));
+    assert!(p.includes(&Location::new(8, 0)));
+  }
+
+  #[test]
+  fn test_next_group_start_without_objects_waits_for_a_new_group() {
+    let subscribe = Subscribe::new_next_group_start(
+      0,
+      1,
+      Tuple::from_utf8_path("moqtail"),
+      "demo".to_string(),
+      1,
+      GroupOrder::Ascending,
+      true,
+      vec![],
+    );
+    let mut p = SubscriptionParams::from_subscribe(&subscribe, None);
+    assert_eq!(p.start_location, None);
+    assert!(p.awaits_next_group);
+    // the group of the first object may be in flight
+    p.resolve_start(3);
+    assert_eq!(p.start_location, Some(Location::new(4, 0)));
+    assert!(!p.includes(&Location::new(3, 5)));
+    assert!(p.includes(&Location::new(4, 0)));
+    p.resolve_start(4);
+    assert_eq!(p.start_location, Some(Location::new(4, 0)));
+  }
+
+  #[test]
+  fn test_absolute_range_filter() {
+    let p = params(Some((5, 2)), Some(10), true);
+    assert!(!p.includes_group(4));
+    assert!(p.includes_group(5));
+    assert!(!p.includes(&Location::new(5, 1)));
+    assert!(p.includes(&Location::new(5, 2)));
+    assert!(p.includes(&Location::new(10, 100)));
+    assert!(!p.is_past_end(10));
+    assert!(p.is_past_end(11));
+    assert!(p.is_end_group(10));
+    assert!(!p.is_end_group(9));
+  }
+
+  #[test]
+  fn test_covers() {
+    let upstream = params(Some((5, 0)), Some(20), true);
+    assert!(upstream.covers(&params(Some((8, 0)), Some(10), false)));
+    assert!(upstream.covers(&params(None, Some(20), true)));
+    assert!(!upstream.covers(&params(Some((4, 0)), Some(20), true)));
+    assert!(!upstream.covers(&params(Some((5, 0)), Some(21), true)));
+    assert!(!upstream.covers(&params(Some((5, 0)), None, true)));
+    assert!(params(None, None, true).covers(&params(Some((0, 0)), None, true)));
+  }
+
+  #[test]
+  fn test_apply_to() {
+    let mut subscribe = Subscribe::new_latest_object(
+      0,
+      1,
+      Tuple::from_utf8_path("moqtail"),
+      "demo".to_string(),
+      1,
+      GroupOrder::Ascending,
+      true,
+      vec![],
+    );
+    params(Some((3, 0)), Some(9), false).apply_to(&mut subscribe, None);
+    assert_eq!(subscribe.filter_type, FilterType::AbsoluteRange);
+    assert_eq!(subscribe.start_location, Some(Location::new(3, 0)));
+    assert_eq!(subscribe.end_group, Some(9));
+    assert!(!subscribe.forward);
+
+    params(None, Some(9), true).apply_to(&mut subscribe, Some(&Location::new(7, 2)));
+    assert_eq!(subscribe.filter_type, FilterType::AbsoluteRange);
+    assert_eq!(subscribe.start_location, Some(Location::new(7, 2)));
+
+    params(None, None, true).apply_to(&mut subscribe, None);
+    assert_eq!(subscribe.filter_type, FilterType::LatestObject);
+    assert_eq!(subscribe.start_location, None);
+    assert_eq!(subscribe.end_group, None);
+  }
+}
@@ -0,0 +1,449 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::track_cache::TrackCache;
+use crate::model::common::location::Location;
+use crate::model::control::constant::{GroupOrder, SubscribeDoneStatusCode};
+use crate::model::control::subscribe::Subscribe;
+use crate::model::control::subscribe_update::SubscribeUpdate;
+use crate::model::data::object::Object;
+use crate::relay::client::MOQTClient;
+use crate::relay::config::RelayConfig;
+use crate::relay::hooks::CacheStore;
+use crate::relay::object_logger::ObjectLogger;
+use crate::relay::stream_id::StreamId;
+use crate::relay::subscription::{Subscription, SubscriptionParams};
+use crate::relay::utils;
+use crate::{model::common::tuple::Tuple, transport::data_stream_handler::HeaderInfo};
+use anyhow::Result;
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use tokio::sync::RwLock;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::time::Instant;
+use tracing::{debug, error, info};
+
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum TrackEvent {
+  Object {
+    stream_id: StreamId,
+    object: Object,
+    header_info: Option<HeaderInfo>,
+    /// When the relay read the object from the publisher
+    received_at: Instant,
+  },
+  Datagram {
+    object: Object,
+  },
+  StreamClosed {
+    stream_id: StreamId,
+  },
+  /// The publisher's stream stalled past the delivery timeout and was stopped
+  StreamTimedOut {
+    stream_id: StreamId,
+  },
+  TrackEnded {
+    status_code: SubscribeDoneStatusCode,
+    reason: String,
+  },
+}
+#[derive(Debug, Clone)]
+pub struct Track {
+  #[allow(dead_code)]
+  pub track_alias: u64,
+  #[allow(dead_code)]
+  pub track_namespace: Tuple,
+  #[allow(dead_code)]
+  pub track_name: String,
+  subscriptions: Arc<RwLock<BTreeMap<usize, Arc<RwLock<Subscription>>>>>,
+  pub publisher_connection_id: usize,
+  #[allow(dead_code)]
+  pub(crate) cache: TrackCache,
+  subscriber_senders: Arc<RwLock<BTreeMap<usize, UnboundedSender<TrackEvent>>>>,
+  pub largest_location: Arc<RwLock<Location>>,
+  has_objects: Arc<RwLock<bool>>,
+  /// Group order from the publisher's SUBSCRIBE_OK
+  pub publisher_group_order: Arc<RwLock<GroupOrder>>,
+  /// Delivery timeout the relay asked for upstream, the one its downstream
+  /// subscriptions need together
+  requested_delivery_timeout: Arc<RwLock<Option<Duration>>>,
+  /// Delivery timeout from the publisher's SUBSCRIBE_OK
+  publisher_delivery_timeout: Arc<RwLock<Option<Duration>>>,
+  pub object_logger: ObjectLogger,
+  config: Arc<RelayConfig>,
+}
+
+// TODO: this track implementation should be static? At least
+// its lifetime should be same as the server's lifetime
+impl Track {
+  pub fn new(
+    track_alias: u64,
+    track_namespace: Tuple,
+    track_name: String,
+    publisher_connection_id: usize,
+    config: Arc<RelayConfig>,
+    cache_store: Arc<dyn CacheStore>,
+  ) -> Self {
+    Track {
+      track_alias,
+      track_namespace,
+      track_name,
+      subscriptions: Arc::new(RwLock::new(BTreeMap::new())),
+      publisher_connection_id,
+      cache: TrackCache::new(track_alias, cache_store),
+      subscriber_senders: Arc::new(RwLock::new(BTreeMap::new())),
+      largest_location: Arc::new(RwLock::new(Location::new(0, 0))),
+      has_objects: Arc::new(RwLock::new(false)),
+      publisher_group_order: Arc::new(RwLock::new(GroupOrder::Original)),
+      requested_delivery_timeout: Arc::new(RwLock::new(None)),
+      publisher_delivery_timeout: Arc::new(RwLock::new(None)),
+      object_logger: ObjectLogger::new(config.log_folder.clone()),
+      config,
+    }
+  }
+
+  /// The largest location received so far, or `None` before the first object
+  pub async fn get_largest_location(&self) -> Option<Location> {
+    if !*self.has_objects.read().await {
+      return None;
+    }
+    Some(self.largest_location.read().await.clone())
+  }
+
+  pub async fn add_subscription(
+    &mut self,
+    subscriber: Arc<MOQTClient>,
+    subscribe_message: Subscribe,
+  ) -> Result<(), anyhow::Error> {
+    let connection_id = { subscriber.connection_id };
+
+    info!(
+      "Adding subscription for subscriber_id: {} to track: {}",
+      connection_id, self.track_alias
+    );
+
+    // Create a separate unbounded channel for this subscriber
+    let (event_tx, event_rx) = tokio::sync::mpsc::unbounded_channel::<TrackEvent>();
+
+    let subscription = Subscription::new(
+      subscribe_message,
+      subscriber.clone(),
+      event_rx,
+      self.cache.clone(),
+      self.publisher_group_order.clone(),
+      self.get_largest_location().await,
+      self.config.clone(),
+    );
+
+    let mut subscriptions = self.subscriptions.write().await;
+    if subscriptions.contains_key(&connection_id) {
+      error!(
+        "Subscriber with connection_id: {} already exists in track: {}",
+        connection_id, self.track_alias
+      );
+      return Err(anyhow::anyhow!("Subscriber already exists"));
+    }
+    subscriptions.insert(connection_id, Arc::new(RwLock::new(subscription)));
+
+    // Store the sender for this subscriber
+    let mut senders = self.subscriber_senders.write().await;
+    senders.insert(connection_id, event_tx);
+
+    Ok(())
+  }
+
+  pub async fn remove_subscription(&mut self, subscriber_id: usize) {
+    info!(
+      "Removing subscription for subscriber_id: {} from track: {}",
+      subscriber_id, self.track_alias
+    );
+    let mut subscriptions = self.subscriptions.write().await;
+    // find the subscription by subscriber_id and finish it
+    if let Some(subscription) = subscriptions.get(&subscriber_id) {
+      let mut sub = subscription.write().await;
+      sub.finish().await;
+    }
+    subscriptions.remove(&subscriber_id);
+
+    // Remove and dispose the sender for this subscriber
+    let mut senders = self.subscriber_senders.write().await;
+    if let Some(_sender) = senders.remove(&subscriber_id) {
+      // The sender is automatically dropped here, which closes the channel
+      info!(
+        "Disposed sender for subscriber_id: {} from track: {}",
+        subscriber_id, self.track_alias
+      );
+    }
+  }
+
+  /// Applies a SUBSCRIBE_UPDATE to a subscriber's subscription. Returns
+  /// `None` if the subscriber has no subscription on this track.
+  pub async fn update_subscription(
+    &self,
+    subscriber_id: usize,
+    update: &SubscribeUpdate,
+  ) -> Option<bool> {
+    let subscriptions = self.subscriptions.read().await;
+    let subscription = subscriptions.get(&subscriber_id)?;
+    let updated = subscription.read().await.update(update).await;
+    Some(updated)
+  }
+
+  /// What the relay needs from the upstream subscription to serve every
+  /// downstream subscription of this track
+  pub async fn aggregate_subscription_params(&self) -> Option<SubscriptionParams> {
+    let subscriptions = self.subscriptions.read().await;
+    let mut params = Vec::with_capacity(subscriptions.len());
+    for subscription in subscriptions.values() {
+      params.push(subscription.read().await.params().await);
+    }
+    SubscriptionParams::aggregate(&params)
+  }
+
+  /// Delivery timeout of the upstream subscription, the smaller of what the
+  /// relay asked for and what the publisher answered. `None` waits for every
+  /// object.
+  pub async fn delivery_timeout(&self) -> Option<Duration> {
+    let requested = *self.requested_delivery_timeout.read().await;
+    let publisher = *self.publisher_delivery_timeout.read().await;
+    match (requested, publisher) {
+      (Some(requested), Some(publisher)) => Some(requested.min(publisher)),
+      (requested, publisher) => requested.or(publisher),
+    }
+  }
+
+  pub async fn set_requested_delivery_timeout(&self, timeout: Option<Duration>) {
+    *self.requested_delivery_timeout.write().await = timeout;
+  }
+
+  pub async fn set_publisher_delivery_timeout(&self, timeout: Option<Duration>) {
+    *self.publisher_delivery_timeout.write().await = timeout;
+  }
+
+  pub async fn new_object(
+    &self,
+    stream_id: &StreamId,
+    object: &Object,
+  ) -> Result<(), anyhow::Error> {
+    self.new_object_with_header(stream_id, object, None).await
+  }
+
+  pub async fn new_object_with_header(
+    &self,
+    stream_id: &StreamId,
+    object: &Object,
+    header_info: Option<&HeaderInfo>,
+  ) -> Result<(), anyhow::Error> {
+    debug!(
+      "new_object: track: {:?} location: {:?} stream_id: {} diff_ms: {}",
+      object.track_alias,
+      object.location,
+      stream_id,
+      utils::passed_time_since_start()
+    );
+
+    if header_info.is_some() {
+      info!(
+        "new group: track: {:?} location: {:?} stream_id: {} time: {}",
+        object.track_alias,
+        object.location,
+        stream_id,
+        utils::passed_time_since_start()
+      );
+    }
+
+    if !self.record_object(object).await {
+      error!(
+        "new_object: track: {:?} location: {:?} stream_id: {} diff_ms: {} object: {:?}",
+        object.track_alias,
+        object.location,
+        stream_id,
+        utils::passed_time_since_start(),
+        object
+      );
+      return Err(anyhow::anyhow!("Object is not a fetch object"));
+    }
+
+    // Send single Object event with optional header info
+    let event = TrackEvent::Object {
+      stream_id: stream_id.clone(),
+      object: object.clone(),
+      header_info: header_info.cloned(),
+      received_at: Instant::now(),
+    };
+
+    self.send_event_to_subscribers(event).await?;
+    Ok(())
+  }
+
+  /// Caches an object that arrived in a datagram and fans it out to the
+  /// subscribers, which forward it as a datagram as well
+  pub async fn new_datagram(&self, object: &Object) -> Result<(), anyhow::Error> {
+    debug!(
+      "new_datagram: track: {:?} location: {:?} diff_ms: {}",
+      object.track_alias,
+      object.location,
+      utils::passed_time_since_start()
+    );
+
+    if !self.record_object(object).await {
+      error!(
+        "new_datagram: track: {:?} location: {:?} object: {:?}",
+        object.track_alias, object.location, object
+      );
+      return Err(anyhow::anyhow!("Object is not a fetch object"));
+    }
+
+    let event = TrackEvent::Datagram {
+      object: object.clone(),
+    };
+
+    self.send_event_to_subscribers(event).await?;
+    Ok(())
+  }
+
+  /// Caches the object, logs it and updates the largest location. Returns
+  /// false if the object cannot be cached.
+  async fn record_object(&self, object: &Object) -> bool {
+    let Ok(fetch_object) = object.clone().try_into_fetch() else {
+      return false;
+    };
+    self.cache.add_object(fetch_object).await;
+
+    // Track-level logging - log every object arrival if enabled
+    if self.config.enable_object_logging {
+      let object_received_time = utils::passed_time_since_start();
+      self
+        .object_logger
+        .log_track_object(self.track_alias, object, object_received_time)
+        .await;
+    }
+
+    // update the largest location
+    *self.has_objects.write().await = true;
+    let mut largest_location = self.largest_location.write().await;
+    if object.location.group > largest_location.group
+      || (object.location.group == largest_location.group
+        && object.location.object > largest_location.object)
+    {
+      largest_location.group = object.location.group;
+      largest_location.object = object.location.object;
+    }
+    true
+  }
+
+  pub async fn stream_closed(&self, stream_id: &StreamId) -> Result<(), anyhow::Error> {
+    let event = TrackEvent::StreamClosed {
+      stream_id: stream_id.clone(),
+    };
+
+    self.send_event_to_subscribers(event).await?;
+
+    Ok(())
+  }
+
+  /// Tells the subscribers to reset a stream whose publisher stream was
+  /// stopped for exceeding the delivery timeout
+  pub async fn stream_timed_out(&self, stream_id: &StreamId) -> Result<(), anyhow::Error> {
+    let event = TrackEvent::StreamTimedOut {
+      stream_id: stream_id.clone(),
+    };
+
+    self.send_event_to_subscribers(event).await?;
+
+    Ok(())
+  }
+
+  /// Send PublisherDisconnected event to all subscribers
+  pub async fn notify_publisher_disconnected(&self) -> Result<(), anyhow::Error> {
+    info!(
+      "Publisher disconnected for track: {} - notifying all subscribers",
+      self.track_alias
+    );
+
+    self
+      .notify_track_ended(
+        SubscribeDoneStatusCode::TrackEnded,
+        "Publisher disconnected",
+      )
+      .await
+  }
+
+  /// Send TrackEnded event to all subscribers so they finish with SUBSCRIBE_DONE
+  pub async fn notify_track_ended(
+    &self,
+    status_code: SubscribeDoneStatusCode,
+    reason: &str,
+  ) -> Result<(), anyhow::Error> {
+    let event = TrackEvent::TrackEnded {
+      status_code,
+      reason: reason.to_string(),
+    };
+
+    self.send_event_to_subscribers(event).await?;
+
+    Ok(())
+  }
+
+  // Send event to all subscribers
+  async fn send_event_to_subscribers(
+    &self,
+    event: TrackEvent,
+  ) -> Result<Vec<usize>, anyhow::Error> {
+    let senders = self.subscriber_senders.read().await;
+    let mut failed_subscribers = Vec::new();
+
+    if !senders.is_empty() {
+      for (subscriber_id, sender) in senders.iter() {
+        if let Err(e) = sender.send(event.clone()) {
+          error!(
+            "Failed to send event to subscriber {}: {}",
+            subscriber_id, e
+          );
+          failed_subscribers.push(*subscriber_id);
+        }
+      }
+
+      if !failed_subscribers.is_empty() {
+        error!(
+          "{:?} event sent to {} subscribers, {} failed for track: {}",
+          event,
+          senders.len() - failed_subscribers.len(),
+          failed_subscribers.len(),
+          self.track_alias
+        );
+
+        // the subscriptions finished on their own, e.g. at the end of their range
+        drop(senders);
+        let mut subscriptions = self.subscriptions.write().await;
+        let mut senders = self.subscriber_senders.write().await;
+        for subscriber_id in failed_subscribers.iter() {
+          senders.remove(subscriber_id);
+          subscriptions.remove(subscriber_id);
+        }
+      } else {
+        debug!(
+          "{:?} event sent successfully to {} subscribers for track: {}",
+          event,
+          senders.len(),
+          self.track_alias
+        );
+      }
+    }
+
+    Ok(failed_subscribers)
+  }
+}
+
+// TODO: Test
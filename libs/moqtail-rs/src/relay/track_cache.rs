@@ -0,0 +1,796 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::model::common::location::Location;
+use crate::model::control::constant::GroupOrder;
+use crate::model::data::constant::ObjectStatus;
+use crate::model::data::fetch_object::FetchObject;
+use moka::Expiry;
+use moka::future::Cache;
+use moka::notification::RemovalCause;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{
+  RwLock,
+  mpsc::{Receiver, channel},
+};
+use tracing::{debug, error, info, warn};
+
+use super::config::{CacheExpirationType, RelayConfig};
+use super::hooks::{BoxFuture, CacheStore, CacheStoreFactory};
+
+/// Composite cache key combining track_alias and group_id for global uniqueness
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+  pub track_alias: u64,
+  pub group_id: u64,
+}
+
+impl CacheKey {
+  /// Create a new cache key
+  pub fn new(track_alias: u64, group_id: u64) -> Self {
+    Self {
+      track_alias,
+      group_id,
+    }
+  }
+}
+
+impl std::fmt::Display for CacheKey {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "track:{}_group:{}", self.track_alias, self.group_id)
+  }
+}
+
+// Type alias for the cached value (group objects)
+pub type GroupObjects = Arc<RwLock<Vec<FetchObject>>>;
+
+#[derive(Debug, Clone)]
+pub struct TrackCache {
+  pub track_alias: u64,
+  store: Arc<dyn CacheStore>,
+}
+
+/// A run of objects in a fetch range, either in the cache or missing from it.
+/// The start is inclusive and the end is encoded like the end location of a
+/// FETCH: one past the last object, or object 0 for the whole group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheSpan {
+  Cached(Location, Location),
+  Missing(Location, Location),
+}
+
+/// The objects of a cached group, from the smallest to the largest object ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct GroupExtent {
+  pub group_id: u64,
+  pub first_object: u64,
+  pub last_object: u64,
+  /// No objects after `last_object` are coming
+  pub complete: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum CacheConsumeEvent {
+  Object(FetchObject),
+  EndLocation(Location),
+  NoObject,
+  /// An upstream fetch filling a gap failed, the objects sent so far are
+  /// all there is
+  Failed,
+}
+
+/// What an upstream fetch filling a gap in the cache reports to the fetch
+/// it is merged into. Dropping the sender ends the fill.
+#[derive(Debug, Clone)]
+pub enum FillEvent {
+  FetchOk(Location),
+  Object(FetchObject),
+  Failed,
+}
+
+/// Default cache store, backed by a moka cache per track
+#[derive(Debug, Clone)]
+pub struct MokaCacheStore {
+  // Moka cache for storing groups of objects with composite keys
+  cache: Cache<CacheKey, GroupObjects>,
+  max_duration: Arc<std::sync::RwLock<Option<Duration>>>,
+}
+
+/// Expires a group once the track's max cache duration has passed since it
+/// was created. The configured TTL or TTI still applies if it is sooner.
+struct GroupExpiry {
+  max_duration: Arc<std::sync::RwLock<Option<Duration>>>,
+}
+
+impl Expiry<CacheKey, GroupObjects> for GroupExpiry {
+  fn expire_after_create(
+    &self,
+    _key: &CacheKey,
+    _value: &GroupObjects,
+    _created_at: Instant,
+  ) -> Option<Duration> {
+    *self.max_duration.read().unwrap()
+  }
+}
+
+impl MokaCacheStore {
+  pub fn new(track_alias: u64, config: &RelayConfig) -> Self {
+    let log_folder_for_listener = config.log_folder.clone();
+    let max_duration = Arc::new(std::sync::RwLock::new(None));
+
+    let cache_builder = Cache::builder()
+      .max_capacity(config.cache_size as u64)
+      .expire_after(GroupExpiry {
+        max_duration: max_duration.clone(),
+      })
+      .eviction_listener(move |key: Arc<CacheKey>, value: GroupObjects, cause| {
+        let track_alias = key.track_alias;
+        let group_id = key.group_id;
+        let log_folder = log_folder_for_listener.clone();
+
+        tokio::spawn(async move {
+          let object_count = value.read().await.len();
+          Self::log_cache_eviction(log_folder, track_alias, group_id, object_count, cause).await;
+        });
+      });
+
+    // Configure expiration based on config
+    let cache = match config.cache_expiration_type {
+      CacheExpirationType::Ttl => {
+        info!(
+          "track_cache::new | configuring TTL cache | track: {} duration: {}min",
+          track_alias, config.cache_expiration_minutes
+        );
+        cache_builder
+          .time_to_live(config.get_cache_expiration_duration())
+          .build()
+      }
+      CacheExpirationType::Tti => {
+        info!(
+          "track_cache::new | configuring TTI cache | track: {} duration: {}min",
+          track_alias, config.cache_expiration_minutes
+        );
+        cache_builder
+          .time_to_idle(config.get_cache_expiration_duration())
+          .build()
+      }
+    };
+
+    Self {
+      cache,
+      max_duration,
+    }
+  }
+
+  /// Manually run pending tasks (for testing or maintenance)
+  pub async fn run_pending_tasks(&self) {
+    self.cache.run_pending_tasks().await;
+  }
+
+  /// Log cache eviction events to cache_eviction.log
+  async fn log_cache_eviction(
+    log_folder: String,
+    track_alias: u64,
+    group_id: u64,
+    object_count: usize,
+    cause: RemovalCause,
+  ) {
+    let log_filename = "cache_eviction.log";
+    let log_path = std::path::Path::new(&log_folder).join(log_filename);
+
+    let cause_str = match cause {
+      RemovalCause::Size => "SIZE",
+      RemovalCause::Expired => "EXPIRED",
+      RemovalCause::Explicit => "EXPLICIT",
+      RemovalCause::Replaced => "REPLACED",
+    };
+
+    let log_entry = format!(
+      "{},{},{},{}\n",
+      track_alias, group_id, object_count, cause_str
+    );
+
+    // Create logs directory if it doesn't exist
+    if let Err(e) = tokio::fs::create_dir_all(&log_folder).await {
+      error!("Failed to create log directory {}: {:?}", log_folder, e);
+      return;
+    }
+
+    // Append to log file
+    match OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(&log_path)
+      .await
+    {
+      Ok(mut file) => {
+        if let Err(e) = file.write_all(log_entry.as_bytes()).await {
+          error!(
+            "Failed to write to cache eviction log file {:?}: {:?}",
+            log_path, e
+          );
+        }
+      }
+      Err(e) => {
+        error!(
+          "Failed to open cache eviction log file {:?}: {:?}",
+          log_path, e
+        );
+      }
+    }
+  }
+}
+
+impl CacheStore for MokaCacheStore {
+  fn get(&self, key: CacheKey) -> BoxFuture<'_, Option<GroupObjects>> {
+    Box::pin(async move { self.cache.get(&key).await })
+  }
+
+  fn insert(&self, key: CacheKey, objects: GroupObjects) -> BoxFuture<'_, ()> {
+    Box::pin(async move { self.cache.insert(key, objects).await })
+  }
+
+  fn contains(&self, key: CacheKey) -> bool {
+    self.cache.contains_key(&key)
+  }
+
+  fn group_ids(&self) -> Vec<u64> {
+    self.cache.iter().map(|(key, _)| key.group_id).collect()
+  }
+
+  fn stats(&self) -> (u64, u64) {
+    (self.cache.entry_count(), self.cache.weighted_size())
+  }
+
+  fn set_max_duration(&self, duration: Duration) {
+    *self.max_duration.write().unwrap() = Some(duration);
+  }
+}
+
+/// Creates a [`MokaCacheStore`] per track from the relay configuration
+pub struct MokaCacheStoreFactory {
+  config: Arc<RelayConfig>,
+}
+
+impl MokaCacheStoreFactory {
+  pub fn new(config: Arc<RelayConfig>) -> Self {
+    Self { config }
+  }
+}
+
+impl CacheStoreFactory for MokaCacheStoreFactory {
+  fn create(&self, track_alias: u64) -> Arc<dyn CacheStore> {
+    Arc::new(MokaCacheStore::new(track_alias, &self.config))
+  }
+}
+
+impl TrackCache {
+  pub fn new(track_alias: u64, store: Arc<dyn CacheStore>) -> Self {
+    Self { track_alias, store }
+  }
+
+  /// Applies the MAX_CACHE_DURATION from the publisher's SUBSCRIBE_OK or
+  /// FETCH_OK to the groups cached from now on
+  pub fn set_max_duration(&self, duration: Duration) {
+    info!(
+      "track_cache::set_max_duration | track: {} duration: {:?}",
+      self.track_alias, duration
+    );
+    self.store.set_max_duration(duration);
+  }
+
+  pub async fn add_object(&self, object: FetchObject) {
+    let cache_key = CacheKey::new(self.track_alias, object.group_id);
+
+    // Check if group al  y exists in cache
+    if let Some(existing_objects) = self.store.get(cache_key).await {
+      // Add object to existing group
+      let mut objects = existing_objects.write().await;
+      objects.push(object.clone());
+      debug!(
+        "track_cache::add_object | added object to existing group | track: {} group: {} object_id: {} total_objects: {}",
+        self.track_alias,
+        object.group_id,
+        object.object_id,
+        objects.len()
+      );
+    } else {
+      // Create new group with this object
+      let new_group_objects = Arc::new(RwLock::new(vec![object.clone()]));
+      self.store.insert(cache_key, new_group_objects).await;
+      debug!(
+        "track_cache::add_object | created new group | track: {} group: {} object_id: {}",
+        self.track_alias, object.group_id, object.object_id
+      );
+    }
+  }
+
+  /// Streams the cached objects between `start` and `end`. Groups come in
+  /// `group_order`, objects within a group always ascend.
+  pub async fn read_objects(
+    &self,
+    start: Location,
+    end: Location,
+    group_order: GroupOrder,
+  ) -> Receiver<CacheConsumeEvent> {
+    let (tx, rx) = channel(32); // Smaller buffer for memory efficiency
+    let cache = self.clone();
+    let track_alias = self.track_alias;
+
+    // TODO: this can be done without using a task and sender-receiver pattern
+    // but I'm doing this in order to lay the foundation for the future
+    // when the cache will be filled eventually.
+    tokio::spawn(async move {
+      info!(
+        "read_objects | track: {} start: {:?}, end: {:?}",
+        track_alias, start, end
+      );
+
+      // TODO: compare objects as well
+      if start.group > end.group {
+        warn!("start group cannot be greater than end group");
+        return;
+      }
+
+      // Collect all groups in the range that exist in cache
+      let mut groups_in_range = cache.cached_groups(start.group, end.group).await;
+
+      if groups_in_range.is_empty() {
+        if let Err(err) = tx.send(CacheConsumeEvent::NoObject).await {
+          warn!("read_objects | An error occurred: {:?}", err);
+          return;
+        }
+        return;
+      }
+
+      // Send end location based on last group found
+      if let Some(end_location) = cache.end_location(&start, &end).await {
+        info!(
+          "read_objects | track: {} groups_found: {} end_location: {:?}",
+          track_alias,
+          groups_in_range.len(),
+          &end_location
+        );
+        if let Err(err) = tx.send(CacheConsumeEvent::EndLocation(end_location)).await {
+          warn!("read_objects | An error occurred: {:?}", err);
+          return;
+        }
+      }
+
+      if group_order == GroupOrder::Descending {
+        groups_in_range.reverse();
+      }
+
+      // Send objects from all groups in range
+      for (group_id, objects_arc) in groups_in_range {
+        let objects = objects_arc.read().await;
+        let mut object_counter = 0;
+        for object in objects.iter() {
+          if !in_range(&start, &end, group_id, object.object_id) {
+            continue;
+          }
+
+          object_counter += 1;
+
+          if let Err(err) = tx.send(CacheConsumeEvent::Object(object.clone())).await {
+            warn!("read_objects | An error occurred: {:?}", err);
+            return; // Client disconnected
+          }
+        }
+
+        info!(
+          "read_objects | track: {} processed group_id: {} with {} objects",
+          track_alias, group_id, object_counter
+        );
+      }
+    });
+
+    rx
+  }
+
+  /// The largest cached object between `start` and `end`
+  pub async fn end_location(&self, start: &Location, end: &Location) -> Option<Location> {
+    for (group_id, objects) in self
+      .cached_groups(start.group, end.group)
+      .await
+      .iter()
+      .rev()
+    {
+      let last_object = objects
+        .read()
+        .await
+        .iter()
+        .map(|object| object.object_id)
+        .filter(|object_id| in_range(start, end, *group_id, *object_id))
+        .max();
+      if let Some(last_object) = last_object {
+        return Some(Location::new(*group_id, last_object));
+      }
+    }
+    None
+  }
+
+  /// Splits the objects between `start` and `end` into cached and missing
+  /// spans. The newest cached group may still be growing, so objects after
+  /// its last cached one count as missing unless it has ended.
+  pub async fn spans(&self, start: &Location, end: &Location) -> Vec<CacheSpan> {
+    let newest_group = self.store.group_ids().into_iter().max();
+    let mut extents = Vec::new();
+    for (group_id, objects) in self.cached_groups(start.group, end.group).await {
+      let objects = objects.read().await;
+      let first_object = objects.iter().map(|object| object.object_id).min();
+      let last_object = objects.iter().map(|object| object.object_id).max();
+      let (Some(first_object), Some(last_object)) = (first_object, last_object) else {
+        continue;
+      };
+      let ended = objects.iter().any(|object| {
+        matches!(
+          object.object_status,
+          Some(ObjectStatus::EndOfGroup | ObjectStatus::EndOfTrack)
+        )
+      });
+      extents.push(GroupExtent {
+        group_id,
+        first_object,
+        last_object,
+        complete: ended || Some(group_id) != newest_group,
+      });
+    }
+    split_range(start, end, &extents)
+  }
+
+  /// The cached groups between `start_group` and `end_group` in ascending
+  /// order. Only the cached keys are visited, however wide the range is.
+  async fn cached_groups(&self, start_group: u64, end_group: u64) -> Vec<(u64, GroupObjects)> {
+    let mut group_ids: Vec<u64> = self
+      .store
+      .group_ids()
+      .into_iter()
+      .filter(|group_id| (start_group..=end_group).contains(group_id))
+      .collect();
+    group_ids.sort_unstable();
+
+    let mut groups = Vec::with_capacity(group_ids.len());
+    for group_id in group_ids {
+      if let Some(objects) = self
+        .store
+        .get(CacheKey::new(self.track_alias, group_id))
+        .await
+      {
+        groups.push((group_id, objects));
+      }
+    }
+    groups
+  }
+
+  /// Get cache statistics (for monitoring/debugging)
+  #[allow(dead_code)]
+  pub async fn get_cache_stats(&self) -> (u64, u64) {
+    self.store.stats()
+  }
+
+  /// Get a specific group if it exists
+  #[allow(dead_code)]
+  pub async fn get_group(&self, group_id: u64) -> Option<GroupObjects> {
+    let cache_key = CacheKey::new(self.track_alias, group_id);
+    self.store.get(cache_key).await
+  }
+
+  /// Check if a group exists in cache
+  #[allow(dead_code)]
+  pub async fn contains_group(&self, group_id: u64) -> bool {
+    let cache_key = CacheKey::new(self.track_alias, group_id);
+    self.store.contains(cache_key)
+  }
+}
+
+/// Whether an object lies between `start` and `end`, where an end object of
+/// zero means the whole end group
+fn in_range(start: &Location, end: &Location, group_id: u64, object_id: u64) -> bool {
+  let before_start = group_id == start.group && object_id < start.object;
+  let after_end = group_id == end.group && end.object > 0 && object_id >= end.object;
+  !before_start && !after_end
+}
+
+/// Walks the cached groups of a fetch range and covers the range with cached
+/// and missing spans, merging neighbouring spans of the same kind.
+pub(crate) fn split_range(
+  start: &Location,
+  end: &Location,
+  groups: &[GroupExtent],
+) -> Vec<CacheSpan> {
+  let mut spans = Vec::new();
+  if start.group > end.group {
+    return spans;
+  }
+
+  // the first object that no span covers yet
+  let mut next = start.clone();
+  for group in groups
+    .iter()
+    .filter(|group| (start.group..=end.group).contains(&group.group_id))
+  {
+    let group_id = group.group_id;
+    if next.group < group_id {
+      push_span(&mut spans, false, next, Location::new(group_id - 1, 0));
+      next = Location::new(group_id, 0);
+    }
+
+    // one past the last object the fetch wants from this group, 0 for all
+    let wanted_end = if group_id == end.group { end.object } else { 0 };
+    let wants_up_to = |object_id: u64| wanted_end != 0 && wanted_end <= object_id;
+
+    if next.object < group.first_object {
+      if wants_up_to(group.first_object) {
+        push_span(&mut spans, false, next, Location::new(group_id, wanted_end));
+        return spans;
+      }
+      let first = Location::new(group_id, group.first_object);
+      push_span(&mut spans, false, next, first.clone());
+      next = first;
+    }
+
+    let cached_end = group.last_object + 1;
+    if wants_up_to(cached_end) {
+      if next.object < wanted_end {
+        push_span(&mut spans, true, next, Location::new(group_id, wanted_end));
+      }
+    } else if group.complete {
+      if next.object < cached_end {
+        push_span(&mut spans, true, next, Location::new(group_id, 0));
+      }
+    } else {
+      if next.object < cached_end {
+        push_span(
+          &mut spans,
+          true,
+          next.clone(),
+          Location::new(group_id, cached_end),
+        );
+      }
+      push_span(
+        &mut spans,
+        false,
+        Location::new(group_id, next.object.max(cached_end)),
+        Location::new(group_id, wanted_end),
+      );
+    }
+
+    match group_id.checked_add(1) {
+      Some(next_group) => next = Location::new(next_group, 0),
+      None => return spans,
+    }
+  }
+  if next.group <= end.group {
+    push_span(&mut spans, false, next, end.clone());
+  }
+  spans
+}
+
+/// Appends a span, extending the last one if it is of the same kind and ends
+/// with the whole group right before it
+fn push_span(spans: &mut Vec<CacheSpan>, cached: bool, start: Location, end: Location) {
+  if let Some(last) = spans.last_mut() {
+    let (last_cached, last_end) = match last {
+      CacheSpan::Cached(_, last_end) => (true, last_end),
+      CacheSpan::Missing(_, last_end) => (false, last_end),
+    };
+    if last_cached == cached
+      && last_end.object == 0
+      && start.object == 0
+      && last_end.group.checked_add(1) == Some(start.group)
+    {
+      *last_end = end;
+      return;
+    }
+  }
+  spans.push(if cached {
+    CacheSpan::Cached(start, end)
+  } else {
+    CacheSpan::Missing(start, end)
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn extent(group_id: u64, first_object: u64, last_object: u64, complete: bool) -> GroupExtent {
+    GroupExtent {
+      group_id,
+      first_object,
+      last_object,
+      complete,
+    }
+  }
+
+  #[test]
+  fn test_split_range_fully_cached() {
+    let groups = [extent(2, 0, 3, true), extent(3, 0, 1, true)];
+    let spans = split_range(&Location::new(2, 0), &Location::new(3, 0), &groups);
+    assert_eq!(
+      spans,
+      vec![CacheSpan::Cached(Location::new(2, 0), Location::new(3, 0))]
+    );
+  }
+
+  #[test]
+  fn test_split_range_with_gaps() {
+    let groups = [
+      extent(1, 0, 0, true),
+      extent(2, 0, 0, true),
+      extent(5, 0, 0, true),
+    ];
+    let spans = split_range(&Location::new(0, 0), &Location::new(6, 0), &groups);
+    assert_eq!(
+      spans,
+      vec![
+        CacheSpan::Missing(Location::new(0, 0), Location::new(0, 0)),
+        CacheSpan::Cached(Location::new(1, 0), Location::new(2, 0)),
+        CacheSpan::Missing(Location::new(3, 0), Location::new(4, 0)),
+        CacheSpan::Cached(Location::new(5, 0), Location::new(5, 0)),
+        CacheSpan::Missing(Location::new(6, 0), Location::new(6, 0)),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_split_range_partial_groups() {
+    // group 1 was joined mid-group, group 3 is still growing
+    let groups = [
+      extent(1, 4, 9, true),
+      extent(2, 0, 5, true),
+      extent(3, 0, 2, false),
+    ];
+    let spans = split_range(&Location::new(1, 2), &Location::new(3, 0), &groups);
+    assert_eq!(
+      spans,
+      vec![
+        CacheSpan::Missing(Location::new(1, 2), Location::new(1, 4)),
+        CacheSpan::Cached(Location::new(1, 4), Location::new(3, 3)),
+        CacheSpan::Missing(Location::new(3, 3), Location::new(3, 0)),
+      ]
+    );
+
+    // the growing group has every object the fetch asks for
+    let spans = split_range(&Location::new(3, 1), &Location::new(3, 2), &groups);
+    assert_eq!(
+      spans,
+      vec![CacheSpan::Cached(Location::new(3, 1), Location::new(3, 2))]
+    );
+
+    // the fetch ends before the first cached object
+    let spans = split_range(&Location::new(1, 0), &Location::new(1, 3), &groups);
+    assert_eq!(
+      spans,
+      vec![CacheSpan::Missing(Location::new(1, 0), Location::new(1, 3))]
+    );
+  }
+
+  #[test]
+  fn test_split_range_empty() {
+    assert!(split_range(&Location::new(4, 0), &Location::new(3, 0), &[]).is_empty());
+  }
+
+  fn object(group_id: u64, object_id: u64) -> FetchObject {
+    FetchObject {
+      group_id,
+      subgroup_id: 0,
+      object_id,
+      publisher_priority: 0,
+      extension_headers: None,
+      object_status: None,
+      payload: None,
+    }
+  }
+
+  #[tokio::test]
+  async fn test_read_objects_descending() {
+    let config = RelayConfig::default();
+    let cache = TrackCache::new(1, Arc::new(MokaCacheStore::new(1, &config)));
+    for group_id in 0..3 {
+      for object_id in 0..2 {
+        cache.add_object(object(group_id, object_id)).await;
+      }
+    }
+
+    let mut rx = cache
+      .read_objects(
+        Location::new(0, 0),
+        Location::new(2, 0),
+        GroupOrder::Descending,
+      )
+      .await;
+    let mut end_location = None;
+    let mut locations = Vec::new();
+    while let Some(event) = rx.recv().await {
+      match event {
+        CacheConsumeEvent::EndLocation(location) => end_location = Some(location),
+        CacheConsumeEvent::Object(o) => locations.push((o.group_id, o.object_id)),
+        CacheConsumeEvent::NoObject | CacheConsumeEvent::Failed => panic!("expected objects"),
+      }
+    }
+
+    assert_eq!(end_location, Some(Location::new(2, 1)));
+    assert_eq!(
+      locations,
+      vec![(2, 0), (2, 1), (1, 0), (1, 1), (0, 0), (0, 1)]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_max_duration_expires_groups() {
+    let config = RelayConfig::default();
+    let cache = TrackCache::new(1, Arc::new(MokaCacheStore::new(1, &config)));
+    cache.add_object(object(0, 0)).await;
+
+    cache.set_max_duration(Duration::from_millis(50));
+    cache.add_object(object(1, 0)).await;
+    assert!(cache.get_group(1).await.is_some());
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(cache.get_group(1).await.is_none());
+    // cached before the publisher set a duration
+    assert!(cache.get_group(0).await.is_some());
+  }
+
+  #[tokio::test]
+  async fn test_without_max_duration_config_expiration_applies() {
+    let config = RelayConfig {
+      cache_expiration_minutes: 5,
+      ..RelayConfig::default()
+    };
+    let store = MokaCacheStore::new(1, &config);
+    assert_eq!(
+      store.cache.policy().time_to_live(),
+      Some(Duration::from_secs(300))
+    );
+
+    let cache = TrackCache::new(1, Arc::new(store));
+    cache.add_object(object(0, 0)).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(cache.get_group(0).await.is_some());
+  }
+
+  #[tokio::test]
+  async fn test_unbounded_range_visits_cached_groups_only() {
+    let config = RelayConfig::default();
+    let cache = TrackCache::new(1, Arc::new(MokaCacheStore::new(1, &config)));
+    cache.add_object(object(1, 0)).await;
+    cache.add_object(object(2, 0)).await;
+
+    let start = Location::new(0, 0);
+    let end = Location::new(u64::MAX, 0);
+    assert_eq!(
+      cache.spans(&start, &end).await,
+      vec![
+        CacheSpan::Missing(Location::new(0, 0), Location::new(0, 0)),
+        CacheSpan::Cached(Location::new(1, 0), Location::new(2, 1)),
+        CacheSpan::Missing(Location::new(2, 1), Location::new(u64::MAX, 0)),
+      ]
+    );
+
+    let mut rx = cache.read_objects(start, end, GroupOrder::Ascending).await;
+    let mut locations = Vec::new();
+    while let Some(event) = rx.recv().await {
+      if let CacheConsumeEvent::Object(o) = event {
+        locations.push((o.group_id, o.object_id));
+      }
+    }
+    assert_eq!(locations, vec![(1, 0), (2, 0)]);
+  }
+}
@@ -12,18 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::server::stream_id::StreamId;
-use bytes::Bytes;
-use fnv::FnvHasher;
-use moqtail::{
+use crate::relay::stream_id::StreamId;
+use crate::{
   model::control::control_message::ControlMessageTrait, transport::data_stream_handler::HeaderInfo,
 };
-use once_cell::sync::Lazy;
+use bytes::Bytes;
+use fnv::FnvHasher;
 use std::hash::Hasher;
+use std::sync::LazyLock;
 use std::time::Instant;
 
 // Static reference time: set when the program starts
-pub static BASE_TIME: Lazy<Instant> = Lazy::new(Instant::now);
+pub static BASE_TIME: LazyLock<Instant> = LazyLock::new(Instant::now);
 
 pub fn print_msg_bytes(msg: &impl ControlMessageTrait) {
   let bytes = msg.serialize();
@@ -14,3 +14,8 @@
 
 pub mod control_stream_handler;
 pub mod data_stream_handler;
+pub mod datagram_handler;
+pub mod loopback;
+pub mod moq_transport;
+pub mod quic;
+pub mod webtransport;
@@ -13,40 +13,54 @@
 // limitations under the License.
 
 use bytes::{Buf, BufMut, BytesMut};
+use std::sync::Arc;
 use tokio::time::{Duration, Instant, sleep_until};
 use tracing::{error, info, warn};
-use wtransport::error::StreamReadError;
-use wtransport::{RecvStream, SendStream};
 
 use crate::model::control::control_message::{ControlMessage, ControlMessageTrait};
 use crate::model::error::{ParseError, TerminationCode};
+use crate::model::version::{Draft11, VersionCodec};
+use crate::transport::moq_transport::{BoxRecvStream, BoxSendStream, TransportError};
 
 const CONTROL_MESSAGE_TIMEOUT: Duration = Duration::from_secs(5);
 
 const MTU_SIZE: usize = 1500; // Standard MTU size, max 2^16-1
 
 pub struct ControlStreamHandler {
-  send: SendStream,
-  recv: RecvStream,
+  send: BoxSendStream,
+  recv: BoxRecvStream,
   recv_bytes: BytesMut,
   recv_buf: Box<[u8; MTU_SIZE]>,
   partial_message_deadline: Option<Instant>,
+  codec: Arc<dyn VersionCodec>,
 }
 
 impl ControlStreamHandler {
-  pub fn new(send: SendStream, recv: RecvStream) -> Self {
+  pub fn new(send: BoxSendStream, recv: BoxRecvStream) -> Self {
     Self {
       send,
       recv,
       recv_bytes: BytesMut::new(),
       recv_buf: Box::new([0; MTU_SIZE]),
       partial_message_deadline: None,
+      codec: Arc::new(Draft11),
     }
   }
 
+  /// Switches to the wire format of the version picked in the setup
+  /// handshake. The handshake itself is read and written as draft-11.
+  pub fn set_codec(&mut self, codec: Arc<dyn VersionCodec>) {
+    self.codec = codec;
+  }
+
+  pub fn version(&self) -> u32 {
+    self.codec.version()
+  }
+
   pub async fn send(&mut self, message: &ControlMessage) -> Result<(), TerminationCode> {
-    let bytes = message
-      .serialize()
+    let bytes = self
+      .codec
+      .serialize_control_message(message)
       .map_err(|_| TerminationCode::InternalError)?;
     if (self.send.write_all(&bytes).await).is_err() {
       warn!("Error sending message: {:?}", message);
@@ -76,7 +90,7 @@ impl ControlStreamHandler {
         let mut bytes = self.recv_bytes.clone().freeze();
         let original_remaining = bytes.remaining();
 
-        match ControlMessage::deserialize(&mut bytes) {
+        match self.codec.deserialize_control_message(&mut bytes) {
           Ok(msg) => {
             let consumed = original_remaining - bytes.remaining();
             self.recv_bytes.advance(consumed);
@@ -137,7 +151,7 @@ impl ControlStreamHandler {
   /// Handle the result of a stream read operation
   fn handle_read_result(
     &mut self,
-    res: Result<Option<usize>, wtransport::error::StreamReadError>,
+    res: Result<Option<usize>, TransportError>,
     is_partial_message: bool,
   ) -> Result<(), TerminationCode> {
     match res {
@@ -162,7 +176,7 @@ impl ControlStreamHandler {
       }
       Err(e) => {
         match e {
-          StreamReadError::NotConnected => {
+          TransportError::NotConnected => {
             info!("Client disconnected while reading control stream");
             // Client has disconnected - this is not an error, return NoError
             // so the caller knows not to try closing the connection
@@ -199,109 +213,33 @@ mod tests {
   use crate::model::control::server_setup::ServerSetup;
   use crate::model::control::subscribe::Subscribe;
   use crate::model::control::subscribe_ok::SubscribeOk;
+  use crate::transport::loopback::{LoopbackConfig, LoopbackTransport};
+  use crate::transport::moq_transport::MoqTransport;
   use bytes::Bytes;
   use std::error::Error;
   use std::sync::Arc;
   use tokio::sync::Mutex;
   use tokio::time::sleep;
-  use wtransport::endpoint::IntoConnectOptions;
-  use wtransport::{ClientConfig, Connection, Endpoint, Identity};
 
   struct TestSetup {
-    client: Connection,
-    server: Connection,
+    client: LoopbackTransport,
+    server: LoopbackTransport,
   }
 
   impl TestSetup {
-    async fn new() -> Result<Self, Box<dyn Error>> {
-      // Create server identity (self-signed certificate)
-      let server_identity = Identity::self_signed(std::iter::once("localhost"))
-        .map_err(|e| format!("Failed to create server identity: {e}"))?;
-
-      // Get the certificate hash from the server identity
-      let server_cert_hash = server_identity.certificate_chain().as_slice()[0].hash();
-
-      // Create server configuration with the identity
-      let server_config = wtransport::ServerConfig::builder()
-        .with_bind_address(
-          "127.0.0.1:0"
-            .parse()
-            .map_err(|e| format!("Failed to parse bind address: {e}"))?,
-        )
-        .with_identity(server_identity)
-        .build();
-
-      // Create and start the server endpoint
-      let server_endpoint = Endpoint::server(server_config)
-        .map_err(|e| format!("Failed to create server endpoint: {e}"))?;
-      let server_addr = server_endpoint
-        .local_addr()
-        .map_err(|e| format!("Failed to get server local address: {e}"))?;
-
-      // Create a channel to get the server connection from the spawned task
-      let (tx, rx) = tokio::sync::oneshot::channel();
-
-      // Spawn a task to handle the server connection acceptance
-      tokio::spawn(async move {
-        let result = async {
-          // Accept an incoming connection
-          let incoming = server_endpoint.accept().await;
-
-          // Await the session request
-          let session_request = incoming
-            .await
-            .map_err(|e| format!("Failed to await session request: {e}"))
-            .unwrap();
-
-          let server = session_request.accept().await.unwrap();
-          Ok::<_, Box<dyn Error + Send>>(server)
-        }
-        .await;
-
-        // Send result back through the channel, log errors if sending fails
-        if tx.send(result).is_err() {
-          eprintln!("Failed to send server connection result back through the channel");
-        }
-      });
-
-      // Create client configuration with server's certificate hash for verification
-      let client_config = ClientConfig::builder()
-        .with_bind_default()
-        .with_server_certificate_hashes(vec![server_cert_hash])
-        .build();
-
-      let client_endpoint = Endpoint::client(client_config)
-        .map_err(|e| format!("Failed to create client endpoint: {e}"))?;
-
-      // Start client connection concurrently while server is accepting
-      let client = client_endpoint
-        .connect(
-          format!("https://{}:{}", server_addr.ip(), server_addr.port())
-            .as_str()
-            .into_options(),
-        )
-        .await
-        .map_err(|e| format!("Client connection failed: {e}"))?;
-
-      // Wait for the server connection from the spawned task
-      let server = rx
-        .await
-        .map_err(|_| "Server task failed to send connection back")?
-        .map_err(|e| format!("Server connection error: {e}"))?;
-
-      Ok(Self { client, server })
+    fn new() -> Self {
+      let (client, server) = LoopbackTransport::pair(LoopbackConfig::default());
+      Self { client, server }
     }
 
     async fn create_control_plane(
       &self,
-    ) -> Result<(ControlStreamHandler, SendStream), Box<dyn Error>> {
-      let (client_send, client_recv) = match self.client.open_bi().await {
-        Ok(stream_fut) => match stream_fut.await {
-          Ok((send, recv)) => (send, recv),
-          Err(e) => return Err(format!("Failed to await client stream: {e}").into()),
-        },
-        Err(e) => return Err(format!("Failed to open client stream: {e}").into()),
-      };
+    ) -> Result<(ControlStreamHandler, BoxSendStream), Box<dyn Error>> {
+      let (client_send, client_recv) = self
+        .client
+        .open_bi()
+        .await
+        .map_err(|e| format!("Failed to open client stream: {e}"))?;
 
       let (server_send, _) = self
         .server
@@ -427,17 +365,15 @@ mod tests {
 
   #[tokio::test]
   async fn test_connection_setup() -> Result<(), Box<dyn Error>> {
-    let setup = TestSetup::new().await?;
+    let setup = TestSetup::new();
 
     // Test that we can create a bidirectional stream
-    let (client_send, _) = setup
+    let (mut client_send, _) = setup
       .client
       .open_bi()
-      .await?
       .await
       .map_err(|e| format!("Failed to open bidirectional stream: {e}"))?;
 
-    let mut client_send = client_send;
     let (_, mut server_recv) = setup
       .server
       .accept_bi()
@@ -447,16 +383,16 @@ mod tests {
     // Test basic data transfer
     client_send.write_all(&[1, 2, 3, 4]).await?;
     let mut buf = [0; 4];
-    server_recv.read_exact(&mut buf).await?;
+    assert_eq!(server_recv.read(&mut buf).await?, Some(4));
 
     assert_eq!(buf, [1, 2, 3, 4]);
 
     Ok(())
   }
 
-  #[tokio::test]
+  #[tokio::test(start_paused = true)]
   async fn test_message_timeout() -> Result<(), Box<dyn Error>> {
-    let setup = TestSetup::new().await?;
+    let setup = TestSetup::new();
     let (mut plane, mut server_send) = setup.create_control_plane().await?;
     let mut bytes = BytesMut::new();
     bytes.put_vi(ControlMessageType::Announce)?;
@@ -473,7 +409,7 @@ mod tests {
 
   #[tokio::test]
   async fn test_successful_message() -> Result<(), Box<dyn Error>> {
-    let setup = TestSetup::new().await?;
+    let setup = TestSetup::new();
     let (mut plane, mut server_send) = setup.create_control_plane().await?;
 
     // Create and send a valid message using helper
@@ -496,7 +432,7 @@ mod tests {
 
   #[tokio::test]
   async fn test_partial_message_completion() -> Result<(), Box<dyn Error>> {
-    let setup = TestSetup::new().await?;
+    let setup = TestSetup::new();
     let (mut plane, mut server_send) = setup.create_control_plane().await?;
 
     // Create a valid message using helper
@@ -527,7 +463,7 @@ mod tests {
 
   #[tokio::test]
   async fn test_multiple_messages() -> Result<(), Box<dyn Error>> {
-    let setup = TestSetup::new().await?;
+    let setup = TestSetup::new();
     let (mut plane, server_send) = setup.create_control_plane().await?;
     // Use Arc<Mutex<>> for SendStream to share it with the spawned task
     let server_send = Arc::new(Mutex::new(server_send));
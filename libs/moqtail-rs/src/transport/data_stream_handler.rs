@@ -17,22 +17,21 @@ use std::time::Duration;
 
 use bytes::{Buf, BufMut, BytesMut};
 use std::sync::Arc;
-use tokio::io::AsyncWriteExt;
 use tokio::sync::{Mutex, Notify, RwLock};
 use tokio::task::yield_now;
 use tokio::time::{Instant, sleep_until};
-use wtransport::error::StreamReadError;
-use wtransport::{RecvStream, SendStream};
 
 use crate::model::control::fetch::Fetch;
 use crate::model::control::subscribe::Subscribe;
-use crate::model::data::constant::FetchHeaderType;
+use crate::model::data::constant::StreamResetCode;
 use crate::model::data::fetch_header::FetchHeader;
 use crate::model::data::fetch_object::FetchObject;
 use crate::model::data::object::Object;
 use crate::model::data::subgroup_header::SubgroupHeader;
 use crate::model::data::subgroup_object::SubgroupObject;
 use crate::model::error::ParseError;
+use crate::model::version::{Draft11, VersionCodec};
+use crate::transport::moq_transport::{BoxRecvStream, BoxSendStream, TransportError};
 use tracing::{debug, error, info};
 
 // Timeout for header and subsequent objects
@@ -66,6 +65,7 @@ pub struct SubscribeRequest {
   pub original_request_id: u64,
   pub requested_by: usize, // connection id
   pub subscribe_request: Subscribe,
+  pub answered: bool, // SUBSCRIBE_OK was relayed to the requester
 }
 
 impl FetchRequest {
@@ -90,6 +90,7 @@ impl SubscribeRequest {
       original_request_id,
       requested_by,
       subscribe_request,
+      answered: false,
     }
   }
 }
@@ -107,7 +108,7 @@ impl SubscribeRequest {
 ///
 /// ```
 pub struct SendDataStream {
-  send_stream: Arc<Mutex<SendStream>>,
+  send_stream: Arc<Mutex<BoxSendStream>>,
   header_info: HeaderInfo,
 }
 
@@ -115,17 +116,26 @@ pub struct SendDataStream {
 // Suggestion: SubgroupHeader should start with Request ID and discard track_alias
 impl SendDataStream {
   pub async fn new(
-    send_stream: Arc<Mutex<SendStream>>,
+    send_stream: Arc<Mutex<BoxSendStream>>,
     header_info: HeaderInfo,
+  ) -> Result<Self, ParseError> {
+    Self::new_with_codec(send_stream, header_info, &Draft11).await
+  }
+
+  /// Writes the header in the wire format of the negotiated version
+  pub async fn new_with_codec(
+    send_stream: Arc<Mutex<BoxSendStream>>,
+    header_info: HeaderInfo,
+    codec: &dyn VersionCodec,
   ) -> Result<Self, ParseError> {
     let mut buf = BytesMut::new();
 
     match &header_info {
       HeaderInfo::Fetch { header, .. } => {
-        buf.extend_from_slice(&header.serialize()?);
+        buf.extend_from_slice(&codec.serialize_fetch_header(header)?);
       }
       HeaderInfo::Subgroup { header, .. } => {
-        buf.extend_from_slice(&header.serialize()?);
+        buf.extend_from_slice(&codec.serialize_subgroup_header(header)?);
       }
     }
 
@@ -221,19 +231,30 @@ pub enum RecvDataStreamReadError {
 /// }
 /// ```
 pub struct RecvDataStream {
-  recv_stream: Arc<Mutex<RecvStream>>,
+  recv_stream: Arc<Mutex<BoxRecvStream>>,
   header_info: Arc<Mutex<Option<HeaderInfo>>>,
   pending_fetches: Arc<RwLock<BTreeMap<u64, FetchRequest>>>, // Mutable borrow to potentially remove entry
   objects: Arc<RwLock<VecDeque<Object>>>,                    // Buffer for parsed objects
   is_closed: Arc<RwLock<bool>>,                              // Track if the stream is closed
   started_read_task: Arc<Mutex<bool>>,                       // Track if read task has started
   notify: Arc<Notify>,
+  cancel_notify: Arc<Notify>, // Stops the read task early
+  codec: Arc<dyn VersionCodec>,
 }
 
 impl RecvDataStream {
   pub fn new(
-    recv_stream: RecvStream,
+    recv_stream: BoxRecvStream,
     pending_fetches: Arc<RwLock<BTreeMap<u64, FetchRequest>>>, // Mutable borrow to potentially remove entry
+  ) -> Self {
+    Self::new_with_codec(recv_stream, pending_fetches, Arc::new(Draft11))
+  }
+
+  /// Reads the header in the wire format of the negotiated version
+  pub fn new_with_codec(
+    recv_stream: BoxRecvStream,
+    pending_fetches: Arc<RwLock<BTreeMap<u64, FetchRequest>>>,
+    codec: Arc<dyn VersionCodec>,
   ) -> Self {
     Self {
       recv_stream: Arc::new(Mutex::new(recv_stream)),
@@ -243,6 +264,8 @@ impl RecvDataStream {
       is_closed: Arc::new(RwLock::new(false)),         // Track if the stream is closed
       started_read_task: Arc::new(Mutex::new(false)),
       notify: Arc::new(Notify::new()),
+      cancel_notify: Arc::new(Notify::new()),
+      codec,
     }
   }
 
@@ -252,13 +275,30 @@ impl RecvDataStream {
     header_info.clone()
   }
 
+  /// Stops reading the stream, e.g. after the request it serves was
+  /// cancelled. Buffered objects are still returned by `next_object`, and the
+  /// stream is stopped once the handler is dropped.
+  pub fn cancel(&self) {
+    self.cancel_notify.notify_one();
+  }
+
+  /// Stops reading and asks the peer to stop sending with `code`
+  pub async fn stop(&self, code: StreamResetCode) {
+    self.cancel();
+    // the read task releases the stream once it sees the cancellation
+    self.recv_stream.lock().await.stop(u64::from(code) as u32);
+  }
+
+  #[allow(clippy::too_many_arguments)]
   async fn read(
-    recv_stream: Arc<Mutex<RecvStream>>,
+    codec: Arc<dyn VersionCodec>,
+    recv_stream: Arc<Mutex<BoxRecvStream>>,
     is_closed: Arc<RwLock<bool>>,
     the_header_info: Arc<Mutex<Option<HeaderInfo>>>,
     pending_fetches: Arc<RwLock<BTreeMap<u64, FetchRequest>>>,
     objects: Arc<RwLock<VecDeque<Object>>>,
     notify: Arc<Notify>,
+    cancel_notify: Arc<Notify>,
   ) -> Result<(), RecvDataStreamReadError> {
     let mut header_info = None;
     let mut recv_buf = Box::new([0u8; MTU_SIZE]);
@@ -268,8 +308,9 @@ impl RecvDataStream {
     loop {
       let bytes_cursor = recv_bytes.clone().freeze();
       if !recv_bytes.is_empty() && header_info.is_none() {
-        let is_fetch = recv_bytes[0] == FetchHeaderType::Type0x05 as u8;
+        let is_fetch = codec.is_fetch_stream(recv_bytes[0]);
         header_info = Self::read_header(
+          codec.as_ref(),
           bytes_cursor,
           is_fetch,
           is_closed.clone(),
@@ -329,6 +370,13 @@ impl RecvDataStream {
       tokio::select! {
           biased;
 
+          _ = cancel_notify.notified() => {
+            debug!("RecvDataStream::read cancelled");
+            *is_closed.write().await = true;
+            notify.notify_waiters();
+            return Err(RecvDataStreamReadError::StreamClosed);
+          }
+
           _ = sleep_until(timeout_at) => {
             info!("Timeout while waiting for data");
             *is_closed.write().await = true;
@@ -356,7 +404,7 @@ impl RecvDataStream {
               Err(e) => {
                 debug!("RecvDataStream::read() Read error: {:?}", e);
                 *is_closed.write().await = true;
-                if e == StreamReadError::NotConnected {
+                if matches!(e, TransportError::NotConnected) {
                   return Err(RecvDataStreamReadError::StreamClosed);
                 }
                 return Err(RecvDataStreamReadError::ParseError(ParseError::Other { context: "RecvDataStream::new(header_read)", msg:e.to_string() }));
@@ -368,6 +416,7 @@ impl RecvDataStream {
   }
 
   async fn read_header(
+    codec: &dyn VersionCodec,
     mut bytes_cursor: bytes::Bytes,
     is_fetch: bool,
     is_closed: Arc<RwLock<bool>>,
@@ -376,7 +425,7 @@ impl RecvDataStream {
     debug!("RecvDataStream::read_header() called");
     let original_remaining = bytes_cursor.remaining();
     if is_fetch {
-      match FetchHeader::deserialize(&mut bytes_cursor) {
+      match codec.deserialize_fetch_header(&mut bytes_cursor) {
         Ok(fetch_header) => {
           let pending_fetches = pending_fetches.read().await;
           if let Some(fetch_request) = pending_fetches.get(&fetch_header.request_id) {
@@ -417,7 +466,7 @@ impl RecvDataStream {
         }
       }
     } else {
-      match SubgroupHeader::deserialize(&mut bytes_cursor) {
+      match codec.deserialize_subgroup_header(&mut bytes_cursor) {
         Ok(subgroup_header) => {
           let consumed = original_remaining - bytes_cursor.remaining();
           let header_info = HeaderInfo::Subgroup {
@@ -524,17 +573,23 @@ impl RecvDataStream {
       let objects = self.objects.clone();
       let header_info = self.header_info.clone();
       let notify = self.notify.clone();
+      let cancel_notify = self.cancel_notify.clone();
+      let codec = self.codec.clone();
       tokio::spawn(async move {
-        match Self::read(
+        let result = Self::read(
+          codec,
           recv_stream,
           is_closed,
           header_info,
           pending_fetches,
           objects,
-          notify,
+          notify.clone(),
+          cancel_notify,
         )
-        .await
-        {
+        .await;
+        // wake up readers waiting on a stream that ended with an error
+        notify.notify_waiters();
+        match result {
           Ok(_) => debug!("RecvDataStream read task completed successfully"),
           Err(e) => {
             error!("RecvDataStream read task encountered an error: {:?}", e);
@@ -584,13 +639,12 @@ mod tests {
   use crate::model::control::fetch::JoiningFetchProps;
   use crate::model::control::{fetch::Fetch, subscribe::Subscribe};
   use crate::model::data::constant::SubgroupHeaderType;
+  use crate::transport::loopback::{LoopbackConfig, LoopbackTransport};
+  use crate::transport::moq_transport::MoqTransport;
   use bytes::Bytes;
-  use std::error::Error;
   use std::sync::Arc;
   use tokio::sync::Mutex;
   use tokio::time::{Duration, sleep};
-  use wtransport::endpoint::IntoConnectOptions;
-  use wtransport::{ClientConfig, Connection, Endpoint, Identity, RecvStream, SendStream};
 
   fn make_fetch_header_and_request() -> (FetchHeader, Fetch) {
     let request_id = 161803;
@@ -646,7 +700,6 @@ mod tests {
     Object::try_from_fetch(fetch_obj.clone(), 0).unwrap()
   }
 
-  #[allow(dead_code)]
   fn make_subgroup_header_and_request() -> (SubgroupHeader, Subscribe) {
     let request_id = 128242;
     let track_alias = 999;
@@ -693,7 +746,6 @@ mod tests {
     (subgroup_header, subscribe)
   }
 
-  #[allow(dead_code)]
   fn make_subgroup_object() -> SubgroupObject {
     let object_id: u64 = 10;
     let extension_headers = Some(vec![
@@ -711,7 +763,6 @@ mod tests {
     }
   }
 
-  #[allow(dead_code)]
   fn make_object_from_subgroup(subgroup_obj: &SubgroupObject, header: &SubgroupHeader) -> Object {
     Object::try_from_subgroup(
       subgroup_obj.clone(),
@@ -723,135 +774,39 @@ mod tests {
     .unwrap()
   }
 
-  struct TestSetup {
-    client: Connection,
-    server: Connection,
-  }
-
-  impl TestSetup {
-    async fn new() -> Result<Self, Box<dyn Error>> {
-      let server_identity = Identity::self_signed(std::iter::once("localhost"))
-        .map_err(|e| format!("Failed to create server identity: {e}"))?;
-      let server_cert_hash = server_identity.certificate_chain().as_slice()[0].hash();
-      let server_config = wtransport::ServerConfig::builder()
-        .with_bind_address(
-          "127.0.0.1:0"
-            .parse()
-            .map_err(|e| format!("Failed to parse bind address: {e}"))?,
-        )
-        .with_identity(server_identity)
-        .build();
-      let server_endpoint = Endpoint::server(server_config)
-        .map_err(|e| format!("Failed to create server endpoint: {e}"))?;
-      let server_addr = server_endpoint
-        .local_addr()
-        .map_err(|e| format!("Failed to get server local address: {e}"))?;
-
-      // Create a channel to get the server connection from the spawned task
-      let (tx, rx) = tokio::sync::oneshot::channel();
-
-      // Spawn a task to handle the server connection acceptance
-      tokio::spawn(async move {
-        let result = async {
-          let incoming = server_endpoint.accept().await;
-          let session_request = incoming
-            .await
-            .map_err(|e| format!("Failed to await session request: {e}"))
-            .unwrap();
-          let server = session_request.accept().await.unwrap();
-          Ok::<_, Box<dyn Error + Send>>(server)
-        }
-        .await;
-
-        if tx.send(result).is_err() {
-          eprintln!("Failed to send server connection result back through the channel");
-        }
-      });
-
-      // Create client configuration with server's certificate hash for verification
-      let client_config = ClientConfig::builder()
-        .with_bind_default()
-        .with_server_certificate_hashes(vec![server_cert_hash])
-        .build();
-
-      let client_endpoint = Endpoint::client(client_config)
-        .map_err(|e| format!("Failed to create client endpoint: {e}"))?;
-
-      // Start client connection concurrently while server is accepting
-      let client = client_endpoint
-        .connect(
-          format!("https://{}:{}", server_addr.ip(), server_addr.port())
-            .as_str()
-            .into_options(),
-        )
-        .await
-        .map_err(|e| format!("Client connection failed: {e}"))?;
-
-      // Wait for the server connection from the spawned task
-      let server = rx
-        .await
-        .map_err(|_| "Server task failed to send connection back")?
-        .map_err(|e| format!("Server connection error: {e}"))?;
-
-      Ok(Self { client, server })
-    }
-
-    async fn create_data_stream_pair(&self) -> Result<(SendStream, RecvStream), Box<dyn Error>> {
-      // Client opens a unidirectional stream (SendStream) in a spawned task
-      let client = self.client.clone();
-      let send_fut = tokio::spawn(async move {
-        // The first await returns Result<SendUni, StreamOpeningError>
-        // The second await returns Result<SendStream, StreamOpeningError>
-        let send_uni = client.open_uni().await.unwrap();
-        send_uni.await
-      });
-
-      // Server accepts the unidirectional stream (RecvStream) in a spawned task
-      let server = self.server.clone();
-      let recv_fut = tokio::spawn(async move {
-        server
-          .accept_uni()
-          .await
-          .map_err(|e| format!("Failed to accept server uni stream: {e}"))
-      });
-
-      // Await both concurrently
-      let (send_res, recv_res) = tokio::try_join!(send_fut, recv_fut)?;
-
-      let send = send_res.map_err(|e| format!("Failed to open client uni stream: {e}"))?;
-      let recv = recv_res.map_err(|e| format!("Failed to accept server uni stream: {e}"))?;
-
-      Ok((send, recv))
-    }
-  }
-
-  async fn setup_stream_pair() -> (SendStream, RecvStream) {
-    let setup = TestSetup::new()
+  async fn setup_stream_pair() -> (BoxSendStream, BoxRecvStream) {
+    let (client, server) = LoopbackTransport::pair(LoopbackConfig::default());
+    let send = client
+      .open_uni()
       .await
-      .expect("Failed to setup test transport");
-    setup
-      .create_data_stream_pair()
+      .expect("Failed to open client uni stream");
+    let recv = server
+      .accept_uni()
       .await
-      .expect("Failed to create data stream pair")
+      .expect("Failed to accept server uni stream");
+    (send, recv)
   }
 
-  /* TODO: rewrite this test
-  #[tokio::test]
-  async fn test_send_recv_fetch_object_success() {
-    let (send, recv) = setup_stream_pair().await;
-    let (fetch_header, fetch_req) = make_fetch_header_and_request();
-    let mut pending_fetches: BTreeMap<u64, FetchRequest> = BTreeMap::new();
+  fn pending_fetches_for(fetch: &Fetch) -> Arc<RwLock<BTreeMap<u64, FetchRequest>>> {
+    let mut pending_fetches = BTreeMap::new();
     pending_fetches.insert(
-      fetch_req.request_id,
+      fetch.request_id,
       FetchRequest {
-        original_request_id: fetch_req.request_id,
+        original_request_id: fetch.request_id,
         requested_by: 1,
-        fetch_request: fetch_req.clone(),
+        fetch_request: fetch.clone(),
         track_alias: 1,
       },
     );
+    Arc::new(RwLock::new(pending_fetches))
+  }
+
+  #[tokio::test]
+  async fn test_send_recv_fetch_object_success() {
+    let (send, recv) = setup_stream_pair().await;
+    let (fetch_header, fetch_req) = make_fetch_header_and_request();
+    let pending_fetches = pending_fetches_for(&fetch_req);
 
-    // Sender
     let mut sender = SendDataStream::new(
       Arc::new(Mutex::new(send)),
       HeaderInfo::Fetch {
@@ -865,30 +820,23 @@ mod tests {
     let fetch_obj = make_fetch_object();
     let object = make_object_from_fetch(&fetch_obj);
 
-    let pending_fetches = Arc::new(RwLock::new(pending_fetches));
-
-    // Receiver
-    let receiver = RecvDataStream::new(Arc::new(Mutex::new(recv)), pending_fetches.clone());
+    let receiver = RecvDataStream::new(recv, pending_fetches);
 
     sender.send_object(&object).await.unwrap();
     let received = receiver.next_object().await.1.unwrap();
 
     assert_eq!(object, received);
   }
-  */
 
-  /* TODO: rewrite this test
   #[tokio::test]
   async fn test_send_recv_subgroup_object_success() {
     let (send, recv) = setup_stream_pair().await;
     let (subgroup_header, _) = make_subgroup_header_and_request();
-    let pending_fetches = BTreeMap::new();
 
     let mut sender = SendDataStream::new(
       Arc::new(Mutex::new(send)),
       HeaderInfo::Subgroup {
         header: subgroup_header,
-        /* subscribe_request: subscribe_req.clone(), */
       },
     )
     .await
@@ -897,91 +845,62 @@ mod tests {
     let subgroup_obj = make_subgroup_object();
     let object = make_object_from_subgroup(&subgroup_obj, &subgroup_header);
 
-    let pending_fetches = Arc::new(RwLock::new(pending_fetches));
-
-    let receiver = RecvDataStream::new(Arc::new(Mutex::new(recv)), pending_fetches.clone());
+    let receiver = RecvDataStream::new(recv, Arc::new(RwLock::new(BTreeMap::new())));
 
     sender.send_object(&object).await.unwrap();
-    let received = receiver.next_object().await.1.unwrap();
+    sender.finish().await.unwrap();
 
-    assert_eq!(object, received);
+    let (receiver, received) = receiver.next_object().await;
+    assert_eq!(Some(object), received);
+    assert_eq!(receiver.next_object().await.1, None);
   }
-  */
 
-  /* TODO: rewrite this test
-  #[tokio::test]
+  #[tokio::test(start_paused = true)]
   async fn test_timeout_on_header() {
     let (_send, recv) = setup_stream_pair().await;
-    let mut pending_fetches = Arc::new(RwLock::new(BTreeMap::new()));
-    let mut pending_subscribes = Arc::new(RwLock::new(BTreeMap::new()));
+    let receiver = RecvDataStream::new(recv, Arc::new(RwLock::new(BTreeMap::new())));
+
     // Don't send any header, just wait for timeout
-    let result = RecvDataStream::new(
-      Arc::new(Mutex::new(recv)),
-      pending_fetches,
-      pending_subscribes,
-    );
-    match result {
-      Err(ParseError::Timeout { .. }) => {}
-      _ => panic!("Should timeout"),
-    }
+    assert_eq!(receiver.next_object().await.1, None);
+    assert!(receiver.get_header_info().await.is_none());
   }
-  */
 
-  /*
-  #[tokio::test]
+  #[tokio::test(start_paused = true)]
   async fn test_partial_object_timeout() {
     let (send, recv) = setup_stream_pair().await;
     let (fetch_header, fetch_req) = make_fetch_header_and_request();
-    let mut pending_fetches = BTreeMap::new();
-    pending_fetches.insert(
-      fetch_req.request_id,
-      FetchRequest {
-        request_id: fetch_req.request_id,
-        requested_by: 1,
-        fetch_request: fetch_req.clone(),
-        track_alias: 1,
-      },
-    );
-    let mut pending_subscribes = BTreeMap::new();
-    // Send only the header, not the object
-    let mut _sender = SendDataStream::new(
+    let pending_fetches = pending_fetches_for(&fetch_req);
+
+    // Send only the header and half an object
+    let sender = SendDataStream::new(
       Arc::new(Mutex::new(send)),
       HeaderInfo::Fetch {
-        header: fetch_header.clone(),
+        header: fetch_header,
         fetch_request: fetch_req.clone(),
       },
     )
     .await
     .unwrap();
-    let pending_fetches = Arc::new(RwLock::new(pending_fetches));
-    let pending_subscribes = Arc::new(RwLock::new(pending_subscribes));
-    let mut receiver = RecvDataStream::new(
-      Arc::new(Mutex::new(recv)),
-      pending_fetches,
-      pending_subscribes,
-    );
-    // Don't send any object, just wait for timeout
-    let result = receiver.next_object().await;
-    match result {
-      Err(ParseError::Timeout { .. }) => {}
-      other => panic!("Expected timeout, got {:?}", other),
-    }
+    let bytes = make_fetch_object().serialize().unwrap();
+    sender
+      .send_stream
+      .lock()
+      .await
+      .write_all(&bytes[..bytes.len() / 2])
+      .await
+      .unwrap();
+
+    let receiver = RecvDataStream::new(recv, pending_fetches);
+
+    assert_eq!(receiver.next_object().await.1, None);
+    assert!(receiver.get_header_info().await.is_some());
   }
-  */
+
   #[tokio::test]
   async fn test_partial_object_completion() {
     let (send, recv) = setup_stream_pair().await;
     let (fetch_header, fetch_req) = make_fetch_header_and_request();
-    let mut pending_fetches = BTreeMap::new();
-    pending_fetches.insert(
-      fetch_req.request_id,
-      FetchRequest {
-        original_request_id: fetch_req.request_id,
-        requested_by: 1,
-        fetch_request: fetch_req.clone(),
-        track_alias: 1,
-      },
-    );
+    let pending_fetches = pending_fetches_for(&fetch_req);
 
     let sender = SendDataStream::new(
       Arc::new(Mutex::new(send)),
@@ -996,8 +915,6 @@ mod tests {
     let fetch_obj = make_fetch_object();
     let object = make_object_from_fetch(&fetch_obj);
 
-    let pending_fetches = Arc::new(RwLock::new(pending_fetches));
-
     let receiver = RecvDataStream::new(recv, pending_fetches);
 
     // Serialize object and send in two parts
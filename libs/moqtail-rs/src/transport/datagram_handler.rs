@@ -0,0 +1,283 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tracing::debug;
+
+use crate::model::common::varint::BufVarIntExt;
+use crate::model::data::constant::ObjectStatus;
+use crate::model::data::datagram_object::DatagramObject;
+use crate::model::data::datagram_status::DatagramStatus;
+use crate::model::data::object::Object;
+use crate::model::error::ParseError;
+use crate::transport::moq_transport::{MoqTransport, TransportError};
+
+/// Sends and receives objects as OBJECT_DATAGRAM and OBJECT_DATAGRAM_STATUS
+/// on a connection. Datagrams are unreliable, an object that does not fit
+/// into a single datagram is rejected instead of being split.
+#[derive(Debug, Clone)]
+pub struct DatagramHandler {
+  connection: Arc<dyn MoqTransport>,
+}
+
+impl DatagramHandler {
+  pub fn new(connection: Arc<dyn MoqTransport>) -> Self {
+    Self { connection }
+  }
+
+  /// The largest datagram the connection can currently carry, or `None` if
+  /// the peer does not support datagrams
+  pub fn max_datagram_size(&self) -> Option<usize> {
+    self.connection.max_datagram_size()
+  }
+
+  /// Sends an object with datagram forwarding preference. Objects without a
+  /// payload are sent as OBJECT_DATAGRAM_STATUS.
+  pub fn send_object(&self, object: &Object, track_alias: u64) -> Result<(), ParseError> {
+    let bytes = Self::serialize_object(object.clone(), track_alias)?;
+    self.send_bytes(bytes)
+  }
+
+  pub fn send_datagram_object(&self, datagram: &DatagramObject) -> Result<(), ParseError> {
+    self.send_bytes(datagram.serialize()?)
+  }
+
+  pub fn send_datagram_status(&self, status: &DatagramStatus) -> Result<(), ParseError> {
+    self.send_bytes(status.serialize()?)
+  }
+
+  /// Waits for the next datagram and parses it. Fails when the connection
+  /// is closed or the datagram is malformed.
+  pub async fn next_object(&self) -> Result<Object, ParseError> {
+    let payload = self
+      .connection
+      .receive_datagram()
+      .await
+      .map_err(|e| ParseError::Other {
+        context: "DatagramHandler::next_object",
+        msg: e.to_string(),
+      })?;
+    Self::parse(payload)
+  }
+
+  /// Parses an OBJECT_DATAGRAM or OBJECT_DATAGRAM_STATUS by its type
+  pub fn parse(payload: Bytes) -> Result<Object, ParseError> {
+    let msg_type = payload.clone().get_vi()?;
+    let mut bytes = payload;
+    match msg_type {
+      0x00 | 0x01 => Object::try_from_datagram(DatagramObject::deserialize(&mut bytes)?),
+      0x02 | 0x03 => Object::try_from_datagram_status(DatagramStatus::deserialize(&mut bytes)?),
+      _ => Err(ParseError::InvalidType {
+        context: "DatagramHandler::parse(msg_type)",
+        details: format!("Accepted types: 0x00-0x03; got {msg_type}"),
+      }),
+    }
+  }
+
+  pub fn serialize_object(object: Object, track_alias: u64) -> Result<Bytes, ParseError> {
+    if object.status == ObjectStatus::Normal {
+      object.try_into_datagram(track_alias)?.serialize()
+    } else {
+      object.try_into_datagram_status(track_alias)?.serialize()
+    }
+  }
+
+  fn send_bytes(&self, bytes: Bytes) -> Result<(), ParseError> {
+    let max_size = self.max_datagram_size().ok_or(ParseError::Other {
+      context: "DatagramHandler::send",
+      msg: "peer does not support datagrams".to_string(),
+    })?;
+    if bytes.len() > max_size {
+      return Err(ParseError::LengthExceedsMax {
+        context: "DatagramHandler::send",
+        max: max_size,
+        len: bytes.len(),
+      });
+    }
+    debug!("sending datagram of {} bytes", bytes.len());
+    self
+      .connection
+      .send_datagram(bytes)
+      .map_err(|e: TransportError| ParseError::Other {
+        context: "DatagramHandler::send",
+        msg: e.to_string(),
+      })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::common::location::Location;
+  use crate::model::common::pair::KeyValuePair;
+  use crate::model::data::constant::ObjectForwardingPreference;
+  use std::error::Error;
+  use wtransport::endpoint::IntoConnectOptions;
+  use wtransport::{ClientConfig, Endpoint, Identity};
+
+  fn make_datagram_object() -> Object {
+    Object {
+      track_alias: 7,
+      location: Location::new(3, 1),
+      publisher_priority: 10,
+      forwarding_preference: ObjectForwardingPreference::Datagram,
+      subgroup_id: None,
+      status: ObjectStatus::Normal,
+      extensions: Some(vec![
+        KeyValuePair::try_new_bytes(1, Bytes::from_static(b"wololoo")).unwrap(),
+      ]),
+      payload: Some(Bytes::from_static(b"01239gjawkk92837aldmi")),
+    }
+  }
+
+  fn make_datagram_status() -> Object {
+    Object {
+      track_alias: 7,
+      location: Location::new(3, 2),
+      publisher_priority: 10,
+      forwarding_preference: ObjectForwardingPreference::Datagram,
+      subgroup_id: None,
+      status: ObjectStatus::EndOfGroup,
+      extensions: None,
+      payload: None,
+    }
+  }
+
+  struct TestSetup {
+    client: DatagramHandler,
+    server: DatagramHandler,
+  }
+
+  impl TestSetup {
+    async fn new() -> Result<Self, Box<dyn Error>> {
+      let server_identity = Identity::self_signed(std::iter::once("localhost"))
+        .map_err(|e| format!("Failed to create server identity: {e}"))?;
+      let server_cert_hash = server_identity.certificate_chain().as_slice()[0].hash();
+      let server_config = wtransport::ServerConfig::builder()
+        .with_bind_address(
+          "127.0.0.1:0"
+            .parse()
+            .map_err(|e| format!("Failed to parse bind address: {e}"))?,
+        )
+        .with_identity(server_identity)
+        .build();
+      let server_endpoint = Endpoint::server(server_config)
+        .map_err(|e| format!("Failed to create server endpoint: {e}"))?;
+      let server_addr = server_endpoint
+        .local_addr()
+        .map_err(|e| format!("Failed to get server local address: {e}"))?;
+
+      let (tx, rx) = tokio::sync::oneshot::channel();
+
+      tokio::spawn(async move {
+        let result = async {
+          let incoming = server_endpoint.accept().await;
+          let session_request = incoming
+            .await
+            .map_err(|e| format!("Failed to await session request: {e}"))
+            .unwrap();
+          let server = session_request.accept().await.unwrap();
+          Ok::<_, Box<dyn Error + Send>>(server)
+        }
+        .await;
+
+        if tx.send(result).is_err() {
+          eprintln!("Failed to send server connection result back through the channel");
+        }
+      });
+
+      let client_config = ClientConfig::builder()
+        .with_bind_default()
+        .with_server_certificate_hashes(vec![server_cert_hash])
+        .build();
+
+      let client_endpoint = Endpoint::client(client_config)
+        .map_err(|e| format!("Failed to create client endpoint: {e}"))?;
+
+      let client = client_endpoint
+        .connect(
+          format!("https://{}:{}", server_addr.ip(), server_addr.port())
+            .as_str()
+            .into_options(),
+        )
+        .await
+        .map_err(|e| format!("Client connection failed: {e}"))?;
+
+      let server = rx
+        .await
+        .map_err(|_| "Server task failed to send connection back")?
+        .map_err(|e| format!("Server connection error: {e}"))?;
+
+      Ok(Self {
+        client: DatagramHandler::new(Arc::new(client)),
+        server: DatagramHandler::new(Arc::new(server)),
+      })
+    }
+  }
+
+  #[test]
+  fn test_parse_roundtrip() {
+    let object = make_datagram_object();
+    let bytes = DatagramHandler::serialize_object(object.clone(), 7).unwrap();
+    assert_eq!(DatagramHandler::parse(bytes).unwrap(), object);
+
+    let status = make_datagram_status();
+    let bytes = DatagramHandler::serialize_object(status.clone(), 7).unwrap();
+    assert_eq!(DatagramHandler::parse(bytes).unwrap(), status);
+  }
+
+  #[test]
+  fn test_parse_invalid_type() {
+    let result = DatagramHandler::parse(Bytes::from_static(&[0x04, 0x01]));
+    assert!(matches!(result, Err(ParseError::InvalidType { .. })));
+  }
+
+  #[test]
+  fn test_serialize_rejects_subgroup_object() {
+    let mut object = make_datagram_object();
+    object.forwarding_preference = ObjectForwardingPreference::Subgroup;
+    object.subgroup_id = Some(0);
+    assert!(DatagramHandler::serialize_object(object, 7).is_err());
+  }
+
+  #[tokio::test]
+  async fn test_send_recv_object() {
+    let setup = TestSetup::new()
+      .await
+      .expect("Failed to setup test transport");
+    let object = make_datagram_object();
+    let status = make_datagram_status();
+
+    setup.client.send_object(&object, 7).unwrap();
+    assert_eq!(setup.server.next_object().await.unwrap(), object);
+
+    setup.client.send_object(&status, 7).unwrap();
+    assert_eq!(setup.server.next_object().await.unwrap(), status);
+  }
+
+  #[tokio::test]
+  async fn test_send_too_large() {
+    let setup = TestSetup::new()
+      .await
+      .expect("Failed to setup test transport");
+    let max_size = setup.client.max_datagram_size().unwrap();
+    let mut object = make_datagram_object();
+    object.payload = Some(Bytes::from(vec![0u8; max_size + 1]));
+
+    let result = setup.client.send_object(&object, 7);
+    assert!(matches!(result, Err(ParseError::LengthExceedsMax { .. })));
+  }
+}
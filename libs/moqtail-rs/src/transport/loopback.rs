@@ -0,0 +1,674 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An in-process transport for tests. Both ends of a [`LoopbackTransport`]
+//! live in the same process, so relay and client logic can be run end to end
+//! without sockets or TLS. Delay, reordering and loss are driven by a seeded
+//! generator and the tokio clock, so a test with a paused clock sees the same
+//! delivery order on every run.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::sync::{Notify, mpsc, watch};
+use tokio::time::{Instant, sleep_until};
+
+use super::moq_transport::{
+  BoxRecvStream, BoxSendStream, MoqConnector, MoqRecvStream, MoqSendStream, MoqTransport,
+  TransportError,
+};
+
+static NEXT_STABLE_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// Network conditions between the two ends of a loopback connection
+#[derive(Debug, Clone)]
+pub struct LoopbackConfig {
+  /// One-way delay of stream data, new streams and datagrams
+  pub delay: Duration,
+  /// Probability that a new stream or a datagram is held back and overtaken
+  /// by the ones sent after it
+  pub reorder_probability: f64,
+  /// How long a reordered stream or datagram is held back on top of `delay`
+  pub reorder_delay: Duration,
+  /// Probability that a datagram is dropped. Streams are reliable as in QUIC.
+  pub loss_probability: f64,
+  /// Seed of the reordering and loss decisions
+  pub seed: u64,
+  /// Largest datagram payload, `None` if datagrams are not supported
+  pub max_datagram_size: Option<usize>,
+}
+
+impl Default for LoopbackConfig {
+  fn default() -> Self {
+    LoopbackConfig {
+      delay: Duration::ZERO,
+      reorder_probability: 0.0,
+      reorder_delay: Duration::from_millis(10),
+      loss_probability: 0.0,
+      seed: 0,
+      max_datagram_size: Some(1200),
+    }
+  }
+}
+
+/// splitmix64, enough to make the loss and reordering decisions repeatable
+#[derive(Debug)]
+struct Rng(u64);
+
+impl Rng {
+  fn next_f64(&mut self) -> f64 {
+    self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = self.0;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+  }
+
+  fn chance(&mut self, probability: f64) -> bool {
+    probability > 0.0 && self.next_f64() < probability
+  }
+}
+
+/// Items waiting for delivery, ordered by delivery time and then by the
+/// order they were sent in
+#[derive(Debug)]
+struct Inbox<T> {
+  queue: Mutex<BTreeMap<(Instant, u64), T>>,
+  next_seq: AtomicU64,
+  notify: Notify,
+}
+
+impl<T> Inbox<T> {
+  fn new() -> Self {
+    Self {
+      queue: Mutex::new(BTreeMap::new()),
+      next_seq: AtomicU64::new(0),
+      notify: Notify::new(),
+    }
+  }
+
+  fn push(&self, deliver_at: Instant, item: T) {
+    let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+    self.queue.lock().unwrap().insert((deliver_at, seq), item);
+    self.notify.notify_waiters();
+  }
+
+  async fn pop(&self, closed: &watch::Sender<bool>) -> Result<T, TransportError> {
+    // the sender outlives this call, so `changed` never fails
+    let mut closed = closed.subscribe();
+    loop {
+      let notified = self.notify.notified();
+      tokio::pin!(notified);
+      notified.as_mut().enable();
+
+      if *closed.borrow_and_update() {
+        return Err(TransportError::NotConnected);
+      }
+
+      let next_delivery = {
+        let mut queue = self.queue.lock().unwrap();
+        match queue.first_key_value() {
+          Some((&(deliver_at, _), _)) if deliver_at <= Instant::now() => {
+            return Ok(queue.pop_first().unwrap().1);
+          }
+          Some((&(deliver_at, _), _)) => Some(deliver_at),
+          None => None,
+        }
+      };
+
+      let wake = async {
+        match next_delivery {
+          Some(deliver_at) => sleep_until(deliver_at).await,
+          None => std::future::pending().await,
+        }
+      };
+
+      tokio::select! {
+        _ = &mut notified => {}
+        _ = wake => {}
+        _ = closed.changed() => {}
+      }
+    }
+  }
+}
+
+#[derive(Debug)]
+enum Frame {
+  Data(Bytes),
+  Fin,
+  Reset(u32),
+}
+
+/// One stream, shared by its sending and receiving half. Like quinn streams,
+/// it keeps the connection alive after the transports are dropped.
+#[derive(Debug)]
+struct Pipe {
+  frames: Inbox<Frame>,
+  stopped: Mutex<Option<u32>>,
+  delay: Duration,
+  closed: Arc<watch::Sender<bool>>,
+}
+
+impl Pipe {
+  fn open(delay: Duration, closed: Arc<watch::Sender<bool>>) -> (BoxSendStream, BoxRecvStream) {
+    let pipe = Arc::new(Pipe {
+      frames: Inbox::new(),
+      stopped: Mutex::new(None),
+      delay,
+      closed,
+    });
+    let send = LoopbackSendStream {
+      pipe: pipe.clone(),
+      finished: false,
+    };
+    let recv = LoopbackRecvStream {
+      pipe,
+      buffered: Bytes::new(),
+      finished: false,
+    };
+    (Box::new(send), Box::new(recv))
+  }
+
+  fn send(&self, frame: Frame) {
+    self.frames.push(Instant::now() + self.delay, frame);
+  }
+}
+
+#[derive(Debug)]
+pub struct LoopbackSendStream {
+  pipe: Arc<Pipe>,
+  finished: bool,
+}
+
+impl LoopbackSendStream {
+  fn check_writable(&self) -> Result<(), TransportError> {
+    if *self.pipe.closed.borrow() {
+      return Err(TransportError::NotConnected);
+    }
+    if self.finished {
+      return Err(TransportError::StreamClosed);
+    }
+    if let Some(code) = *self.pipe.stopped.lock().unwrap() {
+      return Err(TransportError::Stopped(code.into()));
+    }
+    Ok(())
+  }
+}
+
+#[async_trait]
+impl MoqSendStream for LoopbackSendStream {
+  async fn write_all(&mut self, buf: &[u8]) -> Result<(), TransportError> {
+    self.check_writable()?;
+    if !buf.is_empty() {
+      self.pipe.send(Frame::Data(Bytes::copy_from_slice(buf)));
+    }
+    Ok(())
+  }
+
+  async fn flush(&mut self) -> Result<(), TransportError> {
+    self.check_writable()
+  }
+
+  async fn finish(&mut self) -> Result<(), TransportError> {
+    self.check_writable()?;
+    self.finished = true;
+    self.pipe.send(Frame::Fin);
+    Ok(())
+  }
+
+  fn reset(&mut self, error_code: u32) -> Result<(), TransportError> {
+    if self.finished {
+      return Err(TransportError::StreamClosed);
+    }
+    self.finished = true;
+    self.pipe.send(Frame::Reset(error_code));
+    Ok(())
+  }
+
+  fn set_priority(&self, _priority: i32) {
+    // every stream of the loopback is sent as soon as it is written
+  }
+}
+
+impl Drop for LoopbackSendStream {
+  fn drop(&mut self) {
+    // a dropped stream is finished, as with quinn
+    if !self.finished {
+      self.pipe.send(Frame::Fin);
+    }
+  }
+}
+
+#[derive(Debug)]
+pub struct LoopbackRecvStream {
+  pipe: Arc<Pipe>,
+  buffered: Bytes,
+  finished: bool,
+}
+
+#[async_trait]
+impl MoqRecvStream for LoopbackRecvStream {
+  async fn read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, TransportError> {
+    if self.buffered.is_empty() {
+      if self.finished {
+        return Ok(None);
+      }
+      match self.pipe.frames.pop(&self.pipe.closed).await? {
+        Frame::Data(data) => self.buffered = data,
+        Frame::Fin => {
+          self.finished = true;
+          return Ok(None);
+        }
+        Frame::Reset(code) => {
+          self.finished = true;
+          return Err(TransportError::Reset(code.into()));
+        }
+      }
+    }
+
+    let n = buf.len().min(self.buffered.len());
+    buf[..n].copy_from_slice(&self.buffered.split_to(n));
+    Ok(Some(n))
+  }
+
+  fn stop(&mut self, error_code: u32) {
+    *self.pipe.stopped.lock().unwrap() = Some(error_code);
+  }
+}
+
+/// What one end sends to the other
+#[derive(Debug)]
+struct Link {
+  config: LoopbackConfig,
+  rng: Mutex<Rng>,
+  bi_streams: Inbox<(BoxSendStream, BoxRecvStream)>,
+  uni_streams: Inbox<BoxRecvStream>,
+  datagrams: Inbox<Bytes>,
+}
+
+impl Link {
+  fn new(config: LoopbackConfig, seed: u64) -> Self {
+    Self {
+      config,
+      rng: Mutex::new(Rng(seed)),
+      bi_streams: Inbox::new(),
+      uni_streams: Inbox::new(),
+      datagrams: Inbox::new(),
+    }
+  }
+
+  /// Delivery time of a new stream or datagram
+  fn deliver_at(&self) -> Instant {
+    let mut deliver_at = Instant::now() + self.config.delay;
+    if self
+      .rng
+      .lock()
+      .unwrap()
+      .chance(self.config.reorder_probability)
+    {
+      deliver_at += self.config.reorder_delay;
+    }
+    deliver_at
+  }
+
+  fn is_lost(&self) -> bool {
+    self
+      .rng
+      .lock()
+      .unwrap()
+      .chance(self.config.loss_probability)
+  }
+}
+
+/// One end of an in-process connection. Closing either end closes both.
+///
+/// # Example
+/// ```
+/// # use moqtail::transport::loopback::{LoopbackConfig, LoopbackTransport};
+/// # use moqtail::transport::moq_transport::{MoqTransport, TransportError};
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), TransportError> {
+/// let (client, server) = LoopbackTransport::pair(LoopbackConfig::default());
+/// let (mut send, _recv) = client.open_bi().await?;
+/// send.write_all(b"hello").await?;
+/// let (_server_send, mut server_recv) = server.accept_bi().await?;
+/// let mut buf = [0u8; 5];
+/// assert_eq!(server_recv.read(&mut buf).await?, Some(5));
+/// assert_eq!(&buf, b"hello");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LoopbackTransport {
+  stable_id: usize,
+  outgoing: Arc<Link>,
+  incoming: Arc<Link>,
+  closed: Arc<watch::Sender<bool>>,
+}
+
+impl LoopbackTransport {
+  /// Creates both ends of a connection
+  pub fn pair(config: LoopbackConfig) -> (LoopbackTransport, LoopbackTransport) {
+    let closed = Arc::new(watch::Sender::new(false));
+    let seed = config.seed;
+    let a_to_b = Arc::new(Link::new(config.clone(), seed));
+    let b_to_a = Arc::new(Link::new(config, !seed));
+
+    let a = LoopbackTransport {
+      stable_id: NEXT_STABLE_ID.fetch_add(1, Ordering::Relaxed),
+      outgoing: a_to_b.clone(),
+      incoming: b_to_a.clone(),
+      closed: closed.clone(),
+    };
+    let b = LoopbackTransport {
+      stable_id: NEXT_STABLE_ID.fetch_add(1, Ordering::Relaxed),
+      outgoing: b_to_a,
+      incoming: a_to_b,
+      closed,
+    };
+    (a, b)
+  }
+
+  fn check_connected(&self) -> Result<(), TransportError> {
+    if *self.closed.borrow() {
+      return Err(TransportError::NotConnected);
+    }
+    Ok(())
+  }
+}
+
+#[async_trait]
+impl MoqTransport for LoopbackTransport {
+  fn stable_id(&self) -> usize {
+    self.stable_id
+  }
+
+  async fn open_bi(&self) -> Result<(BoxSendStream, BoxRecvStream), TransportError> {
+    self.check_connected()?;
+    let delay = self.outgoing.config.delay;
+    let (send, peer_recv) = Pipe::open(delay, self.closed.clone());
+    let (peer_send, recv) = Pipe::open(delay, self.closed.clone());
+    self
+      .outgoing
+      .bi_streams
+      .push(self.outgoing.deliver_at(), (peer_send, peer_recv));
+    Ok((send, recv))
+  }
+
+  async fn accept_bi(&self) -> Result<(BoxSendStream, BoxRecvStream), TransportError> {
+    self.incoming.bi_streams.pop(&self.closed).await
+  }
+
+  async fn open_uni(&self) -> Result<BoxSendStream, TransportError> {
+    self.check_connected()?;
+    let (send, peer_recv) = Pipe::open(self.outgoing.config.delay, self.closed.clone());
+    self
+      .outgoing
+      .uni_streams
+      .push(self.outgoing.deliver_at(), peer_recv);
+    Ok(send)
+  }
+
+  async fn accept_uni(&self) -> Result<BoxRecvStream, TransportError> {
+    self.incoming.uni_streams.pop(&self.closed).await
+  }
+
+  fn send_datagram(&self, payload: Bytes) -> Result<(), TransportError> {
+    self.check_connected()?;
+    let Some(max_datagram_size) = self.outgoing.config.max_datagram_size else {
+      return Err(TransportError::DatagramsUnsupported);
+    };
+    if payload.len() > max_datagram_size {
+      return Err(TransportError::DatagramTooLarge);
+    }
+    if !self.outgoing.is_lost() {
+      self
+        .outgoing
+        .datagrams
+        .push(self.outgoing.deliver_at(), payload);
+    }
+    Ok(())
+  }
+
+  async fn receive_datagram(&self) -> Result<Bytes, TransportError> {
+    self.incoming.datagrams.pop(&self.closed).await
+  }
+
+  fn max_datagram_size(&self) -> Option<usize> {
+    self.outgoing.config.max_datagram_size
+  }
+
+  fn close(&self, _error_code: u32, _reason: &[u8]) {
+    self.closed.send_replace(true);
+  }
+
+  async fn closed(&self) {
+    let mut closed = self.closed.subscribe();
+    let _ = closed.wait_for(|closed| *closed).await;
+  }
+}
+
+/// Hands the server end of every connection it opens to a
+/// [`LoopbackListener`]. The URL is ignored, so a session moved by GOAWAY
+/// reconnects to the same listener.
+#[derive(Debug, Clone)]
+pub struct LoopbackConnector {
+  config: LoopbackConfig,
+  incoming: mpsc::UnboundedSender<LoopbackTransport>,
+}
+
+/// Accepts the connections opened by a [`LoopbackConnector`]
+#[derive(Debug)]
+pub struct LoopbackListener {
+  incoming: mpsc::UnboundedReceiver<LoopbackTransport>,
+}
+
+impl LoopbackListener {
+  /// Returns `None` once every connector is dropped
+  pub async fn accept(&mut self) -> Option<LoopbackTransport> {
+    self.incoming.recv().await
+  }
+}
+
+/// Creates a connector and the listener that accepts its connections
+pub fn listener(config: LoopbackConfig) -> (LoopbackConnector, LoopbackListener) {
+  let (incoming_tx, incoming_rx) = mpsc::unbounded_channel();
+  (
+    LoopbackConnector {
+      config,
+      incoming: incoming_tx,
+    },
+    LoopbackListener {
+      incoming: incoming_rx,
+    },
+  )
+}
+
+#[async_trait]
+impl MoqConnector for LoopbackConnector {
+  async fn connect(&self, _url: &str) -> Result<Arc<dyn MoqTransport>, TransportError> {
+    let (client, server) = LoopbackTransport::pair(self.config.clone());
+    self
+      .incoming
+      .send(server)
+      .map_err(|_| TransportError::NotConnected)?;
+    Ok(Arc::new(client))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  async fn read_to_end(recv: &mut BoxRecvStream) -> Result<Vec<u8>, TransportError> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4];
+    while let Some(n) = recv.read(&mut buf).await? {
+      data.extend_from_slice(&buf[..n]);
+    }
+    Ok(data)
+  }
+
+  #[tokio::test]
+  async fn test_bi_stream_roundtrip() {
+    let (client, server) = LoopbackTransport::pair(LoopbackConfig::default());
+
+    let (mut send, mut recv) = client.open_bi().await.unwrap();
+    send.write_all(b"hello").await.unwrap();
+    send.write_all(b" world").await.unwrap();
+    send.finish().await.unwrap();
+
+    let (mut server_send, mut server_recv) = server.accept_bi().await.unwrap();
+    assert_eq!(read_to_end(&mut server_recv).await.unwrap(), b"hello world");
+
+    server_send.write_all(b"back").await.unwrap();
+    drop(server_send);
+    assert_eq!(read_to_end(&mut recv).await.unwrap(), b"back");
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn test_delay() {
+    let config = LoopbackConfig {
+      delay: Duration::from_millis(50),
+      ..Default::default()
+    };
+    let (client, server) = LoopbackTransport::pair(config);
+
+    let start = Instant::now();
+    client.send_datagram(Bytes::from_static(b"ping")).unwrap();
+    assert_eq!(server.receive_datagram().await.unwrap(), "ping");
+    assert_eq!(start.elapsed(), Duration::from_millis(50));
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn test_reorder_is_deterministic() {
+    let config = LoopbackConfig {
+      reorder_probability: 0.5,
+      seed: 7,
+      ..Default::default()
+    };
+
+    let mut orders = Vec::new();
+    for _ in 0..2 {
+      let (client, server) = LoopbackTransport::pair(config.clone());
+      for i in 0..16u8 {
+        client.send_datagram(Bytes::from(vec![i])).unwrap();
+      }
+      let mut order = Vec::new();
+      for _ in 0..16 {
+        order.push(server.receive_datagram().await.unwrap()[0]);
+      }
+      orders.push(order);
+    }
+
+    assert_eq!(orders[0], orders[1]);
+    assert_ne!(orders[0], (0..16).collect::<Vec<u8>>());
+    let mut sorted = orders[0].clone();
+    sorted.sort();
+    assert_eq!(sorted, (0..16).collect::<Vec<u8>>());
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn test_loss_drops_datagrams_only() {
+    let config = LoopbackConfig {
+      loss_probability: 1.0,
+      ..Default::default()
+    };
+    let (client, server) = LoopbackTransport::pair(config);
+
+    client.send_datagram(Bytes::from_static(b"lost")).unwrap();
+    let mut send = client.open_uni().await.unwrap();
+    send.write_all(b"kept").await.unwrap();
+    send.finish().await.unwrap();
+
+    let mut recv = server.accept_uni().await.unwrap();
+    assert_eq!(read_to_end(&mut recv).await.unwrap(), b"kept");
+    let received = tokio::time::timeout(Duration::from_secs(1), server.receive_datagram()).await;
+    assert!(received.is_err());
+  }
+
+  #[tokio::test]
+  async fn test_datagram_too_large() {
+    let config = LoopbackConfig {
+      max_datagram_size: Some(4),
+      ..Default::default()
+    };
+    let (client, _server) = LoopbackTransport::pair(config);
+    assert!(matches!(
+      client.send_datagram(Bytes::from_static(b"too large")),
+      Err(TransportError::DatagramTooLarge)
+    ));
+  }
+
+  #[tokio::test]
+  async fn test_stop_and_reset() {
+    let (client, server) = LoopbackTransport::pair(LoopbackConfig::default());
+
+    let mut send = client.open_uni().await.unwrap();
+    let mut recv = server.accept_uni().await.unwrap();
+    recv.stop(3);
+    assert!(matches!(
+      send.write_all(b"data").await,
+      Err(TransportError::Stopped(3))
+    ));
+
+    let mut send = client.open_uni().await.unwrap();
+    let mut recv = server.accept_uni().await.unwrap();
+    send.reset(5).unwrap();
+    assert!(matches!(
+      read_to_end(&mut recv).await,
+      Err(TransportError::Reset(5))
+    ));
+  }
+
+  #[tokio::test]
+  async fn test_close_wakes_both_ends() {
+    let (client, server) = LoopbackTransport::pair(LoopbackConfig::default());
+
+    let accept = tokio::spawn(async move {
+      let result = server.accept_uni().await;
+      server.closed().await;
+      result
+    });
+    client.close(0, b"bye");
+
+    assert!(matches!(
+      accept.await.unwrap(),
+      Err(TransportError::NotConnected)
+    ));
+    assert!(matches!(
+      client.open_uni().await,
+      Err(TransportError::NotConnected)
+    ));
+  }
+
+  #[tokio::test]
+  async fn test_connector_and_listener() {
+    let (connector, mut listener) = listener(LoopbackConfig::default());
+
+    let client = connector.connect("moqt://loopback").await.unwrap();
+    let server = listener.accept().await.unwrap();
+
+    let mut send = server.open_uni().await.unwrap();
+    send.write_all(b"hi").await.unwrap();
+    send.finish().await.unwrap();
+    let mut recv = client.accept_uni().await.unwrap();
+    assert_eq!(read_to_end(&mut recv).await.unwrap(), b"hi");
+  }
+}
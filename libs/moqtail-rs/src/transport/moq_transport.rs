@@ -0,0 +1,105 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use thiserror::Error;
+
+/// Errors from the transport underneath a MOQT session, independent of
+/// whether it runs over WebTransport or native QUIC
+#[derive(Debug, Clone, Error)]
+pub enum TransportError {
+  #[error("not connected")]
+  NotConnected,
+  #[error("stream closed")]
+  StreamClosed,
+  #[error("stream stopped by peer (code: {0})")]
+  Stopped(u64),
+  #[error("stream reset by peer (code: {0})")]
+  Reset(u64),
+  #[error("datagrams not supported by peer")]
+  DatagramsUnsupported,
+  #[error("datagram too large")]
+  DatagramTooLarge,
+  #[error("{0}")]
+  Other(String),
+}
+
+pub type BoxSendStream = Box<dyn MoqSendStream>;
+pub type BoxRecvStream = Box<dyn MoqRecvStream>;
+
+/// The sending half of a MOQT stream
+#[async_trait]
+pub trait MoqSendStream: Debug + Send + Sync {
+  async fn write_all(&mut self, buf: &[u8]) -> Result<(), TransportError>;
+
+  async fn flush(&mut self) -> Result<(), TransportError>;
+
+  /// Finishes the stream after the buffered data is sent
+  async fn finish(&mut self) -> Result<(), TransportError>;
+
+  /// Abandons the stream, dropping the data that is not sent yet
+  fn reset(&mut self, error_code: u32) -> Result<(), TransportError>;
+
+  /// Streams with a higher priority are sent first
+  fn set_priority(&self, priority: i32);
+}
+
+/// The receiving half of a MOQT stream
+#[async_trait]
+pub trait MoqRecvStream: Debug + Send + Sync {
+  /// Reads into `buf`, returns `None` once the peer finished the stream
+  async fn read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, TransportError>;
+
+  /// Asks the peer to stop sending on the stream
+  fn stop(&mut self, error_code: u32);
+}
+
+/// A connection that carries a MOQT session
+#[async_trait]
+pub trait MoqTransport: Debug + Send + Sync {
+  /// Identifies the connection while it is alive
+  fn stable_id(&self) -> usize;
+
+  async fn open_bi(&self) -> Result<(BoxSendStream, BoxRecvStream), TransportError>;
+
+  async fn accept_bi(&self) -> Result<(BoxSendStream, BoxRecvStream), TransportError>;
+
+  async fn open_uni(&self) -> Result<BoxSendStream, TransportError>;
+
+  async fn accept_uni(&self) -> Result<BoxRecvStream, TransportError>;
+
+  fn send_datagram(&self, payload: Bytes) -> Result<(), TransportError>;
+
+  async fn receive_datagram(&self) -> Result<Bytes, TransportError>;
+
+  /// The largest datagram the connection can currently carry, or `None` if
+  /// the peer does not support datagrams
+  fn max_datagram_size(&self) -> Option<usize>;
+
+  fn close(&self, error_code: u32, reason: &[u8]);
+
+  /// Resolves once the connection is closed by either side
+  async fn closed(&self);
+}
+
+/// Opens client connections for a session, and again after GOAWAY moves
+/// the session to a new URI
+#[async_trait]
+pub trait MoqConnector: Send + Sync {
+  async fn connect(&self, url: &str) -> Result<Arc<dyn MoqTransport>, TransportError>;
+}
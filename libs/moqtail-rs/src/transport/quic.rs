@@ -0,0 +1,328 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! [`MoqTransport`] over native QUIC with the `moq-00` ALPN, for
+//! relay-to-relay links and command line tools that have no browser in the
+//! way.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use wtransport::Identity;
+use wtransport::quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+use wtransport::quinn::{
+  self, ClientConfig, Connection, ConnectionError, ReadError, RecvStream, SendDatagramError,
+  SendStream, ServerConfig, TransportConfig, VarInt, WriteError,
+};
+use wtransport::tls::Sha256Digest;
+use wtransport::tls::client::ServerHashVerification;
+
+use super::moq_transport::{
+  BoxRecvStream, BoxSendStream, MoqConnector, MoqRecvStream, MoqSendStream, MoqTransport,
+  TransportError,
+};
+
+/// ALPN of MOQT over native QUIC
+pub const MOQ_ALPN: &[u8] = b"moq-00";
+
+/// URL scheme of MOQT over native QUIC, e.g. `moqt://relay.example:4443`
+pub const MOQT_SCHEME: &str = "moqt";
+
+impl From<ConnectionError> for TransportError {
+  fn from(e: ConnectionError) -> Self {
+    match e {
+      ConnectionError::LocallyClosed
+      | ConnectionError::ConnectionClosed(_)
+      | ConnectionError::ApplicationClosed(_)
+      | ConnectionError::Reset
+      | ConnectionError::TimedOut => TransportError::NotConnected,
+      e => TransportError::Other(e.to_string()),
+    }
+  }
+}
+
+impl From<WriteError> for TransportError {
+  fn from(e: WriteError) -> Self {
+    match e {
+      WriteError::Stopped(code) => TransportError::Stopped(code.into_inner()),
+      WriteError::ConnectionLost(_) => TransportError::NotConnected,
+      WriteError::ClosedStream => TransportError::StreamClosed,
+      e => TransportError::Other(e.to_string()),
+    }
+  }
+}
+
+impl From<ReadError> for TransportError {
+  fn from(e: ReadError) -> Self {
+    match e {
+      ReadError::Reset(code) => TransportError::Reset(code.into_inner()),
+      ReadError::ConnectionLost(_) => TransportError::NotConnected,
+      ReadError::ClosedStream => TransportError::StreamClosed,
+      e => TransportError::Other(e.to_string()),
+    }
+  }
+}
+
+impl From<SendDatagramError> for TransportError {
+  fn from(e: SendDatagramError) -> Self {
+    match e {
+      SendDatagramError::UnsupportedByPeer | SendDatagramError::Disabled => {
+        TransportError::DatagramsUnsupported
+      }
+      SendDatagramError::TooLarge => TransportError::DatagramTooLarge,
+      SendDatagramError::ConnectionLost(_) => TransportError::NotConnected,
+    }
+  }
+}
+
+#[async_trait]
+impl MoqSendStream for SendStream {
+  async fn write_all(&mut self, buf: &[u8]) -> Result<(), TransportError> {
+    Ok(SendStream::write_all(self, buf).await?)
+  }
+
+  async fn flush(&mut self) -> Result<(), TransportError> {
+    // quinn hands written data to the connection right away
+    Ok(())
+  }
+
+  async fn finish(&mut self) -> Result<(), TransportError> {
+    SendStream::finish(self).map_err(|_| TransportError::StreamClosed)
+  }
+
+  fn reset(&mut self, error_code: u32) -> Result<(), TransportError> {
+    SendStream::reset(self, VarInt::from_u32(error_code)).map_err(|_| TransportError::StreamClosed)
+  }
+
+  fn set_priority(&self, priority: i32) {
+    let _ = SendStream::set_priority(self, priority);
+  }
+}
+
+#[async_trait]
+impl MoqRecvStream for RecvStream {
+  async fn read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, TransportError> {
+    Ok(RecvStream::read(self, buf).await?)
+  }
+
+  fn stop(&mut self, error_code: u32) {
+    let _ = RecvStream::stop(self, VarInt::from_u32(error_code));
+  }
+}
+
+#[async_trait]
+impl MoqTransport for Connection {
+  fn stable_id(&self) -> usize {
+    Connection::stable_id(self)
+  }
+
+  async fn open_bi(&self) -> Result<(BoxSendStream, BoxRecvStream), TransportError> {
+    let (send, recv) = Connection::open_bi(self).await?;
+    Ok((Box::new(send), Box::new(recv)))
+  }
+
+  async fn accept_bi(&self) -> Result<(BoxSendStream, BoxRecvStream), TransportError> {
+    let (send, recv) = Connection::accept_bi(self).await?;
+    Ok((Box::new(send), Box::new(recv)))
+  }
+
+  async fn open_uni(&self) -> Result<BoxSendStream, TransportError> {
+    let send = Connection::open_uni(self).await?;
+    Ok(Box::new(send))
+  }
+
+  async fn accept_uni(&self) -> Result<BoxRecvStream, TransportError> {
+    let recv = Connection::accept_uni(self).await?;
+    Ok(Box::new(recv))
+  }
+
+  fn send_datagram(&self, payload: Bytes) -> Result<(), TransportError> {
+    Ok(Connection::send_datagram(self, payload)?)
+  }
+
+  async fn receive_datagram(&self) -> Result<Bytes, TransportError> {
+    Ok(Connection::read_datagram(self).await?)
+  }
+
+  fn max_datagram_size(&self) -> Option<usize> {
+    Connection::max_datagram_size(self)
+  }
+
+  fn close(&self, error_code: u32, reason: &[u8]) {
+    Connection::close(self, VarInt::from_u32(error_code), reason);
+  }
+
+  async fn closed(&self) {
+    Connection::closed(self).await;
+  }
+}
+
+fn transport_config(
+  keep_alive_interval: Duration,
+  max_idle_timeout: Duration,
+) -> Result<TransportConfig, TransportError> {
+  let mut config = TransportConfig::default();
+  config.keep_alive_interval(Some(keep_alive_interval));
+  config.max_idle_timeout(Some(
+    max_idle_timeout
+      .try_into()
+      .map_err(|_| TransportError::Other("idle timeout out of range".to_string()))?,
+  ));
+  Ok(config)
+}
+
+/// Builds a QUIC server configuration that accepts MOQT on the `moq-00` ALPN
+pub fn build_server_config(
+  identity: Identity,
+  keep_alive_interval: Duration,
+  max_idle_timeout: Duration,
+) -> Result<ServerConfig, TransportError> {
+  let mut tls_config = wtransport::tls::server::build_default_tls_config(identity);
+  tls_config.alpn_protocols = vec![MOQ_ALPN.to_vec()];
+  let crypto =
+    QuicServerConfig::try_from(tls_config).map_err(|e| TransportError::Other(e.to_string()))?;
+
+  let mut config = ServerConfig::with_crypto(Arc::new(crypto));
+  config.transport_config(Arc::new(transport_config(
+    keep_alive_interval,
+    max_idle_timeout,
+  )?));
+  Ok(config)
+}
+
+/// Builds a QUIC client configuration for the `moq-00` ALPN. Without
+/// `certificate_hashes` the server is verified against the platform's root
+/// certificates, otherwise only the listed self-signed certificates are
+/// accepted.
+pub fn build_client_config(
+  certificate_hashes: Option<Vec<Sha256Digest>>,
+) -> Result<ClientConfig, TransportError> {
+  let root_store = Arc::new(wtransport::tls::build_native_cert_store());
+  let verifier = certificate_hashes.map(|hashes| {
+    Arc::new(ServerHashVerification::new(hashes))
+      as Arc<dyn wtransport::tls::rustls::client::danger::ServerCertVerifier>
+  });
+  let mut tls_config = wtransport::tls::client::build_default_tls_config(root_store, verifier);
+  tls_config.alpn_protocols = vec![MOQ_ALPN.to_vec()];
+  let crypto =
+    QuicClientConfig::try_from(tls_config).map_err(|e| TransportError::Other(e.to_string()))?;
+  Ok(ClientConfig::new(Arc::new(crypto)))
+}
+
+/// Connects to `moqt://host:port` URLs over native QUIC
+pub struct QuicConnector {
+  endpoint: quinn::Endpoint,
+}
+
+impl QuicConnector {
+  pub fn new(config: ClientConfig) -> Result<Self, TransportError> {
+    let bind_address: SocketAddr = "[::]:0".parse().unwrap();
+    let mut endpoint =
+      quinn::Endpoint::client(bind_address).map_err(|e| TransportError::Other(e.to_string()))?;
+    endpoint.set_default_client_config(config);
+    Ok(Self { endpoint })
+  }
+
+  /// Splits a `moqt://host:port/path` URL into the host and the port
+  fn parse_url(url: &str) -> Result<(&str, u16), TransportError> {
+    let invalid = || TransportError::Other(format!("invalid {MOQT_SCHEME} url: {url}"));
+    let authority = url
+      .strip_prefix(MOQT_SCHEME)
+      .and_then(|rest| rest.strip_prefix("://"))
+      .ok_or_else(invalid)?;
+    let authority = authority.split('/').next().unwrap_or_default();
+    let (host, port) = authority.rsplit_once(':').ok_or_else(invalid)?;
+    let port = port.parse().map_err(|_| invalid())?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    Ok((host, port))
+  }
+}
+
+#[async_trait]
+impl MoqConnector for QuicConnector {
+  async fn connect(&self, url: &str) -> Result<Arc<dyn MoqTransport>, TransportError> {
+    let (host, port) = Self::parse_url(url)?;
+    let address = tokio::net::lookup_host((host, port))
+      .await
+      .map_err(|e| TransportError::Other(e.to_string()))?
+      .next()
+      .ok_or_else(|| TransportError::Other(format!("cannot resolve {host}")))?;
+    let connection = self
+      .endpoint
+      .connect(address, host)
+      .map_err(|e| TransportError::Other(e.to_string()))?
+      .await?;
+    Ok(Arc::new(connection))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_url() {
+    assert_eq!(
+      QuicConnector::parse_url("moqt://relay.example:4443").unwrap(),
+      ("relay.example", 4443)
+    );
+    assert_eq!(
+      QuicConnector::parse_url("moqt://[::1]:4443/live").unwrap(),
+      ("::1", 4443)
+    );
+    assert!(QuicConnector::parse_url("https://relay.example:4443").is_err());
+    assert!(QuicConnector::parse_url("moqt://relay.example").is_err());
+  }
+
+  #[tokio::test]
+  async fn test_send_recv_over_quic() {
+    let identity = Identity::self_signed(["localhost"]).unwrap();
+    let cert_hash = identity.certificate_chain().as_slice()[0].hash();
+    let server_config =
+      build_server_config(identity, Duration::from_secs(3), Duration::from_secs(7)).unwrap();
+    let server = quinn::Endpoint::server(server_config, "127.0.0.1:0".parse().unwrap()).unwrap();
+    let port = server.local_addr().unwrap().port();
+
+    let accept = tokio::spawn(async move {
+      let connection = server.accept().await.unwrap().await.unwrap();
+      let (mut send, mut recv) = MoqTransport::accept_bi(&connection).await.unwrap();
+      let mut buf = [0u8; 5];
+      let n = recv.read(&mut buf).await.unwrap().unwrap();
+      send.write_all(&buf[..n]).await.unwrap();
+      send.finish().await.unwrap();
+      connection.closed().await;
+    });
+
+    let connector =
+      QuicConnector::new(build_client_config(Some(vec![cert_hash])).unwrap()).unwrap();
+    let connection = connector
+      .connect(&format!("moqt://localhost:{port}"))
+      .await
+      .unwrap();
+    let (mut send, mut recv) = connection.open_bi().await.unwrap();
+    send.write_all(b"hello").await.unwrap();
+
+    let mut buf = [0u8; 5];
+    let mut received = Vec::new();
+    while let Some(n) = recv.read(&mut buf).await.unwrap() {
+      received.extend_from_slice(&buf[..n]);
+    }
+    assert_eq!(received, b"hello");
+    connection.close(0, b"done");
+    accept.await.unwrap();
+  }
+}
@@ -0,0 +1,177 @@
+// Copyright 2025 The MOQtail Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! [`MoqTransport`] over WebTransport, for browsers and the default relay
+//! listener.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::io::AsyncWriteExt;
+use wtransport::endpoint::endpoint_side;
+use wtransport::error::{
+  ConnectionError, SendDatagramError, StreamOpeningError, StreamReadError, StreamWriteError,
+};
+use wtransport::{Connection, Endpoint, RecvStream, SendStream, VarInt};
+
+use super::moq_transport::{
+  BoxRecvStream, BoxSendStream, MoqConnector, MoqRecvStream, MoqSendStream, MoqTransport,
+  TransportError,
+};
+
+impl From<ConnectionError> for TransportError {
+  fn from(e: ConnectionError) -> Self {
+    match e {
+      ConnectionError::LocallyClosed
+      | ConnectionError::ConnectionClosed(_)
+      | ConnectionError::ApplicationClosed(_)
+      | ConnectionError::TimedOut => TransportError::NotConnected,
+      e => TransportError::Other(e.to_string()),
+    }
+  }
+}
+
+impl From<StreamOpeningError> for TransportError {
+  fn from(e: StreamOpeningError) -> Self {
+    match e {
+      StreamOpeningError::NotConnected => TransportError::NotConnected,
+      e => TransportError::Other(e.to_string()),
+    }
+  }
+}
+
+impl From<StreamWriteError> for TransportError {
+  fn from(e: StreamWriteError) -> Self {
+    match e {
+      StreamWriteError::NotConnected => TransportError::NotConnected,
+      StreamWriteError::Closed => TransportError::StreamClosed,
+      StreamWriteError::Stopped(code) => TransportError::Stopped(code.into_inner()),
+      e => TransportError::Other(e.to_string()),
+    }
+  }
+}
+
+impl From<StreamReadError> for TransportError {
+  fn from(e: StreamReadError) -> Self {
+    match e {
+      StreamReadError::NotConnected => TransportError::NotConnected,
+      StreamReadError::Reset(code) => TransportError::Reset(code.into_inner()),
+      e => TransportError::Other(e.to_string()),
+    }
+  }
+}
+
+impl From<SendDatagramError> for TransportError {
+  fn from(e: SendDatagramError) -> Self {
+    match e {
+      SendDatagramError::NotConnected => TransportError::NotConnected,
+      SendDatagramError::UnsupportedByPeer => TransportError::DatagramsUnsupported,
+      SendDatagramError::TooLarge => TransportError::DatagramTooLarge,
+    }
+  }
+}
+
+#[async_trait]
+impl MoqSendStream for SendStream {
+  async fn write_all(&mut self, buf: &[u8]) -> Result<(), TransportError> {
+    Ok(SendStream::write_all(self, buf).await?)
+  }
+
+  async fn flush(&mut self) -> Result<(), TransportError> {
+    AsyncWriteExt::flush(self)
+      .await
+      .map_err(|e| TransportError::Other(e.to_string()))
+  }
+
+  async fn finish(&mut self) -> Result<(), TransportError> {
+    Ok(SendStream::finish(self).await?)
+  }
+
+  fn reset(&mut self, error_code: u32) -> Result<(), TransportError> {
+    SendStream::reset(self, VarInt::from_u32(error_code)).map_err(|_| TransportError::StreamClosed)
+  }
+
+  fn set_priority(&self, priority: i32) {
+    SendStream::set_priority(self, priority);
+  }
+}
+
+#[async_trait]
+impl MoqRecvStream for RecvStream {
+  async fn read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, TransportError> {
+    Ok(RecvStream::read(self, buf).await?)
+  }
+
+  fn stop(&mut self, error_code: u32) {
+    let _ = self.quic_stream_mut().stop(error_code.into());
+  }
+}
+
+#[async_trait]
+impl MoqTransport for Connection {
+  fn stable_id(&self) -> usize {
+    Connection::stable_id(self)
+  }
+
+  async fn open_bi(&self) -> Result<(BoxSendStream, BoxRecvStream), TransportError> {
+    let (send, recv) = Connection::open_bi(self).await?.await?;
+    Ok((Box::new(send), Box::new(recv)))
+  }
+
+  async fn accept_bi(&self) -> Result<(BoxSendStream, BoxRecvStream), TransportError> {
+    let (send, recv) = Connection::accept_bi(self).await?;
+    Ok((Box::new(send), Box::new(recv)))
+  }
+
+  async fn open_uni(&self) -> Result<BoxSendStream, TransportError> {
+    let send = Connection::open_uni(self).await?.await?;
+    Ok(Box::new(send))
+  }
+
+  async fn accept_uni(&self) -> Result<BoxRecvStream, TransportError> {
+    let recv = Connection::accept_uni(self).await?;
+    Ok(Box::new(recv))
+  }
+
+  fn send_datagram(&self, payload: Bytes) -> Result<(), TransportError> {
+    Ok(Connection::send_datagram(self, payload)?)
+  }
+
+  async fn receive_datagram(&self) -> Result<Bytes, TransportError> {
+    Ok(Connection::receive_datagram(self).await?.payload())
+  }
+
+  fn max_datagram_size(&self) -> Option<usize> {
+    Connection::max_datagram_size(self)
+  }
+
+  fn close(&self, error_code: u32, reason: &[u8]) {
+    Connection::close(self, VarInt::from_u32(error_code), reason);
+  }
+
+  async fn closed(&self) {
+    Connection::closed(self).await;
+  }
+}
+
+#[async_trait]
+impl MoqConnector for Endpoint<endpoint_side::Client> {
+  async fn connect(&self, url: &str) -> Result<Arc<dyn MoqTransport>, TransportError> {
+    let connection = Endpoint::connect(self, url)
+      .await
+      .map_err(|e| TransportError::Other(e.to_string()))?;
+    Ok(Arc::new(connection))
+  }
+}